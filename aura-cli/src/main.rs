@@ -1,10 +1,15 @@
 mod network;
 
-use clap::{Parser, Subcommand};
+use aura_common::{ColumnType, DataValue, QueryResponse, QueryResult};
+use clap::{Parser, Subcommand, ValueEnum};
 use colored::*;
 use network::AuraClient;
 use rustyline::error::ReadlineError;
+use rustyline::history::History;
 use rustyline::DefaultEditor;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 #[derive(Parser)]
 #[command(name = "aura")]
@@ -16,6 +21,26 @@ struct Cli {
     /// Server Address
     #[arg(long, default_value = "127.0.0.1:7654")]
     host: String,
+
+    /// Log in as this user (the password is read from AURA_PASSWORD)
+    #[arg(long)]
+    user: Option<String>,
+
+    /// How results are printed
+    #[arg(long, value_enum, global = true, default_value_t = Format::Table)]
+    format: Format,
+
+    /// Print how long each statement took, network round trip included
+    #[arg(long, global = true)]
+    timing: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Aligned tables and messages, for people
+    Table,
+    /// One JSON value per result, for scripts (errors are {"error": "..."})
+    Json,
 }
 
 #[derive(Subcommand)]
@@ -23,31 +48,102 @@ enum Commands {
     /// Start an interactive SQL shell (Default)
     Shell,
     /// Execute a single query
-    Exec { query: String },
+    Exec {
+        query: String,
+        /// Bind a value to the next `?` placeholder (repeatable)
+        #[arg(long = "param")]
+        params: Vec<String>,
+        /// Only validate the query (EXPLAIN VALIDATE); nothing is written
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
     let host = cli.host;
+    let user = cli.user.as_deref();
+    let format = cli.format;
+    let timing = cli.timing;
 
     match &cli.command {
-        Some(Commands::Exec { query }) => {
-            let mut client = AuraClient::connect(&host).await?;
-            let res = client.send_query(query).await?;
-            println!("{}", res);
+        Some(Commands::Exec {
+            query,
+            params,
+            dry_run,
+        }) => {
+            let sql = if *dry_run {
+                format!("EXPLAIN VALIDATE {}", query)
+            } else {
+                query.clone()
+            };
+            let outcome = async {
+                let mut client = connect(&host, user, format).await?;
+                let params: Vec<DataValue> = params.iter().map(|p| parse_param(p)).collect();
+                let started = Instant::now();
+                let res = if params.is_empty() {
+                    client.send_query(&sql).await?
+                } else {
+                    client.send_query_params(&sql, &params).await?
+                };
+                let elapsed = started.elapsed();
+                anyhow::Ok((client, res, elapsed))
+            }
+            .await;
+            match outcome {
+                Ok((mut client, res, elapsed)) => {
+                    print_response(&res, format);
+                    if timing {
+                        print_timing(elapsed, format);
+                    }
+                    print_notifications(&mut client, format);
+                }
+                // Scripts read the error from stdout like any other result
+                Err(e) if format == Format::Json => {
+                    println!("{}", to_json(Err(&e)));
+                    std::process::exit(1);
+                }
+                Err(e) => return Err(e),
+            }
         }
         Some(Commands::Shell) | None => {
-            start_repl(&host).await?;
+            start_repl(&host, user, format, timing).await?;
         }
     }
 
     Ok(())
 }
 
-async fn start_repl(host: &str) -> anyhow::Result<()> {
+/// Connects, and logs in when a user is given. The progress lines are only
+/// for people: in JSON, stdout holds nothing but results.
+async fn connect(host: &str, user: Option<&str>, format: Format) -> anyhow::Result<AuraClient> {
+    if format == Format::Table {
+        println!("🔌 Connecting to {}...", host);
+    }
+    let mut client = AuraClient::connect(host).await?;
+    if format == Format::Table {
+        println!("🔒 Handshake Complete. Quantum Secure Session Established.");
+    }
+    if let Some(user) = user {
+        let password = std::env::var("AURA_PASSWORD")
+            .map_err(|_| anyhow::anyhow!("Set AURA_PASSWORD to log in as {}", user))?;
+        client.authenticate(user, &password).await?;
+        if format == Format::Table {
+            println!("👤 Logged in as {}", user);
+        }
+    }
+    Ok(client)
+}
+
+async fn start_repl(
+    host: &str,
+    user: Option<&str>,
+    format: Format,
+    timing: bool,
+) -> anyhow::Result<()> {
     // 1. Connect
-    let mut client = match AuraClient::connect(host).await {
+    let mut client = match connect(host, user, format).await {
         Ok(c) => c,
         Err(e) => {
             eprintln!("{} {}", "Fatal Error:".red().bold(), e);
@@ -57,32 +153,81 @@ async fn start_repl(host: &str) -> anyhow::Result<()> {
 
     println!(
         "{}",
-        "Welcome to AuraDB Shell. Type 'exit' to quit.".green()
+        "Welcome to AuraDB Shell. End statements with ';', type .help for commands.".green()
     );
 
-    // 2. Start Read-Eval-Print Loop
+    // 2. Start Read-Eval-Print Loop, with the history of earlier sessions
     let mut rl = DefaultEditor::new()?;
+    let history = history_path(|name| std::env::var_os(name));
+    if let Some(path) = &history {
+        if let Err(e) = load_history(rl.history_mut(), path) {
+            eprintln!("{} {}: {}", "Warning:".yellow(), path.display(), e);
+        }
+    }
 
+    let mut buffer = StatementBuffer::default();
     loop {
-        let readline = rl.readline(&format!("{} > ", "aura".blue().bold()));
-        match readline {
+        let prompt = if buffer.is_empty() {
+            format!("{} > ", "aura".blue().bold())
+        } else {
+            format!("{} ", "  ...>".blue().bold())
+        };
+        match rl.readline(&prompt) {
             Ok(line) => {
-                let input = line.trim();
-                if input.eq_ignore_ascii_case("exit") {
-                    break;
-                }
-                if input.is_empty() {
-                    continue;
+                let mut statement = None;
+                if buffer.is_empty() {
+                    let input = line.trim();
+                    if input.trim_end_matches(';').eq_ignore_ascii_case("exit") {
+                        break;
+                    }
+                    if input.is_empty() {
+                        continue;
+                    }
+                    if input.starts_with('.') {
+                        rl.add_history_entry(input)?;
+                        match MetaCommand::parse(input) {
+                            Ok(MetaCommand::Exit) => break,
+                            Ok(MetaCommand::Help) => {
+                                println!("{}", META_HELP);
+                                continue;
+                            }
+                            Ok(command) => statement = command.sql(),
+                            Err(e) => {
+                                println!("{} {}", "Error:".red(), e);
+                                continue;
+                            }
+                        }
+                    }
                 }
-
-                rl.add_history_entry(input)?;
+                let statement = match statement {
+                    Some(sql) => sql,
+                    None => {
+                        let Some(statement) = buffer.push(&line) else {
+                            continue;
+                        };
+                        // A lone ';'
+                        if statement.is_empty() {
+                            continue;
+                        }
+                        rl.add_history_entry(statement.replace('\n', " "))?;
+                        statement
+                    }
+                };
 
                 // 3. Send to Server
-                match client.send_query(input).await {
-                    Ok(response) => println!("{}", response),
+                let started = Instant::now();
+                match client.send_query(&statement).await {
+                    Ok(response) => print_response(&response, format),
+                    Err(e) if format == Format::Json => println!("{}", to_json(Err(&e))),
                     Err(e) => println!("{} {}", "Error:".red(), e),
                 }
+                if timing {
+                    print_timing(started.elapsed(), format);
+                }
+                print_notifications(&mut client, format);
             }
+            // Abandons a half-typed statement, or else the shell
+            Err(ReadlineError::Interrupted) if !buffer.is_empty() => buffer.clear(),
             Err(ReadlineError::Interrupted) => {
                 println!("CTRL-C");
                 break;
@@ -97,9 +242,280 @@ async fn start_repl(host: &str) -> anyhow::Result<()> {
             }
         }
     }
+
+    // Creates the file on the first run
+    if let Some(path) = &history {
+        if let Err(e) = rl.save_history(path) {
+            eprintln!("{} {}: {}", "Warning:".yellow(), path.display(), e);
+        }
+    }
     Ok(())
 }
 
+/// What `.help` prints
+const META_HELP: &str = "\
+.tables           List the tables
+.schema <table>   Show a table's columns
+.help             Show this list
+.exit             Leave the shell (also .quit, exit)
+
+Anything else is SQL, sent once a line ends in ';'.";
+
+/// A shell command starting with '.', answered without typing SQL
+#[derive(Debug, PartialEq)]
+enum MetaCommand {
+    Tables,
+    Schema(String),
+    Help,
+    Exit,
+}
+
+impl MetaCommand {
+    /// Reads a line starting with '.' (a trailing ';' is allowed)
+    fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.trim().trim_end_matches(';').split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+        match (name.to_ascii_lowercase().as_str(), args.as_slice()) {
+            (".tables", []) => Ok(MetaCommand::Tables),
+            (".schema", [table]) => Ok(MetaCommand::Schema(table.to_string())),
+            (".schema", _) => Err("Usage: .schema <table>".to_string()),
+            (".help", []) => Ok(MetaCommand::Help),
+            (".exit" | ".quit", []) => Ok(MetaCommand::Exit),
+            (".tables" | ".help" | ".exit" | ".quit", _) => {
+                Err(format!("{} takes no arguments", name))
+            }
+            _ => Err(format!(
+                "Unknown command '{}'. Type .help for the list of commands",
+                name
+            )),
+        }
+    }
+
+    /// The statement the server answers it with (None for commands the shell handles)
+    fn sql(&self) -> Option<String> {
+        match self {
+            MetaCommand::Tables => Some("SHOW TABLES".to_string()),
+            MetaCommand::Schema(table) => Some(format!("DESCRIBE {}", table)),
+            MetaCommand::Help | MetaCommand::Exit => None,
+        }
+    }
+}
+
+/// Collects shell lines until they add up to a statement: text ending in `;`
+/// outside a quoted string. Several statements may share the input.
+#[derive(Default)]
+struct StatementBuffer {
+    text: String,
+}
+
+impl StatementBuffer {
+    /// Adds a line. Once the input is complete, returns it (without its final `;`)
+    /// and starts over.
+    fn push(&mut self, line: &str) -> Option<String> {
+        if !self.text.is_empty() {
+            self.text.push('\n');
+        }
+        self.text.push_str(line.trim_end());
+
+        // An odd number of quotes leaves a string open ('' escapes come in pairs)
+        let in_string = self.text.matches('\'').count() % 2 == 1;
+        if in_string || !self.text.ends_with(';') {
+            return None;
+        }
+        let text = std::mem::take(&mut self.text);
+        Some(text.trim_end_matches(';').trim().to_string())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.text.clear();
+    }
+}
+
+/// Where the shell keeps its history: $AURA_HISTORY, else `.aura_history` in the
+/// home directory. None (history stays in memory) when neither is known.
+fn history_path(var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    let non_empty = |name: &str| var(name).filter(|value| !value.is_empty());
+    if let Some(path) = non_empty("AURA_HISTORY") {
+        return Some(PathBuf::from(path));
+    }
+    non_empty("HOME")
+        .or_else(|| non_empty("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".aura_history"))
+}
+
+/// Loads saved history. A missing file is just a first run, not an error.
+fn load_history(history: &mut impl History, path: &Path) -> rustyline::Result<()> {
+    match history.load(path) {
+        Err(ReadlineError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Interprets a `--param` argument: integers, floats, true/false and NULL are typed,
+/// anything else is bound as text.
+fn parse_param(raw: &str) -> DataValue {
+    if raw.eq_ignore_ascii_case("null") {
+        DataValue::Null
+    } else if let Ok(b) = raw.to_ascii_lowercase().parse::<bool>() {
+        DataValue::Boolean(b)
+    } else if let Ok(i) = raw.parse::<i64>() {
+        DataValue::Integer(i)
+    } else if let Ok(f) = raw.parse::<f64>() {
+        DataValue::Float(f)
+    } else {
+        DataValue::Text(raw.to_string())
+    }
+}
+
+/// Prints a result, then a one-line summary of what it cost (only the result in JSON)
+fn print_response(response: &QueryResponse, format: Format) {
+    if format == Format::Json {
+        println!("{}", to_json(Ok(&response.result)));
+        return;
+    }
+    print_result(&response.result);
+    if let Some(stats) = &response.stats {
+        println!("{}", stats.to_string().dimmed());
+    }
+}
+
+/// `--timing`: "(12.4 ms)" after the result. In JSON it goes to stderr, so stdout
+/// stays one JSON value per statement.
+fn print_timing(elapsed: Duration, format: Format) {
+    let timing = format!("({})", format_duration(elapsed));
+    if format == Format::Json {
+        eprintln!("{}", timing);
+    } else {
+        println!("{}", timing.dimmed());
+    }
+}
+
+/// A duration in the largest unit it has one of: "850 µs", "12.4 ms", "3.21 s"
+fn format_duration(elapsed: Duration) -> String {
+    let micros = elapsed.as_secs_f64() * 1e6;
+    if micros < 1e3 {
+        format!("{:.0} µs", micros)
+    } else if micros < 1e6 {
+        format!("{:.1} ms", micros / 1e3)
+    } else {
+        format!("{:.2} s", micros / 1e6)
+    }
+}
+
+/// What `--format json` prints for a statement: its result, or {"error": "..."}
+fn to_json(outcome: Result<&QueryResult, &anyhow::Error>) -> serde_json::Value {
+    match outcome {
+        Ok(result) => result.to_json(),
+        Err(e) => serde_json::json!({ "error": e.to_string() }),
+    }
+}
+
+/// Result sets are drawn as tables; anything else prints as the server phrased it
+fn print_result(result: &QueryResult) {
+    match result {
+        QueryResult::Rows {
+            columns,
+            types,
+            rows,
+        } => print!("{}", format_table(columns, types, rows)),
+        QueryResult::Batch(results) => {
+            for (i, result) in results.iter().enumerate() {
+                if i > 0 {
+                    println!();
+                }
+                print_result(result);
+            }
+        }
+        other => println!("{}", other),
+    }
+}
+
+/// Renders a result set as an aligned ASCII table with a colored header row.
+/// Numbers are right-aligned, everything else left-aligned.
+fn format_table(columns: &[String], types: &[ColumnType], rows: &[Vec<DataValue>]) -> String {
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(|v| v.to_string()).collect())
+        .collect();
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(j, name)| {
+            cells
+                .iter()
+                .filter_map(|row| row.get(j))
+                .map(|cell| cell.chars().count())
+                .fold(name.chars().count(), usize::max)
+        })
+        .collect();
+
+    let border = format!(
+        "+{}+\n",
+        widths
+            .iter()
+            .map(|w| "-".repeat(w + 2))
+            .collect::<Vec<_>>()
+            .join("+")
+    );
+    let pad = |text: &str, j: usize, right: bool| {
+        let fill = " ".repeat(widths[j] - text.chars().count());
+        if right {
+            format!("{}{}", fill, text)
+        } else {
+            format!("{}{}", text, fill)
+        }
+    };
+
+    let mut table = border.clone();
+    let header: Vec<String> = columns
+        .iter()
+        .enumerate()
+        .map(|(j, name)| pad(name, j, false).bold().cyan().to_string())
+        .collect();
+    table.push_str(&format!("| {} |\n", header.join(" | ")));
+    table.push_str(&border);
+    for row in &cells {
+        let line: Vec<String> = (0..columns.len())
+            .map(|j| {
+                let numeric = matches!(types.get(j), Some(ColumnType::Integer | ColumnType::Float));
+                pad(row.get(j).map_or("", String::as_str), j, numeric)
+            })
+            .collect();
+        table.push_str(&format!("| {} |\n", line.join(" | ")));
+    }
+    if !cells.is_empty() {
+        table.push_str(&border);
+    }
+    table.push_str(&format!(
+        "({} row{})\n",
+        rows.len(),
+        if rows.len() == 1 { "" } else { "s" }
+    ));
+    table
+}
+
+/// Shows any LISTEN notifications that arrived alongside the last response
+fn print_notifications(client: &mut AuraClient, format: Format) {
+    for n in client.take_notifications() {
+        if format == Format::Json {
+            let notification = serde_json::json!({ "channel": n.channel, "payload": n.payload });
+            println!("{}", serde_json::json!({ "notification": notification }));
+            continue;
+        }
+        println!(
+            "{} \"{}\" received: {}",
+            "Asynchronous notification".yellow(),
+            n.channel,
+            n.payload
+        );
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use aura_common::document::{AuraDocument, DataValue};
@@ -133,6 +549,241 @@ mod tests {
         assert_eq!(deserialized.data.len(), 4);
     }
 
+    #[test]
+    fn test_exec_param_parsing() {
+        use super::parse_param;
+
+        assert_eq!(parse_param("42"), DataValue::Integer(42));
+        assert_eq!(parse_param("2.5"), DataValue::Float(2.5));
+        assert_eq!(parse_param("TRUE"), DataValue::Boolean(true));
+        assert_eq!(parse_param("null"), DataValue::Null);
+        assert_eq!(
+            parse_param("O'Brien"),
+            DataValue::Text("O'Brien".to_string())
+        );
+    }
+
+    #[test]
+    fn test_table_formatting() {
+        use super::format_table;
+        use aura_common::ColumnType;
+
+        colored::control::set_override(false);
+        let docs = [
+            AuraDocument::new("user_1")
+                .with_field("name", DataValue::Text("Ann".to_string()))
+                .with_field("age", DataValue::Integer(7)),
+            AuraDocument::new("user_22")
+                .with_field("name", DataValue::Text("Bartholomew".to_string())),
+        ];
+        // Columns are the union of the documents' keys; missing fields are NULL
+        let columns: Vec<String> = ["id", "name", "age"].map(String::from).to_vec();
+        let rows: Vec<Vec<DataValue>> = docs
+            .iter()
+            .map(|doc| {
+                let mut row = vec![DataValue::Text(doc.id.clone())];
+                row.extend(
+                    columns[1..]
+                        .iter()
+                        .map(|c| doc.get(c).cloned().unwrap_or(DataValue::Null)),
+                );
+                row
+            })
+            .collect();
+        let types = [ColumnType::Text, ColumnType::Text, ColumnType::Integer];
+
+        let table = format_table(&columns, &types, &rows);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "+---------+-------------+------+");
+        assert_eq!(lines[1], "| id      | name        | age  |");
+        assert_eq!(lines[3], "| user_1  | Ann         |    7 |");
+        assert_eq!(lines[4], "| user_22 | Bartholomew | NULL |");
+        assert_eq!(lines[6], "(2 rows)");
+        assert!(lines
+            .iter()
+            .take(6)
+            .all(|line| line.len() == lines[0].len()));
+    }
+
+    #[test]
+    fn test_exec_json_output() {
+        use super::{to_json, Cli, Commands, Format};
+        use aura_common::QueryResult;
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["aura", "exec", "SELECT * FROM users", "--format", "json"])
+            .unwrap();
+        assert_eq!(cli.format, Format::Json);
+        assert!(matches!(cli.command, Some(Commands::Exec { .. })));
+
+        let result = QueryResult::rows(
+            vec!["id".to_string(), "age".to_string()],
+            vec![vec![DataValue::Text("u1".to_string()), DataValue::Null]],
+        );
+        let printed = to_json(Ok(&result)).to_string();
+        let parsed: serde_json::Value = serde_json::from_str(&printed).unwrap();
+        assert_eq!(
+            parsed,
+            serde_json::json!({ "columns": ["id", "age"], "rows": [["u1", null]] })
+        );
+
+        // Columns sharing a name (a join's two ids) each keep their value
+        let result = QueryResult::rows(
+            vec!["id".to_string(), "id".to_string()],
+            vec![vec![
+                DataValue::Text("u1".to_string()),
+                DataValue::Text("o7".to_string()),
+            ]],
+        );
+        let parsed = to_json(Ok(&result));
+        assert_eq!(parsed["columns"], serde_json::json!(["id", "id"]));
+        assert_eq!(parsed["rows"], serde_json::json!([["u1", "o7"]]));
+
+        let error = anyhow::anyhow!("Unknown Column: users.\"email\"");
+        let printed = to_json(Err(&error)).to_string();
+        let parsed: serde_json::Value = serde_json::from_str(&printed).unwrap();
+        assert_eq!(parsed["error"], "Unknown Column: users.\"email\"");
+    }
+
+    #[test]
+    fn test_duration_formatting() {
+        use super::{format_duration, Cli};
+        use clap::Parser;
+        use std::time::Duration;
+
+        assert_eq!(format_duration(Duration::from_nanos(850_400)), "850 µs");
+        assert_eq!(format_duration(Duration::from_micros(12_400)), "12.4 ms");
+        assert_eq!(format_duration(Duration::from_micros(999_940)), "999.9 ms");
+        assert_eq!(format_duration(Duration::from_millis(3_210)), "3.21 s");
+        assert_eq!(format_duration(Duration::ZERO), "0 µs");
+
+        let cli = Cli::try_parse_from(["aura", "exec", "SELECT 1", "--timing"]).unwrap();
+        assert!(cli.timing);
+    }
+
+    #[test]
+    fn test_meta_commands() {
+        use super::MetaCommand;
+
+        assert_eq!(MetaCommand::parse(".tables"), Ok(MetaCommand::Tables));
+        assert_eq!(
+            MetaCommand::parse(".schema users"),
+            Ok(MetaCommand::Schema("users".to_string()))
+        );
+        assert_eq!(
+            MetaCommand::parse(" .SCHEMA users; "),
+            MetaCommand::parse(".schema users")
+        );
+        assert_eq!(MetaCommand::parse(".quit"), Ok(MetaCommand::Exit));
+        assert_eq!(MetaCommand::parse(".help"), Ok(MetaCommand::Help));
+
+        assert_eq!(MetaCommand::Tables.sql().as_deref(), Some("SHOW TABLES"));
+        assert_eq!(
+            MetaCommand::Schema("users".to_string()).sql().as_deref(),
+            Some("DESCRIBE users")
+        );
+        assert_eq!(MetaCommand::Help.sql(), None);
+
+        let err = MetaCommand::parse(".drop users").unwrap_err();
+        assert!(err.contains("Unknown command '.drop'"), "{}", err);
+        assert!(err.contains(".help"), "{}", err);
+        assert_eq!(
+            MetaCommand::parse(".schema"),
+            Err("Usage: .schema <table>".to_string())
+        );
+        assert!(MetaCommand::parse(".tables users").is_err());
+    }
+
+    #[test]
+    fn test_statement_buffer() {
+        use super::StatementBuffer;
+
+        let mut buffer = StatementBuffer::default();
+        assert_eq!(buffer.push("INSERT INTO users (id, name)"), None);
+        assert_eq!(buffer.push("VALUES ('u1', 'Ann'),"), None);
+        assert!(!buffer.is_empty());
+        assert_eq!(
+            buffer.push("       ('u2', 'Bob');  "),
+            Some(
+                "INSERT INTO users (id, name)\nVALUES ('u1', 'Ann'),\n       ('u2', 'Bob')"
+                    .to_string()
+            )
+        );
+        assert!(buffer.is_empty());
+
+        // A ';' inside a string doesn't end the statement
+        assert_eq!(buffer.push("SELECT * FROM notes WHERE body = 'a;"), None);
+        assert_eq!(
+            buffer.push("b';"),
+            Some("SELECT * FROM notes WHERE body = 'a;\nb'".to_string())
+        );
+        assert_eq!(buffer.push(";"), Some(String::new()));
+    }
+
+    #[test]
+    fn test_history_path() {
+        use super::history_path;
+        use std::ffi::OsString;
+        use std::path::PathBuf;
+
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| OsString::from(value))
+            }
+        };
+        assert_eq!(
+            history_path(env(&[("HOME", "/home/ann")])),
+            Some(PathBuf::from("/home/ann/.aura_history"))
+        );
+        assert_eq!(
+            history_path(env(&[("HOME", "/home/ann"), ("AURA_HISTORY", "/tmp/h")])),
+            Some(PathBuf::from("/tmp/h"))
+        );
+        assert_eq!(
+            history_path(env(&[
+                ("AURA_HISTORY", ""),
+                ("USERPROFILE", "C:/Users/ann")
+            ])),
+            Some(PathBuf::from("C:/Users/ann/.aura_history"))
+        );
+        assert_eq!(history_path(env(&[])), None);
+    }
+
+    #[test]
+    fn test_history_round_trip() {
+        use super::load_history;
+        use rustyline::history::{FileHistory, History};
+
+        let dir = std::env::temp_dir().join(format!("aura_history_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history");
+        let _ = std::fs::remove_file(&path);
+
+        // First run: nothing to load yet
+        let mut history = FileHistory::new();
+        load_history(&mut history, &path).unwrap();
+        assert_eq!(history.len(), 0);
+
+        history.add("SELECT * FROM users").unwrap();
+        history.add("INSERT INTO users (id) VALUES ('u1')").unwrap();
+        history.save(&path).unwrap();
+
+        let mut reloaded = FileHistory::new();
+        load_history(&mut reloaded, &path).unwrap();
+        let entries: Vec<&String> = reloaded.iter().collect();
+        assert_eq!(
+            entries,
+            [
+                "SELECT * FROM users",
+                "INSERT INTO users (id) VALUES ('u1')"
+            ]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_network_address_parsing() {
         // Test parsing of host:port combinations
@@ -242,4 +893,39 @@ mod tests {
             assert!(!bytes.is_empty());
         }
     }
+
+    #[tokio::test]
+    async fn test_oversized_frame_is_refused() {
+        use aura_security::kem::{HybridKeyPair, KemAlgorithm};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // A server that handshakes, then announces a 4 GiB response
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let hybrid = KemAlgorithm::HybridX25519Kyber1024;
+            let hello = [super::network::PROTOCOL_VERSION, 1, hybrid.id()];
+            socket.write_all(&hello).await.unwrap();
+            let mut choice = [0u8; 1];
+            socket.read_exact(&mut choice).await.unwrap();
+            let keys = HybridKeyPair::generate();
+            socket.write_all(&keys.public_bytes()).await.unwrap();
+            let mut ciphertext = vec![0u8; hybrid.ciphertext_len()];
+            socket.read_exact(&mut ciphertext).await.unwrap();
+
+            let mut request = [0u8; 5];
+            socket.read_exact(&mut request).await.unwrap();
+            socket
+                .write_all(&[0xff, 0xff, 0xff, 0xff, 2])
+                .await
+                .unwrap();
+            // Stay connected: the client must give up on its own
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+        });
+
+        let mut client = super::AuraClient::connect(&addr.to_string()).await.unwrap();
+        let err = client.send_query("SELECT 1").await.unwrap_err();
+        assert!(err.to_string().contains("Frame too large"), "{}", err);
+    }
 }
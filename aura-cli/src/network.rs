@@ -1,68 +1,217 @@
-use anyhow::{Context, Result};
-use pqcrypto_kyber::kyber1024;
-use pqcrypto_traits::kem::{Ciphertext, PublicKey, SharedSecret};
+use anyhow::{bail, Context, Result};
+use aura_common::{AuthRequest, DataValue, QueryRequest, QueryResponse};
+use aura_security::kem::{self, KemAlgorithm};
+use aura_security::symmetric::{self, Direction, SessionKey};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 
+// Must match aura-server's protocol.rs
+pub(crate) const PROTOCOL_VERSION: u8 = 7;
+
+// Upper bound on a single frame (must match aura-server's protocol.rs), so a bad
+// length prefix can't make us allocate gigabytes
+const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+// Frame types (must match aura-server's protocol.rs)
+// Wire format: [Length: u32 (BE)][Type: u8][Payload...], payload sealed with the session key
+// (bound to the frame's direction, sequence number and type, see `symmetric::frame_aad`)
+const FRAME_QUERY: u8 = 1;
+const FRAME_RESPONSE: u8 = 2;
+const FRAME_NOTIFICATION: u8 = 3;
+const FRAME_QUERY_PARAMS: u8 = 4;
+const FRAME_ERROR: u8 = 5;
+const FRAME_AUTH: u8 = 6;
+
+/// A message pushed by the server on a channel this client LISTENs to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub channel: String,
+    pub payload: String,
+}
+
 pub struct AuraClient {
     stream: TcpStream,
-    #[allow(dead_code)] // We will use this in Step 9 for encryption
-    session_key: Vec<u8>,
+    session_key: SessionKey,
+    /// Frames sealed and opened so far (each side numbers its frames from 0)
+    sent: u64,
+    received: u64,
+    /// Notifications received while waiting for query responses
+    notifications: Vec<Notification>,
 }
 
 impl AuraClient {
     /// Connects to the server and performs the PQC Handshake
     pub async fn connect(addr: &str) -> Result<Self> {
-        println!("🔌 Connecting to {}...", addr);
         let mut stream = TcpStream::connect(addr)
             .await
             .context("Failed to connect to AuraDB Server")?;
 
         // --- STEP 1: HANDSHAKE (The Quantum Shield) ---
 
-        // A. Receive Server's Public Key
-        // Kyber1024 Public Key is 1568 bytes
-        let mut pk_buffer = vec![0u8; 1568];
+        // A. Receive the Server's offer: [version][count][algorithm ids...]
+        let mut header = [0u8; 2];
+        stream
+            .read_exact(&mut header)
+            .await
+            .context("Failed to receive Server Hello")?;
+        if header[0] != PROTOCOL_VERSION {
+            bail!(
+                "Unsupported protocol version {} (expected {})",
+                header[0],
+                PROTOCOL_VERSION
+            );
+        }
+        let mut hello = vec![0u8; 2 + header[1] as usize];
+        hello[..2].copy_from_slice(&header);
+        stream
+            .read_exact(&mut hello[2..])
+            .await
+            .context("Failed to receive Server Hello")?;
+        let offered: Vec<KemAlgorithm> = hello[2..]
+            .iter()
+            .copied()
+            .filter_map(KemAlgorithm::from_id)
+            .collect();
+
+        // B. Pick the Server's favourite that we support (hybrid, else pure Kyber)
+        let Some(algorithm) = kem::negotiate(&offered, &KemAlgorithm::ALL) else {
+            bail!("Server offers no key exchange this client supports");
+        };
+        stream
+            .write_all(&[algorithm.id()])
+            .await
+            .context("Failed to send algorithm choice")?;
+
+        // C. Receive Server's Public Key(s)
+        let mut pk_buffer = vec![0u8; algorithm.public_key_len()];
         stream
             .read_exact(&mut pk_buffer)
             .await
             .context("Failed to receive Server Public Key")?;
 
-        // B. Encapsulate (Create Shared Secret)
-        let pk =
-            kyber1024::PublicKey::from_bytes(&pk_buffer).context("Invalid public key received")?;
-        let (shared_secret, ciphertext) = kyber1024::encapsulate(&pk);
+        // D. Encapsulate (Create Shared Secret)
+        let (shared_secret, ciphertext) = match algorithm {
+            KemAlgorithm::Kyber1024 => kem::encapsulate(&pk_buffer),
+            KemAlgorithm::HybridX25519Kyber1024 => kem::hybrid_encapsulate(&pk_buffer),
+        }
+        .context("Invalid public key received")?;
 
-        // C. Send Ciphertext to Server
+        // E. Send Ciphertext to Server
         stream
-            .write_all(ciphertext.as_bytes())
+            .write_all(&ciphertext)
             .await
             .context("Failed to send Ciphertext")?;
 
-        println!("🔒 Handshake Complete. Quantum Secure Session Established.");
+        // F. Bind the key to the whole exchange, the offer and our choice included
+        let transcript = kem::handshake_transcript(&hello, algorithm, &pk_buffer, &ciphertext);
+        let session_key = SessionKey::derive(shared_secret, &transcript);
 
         Ok(Self {
             stream,
-            session_key: shared_secret.as_bytes().to_vec(),
+            session_key,
+            sent: 0,
+            received: 0,
+            notifications: Vec::new(),
         })
     }
 
+    /// Logs in. Servers that require it refuse statements until this succeeds.
+    pub async fn authenticate(&mut self, username: &str, password: &str) -> Result<()> {
+        let payload = AuthRequest::new(username, password)
+            .to_bytes()
+            .context("Failed to serialize credentials")?;
+        self.write_frame(FRAME_AUTH, &payload).await?;
+        self.read_response()
+            .await
+            .with_context(|| format!("Login as {} failed", username))?;
+        Ok(())
+    }
+
     /// Sends a raw SQL query and gets a response
-    pub async fn send_query(&mut self, query: &str) -> Result<String> {
-        // --- STEP 2: TRANSPORT ---
+    pub async fn send_query(&mut self, query: &str) -> Result<QueryResponse> {
+        // --- STEP 2: TRANSPORT (payloads are sealed with the session key) ---
+        self.write_frame(FRAME_QUERY, query.as_bytes()).await?;
+        self.read_response().await
+    }
 
-        // For Step 8, we send the query text directly to verify the pipe works.
-        // In Step 9, we will wrap this with `symmetric::encrypt(payload, &self.session_key)`
-        let payload = query.as_bytes();
+    /// Sends a query with `?`/`$1` placeholders; the values travel separately
+    /// from the SQL text, so they never need quoting or escaping.
+    pub async fn send_query_params(
+        &mut self,
+        query: &str,
+        params: &[DataValue],
+    ) -> Result<QueryResponse> {
+        let payload = QueryRequest::new(query, params.to_vec())
+            .to_bytes()
+            .context("Failed to serialize query parameters")?;
+        self.write_frame(FRAME_QUERY_PARAMS, &payload).await?;
+        self.read_response().await
+    }
 
-        // Write Payload
-        self.stream.write_all(payload).await?;
+    /// Reads frames until our Response arrives; notifications can come first.
+    /// A statement the server rejected comes back as an Err with its message.
+    async fn read_response(&mut self) -> Result<QueryResponse> {
+        loop {
+            let (frame_type, payload) = self.read_frame().await?;
+            match frame_type {
+                FRAME_RESPONSE => {
+                    return QueryResponse::from_bytes(&payload)
+                        .context("Malformed response from server")
+                }
+                FRAME_ERROR => bail!("{}", String::from_utf8_lossy(&payload)),
+                FRAME_NOTIFICATION => self.notifications.push(Self::parse_notification(&payload)),
+                other => bail!("Unexpected frame type {} from server", other),
+            }
+        }
+    }
+
+    /// Returns (and clears) the notifications received so far
+    pub fn take_notifications(&mut self) -> Vec<Notification> {
+        std::mem::take(&mut self.notifications)
+    }
+
+    fn parse_notification(payload: &[u8]) -> Notification {
+        let text = String::from_utf8_lossy(payload);
+        let (channel, payload) = text.split_once(':').unwrap_or((&text, ""));
+        Notification {
+            channel: channel.to_string(),
+            payload: payload.to_string(),
+        }
+    }
+
+    async fn write_frame(&mut self, frame_type: u8, payload: &[u8]) -> Result<()> {
+        let aad = symmetric::frame_aad(Direction::ClientToServer, self.sent, frame_type);
+        let payload = symmetric::encrypt_with_aad(payload, self.session_key.as_bytes(), &aad)
+            .context("Failed to encrypt request")?;
+        self.sent += 1;
+        let mut bytes = Vec::with_capacity(5 + payload.len());
+        bytes.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        bytes.push(frame_type);
+        bytes.extend_from_slice(&payload);
+        self.stream.write_all(&bytes).await?;
+        Ok(())
+    }
+
+    async fn read_frame(&mut self) -> Result<(u8, Vec<u8>)> {
+        let mut header = [0u8; 5];
+        self.stream
+            .read_exact(&mut header)
+            .await
+            .context("Connection closed by server")?;
 
-        // Read Response
-        let mut buffer = [0u8; 4096];
-        let n = self.stream.read(&mut buffer).await?;
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        if len > MAX_FRAME_SIZE {
+            bail!("Frame too large: {} bytes", len);
+        }
+        let mut payload = vec![0u8; len];
+        self.stream.read_exact(&mut payload).await?;
 
-        let response = String::from_utf8_lossy(&buffer[0..n]).to_string();
-        Ok(response)
+        // A frame that doesn't decrypt (or is out of order) means the session
+        // can't be trusted anymore
+        let aad = symmetric::frame_aad(Direction::ServerToClient, self.received, header[4]);
+        let payload = symmetric::decrypt_with_aad(&payload, self.session_key.as_bytes(), &aad)
+            .context("Failed to decrypt frame from server")?;
+        self.received += 1;
+        Ok((header[4], payload))
     }
 }
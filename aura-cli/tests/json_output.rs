@@ -0,0 +1,80 @@
+//! `--format json` runs of the real binary: whatever happens, stdout must be
+//! exactly one JSON value, so scripts can pipe it to a parser.
+
+use aura_common::{QueryResponse, QueryResult};
+use aura_security::kem::{self, HybridKeyPair, KemAlgorithm};
+use aura_security::symmetric::{self, Direction, SessionKey};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const PROTOCOL_VERSION: u8 = 7;
+const FRAME_RESPONSE: u8 = 2;
+
+/// Runs `aura --host <host> --format json exec <sql>` and parses its stdout
+async fn exec_json(host: &str, sql: &str) -> serde_json::Value {
+    let output = tokio::process::Command::new(env!("CARGO_BIN_EXE_aura-cli"))
+        .args(["--host", host, "--format", "json", "exec", sql])
+        .output()
+        .await
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    serde_json::from_str(&stdout).unwrap_or_else(|e| panic!("{}: {:?}", e, stdout))
+}
+
+/// A server that handshakes and answers one request with `result`
+async fn spawn_fake_server(result: QueryResult) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let hybrid = KemAlgorithm::HybridX25519Kyber1024;
+        let hello = [PROTOCOL_VERSION, 1, hybrid.id()];
+        socket.write_all(&hello).await.unwrap();
+        let mut choice = [0u8; 1];
+        socket.read_exact(&mut choice).await.unwrap();
+        let keys = HybridKeyPair::generate();
+        let public_key = keys.public_bytes();
+        socket.write_all(&public_key).await.unwrap();
+        let mut ciphertext = vec![0u8; hybrid.ciphertext_len()];
+        socket.read_exact(&mut ciphertext).await.unwrap();
+        let transcript = kem::handshake_transcript(&hello, hybrid, &public_key, &ciphertext);
+        let key = SessionKey::derive(keys.decapsulate(&ciphertext).unwrap(), &transcript);
+
+        let mut header = [0u8; 5];
+        socket.read_exact(&mut header).await.unwrap();
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        socket.read_exact(&mut vec![0u8; len]).await.unwrap();
+
+        let response = QueryResponse {
+            result,
+            stats: None,
+        };
+        let aad = symmetric::frame_aad(Direction::ServerToClient, 0, FRAME_RESPONSE);
+        let payload =
+            symmetric::encrypt_with_aad(&response.to_bytes().unwrap(), key.as_bytes(), &aad)
+                .unwrap();
+        let mut frame = (payload.len() as u32).to_be_bytes().to_vec();
+        frame.push(FRAME_RESPONSE);
+        frame.extend_from_slice(&payload);
+        socket.write_all(&frame).await.unwrap();
+    });
+    addr.to_string()
+}
+
+#[tokio::test]
+async fn test_json_stdout_is_one_value() {
+    let host = spawn_fake_server(QueryResult::Affected(2)).await;
+    let printed = exec_json(&host, "UPDATE users SET age = 1").await;
+    assert_eq!(printed, serde_json::json!({ "affected": 2 }));
+}
+
+#[tokio::test]
+async fn test_json_connection_error_is_one_value() {
+    // Nothing listens on a port that was just released
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let host = listener.local_addr().unwrap().to_string();
+    drop(listener);
+
+    let printed = exec_json(&host, "SELECT 1").await;
+    assert!(printed["error"].is_string(), "{}", printed);
+}
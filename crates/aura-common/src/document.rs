@@ -1,9 +1,14 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 
 /// The atomic unit of data in AuraDB.
 /// This allows us to store SQL rows AND NoSQL JSON documents in the same engine.
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// Values are totally ordered (see the `Ord` impl), so they can be sorted and used as keys.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum DataValue {
     Null,
     Boolean(bool),
@@ -20,6 +25,267 @@ pub enum DataValue {
 
     /// For NoSQL nested objects: {"address": {"city": "NY"}}
     Object(HashMap<String, DataValue>),
+
+    /// A point in time: milliseconds since 1970-01-01T00:00:00Z.
+    /// (Last, so documents written before it existed still decode.)
+    Timestamp(i64),
+}
+
+/// JSON maps onto the NoSQL variants: objects and arrays nest, numbers become
+/// Integer when they fit in an i64 and Float otherwise.
+impl From<serde_json::Value> for DataValue {
+    fn from(value: serde_json::Value) -> Self {
+        use serde_json::Value;
+        match value {
+            Value::Null => DataValue::Null,
+            Value::Bool(b) => DataValue::Boolean(b),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => DataValue::Integer(i),
+                None => DataValue::Float(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Value::String(s) => DataValue::Text(s),
+            Value::Array(items) => DataValue::Array(items.into_iter().map(Into::into).collect()),
+            Value::Object(map) => {
+                DataValue::Object(map.into_iter().map(|(k, v)| (k, v.into())).collect())
+            }
+        }
+    }
+}
+
+/// `sort_cmp`, made strict enough to be an equality: an Integer sorts just before
+/// the Float of the same value, and Floats are equal only bit for bit (so NaN
+/// equals itself and 0.0 doesn't equal -0.0). Across types the rank of
+/// `sort_cmp` applies: Null < Boolean < Integer/Float < Timestamp < Text < Binary.
+/// Encrypted values, Arrays and Objects follow in that order; their comparisons
+/// (bytes, elements, sorted entries) are stable but carry no meaning.
+impl Ord for DataValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        use DataValue::*;
+        match (self, other) {
+            (Array(a), Array(b)) => a.cmp(b),
+            (Object(a), Object(b)) => {
+                let mut a: Vec<_> = a.iter().collect();
+                let mut b: Vec<_> = b.iter().collect();
+                a.sort();
+                b.sort();
+                a.cmp(&b)
+            }
+            _ => self
+                .sort_cmp(other)
+                .then_with(|| matches!(self, Float(_)).cmp(&matches!(other, Float(_)))),
+        }
+    }
+}
+
+impl PartialOrd for DataValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for DataValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for DataValue {}
+
+impl Hash for DataValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.canonical_hash(state)
+    }
+}
+
+/// Human-readable rendering used when printing result sets
+impl fmt::Display for DataValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DataValue::Null => write!(f, "NULL"),
+            DataValue::Boolean(b) => write!(f, "{}", b),
+            DataValue::Integer(i) => write!(f, "{}", i),
+            DataValue::Float(x) => write!(f, "{}", x),
+            DataValue::Text(s) => write!(f, "{}", s),
+            DataValue::Binary(bytes) => {
+                write!(f, "x'")?;
+                for b in bytes {
+                    write!(f, "{:02x}", b)?;
+                }
+                write!(f, "'")
+            }
+            DataValue::Encrypted(bytes) => write!(f, "<encrypted {} bytes>", bytes.len()),
+            DataValue::Array(items) => {
+                let items: Vec<String> = items.iter().map(|v| v.to_string()).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            DataValue::Object(map) => {
+                // Sort keys so the output is stable
+                let mut keys: Vec<&String> = map.keys().collect();
+                keys.sort();
+                let fields: Vec<String> =
+                    keys.iter().map(|k| format!("{}: {}", k, map[*k])).collect();
+                write!(f, "{{{}}}", fields.join(", "))
+            }
+            DataValue::Timestamp(millis) => match DateTime::from_timestamp_millis(*millis) {
+                Some(time) => write!(f, "{}", time.to_rfc3339_opts(SecondsFormat::Millis, true)),
+                None => write!(f, "<timestamp {}>", millis),
+            },
+        }
+    }
+}
+
+impl DataValue {
+    /// Parses a JSON text (e.g. a string literal inserted into a JSON column)
+    pub fn from_json(text: &str) -> Result<DataValue, serde_json::Error> {
+        serde_json::from_str::<serde_json::Value>(text).map(Into::into)
+    }
+
+    /// The JSON form of a value, the inverse of `From<serde_json::Value>` for the
+    /// types JSON has. NaN and the infinities become null; timestamps, binary
+    /// and encrypted values become their display text.
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::Value;
+        match self {
+            DataValue::Null => Value::Null,
+            DataValue::Boolean(b) => Value::Bool(*b),
+            DataValue::Integer(i) => Value::from(*i),
+            DataValue::Float(x) => {
+                serde_json::Number::from_f64(*x).map_or(Value::Null, Value::Number)
+            }
+            DataValue::Text(s) => Value::String(s.clone()),
+            DataValue::Array(items) => Value::Array(items.iter().map(DataValue::to_json).collect()),
+            DataValue::Object(map) => {
+                Value::Object(map.iter().map(|(k, v)| (k.clone(), v.to_json())).collect())
+            }
+            DataValue::Binary(_) | DataValue::Encrypted(_) | DataValue::Timestamp(_) => {
+                Value::String(self.to_string())
+            }
+        }
+    }
+
+    /// Parses an ISO-8601 date or date-time into a Timestamp: "2024-01-15",
+    /// "2024-01-15 10:30:00", "2024-01-15T10:30:00.250Z" or "...+02:00".
+    /// A time without an offset is taken as UTC.
+    pub fn parse_timestamp(text: &str) -> Option<DataValue> {
+        let text = text.trim();
+        let time = if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+            time.with_timezone(&Utc)
+        } else if let Ok(time) = NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f")
+            .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f"))
+        {
+            time.and_utc()
+        } else {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)?
+                .and_utc()
+        };
+        Some(DataValue::Timestamp(time.timestamp_millis()))
+    }
+
+    /// The current time, as a Timestamp
+    pub fn now() -> DataValue {
+        DataValue::Timestamp(Utc::now().timestamp_millis())
+    }
+
+    /// Reads a boolean written as TRUE/FALSE, 1/0 or 'true'/'false' (any case).
+    /// None for anything else, including other numbers and strings.
+    pub fn as_boolean(&self) -> Option<bool> {
+        match self {
+            DataValue::Boolean(b) => Some(*b),
+            DataValue::Integer(1) => Some(true),
+            DataValue::Integer(0) => Some(false),
+            DataValue::Text(s) if s.trim().eq_ignore_ascii_case("true") => Some(true),
+            DataValue::Text(s) if s.trim().eq_ignore_ascii_case("false") => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Total order used for sorting (ORDER BY).
+    /// Different types rank Null < Boolean < Integer/Float < Timestamp < Text < Binary
+    /// < Encrypted < Array < Object.
+    /// Integers and Floats compare numerically with each other.
+    pub fn sort_cmp(&self, other: &DataValue) -> Ordering {
+        use DataValue::*;
+        match (self, other) {
+            (Integer(a), Integer(b)) => a.cmp(b),
+            (Integer(a), Float(b)) => (*a as f64).total_cmp(b),
+            (Float(a), Integer(b)) => a.total_cmp(&(*b as f64)),
+            (Float(a), Float(b)) => a.total_cmp(b),
+            (Boolean(a), Boolean(b)) => a.cmp(b),
+            (Timestamp(a), Timestamp(b)) => a.cmp(b),
+            (Text(a), Text(b)) => a.cmp(b),
+            (Binary(a), Binary(b)) | (Encrypted(a), Encrypted(b)) => a.cmp(b),
+            (Array(a), Array(b)) => a
+                .iter()
+                .zip(b)
+                .map(|(x, y)| x.sort_cmp(y))
+                .find(|o| o.is_ne())
+                .unwrap_or_else(|| a.len().cmp(&b.len())),
+            (Object(a), Object(b)) => {
+                // Compare as sorted (key, value) lists so HashMap order doesn't leak in
+                let mut a: Vec<_> = a.iter().collect();
+                let mut b: Vec<_> = b.iter().collect();
+                a.sort_by(|x, y| x.0.cmp(y.0));
+                b.sort_by(|x, y| x.0.cmp(y.0));
+                a.iter()
+                    .zip(&b)
+                    .map(|((ka, va), (kb, vb))| ka.cmp(kb).then_with(|| va.sort_cmp(vb)))
+                    .find(|o| o.is_ne())
+                    .unwrap_or_else(|| a.len().cmp(&b.len()))
+            }
+            _ => self.type_rank().cmp(&other.type_rank()),
+        }
+    }
+
+    /// Equality for grouping rows (SELECT DISTINCT): the same as `==`, spelled out
+    /// next to `canonical_hash`, which agrees with it.
+    pub fn canonical_eq(&self, other: &DataValue) -> bool {
+        self == other
+    }
+
+    /// Hash consistent with `canonical_eq`
+    pub fn canonical_hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            DataValue::Null => {}
+            DataValue::Boolean(b) => b.hash(state),
+            DataValue::Integer(i) | DataValue::Timestamp(i) => i.hash(state),
+            DataValue::Float(x) => x.to_bits().hash(state),
+            DataValue::Text(s) => s.hash(state),
+            DataValue::Binary(bytes) | DataValue::Encrypted(bytes) => bytes.hash(state),
+            DataValue::Array(items) => {
+                items.len().hash(state);
+                for item in items {
+                    item.canonical_hash(state);
+                }
+            }
+            DataValue::Object(map) => {
+                // Sort keys so HashMap order doesn't leak in
+                let mut keys: Vec<&String> = map.keys().collect();
+                keys.sort();
+                keys.len().hash(state);
+                for key in keys {
+                    key.hash(state);
+                    map[key].canonical_hash(state);
+                }
+            }
+        }
+    }
+
+    fn type_rank(&self) -> u8 {
+        match self {
+            DataValue::Null => 0,
+            DataValue::Boolean(_) => 1,
+            DataValue::Integer(_) | DataValue::Float(_) => 2,
+            DataValue::Timestamp(_) => 3,
+            DataValue::Text(_) => 4,
+            DataValue::Binary(_) => 5,
+            DataValue::Encrypted(_) => 6,
+            DataValue::Array(_) => 7,
+            DataValue::Object(_) => 8,
+        }
+    }
 }
 
 /// Represents a single Row (SQL) or Document (NoSQL).
@@ -45,6 +311,59 @@ impl AuraDocument {
         }
     }
 
+    /// Sets a field, builder-style:
+    /// `AuraDocument::new("u1").with_field("age", DataValue::Integer(30))`
+    pub fn with_field(mut self, key: impl Into<String>, value: DataValue) -> Self {
+        self.data.insert(key.into(), value);
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&DataValue> {
+        self.data.get(key)
+    }
+
+    /// The field's text; None if it is missing or holds anything but Text
+    pub fn get_text(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            DataValue::Text(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn get_int(&self, key: &str) -> Option<i64> {
+        match self.get(key)? {
+            DataValue::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Integers read as floats too, as FLOAT columns accept them
+    pub fn get_float(&self, key: &str) -> Option<f64> {
+        match self.get(key)? {
+            DataValue::Float(x) => Some(*x),
+            DataValue::Integer(i) => Some(*i as f64),
+            _ => None,
+        }
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key)? {
+            DataValue::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// The document as a JSON object: its fields, and its primary key as "id"
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut object: serde_json::Map<String, serde_json::Value> = self
+            .data
+            .iter()
+            .map(|(k, v)| (k.clone(), v.to_json()))
+            .collect();
+        object.insert("id".to_string(), serde_json::Value::String(self.id.clone()));
+        serde_json::Value::Object(object)
+    }
+
     /// Serializes the document to compact binary format (Postcard)
     /// This is what we will encrypt and store on disk.
     pub fn to_bytes(&self) -> Result<Vec<u8>, postcard::Error> {
@@ -155,6 +474,36 @@ mod tests {
         assert_eq!(doc.data.get("active"), Some(&DataValue::Boolean(true)));
     }
 
+    #[test]
+    fn test_typed_accessors() {
+        let doc = AuraDocument::new("user_1")
+            .with_field("name", DataValue::Text("Alice".to_string()))
+            .with_field("age", DataValue::Integer(30))
+            .with_field("score", DataValue::Float(9.5))
+            .with_field("active", DataValue::Boolean(true))
+            .with_field("nick", DataValue::Null);
+
+        assert_eq!(doc.get_text("name"), Some("Alice"));
+        assert_eq!(doc.get_int("age"), Some(30));
+        assert_eq!(doc.get_float("score"), Some(9.5));
+        assert_eq!(doc.get_bool("active"), Some(true));
+        assert_eq!(doc.get("nick"), Some(&DataValue::Null));
+
+        // Missing fields
+        assert_eq!(doc.get("email"), None);
+        assert_eq!(doc.get_text("email"), None);
+        assert_eq!(doc.get_int("email"), None);
+
+        // The wrong variant is None, not a conversion (NULL included)...
+        assert_eq!(doc.get_text("age"), None);
+        assert_eq!(doc.get_int("name"), None);
+        assert_eq!(doc.get_int("score"), None);
+        assert_eq!(doc.get_bool("age"), None);
+        assert_eq!(doc.get_text("nick"), None);
+        // ...except that an Integer widens to a float
+        assert_eq!(doc.get_float("age"), Some(30.0));
+    }
+
     #[test]
     fn test_document_serialization_edge_cases() {
         // Test empty document
@@ -218,4 +567,173 @@ mod tests {
         // Test with empty bytes
         assert!(AuraDocument::from_bytes(&[]).is_err());
     }
+
+    #[test]
+    fn test_canonical_eq_and_hash() {
+        use std::collections::hash_map::DefaultHasher;
+
+        let hash = |value: &DataValue| {
+            let mut hasher = DefaultHasher::new();
+            value.canonical_hash(&mut hasher);
+            hasher.finish()
+        };
+
+        // NaN is equal to itself (Floats compare bit for bit)
+        let nan = DataValue::Float(f64::NAN);
+        assert_eq!(nan, nan.clone());
+        assert!(nan.canonical_eq(&nan.clone()));
+        assert_eq!(hash(&nan), hash(&nan.clone()));
+
+        // Objects compare by content, whatever order their keys were inserted in
+        let mut a = HashMap::new();
+        let mut b = HashMap::new();
+        for i in 0..10 {
+            a.insert(format!("k{}", i), DataValue::Integer(i));
+            b.insert(format!("k{}", 9 - i), DataValue::Integer(9 - i));
+        }
+        let (a, b) = (DataValue::Object(a), DataValue::Object(b));
+        assert!(a.canonical_eq(&b));
+        assert_eq!(hash(&a), hash(&b));
+
+        // Different types never collapse, even when they compare equal for sorting
+        assert!(!DataValue::Integer(1).canonical_eq(&DataValue::Float(1.0)));
+        assert!(!DataValue::Float(0.0).canonical_eq(&DataValue::Float(-0.0)));
+    }
+
+    #[test]
+    fn test_ord_across_types() {
+        use DataValue::*;
+        let mut values = vec![
+            Object(HashMap::new()),
+            Text("b".to_string()),
+            Float(2.0),
+            Array(vec![Integer(1)]),
+            Binary(vec![1]),
+            Null,
+            Timestamp(0),
+            Integer(2),
+            Encrypted(vec![0]),
+            Boolean(true),
+            Float(-1.5),
+            Text("a".to_string()),
+            Integer(10),
+            Boolean(false),
+        ];
+        values.sort();
+        assert_eq!(
+            values,
+            vec![
+                Null,
+                Boolean(false),
+                Boolean(true),
+                Float(-1.5),
+                // Numbers compare by value, and an Integer comes before an equal Float
+                Integer(2),
+                Float(2.0),
+                Integer(10),
+                Timestamp(0),
+                Text("a".to_string()),
+                Text("b".to_string()),
+                Binary(vec![1]),
+                Encrypted(vec![0]),
+                Array(vec![Integer(1)]),
+                Object(HashMap::new()),
+            ]
+        );
+
+        // Ord is consistent with ==, which tells numbers of different types apart
+        assert_ne!(Integer(2), Float(2.0));
+        assert!(Integer(2).sort_cmp(&Float(2.0)).is_eq());
+        assert!(Array(vec![Integer(2)]) < Array(vec![Float(2.0)]));
+        assert!(Float(-0.0) < Float(0.0));
+    }
+
+    #[test]
+    fn test_from_json() {
+        let value = DataValue::from_json(
+            r#"{"city": "NY", "zip": 10001, "score": 4.5, "tags": ["a", null], "vip": true}"#,
+        )
+        .unwrap();
+        let DataValue::Object(map) = value else {
+            panic!("Expected an object");
+        };
+        assert_eq!(map["city"], DataValue::Text("NY".to_string()));
+        assert_eq!(map["zip"], DataValue::Integer(10001));
+        assert_eq!(map["score"], DataValue::Float(4.5));
+        assert_eq!(
+            map["tags"],
+            DataValue::Array(vec![DataValue::Text("a".to_string()), DataValue::Null])
+        );
+        assert_eq!(map["vip"], DataValue::Boolean(true));
+
+        // Too big for an i64
+        assert_eq!(
+            DataValue::from_json("18446744073709551615").unwrap(),
+            DataValue::Float(18446744073709551615.0)
+        );
+        assert!(DataValue::from_json("{\"city\": ").is_err());
+    }
+
+    #[test]
+    fn test_to_json() {
+        let doc = AuraDocument::new("u1")
+            .with_field("age", DataValue::Integer(30))
+            .with_field("score", DataValue::Float(f64::NAN))
+            .with_field("seen", DataValue::Timestamp(0))
+            .with_field(
+                "tags",
+                DataValue::from_json(r#"["a", {"b": 1.5}]"#).unwrap(),
+            );
+        assert_eq!(
+            doc.to_json(),
+            serde_json::json!({
+                "id": "u1",
+                "age": 30,
+                "score": null,
+                "seen": "1970-01-01T00:00:00.000Z",
+                "tags": ["a", {"b": 1.5}],
+            })
+        );
+
+        // Whatever JSON has survives the round trip
+        let value = DataValue::from_json(r#"{"n": -7, "ok": false, "s": "x"}"#).unwrap();
+        assert_eq!(DataValue::from(value.to_json()), value);
+    }
+
+    #[test]
+    fn test_timestamp_parsing_and_rendering() {
+        let day = DataValue::parse_timestamp("2024-01-15").unwrap();
+        assert_eq!(day, DataValue::Timestamp(1_705_276_800_000));
+        assert_eq!(day.to_string(), "2024-01-15T00:00:00.000Z");
+
+        // The same instant, written several ways
+        let expected = DataValue::Timestamp(1_705_314_600_250);
+        for text in [
+            "2024-01-15T10:30:00.250Z",
+            "2024-01-15 10:30:00.250",
+            "2024-01-15T12:30:00.250+02:00",
+        ] {
+            assert_eq!(
+                DataValue::parse_timestamp(text),
+                Some(expected.clone()),
+                "{text}"
+            );
+        }
+        assert_eq!(expected.to_string(), "2024-01-15T10:30:00.250Z");
+        assert_eq!(DataValue::parse_timestamp("15/01/2024"), None);
+        assert_eq!(DataValue::parse_timestamp("2024-02-30"), None);
+
+        // Ordered in time, and after every number
+        assert!(day.sort_cmp(&expected).is_lt());
+        assert!(DataValue::Integer(i64::MAX).sort_cmp(&day).is_lt());
+
+        // Postcard: the new variant went on the end, so older encodings are unchanged
+        assert_eq!(
+            postcard::to_allocvec(&DataValue::Integer(1)).unwrap(),
+            vec![2, 2]
+        );
+        let bytes = postcard::to_allocvec(&expected).unwrap();
+        assert_eq!(bytes[0], 9);
+        assert_eq!(postcard::from_bytes::<DataValue>(&bytes).unwrap(), expected);
+    }
 }
@@ -1,6 +1,10 @@
 pub mod document;
 pub mod error;
+pub mod query;
+pub mod schema;
 
 // Re-export commonly used types
 pub use document::{AuraDocument, DataValue};
 pub use error::AuraError;
+pub use query::{AuthRequest, QueryRequest, QueryResponse, QueryResult, QueryStats};
+pub use schema::{ColumnDef, ColumnType, IndexDef, Privilege, TableSchema, UserDef};
@@ -0,0 +1,328 @@
+use crate::document::DataValue;
+use crate::schema::ColumnType;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A parameterized statement as sent over the wire.
+/// `params[0]` binds to `$1` (or the first `?`), and so on.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QueryRequest {
+    pub sql: String,
+    pub params: Vec<DataValue>,
+}
+
+impl QueryRequest {
+    pub fn new(sql: impl Into<String>, params: Vec<DataValue>) -> Self {
+        Self {
+            sql: sql.into(),
+            params,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, postcard::Error> {
+        postcard::to_allocvec(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, postcard::Error> {
+        postcard::from_bytes(bytes)
+    }
+}
+
+/// The credentials an AUTH frame carries (inside the encrypted session)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuthRequest {
+    pub username: String,
+    pub password: String,
+}
+
+impl AuthRequest {
+    pub fn new(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Self {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, postcard::Error> {
+        postcard::to_allocvec(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, postcard::Error> {
+        postcard::from_bytes(bytes)
+    }
+}
+
+/// The typed outcome of executing one statement.
+/// This is what travels back to the client (postcard-encoded).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum QueryResult {
+    /// A result set: `rows[i][j]` is the value of `columns[j]` in row i
+    Rows {
+        columns: Vec<String>,
+        /// `types[j]` is the type of `columns[j]`: declared in the catalog, or
+        /// inferred from the values when the table is schemaless
+        types: Vec<ColumnType>,
+        rows: Vec<Vec<DataValue>>,
+    },
+    /// A document was written under this primary key
+    Inserted { id: String },
+    /// Number of documents changed by the statement
+    Affected(u64),
+    /// Anything else worth telling the user (e.g. "LISTEN orders")
+    Message(String),
+    /// Several results for one request: one per statement when several were sent
+    /// at once, or the parts of a statement that doesn't fit one table (DESCRIBE)
+    Batch(Vec<QueryResult>),
+}
+
+impl QueryResult {
+    /// A result set typed by its values: each column takes the type of its first
+    /// non-NULL value (JSON when there is none)
+    pub fn rows(columns: Vec<String>, rows: Vec<Vec<DataValue>>) -> Self {
+        let types = (0..columns.len())
+            .map(|j| {
+                rows.iter()
+                    .filter_map(|row| row.get(j))
+                    .find(|value| **value != DataValue::Null)
+                    .map_or(ColumnType::Any, ColumnType::of)
+            })
+            .collect();
+        QueryResult::Rows {
+            columns,
+            types,
+            rows,
+        }
+    }
+
+    /// Each column's name and type, for clients that deserialize rows by type
+    /// (None when the result isn't a result set)
+    pub fn header(&self) -> Option<Vec<(String, ColumnType)>> {
+        match self {
+            QueryResult::Rows { columns, types, .. } => {
+                Some(columns.iter().cloned().zip(types.iter().copied()).collect())
+            }
+            _ => None,
+        }
+    }
+
+    /// The JSON form for scripts: a result set is {"columns": [...], "rows": [[...], ...]},
+    /// each row an array in column order (so two columns with the same name, as
+    /// in `SELECT a.id, b.id`, both survive), anything else an object naming
+    /// what it is, e.g. {"affected": 3}
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::{json, Value};
+        match self {
+            QueryResult::Rows { columns, rows, .. } => json!({
+                "columns": columns,
+                "rows": rows
+                    .iter()
+                    .map(|row| Value::Array(row.iter().map(DataValue::to_json).collect()))
+                    .collect::<Vec<_>>(),
+            }),
+            QueryResult::Inserted { id } => json!({ "inserted": id }),
+            QueryResult::Affected(n) => json!({ "affected": n }),
+            QueryResult::Message(msg) => json!({ "message": msg }),
+            QueryResult::Batch(results) => {
+                Value::Array(results.iter().map(QueryResult::to_json).collect())
+            }
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, postcard::Error> {
+        postcard::to_allocvec(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, postcard::Error> {
+        postcard::from_bytes(bytes)
+    }
+}
+
+/// What executing a request cost, so accidental full scans show up
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryStats {
+    /// Documents read to find the result (a scan reads all of them)
+    pub rows_examined: u64,
+    /// Rows in the result set (0 for results that aren't one)
+    pub rows_returned: u64,
+    pub pages_read: u64,
+    pub pages_written: u64,
+    pub elapsed_micros: u64,
+}
+
+impl fmt::Display for QueryStats {
+    /// One line for the CLI, e.g. "3 rows (12 pages read, 1.2ms)"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} row{} ({} page{} read, {:.1}ms)",
+            self.rows_returned,
+            if self.rows_returned == 1 { "" } else { "s" },
+            self.pages_read,
+            if self.pages_read == 1 { "" } else { "s" },
+            self.elapsed_micros as f64 / 1000.0
+        )
+    }
+}
+
+/// The payload of a Response frame: a result, and the statistics of the
+/// statements behind it (None for replies that ran no SQL, e.g. AUTH or LISTEN)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QueryResponse {
+    pub result: QueryResult,
+    pub stats: Option<QueryStats>,
+}
+
+impl QueryResponse {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, postcard::Error> {
+        postcard::to_allocvec(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, postcard::Error> {
+        postcard::from_bytes(bytes)
+    }
+}
+
+impl fmt::Display for QueryResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryResult::Rows { columns, rows, .. } => {
+                writeln!(f, "{}", columns.join(" | "))?;
+                for row in rows {
+                    let cells: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+                    writeln!(f, "{}", cells.join(" | "))?;
+                }
+                write!(
+                    f,
+                    "({} row{})",
+                    rows.len(),
+                    if rows.len() == 1 { "" } else { "s" }
+                )
+            }
+            QueryResult::Inserted { id } => write!(f, "Inserted Document ID: {}", id),
+            QueryResult::Affected(n) => write!(f, "{} document(s) affected", n),
+            QueryResult::Message(msg) => write!(f, "{}", msg),
+            QueryResult::Batch(results) => {
+                for (i, result) in results.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", result)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_request_round_trip() {
+        let request = QueryRequest::new(
+            "INSERT INTO users (id, age, active, nick) VALUES (?, ?, ?, ?)",
+            vec![
+                DataValue::Text("user_1".to_string()),
+                DataValue::Integer(42),
+                DataValue::Boolean(true),
+                DataValue::Null,
+            ],
+        );
+
+        let bytes = request.to_bytes().unwrap();
+        assert_eq!(QueryRequest::from_bytes(&bytes).unwrap(), request);
+    }
+
+    #[test]
+    fn test_query_result_round_trip() {
+        let results = vec![
+            QueryResult::Rows {
+                columns: vec!["id".to_string(), "age".to_string()],
+                types: vec![ColumnType::Text, ColumnType::Integer],
+                rows: vec![
+                    vec![
+                        DataValue::Text("user_1".to_string()),
+                        DataValue::Integer(30),
+                    ],
+                    vec![DataValue::Text("user_2".to_string()), DataValue::Null],
+                ],
+            },
+            QueryResult::Inserted {
+                id: "user_1".to_string(),
+            },
+            QueryResult::Affected(3),
+            QueryResult::Message("LISTEN orders".to_string()),
+            QueryResult::Batch(vec![
+                QueryResult::Affected(1),
+                QueryResult::Message("CREATE INDEX idx_age".to_string()),
+            ]),
+        ];
+
+        for result in results {
+            let bytes = result.to_bytes().unwrap();
+            assert_eq!(QueryResult::from_bytes(&bytes).unwrap(), result);
+        }
+    }
+
+    #[test]
+    fn test_query_result_header() {
+        let result = QueryResult::rows(
+            vec!["id".to_string(), "age".to_string(), "nick".to_string()],
+            vec![
+                vec![
+                    DataValue::Text("user_1".to_string()),
+                    DataValue::Null,
+                    DataValue::Null,
+                ],
+                vec![
+                    DataValue::Text("user_2".to_string()),
+                    DataValue::Integer(30),
+                    DataValue::Null,
+                ],
+            ],
+        );
+        // A leading NULL doesn't decide the type; an all-NULL column is JSON
+        assert_eq!(
+            result.header().unwrap(),
+            vec![
+                ("id".to_string(), ColumnType::Text),
+                ("age".to_string(), ColumnType::Integer),
+                ("nick".to_string(), ColumnType::Any),
+            ]
+        );
+        assert_eq!(QueryResult::Affected(1).header(), None);
+    }
+
+    #[test]
+    fn test_query_stats_display() {
+        let stats = QueryStats {
+            rows_examined: 40,
+            rows_returned: 3,
+            pages_read: 12,
+            pages_written: 0,
+            elapsed_micros: 1234,
+        };
+        assert_eq!(stats.to_string(), "3 rows (12 pages read, 1.2ms)");
+
+        let response = QueryResponse {
+            result: QueryResult::Affected(1),
+            stats: Some(stats),
+        };
+        let bytes = response.to_bytes().unwrap();
+        assert_eq!(QueryResponse::from_bytes(&bytes).unwrap(), response);
+    }
+
+    #[test]
+    fn test_query_result_display() {
+        let result = QueryResult::rows(
+            vec!["id".to_string(), "name".to_string()],
+            vec![vec![
+                DataValue::Text("user_1".to_string()),
+                DataValue::Text("Alice".to_string()),
+            ]],
+        );
+        assert_eq!(result.to_string(), "id | name\nuser_1 | Alice\n(1 row)");
+    }
+}
@@ -0,0 +1,306 @@
+use crate::document::DataValue;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The declared type of a column (from CREATE TABLE).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Boolean,
+    Integer,
+    Float,
+    Text,
+    Binary,
+    Encrypted,
+    /// JSON-style columns: arrays, objects, or any scalar
+    Any,
+    /// Dates and times (stored as epoch milliseconds, UTC)
+    Timestamp,
+}
+
+impl ColumnType {
+    /// The type a value suggests when no column declares one. NULL, arrays and
+    /// objects only fit a JSON column.
+    pub fn of(value: &DataValue) -> ColumnType {
+        match value {
+            DataValue::Boolean(_) => ColumnType::Boolean,
+            DataValue::Integer(_) => ColumnType::Integer,
+            DataValue::Float(_) => ColumnType::Float,
+            DataValue::Text(_) => ColumnType::Text,
+            DataValue::Binary(_) => ColumnType::Binary,
+            DataValue::Encrypted(_) => ColumnType::Encrypted,
+            DataValue::Timestamp(_) => ColumnType::Timestamp,
+            DataValue::Null | DataValue::Array(_) | DataValue::Object(_) => ColumnType::Any,
+        }
+    }
+
+    /// Can `value` be stored in a column of this type? NULL fits every column.
+    pub fn accepts(&self, value: &DataValue) -> bool {
+        matches!(
+            (self, value),
+            (_, DataValue::Null)
+                | (ColumnType::Any, _)
+                | (ColumnType::Boolean, DataValue::Boolean(_))
+                | (ColumnType::Integer, DataValue::Integer(_))
+                | (
+                    ColumnType::Float,
+                    DataValue::Float(_) | DataValue::Integer(_)
+                )
+                | (ColumnType::Text, DataValue::Text(_))
+                | (ColumnType::Binary, DataValue::Binary(_))
+                | (ColumnType::Encrypted, DataValue::Encrypted(_))
+                | (ColumnType::Timestamp, DataValue::Timestamp(_))
+        )
+    }
+
+    /// `CAST(value AS <this type>)`. None when the value has no such reading
+    /// ('abc' as INTEGER, a Float too large for an Integer). NULL casts to NULL,
+    /// Floats truncate towards zero as Integers, and numbers are true when non-zero.
+    pub fn cast(&self, value: &DataValue) -> Option<DataValue> {
+        use DataValue::*;
+        Some(match (self, value) {
+            (_, Null) | (ColumnType::Any, _) => value.clone(),
+            (ColumnType::Integer, Integer(i)) => Integer(*i),
+            (ColumnType::Integer, Float(x)) => {
+                let x = x.trunc();
+                // `as` would saturate, which hides the overflow
+                if !(i64::MIN as f64..i64::MAX as f64).contains(&x) {
+                    return None;
+                }
+                Integer(x as i64)
+            }
+            (ColumnType::Integer, Boolean(b)) => Integer(*b as i64),
+            (ColumnType::Integer, Text(s)) => Integer(s.trim().parse().ok()?),
+            (ColumnType::Float, Integer(i)) => Float(*i as f64),
+            (ColumnType::Float, Float(x)) => Float(*x),
+            (ColumnType::Float, Boolean(b)) => Float(*b as i64 as f64),
+            (ColumnType::Float, Text(s)) => Float(s.trim().parse().ok()?),
+            (ColumnType::Boolean, Integer(i)) => Boolean(*i != 0),
+            (ColumnType::Boolean, Float(x)) => Boolean(*x != 0.0),
+            (ColumnType::Boolean, Boolean(_) | Text(_)) => Boolean(value.as_boolean()?),
+            (ColumnType::Text, Boolean(_) | Integer(_) | Float(_) | Text(_) | Timestamp(_)) => {
+                Text(value.to_string())
+            }
+            (ColumnType::Timestamp, Timestamp(t)) => Timestamp(*t),
+            (ColumnType::Timestamp, Text(s)) => DataValue::parse_timestamp(s)?,
+            (ColumnType::Binary, Binary(bytes)) => Binary(bytes.clone()),
+            (ColumnType::Binary, Text(s)) => Binary(s.as_bytes().to_vec()),
+            (ColumnType::Encrypted, Encrypted(bytes)) => Encrypted(bytes.clone()),
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ColumnType::Boolean => "BOOLEAN",
+            ColumnType::Integer => "INTEGER",
+            ColumnType::Float => "FLOAT",
+            ColumnType::Text => "TEXT",
+            ColumnType::Binary => "BINARY",
+            ColumnType::Encrypted => "ENCRYPTED",
+            ColumnType::Any => "JSON",
+            ColumnType::Timestamp => "TIMESTAMP",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ColumnDef {
+    pub name: String,
+    pub data_type: ColumnType,
+    /// NOT NULL: every document has a non-NULL value here
+    pub not_null: bool,
+    /// UNIQUE: no two documents share a (non-NULL) value here
+    pub unique: bool,
+}
+
+/// A table's entry in the catalog.
+/// Tables without one are schemaless: any document can be inserted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TableSchema {
+    pub name: String,
+    pub columns: Vec<ColumnDef>,
+    /// `id INTEGER ... AUTOINCREMENT`: the highest id so far, generated or inserted
+    /// (None when ids are not generated this way)
+    pub auto_increment: Option<i64>,
+}
+
+impl TableSchema {
+    pub fn column(&self, name: &str) -> Option<&ColumnDef> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, postcard::Error> {
+        postcard::to_allocvec(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, postcard::Error> {
+        postcard::from_bytes(bytes)
+    }
+}
+
+/// A secondary index (CREATE INDEX): a B-tree from a column's value to data pages
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexDef {
+    pub name: String,
+    pub table: String,
+    pub column: String,
+    /// Page id of the B-tree root (moves when the root splits)
+    pub root_page: u32,
+    /// CREATE UNIQUE INDEX (or a UNIQUE column): no two documents share a
+    /// non-NULL value of the column
+    pub unique: bool,
+}
+
+impl IndexDef {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, postcard::Error> {
+        postcard::to_allocvec(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, postcard::Error> {
+        postcard::from_bytes(bytes)
+    }
+}
+
+/// What a GRANT lets a user do to a table
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Privilege {
+    Select,
+    Insert,
+    /// Overwrite existing documents (INSERT ... ON CONFLICT DO UPDATE)
+    Update,
+    Truncate,
+}
+
+impl Privilege {
+    /// What `GRANT ALL` grants
+    pub const ALL: [Privilege; 4] = [
+        Privilege::Select,
+        Privilege::Insert,
+        Privilege::Update,
+        Privilege::Truncate,
+    ];
+}
+
+impl fmt::Display for Privilege {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Privilege::Select => "SELECT",
+            Privilege::Insert => "INSERT",
+            Privilege::Update => "UPDATE",
+            Privilege::Truncate => "TRUNCATE",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A login allowed past the AUTH step: the password is kept only as an Argon2id hash
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserDef {
+    pub name: String,
+    /// PHC string from `aura_security::password::hash`
+    pub password_hash: String,
+    /// (table, privilege) pairs granted with GRANT, sorted
+    pub grants: Vec<(String, Privilege)>,
+}
+
+impl UserDef {
+    pub fn can(&self, privilege: Privilege, table: &str) -> bool {
+        self.grants
+            .binary_search_by(|(t, p)| (t.as_str(), *p).cmp(&(table, privilege)))
+            .is_ok()
+    }
+
+    /// Adds a grant (a no-op if the user already has it)
+    pub fn grant(&mut self, privilege: Privilege, table: &str) {
+        if let Err(i) = self
+            .grants
+            .binary_search_by(|(t, p)| (t.as_str(), *p).cmp(&(table, privilege)))
+        {
+            self.grants.insert(i, (table.to_string(), privilege));
+        }
+    }
+
+    pub fn revoke(&mut self, privilege: Privilege, table: &str) {
+        self.grants
+            .retain(|(t, p)| !(t == table && *p == privilege));
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, postcard::Error> {
+        postcard::to_allocvec(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, postcard::Error> {
+        postcard::from_bytes(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_type_accepts() {
+        assert!(ColumnType::Integer.accepts(&DataValue::Integer(1)));
+        assert!(ColumnType::Integer.accepts(&DataValue::Null));
+        assert!(!ColumnType::Integer.accepts(&DataValue::Text("1".to_string())));
+
+        // Integers widen into Float columns, not the other way round
+        assert!(ColumnType::Float.accepts(&DataValue::Integer(1)));
+        assert!(!ColumnType::Integer.accepts(&DataValue::Float(1.5)));
+
+        assert!(ColumnType::Any.accepts(&DataValue::Array(vec![])));
+    }
+
+    #[test]
+    fn test_table_schema_round_trip() {
+        let schema = TableSchema {
+            name: "users".to_string(),
+            columns: vec![
+                ColumnDef {
+                    name: "id".to_string(),
+                    data_type: ColumnType::Text,
+                    not_null: true,
+                    unique: true,
+                },
+                ColumnDef {
+                    name: "age".to_string(),
+                    data_type: ColumnType::Integer,
+                    not_null: false,
+                    unique: false,
+                },
+            ],
+            auto_increment: Some(41),
+        };
+
+        let bytes = schema.to_bytes().unwrap();
+        let loaded = TableSchema::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded, schema);
+        assert_eq!(loaded.column("age").unwrap().data_type, ColumnType::Integer);
+        assert!(loaded.column("email").is_none());
+        assert_eq!(loaded.auto_increment, Some(41));
+    }
+
+    #[test]
+    fn test_user_grants() {
+        let mut user = UserDef {
+            name: "alice".to_string(),
+            password_hash: String::new(),
+            grants: Vec::new(),
+        };
+        user.grant(Privilege::Select, "users");
+        user.grant(Privilege::Insert, "logs");
+        user.grant(Privilege::Select, "users");
+        assert_eq!(user.grants.len(), 2);
+
+        assert!(user.can(Privilege::Select, "users"));
+        assert!(!user.can(Privilege::Insert, "users"));
+        assert!(!user.can(Privilege::Select, "logs"));
+
+        user.revoke(Privilege::Select, "users");
+        assert!(!user.can(Privilege::Select, "users"));
+        assert!(user.can(Privilege::Insert, "logs"));
+    }
+}
@@ -5,13 +5,76 @@ use std::time::Duration;
 use rand::Rng;
 use tracing::{info, warn};
 
-/// The Heartbeat interval (Leader pings followers every 150ms)
-/// const HEARTBEAT_INTERVAL: u64 = 150; 
+// The Heartbeat interval (Leader pings followers every 150ms)
+// const HEARTBEAT_INTERVAL: u64 = 150;
 
 /// Min/Max Election Timeout (Randomized 300ms - 600ms)
 const ELECTION_TIMEOUT_MIN: u64 = 300;
 const ELECTION_TIMEOUT_MAX: u64 = 600;
 
+/// Under latency, an election timeout lasts at least this many round trips...
+const RTT_TIMEOUT_FLOOR: u32 = 4;
+/// ...and timeouts are spread over at least this many, so the first candidate's
+/// RequestVote usually lands before anyone else times out (no split vote)
+const RTT_TIMEOUT_SPREAD: u32 = 10;
+
+/// The window election timeouts are drawn from. It starts at
+/// ELECTION_TIMEOUT_MIN..ELECTION_TIMEOUT_MAX and widens once the observed RPC
+/// round-trip time makes that too tight; it never shrinks below it.
+#[derive(Debug, Clone)]
+pub struct ElectionTimeout {
+    min: Duration,
+    max: Duration,
+    /// Smoothed round-trip time (an EWMA, as TCP keeps), once one was observed
+    srtt: Option<Duration>,
+}
+
+impl Default for ElectionTimeout {
+    fn default() -> Self {
+        Self::new(
+            Duration::from_millis(ELECTION_TIMEOUT_MIN),
+            Duration::from_millis(ELECTION_TIMEOUT_MAX),
+        )
+    }
+}
+
+impl ElectionTimeout {
+    /// Panics unless `min < max`: `sample` draws from the half-open `min..max`
+    pub fn new(min: Duration, max: Duration) -> Self {
+        assert!(
+            min < max,
+            "election timeout window is empty: min {:?} must be below max {:?}",
+            min,
+            max
+        );
+        Self { min, max, srtt: None }
+    }
+
+    /// Folds in the round-trip time of one RPC (weight 1/8, like TCP's SRTT)
+    pub fn observe_rtt(&mut self, rtt: Duration) {
+        self.srtt = Some(match self.srtt {
+            None => rtt,
+            Some(srtt) => (srtt * 7 + rtt) / 8,
+        });
+    }
+
+    /// Current (min, max) of the window
+    pub fn window(&self) -> (Duration, Duration) {
+        let Some(rtt) = self.srtt else {
+            return (self.min, self.max);
+        };
+        let min = self.min.max(rtt * RTT_TIMEOUT_FLOOR);
+        let spread = (self.max - self.min).max(rtt * RTT_TIMEOUT_SPREAD);
+        (min, min + spread)
+    }
+
+    /// A random timeout from the window
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Duration {
+        let (min, max) = self.window();
+        rng.gen_range(min..max)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Role {
     Follower,
@@ -28,6 +91,7 @@ pub struct RaftNode {
     // Timer state
     last_heartbeat: std::time::Instant,
     election_timeout: std::time::Duration,
+    timeout_window: ElectionTimeout,
 }
 
 impl RaftNode {
@@ -38,10 +102,17 @@ impl RaftNode {
             voted_for: None,
             role: Role::Follower, // Everyone starts as a Follower
             last_heartbeat: std::time::Instant::now(),
-            election_timeout: Self::random_timeout(),
+            election_timeout: ElectionTimeout::default().sample(&mut rand::thread_rng()),
+            timeout_window: ElectionTimeout::default(),
         }
     }
 
+    /// Records how long an RPC to a peer took to answer, so election timeouts
+    /// widen with the network's latency (see `ElectionTimeout`)
+    pub fn record_rtt(&mut self, rtt: Duration) {
+        self.timeout_window.observe_rtt(rtt);
+    }
+
     /// The Main Loop Tick: Checks if we need to start an election
     pub fn tick(&mut self) {
         if self.role == Role::Leader {
@@ -63,7 +134,7 @@ impl RaftNode {
         self.current_term += 1;          // Increment Term
         self.voted_for = Some(self.id);  // Vote for self
         self.last_heartbeat = std::time::Instant::now(); // Reset timer
-        self.election_timeout = Self::random_timeout();  // Pick new random timeout
+        self.election_timeout = self.random_timeout();   // Pick new random timeout
 
         // TODO: Send RequestVote RPC to all other peers
         self.request_votes();
@@ -77,13 +148,11 @@ impl RaftNode {
     /// Reset the timer (Called when we get a valid heartbeat from Leader)
     pub fn reset_election_timer(&mut self) {
         self.last_heartbeat = std::time::Instant::now();
-        self.election_timeout = Self::random_timeout();
+        self.election_timeout = self.random_timeout();
     }
 
-    fn random_timeout() -> Duration {
-        let mut rng = rand::thread_rng();
-        let millis = rng.gen_range(ELECTION_TIMEOUT_MIN..ELECTION_TIMEOUT_MAX);
-        Duration::from_millis(millis)
+    fn random_timeout(&self) -> Duration {
+        self.timeout_window.sample(&mut rand::thread_rng())
     }
 }
 
@@ -114,4 +183,74 @@ mod tests {
         
         println!("✅ Node 1 successfully started election due to timeout.");
     }
+
+    /// Elections among `nodes` followers whose peers answer after `rtt`: all
+    /// time out at once (the leader died), and the first candidate wins unless
+    /// another times out before its RequestVote arrives (half a round trip),
+    /// which splits the vote and starts a new round. Returns the failed rounds.
+    fn failed_election_rounds(timeout: &ElectionTimeout, rtt: Duration, nodes: usize) -> usize {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut failed = 0;
+        for _ in 0..200 {
+            loop {
+                let mut timeouts: Vec<Duration> =
+                    (0..nodes).map(|_| timeout.sample(&mut rng)).collect();
+                timeouts.sort();
+                if timeouts[1] - timeouts[0] > rtt / 2 {
+                    break;
+                }
+                failed += 1;
+            }
+        }
+        failed
+    }
+
+    #[test]
+    fn test_adaptive_election_timeout() {
+        let rtt = Duration::from_millis(250);
+        let fixed = ElectionTimeout::default();
+        let mut adaptive = ElectionTimeout::default();
+        for _ in 0..20 {
+            adaptive.observe_rtt(rtt);
+        }
+
+        // The window widens with latency but never drops below the configured one
+        let (min, max) = adaptive.window();
+        assert_eq!(min, rtt * RTT_TIMEOUT_FLOOR);
+        assert_eq!(max - min, rtt * RTT_TIMEOUT_SPREAD);
+        let mut fast = ElectionTimeout::default();
+        fast.observe_rtt(Duration::from_millis(1));
+        assert_eq!(fast.window(), fixed.window());
+
+        let fixed_failures = failed_election_rounds(&fixed, rtt, 5);
+        let adaptive_failures = failed_election_rounds(&adaptive, rtt, 5);
+        assert!(
+            adaptive_failures * 4 < fixed_failures,
+            "adaptive: {} failed rounds, fixed: {}",
+            adaptive_failures,
+            fixed_failures
+        );
+
+        // A node's timer draws from its adapted window
+        let mut node = RaftNode::new(1);
+        node.record_rtt(rtt);
+        node.reset_election_timer();
+        assert!(node.election_timeout >= rtt * RTT_TIMEOUT_FLOOR);
+    }
+
+    #[test]
+    #[should_panic(expected = "election timeout window is empty")]
+    fn test_election_timeout_rejects_an_empty_window() {
+        let timeout = Duration::from_millis(300);
+        ElectionTimeout::new(timeout, timeout);
+    }
+
+    #[test]
+    #[should_panic(expected = "election timeout window is empty")]
+    fn test_election_timeout_rejects_an_inverted_window() {
+        ElectionTimeout::new(Duration::from_millis(300), Duration::from_millis(150));
+    }
 }
@@ -0,0 +1,234 @@
+// The schema catalog: one TableSchema per CREATE TABLE, one IndexDef per CREATE INDEX,
+// one UserDef (with its grants) per login.
+// Entries live in the primary index under the "$catalog/", "$index/" and "$user/" namespaces,
+// pointing at a page that holds the postcard-encoded entry. Because they go through
+// the same index as documents, an entry becomes visible exactly when the index is synced.
+// A statement's catalog and index pages share one WAL batch, so a crash leaves either
+// the old catalog or the new one, never an index pointing at a half-written entry.
+// ("$" cannot start an unquoted identifier, so no user table can collide with them.)
+use crate::QueryError;
+use aura_common::{ColumnType, IndexDef, TableSchema, UserDef};
+use aura_store::page::{Page, DATA_SIZE};
+use aura_store::pager::Pager;
+use aura_store::StoreError;
+use sqlparser::ast::DataType;
+
+pub const CATALOG_NAMESPACE: &str = "$catalog";
+pub const INDEX_NAMESPACE: &str = "$index";
+pub const USER_NAMESPACE: &str = "$user";
+
+/// The superuser: needs no grants, and is the only login that can change schemas
+/// or GRANT and REVOKE
+pub const ADMIN_USER: &str = "admin";
+
+/// Page type of catalog pages (1 = Data, 2 = Index)
+pub const CATALOG_PAGE_TYPE: u8 = 3;
+
+fn catalog_key(table: &str) -> String {
+    format!("{}/{}", CATALOG_NAMESPACE, table)
+}
+
+fn index_def_key(name: &str) -> String {
+    format!("{}/{}", INDEX_NAMESPACE, name)
+}
+
+fn user_key(name: &str) -> String {
+    format!("{}/{}", USER_NAMESPACE, name)
+}
+
+/// Returns the declared schema of `table`, or None if it is schemaless.
+pub fn load_schema(pager: &mut Pager, table: &str) -> Result<Option<TableSchema>, QueryError> {
+    read_entry(
+        pager,
+        &catalog_key(table),
+        "table schema",
+        TableSchema::from_bytes,
+    )
+}
+
+/// Writes the schema and registers it in the index.
+/// The caller decides when to sync the index.
+pub fn store_schema(pager: &mut Pager, schema: &TableSchema) -> Result<(), QueryError> {
+    let bytes = schema
+        .to_bytes()
+        .map_err(|e| QueryError::Serialization(e.to_string()))?;
+    write_entry(pager, catalog_key(&schema.name), &bytes)
+}
+
+/// Names of every table with a catalog entry (i.e. declared with CREATE TABLE), sorted
+pub fn list_tables(pager: &Pager) -> Vec<String> {
+    let prefix = catalog_key("");
+    pager
+        .index
+        .map
+        .range(prefix.clone()..)
+        .take_while(|(key, _)| key.starts_with(&prefix))
+        .map(|(key, _)| key[prefix.len()..].to_string())
+        .collect()
+}
+
+/// Removes the table's catalog entry and frees its page (the caller syncs the index)
+pub fn drop_schema(pager: &mut Pager, table: &str) -> Result<(), QueryError> {
+    remove_entry(pager, &catalog_key(table))
+}
+
+pub fn load_index(pager: &mut Pager, name: &str) -> Result<Option<IndexDef>, QueryError> {
+    read_entry(
+        pager,
+        &index_def_key(name),
+        "index definition",
+        IndexDef::from_bytes,
+    )
+}
+
+/// Every secondary index defined on `table`
+pub fn table_indexes(pager: &mut Pager, table: &str) -> Result<Vec<IndexDef>, QueryError> {
+    let prefix = index_def_key("");
+    let names: Vec<String> = pager
+        .index
+        .map
+        .range(prefix.clone()..)
+        .take_while(|(key, _)| key.starts_with(&prefix))
+        .map(|(key, _)| key[prefix.len()..].to_string())
+        .collect();
+
+    let mut indexes = Vec::new();
+    for name in names {
+        if let Some(def) = load_index(pager, &name)? {
+            if def.table == table {
+                indexes.push(def);
+            }
+        }
+    }
+    Ok(indexes)
+}
+
+/// Creates or updates (e.g. after a root split) an index definition
+pub fn store_index(pager: &mut Pager, def: &IndexDef) -> Result<(), QueryError> {
+    let bytes = def
+        .to_bytes()
+        .map_err(|e| QueryError::Serialization(e.to_string()))?;
+    write_entry(pager, index_def_key(&def.name), &bytes)
+}
+
+/// Removes an index definition and frees its page. The B-tree itself is the caller's.
+pub fn drop_index(pager: &mut Pager, name: &str) -> Result<(), QueryError> {
+    remove_entry(pager, &index_def_key(name))
+}
+
+pub fn load_user(pager: &mut Pager, name: &str) -> Result<Option<UserDef>, QueryError> {
+    read_entry(pager, &user_key(name), "login", UserDef::from_bytes)
+}
+
+/// Creates or replaces a login (the caller syncs the index)
+pub fn store_user(pager: &mut Pager, user: &UserDef) -> Result<(), QueryError> {
+    let bytes = user
+        .to_bytes()
+        .map_err(|e| QueryError::Serialization(e.to_string()))?;
+    write_entry(pager, user_key(&user.name), &bytes)
+}
+
+/// Reads and decodes an entry; a page that doesn't decode as `what` is `Malformed`
+fn read_entry<T, E: std::fmt::Display>(
+    pager: &mut Pager,
+    key: &str,
+    what: &str,
+    decode: impl FnOnce(&[u8]) -> Result<T, E>,
+) -> Result<Option<T>, QueryError> {
+    let Some(page_id) = pager.index.get(key) else {
+        return Ok(None);
+    };
+
+    let page = pager.read_page(page_id)?;
+    let reason = if page.page_type != CATALOG_PAGE_TYPE {
+        format!("not a catalog page (type {})", page.page_type)
+    } else {
+        match decode(&page.data[..page.used_space as usize]) {
+            Ok(entry) => return Ok(Some(entry)),
+            Err(e) => format!("not a valid {} ({})", what, e),
+        }
+    };
+    Err(StoreError::Malformed { page_id, reason }.into())
+}
+
+/// Overwrites the entry's page in place if it exists, otherwise allocates one
+fn write_entry(pager: &mut Pager, key: String, bytes: &[u8]) -> Result<(), QueryError> {
+    if bytes.len() > DATA_SIZE {
+        return Err(QueryError::Serialization(format!(
+            "Catalog entry '{}' too large for a single page",
+            key
+        )));
+    }
+
+    let page_id = match pager.index.get(&key) {
+        Some(page_id) => page_id,
+        None => pager.allocate_page(),
+    };
+    let mut page = Page::new(page_id);
+    page.page_type = CATALOG_PAGE_TYPE;
+    page.data[..bytes.len()].copy_from_slice(bytes);
+    page.used_space = bytes.len() as u16;
+    pager.write_page(&page)?;
+
+    pager.index.insert(key, page_id);
+    Ok(())
+}
+
+fn remove_entry(pager: &mut Pager, key: &str) -> Result<(), QueryError> {
+    if let Some(page_id) = pager.index.remove(key) {
+        pager.free_page(page_id)?;
+    }
+    Ok(())
+}
+
+/// Maps a SQL column type onto the DataValue it will hold
+pub fn column_type(data_type: &DataType) -> Result<ColumnType, QueryError> {
+    Ok(match data_type {
+        DataType::Bool | DataType::Boolean => ColumnType::Boolean,
+        DataType::TinyInt(_)
+        | DataType::SmallInt(_)
+        | DataType::Int2(_)
+        | DataType::MediumInt(_)
+        | DataType::Int(_)
+        | DataType::Int4(_)
+        | DataType::Int8(_)
+        | DataType::Int64
+        | DataType::Integer(_)
+        | DataType::BigInt(_) => ColumnType::Integer,
+        DataType::Float(_)
+        | DataType::Float4
+        | DataType::Float8
+        | DataType::Float64
+        | DataType::Real
+        | DataType::Double
+        | DataType::DoublePrecision
+        | DataType::Decimal(_)
+        | DataType::Numeric(_)
+        | DataType::Dec(_) => ColumnType::Float,
+        DataType::Text
+        | DataType::String(_)
+        | DataType::Char(_)
+        | DataType::Character(_)
+        | DataType::Varchar(_)
+        | DataType::CharVarying(_)
+        | DataType::CharacterVarying(_)
+        | DataType::Nvarchar(_)
+        | DataType::Uuid => ColumnType::Text,
+        DataType::Binary(_)
+        | DataType::Varbinary(_)
+        | DataType::Blob(_)
+        | DataType::Bytes(_)
+        | DataType::Bytea => ColumnType::Binary,
+        DataType::JSON | DataType::JSONB => ColumnType::Any,
+        DataType::Timestamp(_, _) | DataType::Datetime(_) | DataType::Date => ColumnType::Timestamp,
+        DataType::Custom(name, _) if name.to_string().eq_ignore_ascii_case("ENCRYPTED") => {
+            ColumnType::Encrypted
+        }
+        other => {
+            return Err(QueryError::Unimplemented(format!(
+                "Unsupported column type: {}",
+                other
+            )))
+        }
+    })
+}
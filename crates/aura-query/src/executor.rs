@@ -1,168 +1,3765 @@
+use crate::catalog;
+use crate::temp::TempTables;
 use crate::QueryError;
-use aura_common::{AuraDocument, DataValue};
-use aura_store::page::Page;
+use aura_common::{
+    AuraDocument, ColumnDef, ColumnType, DataValue, IndexDef, Privilege, QueryResult, QueryStats,
+    TableSchema,
+};
+use aura_security::homomorphic::{FheComputer, ServerKey};
+use aura_store::btree::manager::BTreeManager;
 use aura_store::pager::Pager;
-use sqlparser::ast::{Expr, SetExpr, Statement, Value, Values};
+use aura_store::StoreError;
+use sqlparser::ast::{
+    Action, Assignment, BinaryOperator, ColumnOption, ConflictTarget, DataType, Distinct, DoUpdate,
+    Expr, Function, FunctionArg, FunctionArgExpr, GrantObjects, Ident, JoinConstraint,
+    JoinOperator, ObjectName, ObjectType, OnConflict, OnConflictAction, OnInsert, OrderByExpr,
+    Privileges, Select, SelectItem, SetExpr, Statement, TableFactor, TableWithJoins, UnaryOperator,
+    Value, Values,
+};
 use sqlparser::dialect::GenericDialect;
-use sqlparser::parser::Parser;
-use std::collections::HashMap;
+use sqlparser::keywords::Keyword;
+use sqlparser::parser::{Parser, ParserError};
+use sqlparser::tokenizer::{Token, Tokenizer};
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::info;
+
+/// Largest serialized document INSERT accepts (it is spread over overflow pages)
+pub const MAX_DOCUMENT_SIZE: usize = 16 * 1024 * 1024;
+
+/// Longest Text and Binary values (in bytes) INSERT and UPDATE accept, so a single
+/// value can be capped below the whole document's allowance (see `with_value_limits`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueLimits {
+    pub max_text: usize,
+    pub max_binary: usize,
+}
+
+impl Default for ValueLimits {
+    /// Only the document size limit applies
+    fn default() -> Self {
+        Self {
+            max_text: MAX_DOCUMENT_SIZE,
+            max_binary: MAX_DOCUMENT_SIZE,
+        }
+    }
+}
+
+/// Read-only pseudo-column holding a document's version (bumped on every overwrite)
+pub const VERSION_COLUMN: &str = "_version";
+
+/// How deep `IN (SELECT ...)` subqueries may nest inside one another
+pub const MAX_SUBQUERY_DEPTH: usize = 4;
+
+/// An FHE_SUM whose ciphertexts have been read, but not added up yet
+/// (see `QueryEngine::with_deferred_fhe_sum`). Computing it needs no database.
+pub struct PendingFheSum {
+    column: String,
+    field: String,
+    values: Vec<Vec<u8>>,
+    fhe: FheComputer,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl PendingFheSum {
+    /// Folds the ciphertexts into the statement's result: one row holding the
+    /// total, itself a ciphertext only the client can decrypt (NULL if there
+    /// were no values, as SUM gives)
+    pub fn compute(self) -> Result<QueryResult, QueryError> {
+        let mut total: Option<Vec<u8>> = None;
+        for bytes in self.values {
+            // Each addition can take seconds, so a cancel is honoured between them
+            if self
+                .cancel
+                .as_ref()
+                .is_some_and(|cancel| cancel.load(AtomicOrdering::Relaxed))
+            {
+                return Err(QueryError::Cancelled);
+            }
+            total = Some(match total {
+                None => bytes,
+                Some(sum) => self
+                    .fhe
+                    .sum_encrypted(&sum, &bytes)
+                    .map_err(|e| QueryError::Invalid(format!("FHE_SUM({}): {}", self.field, e)))?,
+            });
+        }
+
+        Ok(QueryResult::Rows {
+            columns: vec![self.column],
+            types: vec![ColumnType::Encrypted],
+            rows: vec![vec![total.map_or(DataValue::Null, DataValue::Encrypted)]],
+        })
+    }
+}
+
+/// What an aggregate SELECT list computes
+enum Aggregate {
+    /// `COUNT(*)`
+    CountStar,
+    /// `FHE_SUM(column)`: adds Encrypted values without decrypting them
+    FheSum(String),
+}
+
+/// How a SELECT found its documents, as reported by the query log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Plan {
+    /// Every document of the table was read
+    Scan,
+    /// `id = ...` / `id IN (...)` through the primary index
+    PrimaryKey,
+    /// `col = ...` through a secondary index
+    SecondaryIndex,
+    /// `COUNT(*)` answered from the index's row counts
+    RowCount,
+    /// A connection's temporary table (in memory)
+    TempTable,
+    /// Both tables of a JOIN read, one of them hashed on its join column
+    HashJoin,
+}
+
+impl Plan {
+    fn name(self) -> &'static str {
+        match self {
+            Plan::Scan => "scan",
+            Plan::PrimaryKey => "primary_key",
+            Plan::SecondaryIndex => "secondary_index",
+            Plan::RowCount => "row_count",
+            Plan::TempTable => "temp_table",
+            Plan::HashJoin => "hash_join",
+        }
+    }
+}
+
+/// What a SELECT-list column reads
+enum Projected<'e> {
+    /// A field, or a path into a nested value: profile.address.city
+    Field(String),
+    /// Any other expression, evaluated per document
+    Computed(&'e Expr),
+}
+
+/// One table of a JOIN and the column it is joined on
+struct JoinSide {
+    table: String,
+    /// What its columns are qualified with: the alias, or else the table name
+    qualifier: String,
+    column: String,
+}
+
+/// `FROM a [x] JOIN b [y] ON x.col = y.col`
+struct JoinSpec {
+    left: JoinSide,
+    right: JoinSide,
+}
+
+/// What the column references of a SELECT are resolved against (see `resolve_columns`)
+enum Scope<'j> {
+    /// A single table, called by its alias if it has one, else by its name
+    Table(String),
+    /// The two tables of a join, with each one's columns once they are needed
+    Join(&'j JoinSpec, Option<[HashSet<String>; 2]>),
+}
+
+/// A projected row as a hash key for SELECT DISTINCT (see `DataValue::canonical_eq`)
+struct DistinctRow(Vec<DataValue>);
+
+impl PartialEq for DistinctRow {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len() && self.0.iter().zip(&other.0).all(|(a, b)| a.canonical_eq(b))
+    }
+}
+
+impl Eq for DistinctRow {}
+
+impl Hash for DistinctRow {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for value in &self.0 {
+            value.canonical_hash(state);
+        }
+    }
+}
 
 pub struct QueryEngine<'a> {
     pager: &'a mut Pager,
+
+    /// Values bound to `$1..$n` for the statement currently executing
+    params: Vec<DataValue>,
+
+    /// If set, projecting a column no document has is an error instead of being dropped
+    strict_columns: bool,
+
+    /// Validate statements fully but never write anything (`EXPLAIN VALIDATE`)
+    dry_run: bool,
+
+    /// The caller's temporary tables, if it has any (see `with_temp_tables`)
+    temp_tables: Option<&'a mut TempTables>,
+
+    /// Computes on Encrypted values (FHE_SUM), once a server key is registered
+    fhe: Option<FheComputer>,
+
+    /// Emit a `query` tracing event after every statement (see `with_query_log`)
+    query_log: bool,
+
+    /// Access path of the statement currently executing, if it read any documents
+    plan: Option<Plan>,
+
+    /// The login statements run as, checked against its grants (see `with_user`)
+    user: Option<String>,
+
+    /// Documents read so far by the current `execute` call (a Cell: scans of
+    /// temporary tables only borrow the engine)
+    rows_examined: Cell<u64>,
+
+    /// What the last `execute` call cost (see `stats`)
+    stats: QueryStats,
+
+    /// Values of the `IN (SELECT ...)` subqueries run for the current statement,
+    /// keyed by their SQL text: sorted and without NULLs, for binary search
+    subqueries: HashMap<String, Vec<DataValue>>,
+
+    /// Subqueries enclosing the SELECT executing now (0 for a top-level one)
+    subquery_depth: usize,
+
+    /// Names and aliases of the tables of the queries enclosing it
+    enclosing_tables: Vec<String>,
+
+    /// Longest Text and Binary values a stored document may hold
+    value_limits: ValueLimits,
+
+    /// Set by another thread to stop a long computation (see `with_cancel`)
+    cancel: Option<Arc<AtomicBool>>,
+
+    /// Between BEGIN and COMMIT/ROLLBACK: statements share one WAL batch
+    in_transaction: bool,
+
+    /// Leave a lone FHE_SUM's additions to the caller (see `with_deferred_fhe_sum`)
+    defer_fhe_sum: bool,
+
+    /// The FHE_SUM the last `execute` call read the ciphertexts of, if deferred
+    pending_fhe_sum: Option<PendingFheSum>,
 }
 
 impl<'a> QueryEngine<'a> {
     pub fn new(pager: &'a mut Pager) -> Self {
-        Self { pager }
+        // A transaction an earlier engine left open ends with it
+        let _ = pager.rollback();
+        Self {
+            pager,
+            params: Vec::new(),
+            strict_columns: false,
+            dry_run: false,
+            temp_tables: None,
+            fhe: None,
+            query_log: false,
+            plan: None,
+            user: None,
+            rows_examined: Cell::new(0),
+            stats: QueryStats::default(),
+            subqueries: HashMap::new(),
+            subquery_depth: 0,
+            enclosing_tables: Vec::new(),
+            value_limits: ValueLimits::default(),
+            cancel: None,
+            in_transaction: false,
+            defer_fhe_sum: false,
+            pending_fhe_sum: None,
+        }
+    }
+
+    /// Report unknown columns in the SELECT list (`UnknownColumn`) instead of omitting them
+    pub fn with_strict_columns(mut self, strict: bool) -> Self {
+        self.strict_columns = strict;
+        self
+    }
+
+    /// Only validate statements (parse, resolve against the catalog, type-check),
+    /// as if every statement were prefixed with `EXPLAIN VALIDATE`
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Enables CREATE TEMP TABLE, with the tables kept in `temp_tables`.
+    /// A temporary table hides a regular table of the same name.
+    pub fn with_temp_tables(mut self, temp_tables: &'a mut TempTables) -> Self {
+        self.temp_tables = Some(temp_tables);
+        self
+    }
+
+    /// Logs every statement as an INFO `query` event (target `aura_query`) carrying
+    /// the SQL, the access path (`plan`), `pages_read` and `rows_returned`
+    pub fn with_query_log(mut self, enabled: bool) -> Self {
+        self.query_log = enabled;
+        self
+    }
+
+    /// Runs every statement as `user`: each one needs the user's grants on the tables
+    /// it touches, and only ADMIN_USER can change schemas or GRANT. Without a user,
+    /// nothing is checked. Temporary tables are always their connection's own.
+    pub fn with_user(mut self, user: &str) -> Self {
+        self.user = Some(user.to_string());
+        self
+    }
+
+    /// Rejects documents holding a longer Text or Binary value (`ValueTooLong`),
+    /// anywhere in them: nested in arrays and objects too
+    pub fn with_value_limits(mut self, limits: ValueLimits) -> Self {
+        self.value_limits = limits;
+        self
+    }
+
+    /// Lets FHE computations be abandoned: once `cancel` is set, the statement
+    /// stops at its next step and fails with `Cancelled`
+    pub fn with_cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Computes on Encrypted values (FHE_SUM) with `fhe`, e.g. one shared by every
+    /// connection of a server (see `set_fhe_server_key`)
+    pub fn with_fhe(mut self, fhe: FheComputer) -> Self {
+        self.fhe = Some(fhe);
+        self
+    }
+
+    /// A request that is a single FHE_SUM statement then only reads and checks
+    /// the ciphertexts, with the pager; adding them up, which can take seconds,
+    /// is left to the caller: its result is a placeholder until the
+    /// `take_pending_fhe_sum` computation replaces it. In a batch of statements,
+    /// FHE_SUM is computed in place as usual.
+    pub fn with_deferred_fhe_sum(mut self, deferred: bool) -> Self {
+        self.defer_fhe_sum = deferred;
+        self
+    }
+
+    /// The FHE_SUM the last `execute` call left to compute, if any (see
+    /// `with_deferred_fhe_sum`)
+    pub fn take_pending_fhe_sum(&mut self) -> Option<PendingFheSum> {
+        self.pending_fhe_sum.take()
+    }
+
+    /// Rolls back the transaction a BEGIN left open, if any. Returns whether
+    /// there was one.
+    pub fn abandon_transaction(&mut self) -> Result<bool, QueryError> {
+        if !std::mem::take(&mut self.in_transaction) {
+            return Ok(false);
+        }
+        self.pager.rollback()?;
+        Ok(true)
+    }
+
+    /// Registers the clients' FHE server key, which FHE_SUM needs.
+    /// It can only compute on ciphertexts, never decrypt them.
+    pub fn set_fhe_server_key(&mut self, server_key: ServerKey) {
+        self.fhe = Some(FheComputer::new(server_key));
+    }
+
+    /// Rows examined and returned, pages read and written, and time taken by the
+    /// last `execute` call (all of its statements, if it had several)
+    pub fn stats(&self) -> QueryStats {
+        self.stats
+    }
+
+    /// Every document of a stored table, read lazily in primary key order, for
+    /// tools (backup, export) that walk a whole table rather than run a SELECT.
+    /// Needs SELECT on the table like a query would; a user without it gets
+    /// one AccessDenied error instead of documents.
+    pub fn scan_table<'e>(
+        &'e mut self,
+        table: &str,
+    ) -> impl Iterator<Item = Result<AuraDocument, QueryError>> + use<'a, 'e> {
+        let denied = match self.user.clone() {
+            Some(name) if name != catalog::ADMIN_USER => self
+                .require(&name, vec![(Privilege::Select, table.to_string())])
+                .err(),
+            _ => None,
+        };
+        let page_ids = match denied {
+            Some(_) => Vec::new(),
+            None => self.table_page_ids(table),
+        };
+        denied.map(Err).into_iter().chain(
+            page_ids
+                .into_iter()
+                .map(|page_id| self.read_document(page_id)),
+        )
     }
 
     /// The Main Entry Point: Takes SQL, Writes to Disk
-    pub fn execute(&mut self, sql: &str) -> Result<String, QueryError> {
+    pub fn execute(&mut self, sql: &str) -> Result<QueryResult, QueryError> {
+        self.execute_prepared(sql, &[])
+    }
+
+    /// Executes SQL containing `?` or `$1`-style placeholders.
+    /// Parameters are bound as DataValues, never spliced into the SQL text,
+    /// so quoting and injection are not a concern for the caller.
+    pub fn execute_prepared(
+        &mut self,
+        sql: &str,
+        params: &[DataValue],
+    ) -> Result<QueryResult, QueryError> {
+        let started = Instant::now();
+        let pager_before = self.pager.stats();
+        self.stats = QueryStats::default();
+        self.rows_examined.set(0);
+        self.pending_fhe_sum = None;
+
+        let (sql, expected) = Self::number_placeholders(sql)?;
+        if expected != params.len() {
+            return Err(QueryError::Bind(format!(
+                "Statement expects {} parameter(s), got {}",
+                expected,
+                params.len()
+            )));
+        }
+
+        self.params = params.to_vec();
+        let result = self.run(&sql);
+        self.params.clear();
+
+        let io = self.pager.stats() - pager_before;
+        self.stats = QueryStats {
+            rows_examined: self.rows_examined.get(),
+            rows_returned: result.as_ref().map_or(0, Self::rows_returned),
+            pages_read: io.pages_read,
+            pages_written: io.pages_written,
+            elapsed_micros: started.elapsed().as_micros() as u64,
+        };
+        result
+    }
+
+    /// Rows in a result set, or in every result set of a batch
+    fn rows_returned(result: &QueryResult) -> u64 {
+        match result {
+            QueryResult::Rows { rows, .. } => rows.len() as u64,
+            QueryResult::Batch(results) => results.iter().map(Self::rows_returned).sum(),
+            _ => 0,
+        }
+    }
+
+    /// Runs every statement of `sql` in order. Several statements give a `Batch`
+    /// of their results; the first one to fail stops the rest, and the error says which.
+    fn run(&mut self, sql: &str) -> Result<QueryResult, QueryError> {
+        // Not a statement sqlparser knows, so it is peeled off before parsing
+        if let Some(statement) = Self::strip_explain_validate(sql) {
+            let dry_run = std::mem::replace(&mut self.dry_run, true);
+            let result = self.run(statement);
+            self.dry_run = dry_run;
+            return result;
+        }
+
         let dialect = GenericDialect {};
         let ast = Parser::parse_sql(&dialect, sql)?;
 
-        if ast.is_empty() {
-            return Err(QueryError::Unimplemented("Empty SQL statement".to_string()));
+        match ast.as_slice() {
+            [] => Err(QueryError::Unimplemented("Empty SQL statement".to_string())),
+            [statement] => self.run_statement(statement),
+            statements => {
+                // Only a lone statement's result can be filled in afterwards
+                let defer_fhe_sum = std::mem::replace(&mut self.defer_fhe_sum, false);
+                let results = self.run_batch(statements);
+                self.defer_fhe_sum = defer_fhe_sum;
+                results.map(QueryResult::Batch)
+            }
+        }
+    }
+
+    fn run_batch(&mut self, statements: &[Statement]) -> Result<Vec<QueryResult>, QueryError> {
+        let mut results = Vec::with_capacity(statements.len());
+        for (i, statement) in statements.iter().enumerate() {
+            let result = self
+                .run_statement(statement)
+                .map_err(|e| QueryError::Statement {
+                    index: i + 1,
+                    source: Box::new(e),
+                })?;
+            results.push(result);
+        }
+        Ok(results)
+    }
+
+    fn run_statement(&mut self, statement: &Statement) -> Result<QueryResult, QueryError> {
+        // One statement = one WAL batch: its data, index and catalog pages
+        // land in the main file together or not at all (none of them if it fails)
+        self.plan = None;
+        // An earlier statement may have changed what a subquery returns
+        self.subqueries.clear();
+        let pages_before = self.pager.pages_read();
+        let result = match statement {
+            Statement::StartTransaction { .. }
+            | Statement::Commit { .. }
+            | Statement::Rollback { .. } => self.handle_transaction(statement),
+            // A failed statement would leave half its writes in the transaction,
+            // so the whole transaction is abandoned instead
+            _ if self.in_transaction => {
+                let result = self.dispatch(statement);
+                if result.is_err() {
+                    self.in_transaction = false;
+                    self.pager.rollback()?;
+                }
+                result
+            }
+            // Reads have nothing to undo, so they skip the savepoint
+            _ if Self::is_read_only(statement) => {
+                self.pager.begin();
+                let result = self.dispatch(statement);
+                self.pager.commit()?;
+                result
+            }
+            // A failed statement leaves none of its writes behind
+            _ => {
+                self.pager.begin_transaction();
+                let result = self.dispatch(statement);
+                match result {
+                    Ok(_) => self.pager.commit()?,
+                    Err(_) => self.pager.rollback()?,
+                }
+                result
+            }
+        };
+
+        if self.query_log {
+            let rows_returned = match &result {
+                Ok(QueryResult::Rows { rows, .. }) => rows.len(),
+                _ => 0,
+            };
+            info!(
+                target: "aura_query",
+                sql = %statement,
+                plan = self.plan.map_or("none", Plan::name),
+                pages_read = self.pager.pages_read() - pages_before,
+                rows_returned,
+                ok = result.is_ok(),
+                "query"
+            );
+        }
+        result
+    }
+
+    /// Statements that never write a page
+    fn is_read_only(statement: &Statement) -> bool {
+        matches!(
+            statement,
+            Statement::Query(_)
+                | Statement::ShowTables { .. }
+                | Statement::ShowColumns { .. }
+                | Statement::ExplainTable { .. }
+        )
+    }
+
+    /// BEGIN, COMMIT and ROLLBACK. A failing statement rolls the transaction back,
+    /// and so does the next engine made on the pager if this one left it open.
+    /// Temporary tables are not part of it.
+    fn handle_transaction(&mut self, statement: &Statement) -> Result<QueryResult, QueryError> {
+        if self.dry_run {
+            return Ok(Self::validated());
         }
+        let no_transaction =
+            || QueryError::Invalid(format!("{} without a transaction in progress", statement));
+        match statement {
+            Statement::StartTransaction { .. } => {
+                if self.in_transaction {
+                    return Err(QueryError::Invalid(
+                        "A transaction is already in progress".into(),
+                    ));
+                }
+                self.pager.begin_transaction();
+                self.in_transaction = true;
+                Ok(QueryResult::Message("BEGIN".into()))
+            }
+            Statement::Commit { chain: false } => {
+                if !self.in_transaction {
+                    return Err(no_transaction());
+                }
+                self.in_transaction = false;
+                self.pager.commit()?;
+                Ok(QueryResult::Message("COMMIT".into()))
+            }
+            Statement::Rollback {
+                chain: false,
+                savepoint: None,
+            } => {
+                if !self.in_transaction {
+                    return Err(no_transaction());
+                }
+                self.in_transaction = false;
+                self.pager.rollback()?;
+                Ok(QueryResult::Message("ROLLBACK".into()))
+            }
+            _ => Err(QueryError::Unimplemented(
+                "AND CHAIN and savepoints are not supported".into(),
+            )),
+        }
+    }
 
-        match &ast[0] {
+    fn dispatch(&mut self, statement: &Statement) -> Result<QueryResult, QueryError> {
+        self.authorize(statement)?;
+        match statement {
             Statement::Insert {
-                columns, source, ..
+                table_name,
+                columns,
+                source,
+                on,
+                ..
             } => {
                 if let Some(query) = source {
-                    self.handle_insert(columns, query)
+                    self.handle_insert(table_name, columns, query, on.as_ref())
                 } else {
                     Err(QueryError::Unimplemented(
                         "INSERT without source not supported".into(),
                     ))
                 }
             }
-            Statement::Query(query) => {
-                // Very simplified parser for: SELECT * FROM users WHERE id = 'x'
-                self.handle_select(query)
+            Statement::Query(query) => self.handle_select(query),
+            Statement::CreateTable {
+                name,
+                columns,
+                if_not_exists,
+                temporary,
+                ..
+            } => self.handle_create_table(name, columns, *if_not_exists, *temporary),
+            Statement::CreateIndex {
+                name,
+                table_name,
+                columns,
+                unique,
+                if_not_exists,
+                ..
+            } => self.handle_create_index(
+                name.as_ref(),
+                table_name,
+                columns,
+                *unique,
+                *if_not_exists,
+            ),
+            Statement::Truncate { table_name, .. } => self.handle_truncate(table_name),
+            Statement::Drop {
+                object_type: ObjectType::Table,
+                if_exists,
+                names,
+                ..
+            } => self.handle_drop_table(names, *if_exists),
+            Statement::ShowTables { filter: None, .. } => self.handle_show_tables(),
+            Statement::ShowColumns {
+                table_name,
+                filter: None,
+                ..
+            }
+            | Statement::ExplainTable { table_name, .. } => self.handle_describe(table_name),
+            Statement::Grant {
+                privileges,
+                objects,
+                grantees,
+                with_grant_option,
+                ..
+            } => {
+                if *with_grant_option {
+                    return Err(QueryError::Unimplemented(
+                        "WITH GRANT OPTION is not supported".into(),
+                    ));
+                }
+                self.handle_grant(privileges, objects, grantees, true)
             }
+            Statement::Revoke {
+                privileges,
+                objects,
+                grantees,
+                ..
+            } => self.handle_grant(privileges, objects, grantees, false),
             _ => Err(QueryError::Unimplemented(
-                "Only INSERT and SELECT are supported".into(),
+                "Only CREATE TABLE, CREATE INDEX, DROP TABLE, TRUNCATE, INSERT, SELECT, \
+                 SHOW TABLES, DESCRIBE, GRANT, REVOKE, BEGIN, COMMIT and ROLLBACK are supported"
+                    .into(),
             )),
         }
     }
 
-    fn handle_insert(
-        &mut self,
-        columns: &[sqlparser::ast::Ident],
-        source: &sqlparser::ast::Query,
-    ) -> Result<String, QueryError> {
-        // 1. Extract Values from the AST
-        // This is simplified: assuming VALUES (...) structure
-        let row_values = match &*source.body {
-            SetExpr::Values(Values { rows, .. }) => &rows[0],
+    /// Refuses the statement unless the current user (if any) may run it.
+    /// Statements the engine doesn't support are let through to fail as such.
+    fn authorize(&mut self, statement: &Statement) -> Result<(), QueryError> {
+        let Some(name) = self.user.clone() else {
+            return Ok(());
+        };
+        if name == catalog::ADMIN_USER {
+            return Ok(());
+        }
+
+        let needed = match statement {
+            Statement::Query(query) => match &*query.body {
+                SetExpr::Select(select) => Self::tables_from(&select.from)
+                    .into_iter()
+                    .map(|table| (Privilege::Select, table))
+                    .collect(),
+                _ => vec![],
+            },
+            Statement::Insert {
+                table_name,
+                on,
+                source,
+                ..
+            } => {
+                let table = table_name.to_string();
+                let mut needed = vec![(Privilege::Insert, table.clone())];
+                if let Ok(Some(OnConflictAction::DoUpdate(_))) =
+                    Self::on_conflict_action(on.as_ref())
+                {
+                    needed.push((Privilege::Update, table));
+                }
+                // INSERT ... SELECT reads its source tables, joined ones included
+                // (subqueries are authorized as they run)
+                if let Some(SetExpr::Select(select)) = source.as_ref().map(|q| &*q.body) {
+                    needed.extend(
+                        Self::tables_from(&select.from)
+                            .into_iter()
+                            .map(|table| (Privilege::Select, table)),
+                    );
+                }
+                needed
+            }
+            Statement::Truncate { table_name, .. } => {
+                vec![(Privilege::Truncate, table_name.to_string())]
+            }
+            Statement::ShowColumns { table_name, .. }
+            | Statement::ExplainTable { table_name, .. } => {
+                vec![(Privilege::Select, table_name.to_string())]
+            }
+            Statement::ShowTables { .. } => vec![],
+            // A connection's own temporary tables need no grants
+            Statement::CreateTable {
+                temporary: true, ..
+            } => vec![],
+            Statement::Drop {
+                object_type: ObjectType::Table,
+                names,
+                ..
+            } if names.iter().all(|name| self.is_temp(&name.to_string())) => vec![],
             _ => {
-                return Err(QueryError::Unimplemented(
-                    "Complex INSERT not supported".into(),
-                ))
+                return Err(QueryError::AccessDenied(format!(
+                    "only {} can change schemas and grants",
+                    catalog::ADMIN_USER
+                )))
             }
         };
 
-        // 2. Build the AuraDocument
-        let mut doc_data = HashMap::new();
-        let mut doc_id = String::new();
+        self.require(&name, needed)
+    }
+
+    /// Fails unless `name` holds every (privilege, table) pair (temporary tables
+    /// need none)
+    fn require(&mut self, name: &str, needed: Vec<(Privilege, String)>) -> Result<(), QueryError> {
+        let user = catalog::load_user(self.pager, name)?;
+        for (privilege, table) in needed {
+            let granted = user.as_ref().is_some_and(|u| u.can(privilege, &table));
+            if !granted && !self.is_temp(&table) {
+                return Err(QueryError::AccessDenied(format!(
+                    "{} has no {} privilege on {}",
+                    name, privilege, table
+                )));
+            }
+        }
+        Ok(())
+    }
 
-        for (i, col) in columns.iter().enumerate() {
-            let col_name = col.value.clone();
-            let val_expr = &row_values[i];
+    /// GRANT / REVOKE privileges ON tables TO / FROM users. The users must exist;
+    /// the tables need not (yet).
+    fn handle_grant(
+        &mut self,
+        privileges: &Privileges,
+        objects: &GrantObjects,
+        grantees: &[Ident],
+        grant: bool,
+    ) -> Result<QueryResult, QueryError> {
+        let privileges = match privileges {
+            Privileges::All { .. } => Privilege::ALL.to_vec(),
+            Privileges::Actions(actions) => actions
+                .iter()
+                .map(|action| match action {
+                    Action::Select { columns: None } => Ok(Privilege::Select),
+                    Action::Insert { columns: None } => Ok(Privilege::Insert),
+                    Action::Update { columns: None } => Ok(Privilege::Update),
+                    Action::Truncate => Ok(Privilege::Truncate),
+                    other => Err(QueryError::Unimplemented(format!(
+                        "Privilege {} is not supported (column lists neither)",
+                        other
+                    ))),
+                })
+                .collect::<Result<_, _>>()?,
+        };
+        let GrantObjects::Tables(tables) = objects else {
+            return Err(QueryError::Unimplemented(
+                "Privileges can only be granted on tables".into(),
+            ));
+        };
 
-            // Map SQL Value -> Aura DataValue
-            let value = match val_expr {
-                Expr::Value(Value::Number(n, _)) => DataValue::Integer(n.parse().unwrap_or(0)),
-                Expr::Value(Value::SingleQuotedString(s)) => DataValue::Text(s.clone()),
-                Expr::Value(Value::Boolean(b)) => DataValue::Boolean(*b),
-                _ => DataValue::Null,
+        let mut users = Vec::with_capacity(grantees.len());
+        for grantee in grantees {
+            let Some(user) = catalog::load_user(self.pager, &grantee.value)? else {
+                return Err(QueryError::Invalid(format!(
+                    "User {} does not exist",
+                    grantee.value
+                )));
             };
+            users.push(user);
+        }
+        if self.dry_run {
+            return Ok(Self::validated());
+        }
 
-            // Special handling: treat 'id' column as the Primary Key
-            if col_name == "id" {
-                if let DataValue::Text(s) = &value {
-                    doc_id = s.clone();
+        for mut user in users {
+            for table in tables {
+                for &privilege in &privileges {
+                    if grant {
+                        user.grant(privilege, &table.to_string());
+                    } else {
+                        user.revoke(privilege, &table.to_string());
+                    }
                 }
             }
+            catalog::store_user(self.pager, &user)?;
+        }
+        self.pager.sync_index()?;
+        Ok(QueryResult::Message(
+            if grant { "GRANT" } else { "REVOKE" }.to_string(),
+        ))
+    }
 
-            doc_data.insert(col_name, value);
+    /// TRUNCATE TABLE: drops every document in one pass. The data pages and the
+    /// secondary index nodes go back to the free list; the schema and the (now empty)
+    /// indexes stay defined.
+    fn handle_truncate(&mut self, table_name: &ObjectName) -> Result<QueryResult, QueryError> {
+        let table = table_name.to_string();
+        if self.dry_run {
+            return Ok(Self::validated());
         }
 
-        if doc_id.is_empty() {
-            doc_id = uuid::Uuid::new_v4().to_string(); // Auto-generate ID if missing
+        if let Some(temp) = self.temp_table_mut(&table) {
+            let count = temp.docs.len();
+            temp.docs.clear();
+            return Ok(QueryResult::Affected(count as u64));
         }
 
-        let document = AuraDocument {
-            id: doc_id.clone(),
-            version: 1,
-            data: doc_data,
-        };
+        let count = self.delete_all(&table)?;
+        for def in catalog::table_indexes(self.pager, &table)? {
+            BTreeManager::new(self.pager, def.root_page).clear()?;
+        }
+
+        self.pager.sync_index()?;
+        Ok(QueryResult::Affected(count as u64))
+    }
 
-        // 3. Serialize & Store (The "Map to Page" step)
-        let new_page_id = self.write_document_to_disk(document)?;
+    /// Removes every document of a stored table from the primary index and frees
+    /// their pages. Secondary indexes are the caller's. Returns how many there were.
+    fn delete_all(&mut self, table: &str) -> Result<usize, QueryError> {
+        let prefix = Self::index_key(table, "");
+        let entries: Vec<(String, u32)> = self
+            .pager
+            .index
+            .map
+            .range(prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .map(|(key, &page_id)| (key.clone(), page_id))
+            .collect();
 
-        // UPDATE INDEX
-        self.pager.index.insert(doc_id.clone(), new_page_id);
+        for (key, page_id) in &entries {
+            self.pager.index.remove(key);
+            // Page 0 is the Index Page, never a document
+            if *page_id != 0 {
+                self.pager.free_chain(*page_id)?;
+            }
+        }
+        Ok(entries.len())
+    }
 
-        // Save the index to disk immediately (or wait for a commit)
-        self.pager.sync_index()?;
+    /// DROP TABLE: the documents, the secondary indexes and the catalog entry all go,
+    /// and their pages return to the free list. A table that is neither declared nor
+    /// holds documents doesn't exist (an error, unless IF EXISTS).
+    fn handle_drop_table(
+        &mut self,
+        names: &[ObjectName],
+        if_exists: bool,
+    ) -> Result<QueryResult, QueryError> {
+        let mut dropped = Vec::with_capacity(names.len());
+        for name in names {
+            let table = name.to_string();
+            let exists = self.is_temp(&table)
+                || catalog::load_schema(self.pager, &table)?.is_some()
+                || self.pager.index.row_count(&table) > 0;
+            if !exists {
+                if if_exists {
+                    continue;
+                }
+                return Err(QueryError::Invalid(format!(
+                    "Table {} does not exist",
+                    table
+                )));
+            }
+            dropped.push(table);
+        }
+        if self.dry_run {
+            return Ok(Self::validated());
+        }
+
+        for table in &dropped {
+            if let Some(temp_tables) = self.temp_tables.as_deref_mut() {
+                if temp_tables.drop(table) {
+                    continue;
+                }
+            }
+            self.delete_all(table)?;
+            for def in catalog::table_indexes(self.pager, table)? {
+                let mut btree = BTreeManager::new(self.pager, def.root_page);
+                btree.clear()?;
+                self.pager.free_page(def.root_page)?;
+                catalog::drop_index(self.pager, &def.name)?;
+            }
+            catalog::drop_schema(self.pager, table)?;
+        }
 
-        Ok(format!("Inserted Document ID: {}", doc_id))
+        self.pager.sync_index()?;
+        Ok(QueryResult::Message(format!(
+            "DROP TABLE {}",
+            dropped.join(", ")
+        )))
     }
 
-    fn write_document_to_disk(&mut self, doc: AuraDocument) -> Result<u32, QueryError> {
-        // A. Serialize
-        let bytes = doc
-            .to_bytes()
-            .map_err(|e| QueryError::Serialization(e.to_string()))?;
+    /// SHOW TABLES: every declared table (and the caller's temporary tables)
+    /// with its row count
+    fn handle_show_tables(&mut self) -> Result<QueryResult, QueryError> {
+        let mut tables: BTreeSet<String> = catalog::list_tables(self.pager).into_iter().collect();
+        if let Some(temp_tables) = self.temp_tables.as_deref() {
+            tables.extend(temp_tables.names());
+        }
 
-        // B. Allocate a new Page
-        // Note: In a real DB, we would split large docs across multiple pages.
-        // For Step 5, we assume the document fits in one 4KB page.
-        if bytes.len() > aura_store::page::DATA_SIZE {
-            return Err(QueryError::Serialization(
-                "Document too large for single page".into(),
-            ));
+        let rows = tables
+            .into_iter()
+            .map(|table| {
+                let count = self.table_row_count(&table);
+                vec![DataValue::Text(table), DataValue::Integer(count as i64)]
+            })
+            .collect();
+        Ok(QueryResult::rows(
+            vec!["table".to_string(), "rows".to_string()],
+            rows,
+        ))
+    }
+
+    /// DESCRIBE t / SHOW COLUMNS FROM t: the declared columns with their types,
+    /// then the table's row count. A schemaless table has no columns to list.
+    fn handle_describe(&mut self, table_name: &ObjectName) -> Result<QueryResult, QueryError> {
+        let table = table_name.to_string();
+        let schema = self.schema(&table)?;
+        let count = self.table_row_count(&table);
+        if schema.is_none() && count == 0 {
+            return Err(QueryError::Invalid(format!(
+                "Table {} does not exist",
+                table
+            )));
         }
 
-        let new_page_id = self.pager.allocate_page();
-        let mut page = Page::new(new_page_id);
+        let auto_increment = schema.as_ref().is_some_and(|s| s.auto_increment.is_some());
+        let columns = schema
+            .map(|schema| schema.columns)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|column| {
+                let mut declared = column.data_type.to_string();
+                if column.not_null {
+                    declared.push_str(" NOT NULL");
+                }
+                if column.unique {
+                    declared.push_str(" UNIQUE");
+                }
+                if auto_increment && column.name == "id" {
+                    declared.push_str(" AUTOINCREMENT");
+                }
+                vec![DataValue::Text(column.name), DataValue::Text(declared)]
+            })
+            .collect();
+        Ok(QueryResult::Batch(vec![
+            QueryResult::Rows {
+                columns: vec!["column".to_string(), "type".to_string()],
+                types: vec![ColumnType::Text, ColumnType::Text],
+                rows: columns,
+            },
+            Self::count_result("rows", count),
+        ]))
+    }
 
-        // C. Copy data into Page
-        // page.data is [u8; 4000]. We copy our bytes into it.
-        page.data[..bytes.len()].copy_from_slice(&bytes);
-        page.used_space = bytes.len() as u16;
+    /// Documents in `table`, counted by the index (or the temporary table itself)
+    fn table_row_count(&self, table: &str) -> usize {
+        match self.temp_tables.as_deref().and_then(|t| t.get(table)) {
+            Some(temp) => temp.docs.len(),
+            None => self.pager.index.row_count(table),
+        }
+    }
+
+    /// What a dry run returns once every check has passed
+    fn validated() -> QueryResult {
+        QueryResult::Message("Statement is valid".to_string())
+    }
 
-        // D. Write (This triggers the Automatic Encryption from Step 4)
-        self.pager.write_page(&page)?;
+    /// `EXPLAIN VALIDATE <statement>` -> `<statement>`
+    fn strip_explain_validate(sql: &str) -> Option<&str> {
+        let keyword = |s: &'_ str, kw: &str| -> Option<usize> {
+            let s = s.trim_start();
+            let rest = s.get(kw.len()..)?;
+            (s[..kw.len()].eq_ignore_ascii_case(kw) && rest.starts_with(char::is_whitespace))
+                .then_some(kw.len())
+        };
 
-        Ok(new_page_id)
+        let sql = sql.trim_start();
+        let sql = &sql[keyword(sql, "EXPLAIN")?..];
+        let sql = sql.trim_start();
+        Some(&sql[keyword(sql, "VALIDATE")?..])
     }
 
-    // New Function
-    fn handle_select(&mut self, _query: &sqlparser::ast::Query) -> Result<String, QueryError> {
-        // 1. Extract the WHERE clause (Looking for ID)
-        // This requires traversing the AST. For Step 6, let's cheat and hardcode:
-        // "Find the document with ID = 'user_007'"
+    fn handle_create_table(
+        &mut self,
+        name: &ObjectName,
+        columns: &[sqlparser::ast::ColumnDef],
+        if_not_exists: bool,
+        temporary: bool,
+    ) -> Result<QueryResult, QueryError> {
+        let table = name.to_string();
+
+        let mut schema = TableSchema {
+            name: table.clone(),
+            columns: Vec::with_capacity(columns.len()),
+            auto_increment: None,
+        };
+        for column in columns {
+            if schema.column(&column.name.value).is_some() {
+                return Err(QueryError::Invalid(format!(
+                    "Column '{}' declared twice",
+                    column.name.value
+                )));
+            }
+            let mut def = aura_common::ColumnDef {
+                name: column.name.value.clone(),
+                data_type: catalog::column_type(&column.data_type)?,
+                not_null: false,
+                unique: false,
+            };
+            for option in &column.options {
+                match &option.option {
+                    ColumnOption::NotNull => def.not_null = true,
+                    ColumnOption::Unique {
+                        is_primary: false, ..
+                    } => def.unique = true,
+                    // Documents are keyed by id, so no other column can be the key
+                    ColumnOption::Unique {
+                        is_primary: true, ..
+                    } if def.name == "id" => {
+                        def.not_null = true;
+                        def.unique = true;
+                    }
+                    ColumnOption::Unique {
+                        is_primary: true, ..
+                    } => {
+                        return Err(QueryError::Unimplemented(format!(
+                            "Only id can be the PRIMARY KEY, not '{}'",
+                            def.name
+                        )))
+                    }
+                    ColumnOption::DialectSpecific(tokens) if Self::is_auto_increment(tokens) => {
+                        if def.name != "id" || def.data_type != ColumnType::Integer {
+                            return Err(QueryError::Invalid(format!(
+                                "AUTOINCREMENT needs an INTEGER id column, not '{} {}'",
+                                def.name, def.data_type
+                            )));
+                        }
+                        schema.auto_increment = Some(0);
+                    }
+                    _ => {}
+                }
+            }
+            schema.columns.push(def);
+        }
+
+        let exists = if temporary {
+            let Some(temp_tables) = &self.temp_tables else {
+                return Err(QueryError::Unimplemented(
+                    "Temporary tables are only available on a connection".into(),
+                ));
+            };
+            temp_tables.contains(&table)
+        } else {
+            catalog::load_schema(self.pager, &table)?.is_some()
+        };
+        if exists {
+            if if_not_exists {
+                return Ok(QueryResult::Message(format!(
+                    "Table {} already exists",
+                    table
+                )));
+            }
+            return Err(QueryError::TableExists(table));
+        }
+
+        if self.dry_run {
+            return Ok(Self::validated());
+        }
+
+        if let (true, Some(temp_tables)) = (temporary, self.temp_tables.as_deref_mut()) {
+            temp_tables.create(schema);
+            return Ok(QueryResult::Message(format!("CREATE TEMP TABLE {}", table)));
+        }
+
+        catalog::store_schema(self.pager, &schema)?;
+        // UNIQUE columns are checked through an index (the id already is one)
+        for def in schema
+            .columns
+            .iter()
+            .filter(|def| def.unique && def.name != "id")
+        {
+            let name = self.unused_index_name(&format!("{}_{}_key", table, def.name))?;
+            self.create_index(name, &table, def.name.clone(), true)?;
+        }
+        self.pager.sync_index()?;
+        Ok(QueryResult::Message(format!("CREATE TABLE {}", table)))
+    }
 
-        // Assume we parsed the ID from the query string...
-        let target_id = "user_007"; // In real code, get this from AST
+    /// `base`, or else the first of base1, base2... no index has: "a_b" + "c" and
+    /// "a" + "b_c" both make "a_b_c_key", and CREATE INDEX can take any name
+    fn unused_index_name(&mut self, base: &str) -> Result<String, QueryError> {
+        let mut name = base.to_string();
+        let mut n = 0;
+        while catalog::load_index(self.pager, &name)?.is_some() {
+            n += 1;
+            name = format!("{}{}", base, n);
+        }
+        Ok(name)
+    }
 
-        // 2. INDEX LOOKUP (O(1) Speed)
-        match self.pager.index.get(target_id) {
-            Some(page_id) => {
-                // 3. FETCH ONLY THE ONE PAGE
-                let page = self.pager.read_page(page_id)?;
+    /// AUTOINCREMENT (SQLite) / AUTO_INCREMENT (MySQL), which the parser leaves as raw tokens
+    fn is_auto_increment(tokens: &[Token]) -> bool {
+        matches!(
+            tokens,
+            [Token::Word(word)] if matches!(word.keyword, Keyword::AUTOINCREMENT | Keyword::AUTO_INCREMENT)
+        )
+    }
 
-                // 4. Deserialize
-                let stored_bytes = &page.data[..page.used_space as usize];
-                let doc = AuraDocument::from_bytes(stored_bytes)
-                    .map_err(|e| QueryError::Serialization(e.to_string()))?;
+    /// CREATE INDEX name ON table(column): builds a B-tree over the column's Text
+    /// values from the existing rows; INSERT keeps it up to date from then on.
+    fn handle_create_index(
+        &mut self,
+        name: Option<&ObjectName>,
+        table_name: &ObjectName,
+        columns: &[OrderByExpr],
+        unique: bool,
+        if_not_exists: bool,
+    ) -> Result<QueryResult, QueryError> {
+        let table = table_name.to_string();
+        let column = match columns {
+            [OrderByExpr {
+                expr: Expr::Identifier(col),
+                ..
+            }] => col.value.clone(),
+            _ => {
+                return Err(QueryError::Unimplemented(
+                    "CREATE INDEX supports exactly one column".into(),
+                ))
+            }
+        };
+        let name = name
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| format!("{}_{}_idx", table, column));
 
-                Ok(format!("Found: {:?}", doc))
+        if self.is_temp(&table) {
+            return Err(QueryError::Unimplemented(
+                "Temporary tables cannot be indexed".into(),
+            ));
+        }
+        if catalog::load_index(self.pager, &name)?.is_some() {
+            if if_not_exists {
+                return Ok(QueryResult::Message(format!(
+                    "Index {} already exists",
+                    name
+                )));
+            }
+            return Err(QueryError::Invalid(format!(
+                "Index {} already exists",
+                name
+            )));
+        }
+        if let Some(schema) = catalog::load_schema(self.pager, &table)? {
+            if column != "id" && schema.column(&column).is_none() {
+                return Err(QueryError::UnknownColumn(format!("{}.{}", table, column)));
             }
-            None => Ok("Document not found".to_string()),
         }
+
+        if self.dry_run {
+            return Ok(Self::validated());
+        }
+
+        self.create_index(name.clone(), &table, column, unique)?;
+        self.pager.sync_index()?;
+        Ok(QueryResult::Message(format!("CREATE INDEX {}", name)))
+    }
+
+    /// Builds an index over the table's existing rows and registers it.
+    /// A unique one fails if two of those rows already share a value.
+    fn create_index(
+        &mut self,
+        name: String,
+        table: &str,
+        column: String,
+        unique: bool,
+    ) -> Result<(), QueryError> {
+        let root_page = BTreeManager::create(self.pager)?.root_id();
+        let mut indexes = [IndexDef {
+            name,
+            table: table.to_string(),
+            column,
+            root_page,
+            unique,
+        }];
+
+        // Backfill from the rows that are already there
+        let mut seen = HashSet::new();
+        for page_id in self.table_page_ids(table) {
+            let doc = self.read_document(page_id)?;
+            let value = Self::field(&doc, &indexes[0].column);
+            if unique && value != DataValue::Null && !seen.insert(DistinctRow(vec![value])) {
+                return Err(QueryError::ConstraintViolation {
+                    column: format!("{}.{}", table, indexes[0].column),
+                    constraint: "UNIQUE",
+                });
+            }
+            self.index_document(&mut indexes, &doc, page_id)?;
+        }
+
+        catalog::store_index(self.pager, &indexes[0])?;
+        Ok(())
+    }
+
+    fn handle_insert(
+        &mut self,
+        table_name: &ObjectName,
+        columns: &[sqlparser::ast::Ident],
+        source: &sqlparser::ast::Query,
+        on: Option<&OnInsert>,
+    ) -> Result<QueryResult, QueryError> {
+        let table = table_name.to_string();
+        let schema = self.schema(&table)?;
+        let mut last_id = schema.as_ref().and_then(|schema| schema.auto_increment);
+        let on_conflict = Self::on_conflict_action(on)?;
+
+        // 1. Extract the rows: literal VALUES, or whatever a SELECT returns
+        let (source_columns, rows) = match &*source.body {
+            SetExpr::Values(Values { rows, .. }) => {
+                let mut values = Vec::with_capacity(rows.len());
+                for row in rows {
+                    values.push(
+                        row.iter()
+                            .map(|expr| self.evaluate(None, expr))
+                            .collect::<Result<Vec<_>, _>>()?,
+                    );
+                }
+                (None, values)
+            }
+            SetExpr::Select(_) => match self.handle_select(source)? {
+                QueryResult::Rows { columns, rows, .. } => (Some(columns), rows),
+                // A dry run only validates the SELECT
+                _ => (None, Vec::new()),
+            },
+            _ => {
+                return Err(QueryError::Unimplemented(
+                    "INSERT takes VALUES (...) or a SELECT".into(),
+                ))
+            }
+        };
+
+        // 2. Work out which column each value goes to.
+        // Without a column list: every declared column in order, or else
+        // (INSERT ... SELECT into a schemaless table) the SELECT's own columns.
+        let columns: Vec<String> = match (&schema, columns.is_empty(), source_columns) {
+            (_, false, _) => columns.iter().map(|c| c.value.clone()).collect(),
+            (Some(schema), true, _) => schema.columns.iter().map(|c| c.name.clone()).collect(),
+            (None, true, Some(source_columns)) => source_columns,
+            (None, true, None) => Vec::new(),
+        };
+
+        // 3. Build every AuraDocument before storing any of them,
+        // so a bad row can't leave the statement half applied
+        let mut documents = Vec::with_capacity(rows.len());
+        for (i, row) in rows.into_iter().enumerate() {
+            if columns.len() != row.len() {
+                return Err(QueryError::Invalid(format!(
+                    "INSERT has {} column(s) but row {} has {} value(s)",
+                    columns.len(),
+                    i + 1,
+                    row.len()
+                )));
+            }
+            documents.push(Self::build_document(
+                schema.as_ref(),
+                &columns,
+                row,
+                last_id.as_mut(),
+            )?);
+        }
+
+        // 4. Settle conflicts with existing ids (and with earlier rows of this statement)
+        // up front as well: what gets written is the final version of each document
+        let mut planned: Vec<AuraDocument> = Vec::with_capacity(documents.len());
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for document in documents {
+            let existing = match positions.get(&document.id) {
+                Some(&i) => Some(planned[i].clone()),
+                None => self.existing_document(&table, &document.id)?,
+            };
+            let Some(existing) = existing else {
+                positions.insert(document.id.clone(), planned.len());
+                planned.push(document);
+                continue;
+            };
+            match on_conflict {
+                None => {
+                    return Err(QueryError::DuplicateKey(Self::index_key(
+                        &table,
+                        &document.id,
+                    )))
+                }
+                Some(OnConflictAction::DoNothing) => {}
+                Some(OnConflictAction::DoUpdate(DoUpdate { assignments, .. })) => {
+                    let merged =
+                        self.merge_assignments(schema.as_ref(), existing, &document, assignments)?;
+                    match positions.get(&document.id) {
+                        Some(&i) => planned[i] = merged,
+                        None => {
+                            positions.insert(document.id.clone(), planned.len());
+                            planned.push(merged);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.check_unique(&table, schema.as_ref(), &planned)?;
+
+        if self.dry_run {
+            return Ok(Self::validated());
+        }
+
+        if let Some(mut schema) = schema.filter(|schema| schema.auto_increment != last_id) {
+            schema.auto_increment = last_id;
+            self.update_schema(schema)?;
+        }
+
+        let mut ids = Vec::with_capacity(planned.len());
+        for document in planned {
+            ids.push(Self::field(&document, "id").to_string());
+            self.store_document(&table, document)?;
+        }
+
+        // Save the index to disk immediately (or wait for a commit)
+        self.pager.sync_index()?;
+
+        // A single VALUES row reports its (possibly generated) id
+        match (matches!(&*source.body, SetExpr::Values(_)), ids.len()) {
+            (true, 1) => Ok(QueryResult::Inserted {
+                id: ids.pop().expect("one id"),
+            }),
+            _ => Ok(QueryResult::Affected(ids.len() as u64)),
+        }
+    }
+
+    /// Turns one row of an INSERT into a document, checking it against the schema.
+    /// `last_id` is the AUTOINCREMENT counter, for tables that have one.
+    fn build_document(
+        schema: Option<&TableSchema>,
+        columns: &[String],
+        row: Vec<DataValue>,
+        last_id: Option<&mut i64>,
+    ) -> Result<AuraDocument, QueryError> {
+        let mut doc_data = HashMap::new();
+        let mut doc_id = String::new();
+
+        for (col_name, value) in columns.iter().zip(row) {
+            if col_name == VERSION_COLUMN {
+                return Err(QueryError::Invalid(format!(
+                    "{} is maintained by the database and cannot be written",
+                    VERSION_COLUMN
+                )));
+            }
+
+            // Declared tables only take their own columns, with matching types
+            let value = match schema {
+                Some(schema) => {
+                    let value = Self::coerce_value(schema, col_name, value)?;
+                    Self::check_column(schema, col_name, &value)?;
+                    value
+                }
+                None => value,
+            };
+
+            // Special handling: treat 'id' column as the Primary Key
+            if col_name == "id" {
+                if let Some(key) = Self::document_key(&value) {
+                    doc_id = key;
+                }
+            }
+
+            doc_data.insert(col_name.clone(), value);
+        }
+
+        if doc_id.is_empty() {
+            doc_id = uuid::Uuid::new_v4().to_string(); // Auto-generate ID if missing
+        }
+
+        let mut document = AuraDocument {
+            id: doc_id,
+            version: 1,
+            data: doc_data,
+        };
+        if let Some(last_id) = last_id {
+            Self::number_document(last_id, &mut document)?;
+        }
+        if let Some(schema) = schema {
+            Self::check_not_null(schema, &document)?;
+        }
+        Ok(document)
+    }
+
+    /// AUTOINCREMENT: a row without an id gets the one after `last_id`,
+    /// and an explicit id beyond `last_id` moves the counter up to it
+    fn number_document(last_id: &mut i64, document: &mut AuraDocument) -> Result<(), QueryError> {
+        match document.data.get("id") {
+            Some(DataValue::Integer(id)) => *last_id = (*last_id).max(*id),
+            None | Some(DataValue::Null) => {
+                *last_id = last_id
+                    .checked_add(1)
+                    .ok_or_else(|| QueryError::Invalid("AUTOINCREMENT ids exhausted".into()))?;
+                document.id =
+                    Self::document_key(&DataValue::Integer(*last_id)).expect("an integer key");
+                document
+                    .data
+                    .insert("id".into(), DataValue::Integer(*last_id));
+            }
+            // The column is INTEGER, so nothing else gets this far
+            Some(_) => {}
+        }
+        Ok(())
+    }
+
+    /// The `ON CONFLICT` clause of an INSERT, if any. Documents conflict on their id only.
+    fn on_conflict_action(on: Option<&OnInsert>) -> Result<Option<&OnConflictAction>, QueryError> {
+        let Some(on) = on else {
+            return Ok(None);
+        };
+        let OnInsert::OnConflict(OnConflict {
+            conflict_target,
+            action,
+        }) = on
+        else {
+            return Err(QueryError::Unimplemented(
+                "ON DUPLICATE KEY UPDATE is not supported, use ON CONFLICT (id)".into(),
+            ));
+        };
+        match conflict_target {
+            None => {}
+            Some(ConflictTarget::Columns(columns))
+                if columns.len() == 1 && columns[0].value == "id" => {}
+            Some(_) => {
+                return Err(QueryError::Unimplemented(
+                    "ON CONFLICT only supports the id column".into(),
+                ))
+            }
+        }
+        if let OnConflictAction::DoUpdate(DoUpdate {
+            selection: Some(_), ..
+        }) = action
+        {
+            return Err(QueryError::Unimplemented(
+                "ON CONFLICT DO UPDATE does not support WHERE".into(),
+            ));
+        }
+        Ok(Some(action))
+    }
+
+    /// The document currently stored under `id`, if there is one
+    fn existing_document(
+        &mut self,
+        table: &str,
+        id: &str,
+    ) -> Result<Option<AuraDocument>, QueryError> {
+        if let Some(temp) = self.temp_tables.as_deref().and_then(|t| t.get(table)) {
+            return Ok(temp.docs.get(id).cloned());
+        }
+        match self.pager.index.get(&Self::index_key(table, id)) {
+            Some(page_id) => Ok(Some(self.read_document(page_id)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// ON CONFLICT DO UPDATE SET ...: applies the assignments to the existing document.
+    /// Values may refer to the existing row's columns or to the proposed row as
+    /// `excluded.column`; columns that are not assigned keep their current value.
+    fn merge_assignments(
+        &self,
+        schema: Option<&TableSchema>,
+        mut existing: AuraDocument,
+        excluded: &AuraDocument,
+        assignments: &[Assignment],
+    ) -> Result<AuraDocument, QueryError> {
+        let mut updates = Vec::with_capacity(assignments.len());
+        for assignment in assignments {
+            let column = match assignment.id.as_slice() {
+                [column] => column.value.clone(),
+                _ => {
+                    return Err(QueryError::Unimplemented(format!(
+                        "Unsupported assignment target: {}",
+                        assignment
+                    )))
+                }
+            };
+            if Self::is_pseudo_column(&column) {
+                return Err(QueryError::Invalid(format!(
+                    "{} cannot be changed by ON CONFLICT DO UPDATE",
+                    column
+                )));
+            }
+
+            let value = match &assignment.value {
+                Expr::CompoundIdentifier(parts)
+                    if parts.len() == 2 && parts[0].value.eq_ignore_ascii_case("excluded") =>
+                {
+                    Self::field(excluded, &parts[1].value)
+                }
+                expr => self.evaluate(Some(&existing), expr)?,
+            };
+            let value = match schema {
+                Some(schema) => {
+                    let value = Self::coerce_value(schema, &column, value)?;
+                    Self::check_column(schema, &column, &value)?;
+                    value
+                }
+                None => value,
+            };
+            updates.push((column, value));
+        }
+
+        // Every value is computed from the row as it was, not half updated
+        existing.data.extend(updates);
+        if let Some(schema) = schema {
+            Self::check_not_null(schema, &existing)?;
+        }
+        Ok(existing)
+    }
+
+    /// Writes a document and points the indexes at it (the caller syncs the index).
+    /// Overwriting an existing id continues that document's version count.
+    fn store_document(
+        &mut self,
+        table: &str,
+        mut document: AuraDocument,
+    ) -> Result<(), QueryError> {
+        self.check_value_lengths(table, &document)?;
+
+        // Temporary tables never touch the pager
+        if let Some(temp) = self.temp_table_mut(table) {
+            if let Some(previous) = temp.docs.get(&document.id) {
+                document.version = previous.version + 1;
+            }
+            temp.docs.insert(document.id.clone(), document);
+            return Ok(());
+        }
+
+        // The previous version's pages are freed first, so the new one reuses them
+        let mut indexes = catalog::table_indexes(self.pager, table)?;
+        if let Some(page_id) = self.pager.index.get(&Self::index_key(table, &document.id)) {
+            let previous = self.read_document(page_id)?;
+            document.version = previous.version + 1;
+            self.unindex_document(&mut indexes, &previous)?;
+            self.pager.free_chain(page_id)?;
+        }
+
+        // Serialize & Store (The "Map to Page" step)
+        let new_page_id = self.write_document_to_disk(&document)?;
+
+        // Secondary indexes point at the new page too
+        self.index_document(&mut indexes, &document, new_page_id)?;
+
+        // UPDATE INDEX (keys are namespaced per table: "users/user_007")
+        self.pager
+            .index
+            .insert(Self::index_key(table, &document.id), new_page_id);
+        Ok(())
+    }
+
+    /// Converts literals written in another form to the column's type:
+    /// - JSON columns take objects and arrays written as JSON text
+    ///   ('{"city": "NY"}' is stored as an Object, not as a string)
+    /// - BOOLEAN columns take TRUE/FALSE, 1/0 and 'true'/'false'; any other
+    ///   number or string is refused rather than guessed at
+    /// - TIMESTAMP columns take ISO-8601 strings ('2024-01-15 10:30:00')
+    fn coerce_value(
+        schema: &TableSchema,
+        column: &str,
+        value: DataValue,
+    ) -> Result<DataValue, QueryError> {
+        let Some(def) = schema.column(column) else {
+            return Ok(value);
+        };
+        match (def.data_type, value) {
+            (ColumnType::Any, DataValue::Text(text)) if text.trim_start().starts_with(['{', '[']) => {
+                DataValue::from_json(&text).map_err(|e| {
+                    QueryError::Serialization(format!(
+                        "Column '{}' holds invalid JSON: {}",
+                        column, e
+                    ))
+                })
+            }
+            (ColumnType::Boolean, value @ (DataValue::Integer(_) | DataValue::Text(_))) => {
+                match value.as_boolean() {
+                    Some(b) => Ok(DataValue::Boolean(b)),
+                    None => Err(QueryError::TypeMismatch(format!(
+                        "Column '{}' is BOOLEAN, {} is not a boolean (use TRUE/FALSE, 1/0 or 'true'/'false')",
+                        column, value
+                    ))),
+                }
+            }
+            (ColumnType::Timestamp, DataValue::Text(text)) => {
+                DataValue::parse_timestamp(&text).ok_or_else(|| {
+                    QueryError::TypeMismatch(format!(
+                        "Column '{}' is TIMESTAMP, '{}' is not an ISO-8601 date",
+                        column, text
+                    ))
+                })
+            }
+            (_, value) => Ok(value),
+        }
+    }
+
+    fn check_column(
+        schema: &TableSchema,
+        column: &str,
+        value: &DataValue,
+    ) -> Result<(), QueryError> {
+        let Some(def) = schema.column(column) else {
+            return Err(QueryError::UnknownColumn(format!(
+                "{}.{}",
+                schema.name, column
+            )));
+        };
+        if !def.data_type.accepts(value) {
+            return Err(QueryError::TypeMismatch(format!(
+                "Column '{}' is {}, got {:?}",
+                column, def.data_type, value
+            )));
+        }
+        Ok(())
+    }
+
+    /// NOT NULL columns: the document must have a non-NULL value for each
+    /// (an id generated on INSERT counts as given)
+    fn check_not_null(schema: &TableSchema, doc: &AuraDocument) -> Result<(), QueryError> {
+        for def in schema.columns.iter().filter(|def| def.not_null) {
+            if Self::field(doc, &def.name) == DataValue::Null {
+                return Err(QueryError::ConstraintViolation {
+                    column: format!("{}.{}", schema.name, def.name),
+                    constraint: "NOT NULL",
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// UNIQUE columns (declared, or covered by a unique index): no non-NULL value may
+    /// appear twice in the table as it will be once `planned` is written. A document
+    /// being rewritten only counts in its new form, so an update that keeps its own
+    /// value passes. Values compare like DISTINCT does (1 and 1.0 are the same).
+    /// Text values are looked up in the column's index; anything else needs a scan.
+    fn check_unique(
+        &mut self,
+        table: &str,
+        schema: Option<&TableSchema>,
+        planned: &[AuraDocument],
+    ) -> Result<(), QueryError> {
+        let indexes = catalog::table_indexes(self.pager, table)?;
+        // Ids are unique by construction
+        let mut columns: Vec<&str> = schema
+            .iter()
+            .flat_map(|schema| &schema.columns)
+            .filter(|def| def.unique)
+            .map(|def| def.name.as_str())
+            .chain(
+                indexes
+                    .iter()
+                    .filter(|def| def.unique)
+                    .map(|def| def.column.as_str()),
+            )
+            .filter(|column| *column != "id")
+            .collect();
+        columns.sort_unstable();
+        columns.dedup();
+        if columns.is_empty() {
+            return Ok(());
+        }
+
+        let violation = |column: &str| QueryError::ConstraintViolation {
+            column: format!("{}.{}", table, column),
+            constraint: "UNIQUE",
+        };
+        let rewritten: HashSet<&str> = planned.iter().map(|doc| doc.id.as_str()).collect();
+        // The stored rows, read at most once and only if some column needs a scan
+        let mut stored: Option<Vec<AuraDocument>> = None;
+
+        for column in columns {
+            let mut seen = HashSet::new();
+            for doc in planned {
+                let value = Self::field(doc, column);
+                if value != DataValue::Null && !seen.insert(DistinctRow(vec![value])) {
+                    return Err(violation(column));
+                }
+            }
+
+            let index = indexes.iter().find(|def| def.column == column);
+            let all_text = planned.iter().all(|doc| {
+                matches!(
+                    Self::field(doc, column),
+                    DataValue::Text(_) | DataValue::Null
+                )
+            });
+            if let (Some(def), true) = (index, all_text) {
+                for doc in planned {
+                    let Some(value) = doc.get_text(column) else {
+                        continue;
+                    };
+                    let clash = self.index_matches(def, value)?.iter().any(|other| {
+                        !rewritten.contains(other.id.as_str())
+                            && Self::field(other, column) == DataValue::Text(value.to_string())
+                    });
+                    if clash {
+                        return Err(violation(column));
+                    }
+                }
+                continue;
+            }
+
+            let stored = match &mut stored {
+                Some(stored) => stored,
+                None => stored.insert(match self.temp_documents(table, None)? {
+                    Some(docs) => docs,
+                    None => self.scan_documents(table, None, None)?,
+                }),
+            };
+            for doc in stored
+                .iter()
+                .filter(|doc| !rewritten.contains(doc.id.as_str()))
+            {
+                let value = Self::field(doc, column);
+                if value != DataValue::Null && seen.contains(&DistinctRow(vec![value])) {
+                    return Err(violation(column));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the document to every secondary index that covers one of its Text or
+    /// numeric fields (see `index_value`).
+    /// A root split moves the tree's root, so the catalog entry is rewritten then.
+    fn index_document(
+        &mut self,
+        indexes: &mut [IndexDef],
+        doc: &AuraDocument,
+        page_id: u32,
+    ) -> Result<(), QueryError> {
+        for def in indexes.iter_mut() {
+            let Some(value) = doc.get(&def.column).and_then(Self::index_value) else {
+                continue;
+            };
+
+            let mut tree = BTreeManager::new(self.pager, def.root_page);
+            tree.insert(Self::secondary_key(&value, &doc.id), page_id)?;
+            let root_page = tree.root_id();
+
+            if root_page != def.root_page {
+                def.root_page = root_page;
+                catalog::store_index(self.pager, def)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the document's entries out of its table's secondary indexes
+    fn unindex_document(
+        &mut self,
+        indexes: &mut [IndexDef],
+        doc: &AuraDocument,
+    ) -> Result<(), QueryError> {
+        for def in indexes.iter_mut() {
+            let Some(value) = doc.get(&def.column).and_then(Self::index_value) else {
+                continue;
+            };
+
+            let mut tree = BTreeManager::new(self.pager, def.root_page);
+            tree.delete(&Self::secondary_key(&value, &doc.id))?;
+            let root_page = tree.root_id();
+
+            if root_page != def.root_page {
+                def.root_page = root_page;
+                catalog::store_index(self.pager, def)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_value_lengths(&self, table: &str, doc: &AuraDocument) -> Result<(), QueryError> {
+        for (column, value) in &doc.data {
+            if let Some((len, max)) = self.oversized(value) {
+                return Err(QueryError::ValueTooLong {
+                    column: format!("{}.{}", table, column),
+                    len,
+                    max,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The length and limit of the first value over its limit, if any
+    fn oversized(&self, value: &DataValue) -> Option<(usize, usize)> {
+        let limits = self.value_limits;
+        match value {
+            DataValue::Text(s) if s.len() > limits.max_text => Some((s.len(), limits.max_text)),
+            DataValue::Binary(b) if b.len() > limits.max_binary => {
+                Some((b.len(), limits.max_binary))
+            }
+            DataValue::Array(items) => items.iter().find_map(|v| self.oversized(v)),
+            DataValue::Object(fields) => fields.values().find_map(|v| self.oversized(v)),
+            _ => None,
+        }
+    }
+
+    fn write_document_to_disk(&mut self, doc: &AuraDocument) -> Result<u32, QueryError> {
+        // A. Serialize and validate BEFORE allocating, so a rejected
+        // document never consumes (and leaks) a page id.
+        let bytes = doc
+            .to_bytes()
+            .map_err(|e| QueryError::Serialization(e.to_string()))?;
+        if bytes.len() > MAX_DOCUMENT_SIZE {
+            return Err(QueryError::Serialization(format!(
+                "Document too large ({} > {} bytes)",
+                bytes.len(),
+                MAX_DOCUMENT_SIZE
+            )));
+        }
+
+        // B. Write it as a chain of pages (just one if it fits).
+        // The head page is what the index points at.
+        // (This triggers the Automatic Encryption from Step 4)
+        Ok(self.pager.write_chain(1, &bytes)?)
+    }
+
+    fn handle_select(&mut self, query: &sqlparser::ast::Query) -> Result<QueryResult, QueryError> {
+        let select = match &*query.body {
+            SetExpr::Select(select) => select,
+            _ => {
+                return Err(QueryError::Unimplemented(
+                    "Only simple SELECT queries are supported".into(),
+                ))
+            }
+        };
+
+        let table = Self::table_from(&select.from)?;
+        let join = Self::join_spec(&select.from)?;
+        // From here on, column references are the names of document fields
+        let query = &self.resolve_columns(query, join.as_ref())?;
+        let SetExpr::Select(select) = &*query.body else {
+            unreachable!("resolve_columns keeps the SELECT");
+        };
+        let aggregate = Self::aggregate(&select.projection)?;
+        if let Some(Distinct::On(_)) = &select.distinct {
+            return Err(QueryError::Unimplemented(
+                "DISTINCT ON is not supported".into(),
+            ));
+        }
+
+        // The schemas behind the result's columns: keyed by qualifier in a join
+        let schemas: Vec<(Option<String>, TableSchema)> = match &join {
+            None => self
+                .schema(&table)?
+                .map(|s| (None, s))
+                .into_iter()
+                .collect(),
+            Some(join) => {
+                let mut schemas = Vec::new();
+                for side in [&join.left, &join.right] {
+                    if let Some(schema) = self.schema(&side.table)? {
+                        schemas.push((Some(side.qualifier.clone()), schema));
+                    }
+                }
+                schemas
+            }
+        };
+        // The declaration of a result column, if its table declares it
+        let declared = |col: &str| -> Option<&ColumnDef> {
+            schemas
+                .iter()
+                .find_map(|(qualifier, schema)| match qualifier {
+                    None => schema.column(col),
+                    Some(qualifier) => col
+                        .strip_prefix(qualifier.as_str())
+                        .and_then(|rest| rest.strip_prefix('.'))
+                        .and_then(|rest| schema.column(rest)),
+                })
+        };
+
+        // Declared tables know their columns up front
+        if join.is_some() {
+            for item in &select.projection {
+                let (SelectItem::UnnamedExpr(Expr::CompoundIdentifier(parts))
+                | SelectItem::ExprWithAlias {
+                    expr: Expr::CompoundIdentifier(parts),
+                    ..
+                }) = item
+                else {
+                    continue;
+                };
+                let [qualifier, col, ..] = parts.as_slice() else {
+                    continue;
+                };
+                let known = Self::is_pseudo_column(&col.value)
+                    || declared(&format!("{}.{}", qualifier.value, col.value)).is_some()
+                    || !schemas
+                        .iter()
+                        .any(|(q, _)| q.as_deref() == Some(&qualifier.value));
+                if !known {
+                    return Err(QueryError::UnknownColumn(format!(
+                        "{}.{}",
+                        qualifier.value, col.value
+                    )));
+                }
+            }
+        } else if let Some((_, schema)) = schemas.first() {
+            for item in &select.projection {
+                let expr = match item {
+                    SelectItem::UnnamedExpr(expr) | SelectItem::ExprWithAlias { expr, .. } => expr,
+                    _ => continue,
+                };
+                let col = match expr {
+                    Expr::Identifier(col) => Some(col),
+                    // Paths are checked by their top-level column
+                    Expr::CompoundIdentifier(parts) => parts.first(),
+                    _ => None,
+                };
+                if let Some(col) = col {
+                    if !Self::is_pseudo_column(&col.value) && schema.column(&col.value).is_none() {
+                        return Err(QueryError::UnknownColumn(format!(
+                            "{}.{}",
+                            table, col.value
+                        )));
+                    }
+                }
+            }
+        }
+
+        if self.dry_run {
+            return Ok(Self::validated());
+        }
+
+        if let Some(selection) = &select.selection {
+            self.run_subqueries(selection, &select.from)?;
+        }
+        let mut result = self.select_rows(query, select, &table, join.as_ref(), aggregate)?;
+        // Declared columns report their declared type, even when every value is NULL
+        if let QueryResult::Rows { columns, types, .. } = &mut result {
+            for (col, column_type) in columns.iter().zip(types.iter_mut()) {
+                if let Some(def) = declared(col) {
+                    *column_type = def.data_type;
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Runs a validated SELECT against `table` (joined with another one if `join`)
+    fn select_rows(
+        &mut self,
+        query: &sqlparser::ast::Query,
+        select: &Select,
+        table: &str,
+        join: Option<&JoinSpec>,
+        aggregate: Option<(String, Aggregate)>,
+    ) -> Result<QueryResult, QueryError> {
+        // Joins and temporary tables are filtered in memory, up front
+        let filtered = match join {
+            Some(join) => Some(self.join_documents(join, select.selection.as_ref())?),
+            None => {
+                let docs = self.temp_documents(table, select.selection.as_ref())?;
+                if docs.is_some() {
+                    self.plan = Some(Plan::TempTable);
+                }
+                docs
+            }
+        };
+
+        // COUNT(*) without WHERE is answered from the index's row counts:
+        // O(1), no page reads. Any filter falls through to a scan below.
+        if let (Some((column, Aggregate::CountStar)), None, None) =
+            (&aggregate, &select.selection, &filtered)
+        {
+            let count = self.pager.index.row_count(table);
+            self.plan = Some(Plan::RowCount);
+            return Ok(Self::count_result(column, count));
+        }
+
+        let limit = match &query.limit {
+            Some(limit) => Some(self.limit_value(limit)?),
+            None => None,
+        };
+        if query.offset.is_some() {
+            return Err(QueryError::Unimplemented("OFFSET is not supported".into()));
+        }
+        // Without anything that needs every match first, a scan can stop at the limit
+        let scan_limit = limit.filter(|_| {
+            aggregate.is_none() && query.order_by.is_empty() && select.distinct.is_none()
+        });
+
+        let mut docs = match filtered {
+            Some(docs) => docs,
+            None => self.select_documents(table, select.selection.as_ref(), scan_limit)?,
+        };
+
+        match &aggregate {
+            Some((column, Aggregate::CountStar)) => {
+                return Ok(Self::count_result(column, docs.len()))
+            }
+            Some((column, Aggregate::FheSum(field))) => return self.fhe_sum(column, field, &docs),
+            None => {}
+        }
+
+        // ORDER BY and LIMIT apply to whole documents, so sorting on a column
+        // that isn't projected works too
+        self.sort_documents(&mut docs, &query.order_by)?;
+
+        if select.distinct.is_none() {
+            if let Some(limit) = limit {
+                docs.truncate(limit);
+            }
+            return self.project(&select.projection, &docs, join);
+        }
+
+        // DISTINCT compares projected rows, so duplicates are dropped before LIMIT counts
+        match self.project(&select.projection, &docs, join)? {
+            QueryResult::Rows {
+                columns,
+                types,
+                mut rows,
+            } => {
+                let mut seen = HashSet::new();
+                rows.retain(|row| seen.insert(DistinctRow(row.clone())));
+                if let Some(limit) = limit {
+                    rows.truncate(limit);
+                }
+                Ok(QueryResult::Rows {
+                    columns,
+                    types,
+                    rows,
+                })
+            }
+            other => Ok(other),
+        }
+    }
+
+    /// The documents of a stored table that pass the WHERE clause,
+    /// using the cheapest access path the clause allows. A scan stops reading
+    /// once it has `limit` matches.
+    fn select_documents(
+        &mut self,
+        table: &str,
+        selection: Option<&Expr>,
+        limit: Option<usize>,
+    ) -> Result<Vec<AuraDocument>, QueryError> {
+        let (plan, docs) = match selection {
+            // No WHERE clause: walk the whole table
+            None => (Plan::Scan, self.scan_documents(table, None, limit)?),
+            // WHERE id = '...' / id IN (...): index point lookups (O(log n) each)
+            Some(expr) => match self.id_filter(expr)? {
+                Some(ids) => {
+                    let mut docs = Vec::with_capacity(ids.len());
+                    for id in ids {
+                        // Missing ids are simply skipped
+                        if let Some(page_id) = self.pager.index.get(&Self::index_key(table, &id)) {
+                            docs.push(self.read_document(page_id)?);
+                        }
+                    }
+                    (Plan::PrimaryKey, docs)
+                }
+                // WHERE col = '...' on a column with a secondary index
+                None => match self.indexed_lookup(table, expr)? {
+                    Some(docs) => (Plan::SecondaryIndex, docs),
+                    // Anything else: scan and filter
+                    None => (Plan::Scan, self.scan_documents(table, Some(expr), limit)?),
+                },
+            },
+        };
+        self.plan = Some(plan);
+        Ok(docs)
+    }
+
+    /// A hash join: the smaller table is hashed on its join column and the other
+    /// one probes it. Each match becomes one document (see `joined_document`),
+    /// kept if it passes `selection`. Keys match when they are equal values of
+    /// the same type; NULL matches nothing.
+    fn join_documents(
+        &mut self,
+        join: &JoinSpec,
+        selection: Option<&Expr>,
+    ) -> Result<Vec<AuraDocument>, QueryError> {
+        let left = self.table_documents(&join.left.table)?;
+        let right = self.table_documents(&join.right.table)?;
+        self.plan = Some(Plan::HashJoin);
+
+        let build_left = left.len() <= right.len();
+        let (build, build_side, probe, probe_side) = if build_left {
+            (&left, &join.left, &right, &join.right)
+        } else {
+            (&right, &join.right, &left, &join.left)
+        };
+        let mut hashed: HashMap<DataValue, Vec<&AuraDocument>> = HashMap::new();
+        for doc in build {
+            let key = Self::field(doc, &build_side.column);
+            if key != DataValue::Null {
+                hashed.entry(key).or_default().push(doc);
+            }
+        }
+
+        let mut docs = Vec::new();
+        for doc in probe {
+            let key = Self::field(doc, &probe_side.column);
+            for &other in hashed.get(&key).into_iter().flatten() {
+                let (l, r) = if build_left {
+                    (other, doc)
+                } else {
+                    (doc, other)
+                };
+                let joined = Self::joined_document(l, &join.left, r, &join.right);
+                if selection.map_or(Ok(true), |expr| self.matches(&joined, expr))? {
+                    docs.push(joined);
+                }
+            }
+        }
+        Ok(docs)
+    }
+
+    /// Every document of a table, stored or temporary
+    fn table_documents(&mut self, table: &str) -> Result<Vec<AuraDocument>, QueryError> {
+        match self.temp_documents(table, None)? {
+            Some(docs) => Ok(docs),
+            None => self.select_documents(table, None, None),
+        }
+    }
+
+    /// A joined pair as one document: every field (and the id) of each side,
+    /// named `<qualifier>.<field>`
+    fn joined_document(
+        left: &AuraDocument,
+        left_side: &JoinSide,
+        right: &AuraDocument,
+        right_side: &JoinSide,
+    ) -> AuraDocument {
+        let mut joined = AuraDocument::new(format!("{}/{}", left.id, right.id));
+        for (doc, side) in [(left, left_side), (right, right_side)] {
+            joined
+                .data
+                .insert(format!("{}.id", side.qualifier), Self::field(doc, "id"));
+            for (field, value) in &doc.data {
+                joined
+                    .data
+                    .insert(format!("{}.{}", side.qualifier, field), value.clone());
+            }
+        }
+        joined
+    }
+
+    /// `SELECT *` over a join: each table's columns in FROM order, `id` first
+    fn joined_columns(docs: &[AuraDocument], join: &JoinSpec) -> Vec<String> {
+        let mut columns = Vec::new();
+        for side in [&join.left, &join.right] {
+            let prefix = format!("{}.", side.qualifier);
+            let id = format!("{}id", prefix);
+            let fields: BTreeSet<&String> = docs
+                .iter()
+                .flat_map(|doc| doc.data.keys())
+                .filter(|name| name.starts_with(&prefix) && **name != id)
+                .collect();
+            columns.push(id);
+            columns.extend(fields.into_iter().cloned());
+        }
+        columns
+    }
+
+    /// Sorts by each ORDER BY key in turn (stable, so ties keep index order).
+    /// NULL sorts lowest unless NULLS FIRST/LAST says otherwise.
+    fn sort_documents(
+        &self,
+        docs: &mut [AuraDocument],
+        order_by: &[OrderByExpr],
+    ) -> Result<(), QueryError> {
+        if order_by.is_empty() {
+            return Ok(());
+        }
+
+        let mut keys = Vec::with_capacity(order_by.len());
+        for item in order_by {
+            let col = match &item.expr {
+                Expr::Identifier(col) => col.value.clone(),
+                // A qualified column of a join, or a path: o.total, profile.age
+                Expr::CompoundIdentifier(parts) => parts
+                    .iter()
+                    .map(|part| part.value.as_str())
+                    .collect::<Vec<_>>()
+                    .join("."),
+                _ => {
+                    return Err(QueryError::Unimplemented(
+                        "ORDER BY only supports column names".into(),
+                    ))
+                }
+            };
+            keys.push((col, item.asc.unwrap_or(true), item.nulls_first));
+        }
+
+        docs.sort_by(|a, b| {
+            keys.iter()
+                .map(|(col, asc, nulls_first)| {
+                    let (col, asc, nulls_first) = (col.as_str(), *asc, *nulls_first);
+                    let (x, y) = (Self::field(a, col), Self::field(b, col));
+                    let (x_null, y_null) = (x == DataValue::Null, y == DataValue::Null);
+                    match (x_null, y_null, nulls_first) {
+                        (true, false, Some(first)) => return Self::nulls(first),
+                        (false, true, Some(first)) => return Self::nulls(first).reverse(),
+                        _ => {}
+                    }
+                    let ordering = x.sort_cmp(&y);
+                    if asc {
+                        ordering
+                    } else {
+                        ordering.reverse()
+                    }
+                })
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+        Ok(())
+    }
+
+    /// Where a NULL goes relative to a non-NULL value
+    fn nulls(first: bool) -> Ordering {
+        if first {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        }
+    }
+
+    fn limit_value(&self, limit: &Expr) -> Result<usize, QueryError> {
+        match self.literal(limit)? {
+            DataValue::Integer(n) if n >= 0 => Ok(n as usize),
+            other => Err(QueryError::Unimplemented(format!(
+                "LIMIT must be a non-negative integer, got {:?}",
+                other
+            ))),
+        }
+    }
+
+    /// Recognizes an aggregate SELECT list and returns its result column name,
+    /// or None for a plain projection. Only `COUNT(*)` and `FHE_SUM(column)`
+    /// (also spelled `SUM_ENC(column)`) are supported so far.
+    fn aggregate(projection: &[SelectItem]) -> Result<Option<(String, Aggregate)>, QueryError> {
+        let function = |item: &SelectItem| -> Option<(Function, Option<String>)> {
+            match item {
+                SelectItem::UnnamedExpr(Expr::Function(f)) => Some((f.clone(), None)),
+                SelectItem::ExprWithAlias {
+                    expr: Expr::Function(f),
+                    alias,
+                } => Some((f.clone(), Some(alias.value.clone()))),
+                _ => None,
+            }
+        };
+
+        let Some((f, alias)) = projection.iter().find_map(function) else {
+            return Ok(None);
+        };
+        if projection.len() > 1 {
+            return Err(QueryError::Unimplemented(
+                "Aggregates cannot be combined with other columns".into(),
+            ));
+        }
+
+        let name = f.name.to_string();
+        let plain = !f.distinct && f.filter.is_none() && f.over.is_none();
+        let aggregate = match f.args.as_slice() {
+            [FunctionArg::Unnamed(FunctionArgExpr::Wildcard)]
+                if plain && name.eq_ignore_ascii_case("COUNT") =>
+            {
+                Aggregate::CountStar
+            }
+            [FunctionArg::Unnamed(FunctionArgExpr::Expr(Expr::Identifier(col)))]
+                if plain
+                    && (name.eq_ignore_ascii_case("FHE_SUM")
+                        || name.eq_ignore_ascii_case("SUM_ENC")) =>
+            {
+                Aggregate::FheSum(col.value.clone())
+            }
+            _ => {
+                return Err(QueryError::Unimplemented(format!(
+                    "Aggregate {} is not supported (only COUNT(*) and FHE_SUM(column))",
+                    f
+                )))
+            }
+        };
+
+        let column = alias.unwrap_or_else(|| match &aggregate {
+            Aggregate::CountStar => "COUNT(*)".to_string(),
+            // Named as it was written: FHE_SUM(balance) or SUM_ENC(balance)
+            Aggregate::FheSum(col) => format!("{}({})", name.to_uppercase(), col),
+        });
+        Ok(Some((column, aggregate)))
+    }
+
+    fn count_result(column: &str, count: usize) -> QueryResult {
+        QueryResult::Rows {
+            columns: vec![column.to_string()],
+            types: vec![ColumnType::Integer],
+            rows: vec![vec![DataValue::Integer(count as i64)]],
+        }
+    }
+
+    /// Folds the Encrypted values of `field` with the FHE server key. The total
+    /// is itself a ciphertext only the client can decrypt. NULLs are skipped,
+    /// and summing no values at all gives NULL, as SUM does.
+    fn fhe_sum(
+        &mut self,
+        column: &str,
+        field: &str,
+        docs: &[AuraDocument],
+    ) -> Result<QueryResult, QueryError> {
+        let Some(fhe) = &self.fhe else {
+            return Err(QueryError::Invalid(
+                "FHE_SUM needs an FHE server key, and none is registered".into(),
+            ));
+        };
+
+        let mut values = Vec::with_capacity(docs.len());
+        for doc in docs {
+            match Self::field(doc, field) {
+                DataValue::Encrypted(bytes) => values.push(bytes),
+                DataValue::Null => {}
+                other => {
+                    return Err(QueryError::TypeMismatch(format!(
+                        "FHE_SUM({}) needs Encrypted values, but '{}' has {}",
+                        field, doc.id, other
+                    )))
+                }
+            }
+        }
+
+        let sum = PendingFheSum {
+            column: column.to_string(),
+            field: field.to_string(),
+            values,
+            fhe: fhe.clone(),
+            cancel: self.cancel.clone(),
+        };
+        if !self.defer_fhe_sum || self.subquery_depth > 0 {
+            return sum.compute();
+        }
+        self.pending_fhe_sum = Some(sum);
+        Ok(QueryResult::Rows {
+            columns: vec![column.to_string()],
+            types: vec![ColumnType::Encrypted],
+            rows: vec![vec![DataValue::Null]],
+        })
+    }
+
+    /// Applies the SELECT list: `*` keeps every field, named columns keep only those,
+    /// and any other expression (e.g. a CAST) is evaluated per document and named
+    /// after its SQL text.
+    fn project(
+        &self,
+        projection: &[SelectItem],
+        docs: &[AuraDocument],
+        join: Option<&JoinSpec>,
+    ) -> Result<QueryResult, QueryError> {
+        if projection
+            .iter()
+            .any(|item| matches!(item, SelectItem::Wildcard(_)))
+        {
+            let columns = match join {
+                Some(join) => Self::joined_columns(docs, join),
+                None => Self::all_columns(docs),
+            };
+            return Ok(Self::to_rows(docs, &columns));
+        }
+
+        // Each column's name with what it reads. Unaliased expressions are named
+        // by their position in the SELECT list: expr_1, expr_2, ...
+        let mut columns: Vec<(String, Projected)> = Vec::with_capacity(projection.len());
+        for (position, item) in projection.iter().enumerate() {
+            let (expr, alias) = match item {
+                SelectItem::UnnamedExpr(expr) => (expr, None),
+                SelectItem::ExprWithAlias { expr, alias } => (expr, Some(alias.value.clone())),
+                _ => {
+                    return Err(QueryError::Unimplemented(
+                        "Only column names, expressions and * are supported in the SELECT list"
+                            .into(),
+                    ))
+                }
+            };
+            let projected = match expr {
+                Expr::Identifier(ident) => Projected::Field(ident.value.clone()),
+                Expr::CompoundIdentifier(parts) => Projected::Field(
+                    parts
+                        .iter()
+                        .map(|part| part.value.as_str())
+                        .collect::<Vec<_>>()
+                        .join("."),
+                ),
+                expr => Projected::Computed(expr),
+            };
+            let name = alias.unwrap_or_else(|| match &projected {
+                Projected::Field(path) => path.clone(),
+                Projected::Computed(_) => format!("expr_{}", position + 1),
+            });
+            columns.push((name, projected));
+        }
+
+        // Documents are schemaless: a column is unknown if none of the matched
+        // documents has it. With no matches there is nothing to check against.
+        if !docs.is_empty() {
+            let is_known = |projected: &Projected| {
+                let Projected::Field(col) = projected else {
+                    return true;
+                };
+                let root = col.split('.').next().unwrap_or(col);
+                Self::is_pseudo_column(col)
+                    || docs
+                        .iter()
+                        .any(|doc| doc.data.contains_key(col) || doc.data.contains_key(root))
+            };
+            if let Some((_, Projected::Field(unknown))) =
+                columns.iter().find(|(_, projected)| !is_known(projected))
+            {
+                if self.strict_columns {
+                    return Err(QueryError::UnknownColumn(unknown.clone()));
+                }
+            }
+            columns.retain(|(_, projected)| is_known(projected));
+        }
+
+        let mut rows = Vec::with_capacity(docs.len());
+        for doc in docs {
+            let mut row = Vec::with_capacity(columns.len());
+            for (_, projected) in &columns {
+                row.push(match projected {
+                    Projected::Field(col) => Self::field(doc, col),
+                    Projected::Computed(expr) => self.evaluate(Some(doc), expr)?,
+                });
+            }
+            rows.push(row);
+        }
+
+        Ok(QueryResult::rows(
+            columns.into_iter().map(|(name, _)| name).collect(),
+            rows,
+        ))
+    }
+
+    /// Serves `col = 'value'` (or a number) from a secondary index on `col`, if
+    /// there is one. Text '30' and Integer 30 share a key, so the matches are
+    /// checked against `expr` again.
+    fn indexed_lookup(
+        &mut self,
+        table: &str,
+        expr: &Expr,
+    ) -> Result<Option<Vec<AuraDocument>>, QueryError> {
+        let Expr::BinaryOp {
+            left,
+            op: BinaryOperator::Eq,
+            right,
+        } = expr
+        else {
+            return Ok(None);
+        };
+        let (column, literal) = match (&**left, &**right) {
+            (Expr::Identifier(col), value) | (value, Expr::Identifier(col))
+                if Self::is_literal(value) =>
+            {
+                (&col.value, value)
+            }
+            _ => return Ok(None),
+        };
+        let Some(value) = Self::index_value(&self.literal(literal)?) else {
+            return Ok(None);
+        };
+        let Some(def) = catalog::table_indexes(self.pager, table)?
+            .into_iter()
+            .find(|def| &def.column == column)
+        else {
+            return Ok(None);
+        };
+        let mut docs = Vec::new();
+        for doc in self.index_matches(&def, &value)? {
+            if self.matches(&doc, expr)? {
+                docs.push(doc);
+            }
+        }
+        Ok(Some(docs))
+    }
+
+    /// The documents a secondary index lists under `value`
+    fn index_matches(
+        &mut self,
+        def: &IndexDef,
+        value: &str,
+    ) -> Result<Vec<AuraDocument>, QueryError> {
+        let prefix = Self::secondary_key(value, "");
+        let entries = BTreeManager::new(self.pager, def.root_page).search_prefix(&prefix)?;
+
+        let mut docs = Vec::with_capacity(entries.len());
+        for (key, page_id) in entries {
+            // Entries for documents that have since been overwritten are stale:
+            // only trust one whose page is still the document's current page.
+            let doc_id = &key[prefix.len()..];
+            if self.pager.index.get(&Self::index_key(&def.table, doc_id)) == Some(page_id) {
+                docs.push(self.read_document(page_id)?);
+            }
+        }
+        Ok(docs)
+    }
+
+    fn is_temp(&self, table: &str) -> bool {
+        self.temp_tables
+            .as_ref()
+            .is_some_and(|temp_tables| temp_tables.contains(table))
+    }
+
+    fn temp_table_mut(&mut self, table: &str) -> Option<&mut crate::temp::TempTable> {
+        self.temp_tables.as_deref_mut()?.get_mut(table)
+    }
+
+    /// A table's declared schema: a temporary table's own, else the catalog's
+    fn schema(&mut self, table: &str) -> Result<Option<TableSchema>, QueryError> {
+        if let Some(temp) = self.temp_tables.as_deref().and_then(|t| t.get(table)) {
+            return Ok(Some(temp.schema.clone()));
+        }
+        catalog::load_schema(self.pager, table)
+    }
+
+    /// Saves a changed schema: in the catalog, or on the temporary table it belongs to
+    fn update_schema(&mut self, schema: TableSchema) -> Result<(), QueryError> {
+        if let Some(temp) = self.temp_table_mut(&schema.name) {
+            temp.schema = schema;
+            return Ok(());
+        }
+        catalog::store_schema(self.pager, &schema)
+    }
+
+    /// The documents of a temporary table that pass the WHERE clause
+    /// (None if `table` is not a temporary table)
+    fn temp_documents(
+        &self,
+        table: &str,
+        selection: Option<&Expr>,
+    ) -> Result<Option<Vec<AuraDocument>>, QueryError> {
+        let Some(temp) = self.temp_tables.as_deref().and_then(|t| t.get(table)) else {
+            return Ok(None);
+        };
+
+        let mut docs = Vec::new();
+        self.rows_examined
+            .set(self.rows_examined.get() + temp.docs.len() as u64);
+        for doc in temp.docs.values() {
+            if selection.map_or(Ok(true), |expr| self.matches(doc, expr))? {
+                docs.push(doc.clone());
+            }
+        }
+        Ok(Some(docs))
+    }
+
+    /// Does the document satisfy the WHERE clause? Only a definite "true" counts.
+    fn matches(&self, doc: &AuraDocument, expr: &Expr) -> Result<bool, QueryError> {
+        Ok(self.predicate(doc, expr)? == Some(true))
+    }
+
+    /// Evaluates a predicate with SQL's three-valued logic: `None` is "unknown",
+    /// which is what any comparison against NULL (or a missing field) gives.
+    /// Unknown stays unknown under NOT, so `NOT age > 18` doesn't match a document
+    /// without an age either. Comparisons use the same ordering as ORDER BY
+    /// (so 2 = 2.0 and 1 < 1.5).
+    fn predicate(&self, doc: &AuraDocument, expr: &Expr) -> Result<Option<bool>, QueryError> {
+        Ok(match expr {
+            Expr::Nested(inner) => self.predicate(doc, inner)?,
+            Expr::UnaryOp {
+                op: UnaryOperator::Not,
+                expr,
+            } => self.predicate(doc, expr)?.map(|b| !b),
+            Expr::BinaryOp {
+                left,
+                op: BinaryOperator::And,
+                right,
+            } => match (self.predicate(doc, left)?, self.predicate(doc, right)?) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            },
+            Expr::BinaryOp {
+                left,
+                op: BinaryOperator::Or,
+                right,
+            } => match (self.predicate(doc, left)?, self.predicate(doc, right)?) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            },
+            Expr::IsNull(expr) => Some(self.evaluate(Some(doc), expr)? == DataValue::Null),
+            Expr::IsNotNull(expr) => Some(self.evaluate(Some(doc), expr)? != DataValue::Null),
+            Expr::BinaryOp { left, op, right } => {
+                let (left, right) = (
+                    self.evaluate(Some(doc), left)?,
+                    self.evaluate(Some(doc), right)?,
+                );
+                let right = Self::comparable(right, &left);
+                let left = Self::comparable(left, &right);
+                if left == DataValue::Null || right == DataValue::Null {
+                    return Ok(None);
+                }
+                let ordering = left.sort_cmp(&right);
+                Some(match op {
+                    BinaryOperator::Eq => ordering.is_eq(),
+                    BinaryOperator::NotEq => ordering.is_ne(),
+                    BinaryOperator::Lt => ordering.is_lt(),
+                    BinaryOperator::LtEq => ordering.is_le(),
+                    BinaryOperator::Gt => ordering.is_gt(),
+                    BinaryOperator::GtEq => ordering.is_ge(),
+                    other => {
+                        return Err(QueryError::Unimplemented(format!(
+                            "Unsupported operator in WHERE: {}",
+                            other
+                        )))
+                    }
+                })
+            }
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let value = self.evaluate(Some(doc), expr)?;
+                if value == DataValue::Null {
+                    return Ok(None);
+                }
+                let mut found = false;
+                for item in list {
+                    let item = Self::comparable(self.evaluate(Some(doc), item)?, &value);
+                    if value.sort_cmp(&item).is_eq() {
+                        found = true;
+                        break;
+                    }
+                }
+                Some(found != *negated)
+            }
+            Expr::InSubquery {
+                expr,
+                subquery,
+                negated,
+            } => {
+                let value = self.evaluate(Some(doc), expr)?;
+                if value == DataValue::Null {
+                    return Ok(None);
+                }
+                let Some(values) = self.subqueries.get(&subquery.to_string()) else {
+                    return Err(QueryError::Invalid(format!(
+                        "Subquery ({}) was not run before the scan",
+                        subquery
+                    )));
+                };
+                let found = values
+                    .binary_search_by(|item| item.sort_cmp(&value))
+                    .is_ok();
+                Some(found != *negated)
+            }
+            Expr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            } => {
+                let value = self.evaluate(Some(doc), expr)?;
+                let (low, high) = (
+                    self.evaluate(Some(doc), low)?,
+                    self.evaluate(Some(doc), high)?,
+                );
+                if [&value, &low, &high].contains(&&DataValue::Null) {
+                    return Ok(None);
+                }
+                let inside = value.sort_cmp(&low).is_ge() && value.sort_cmp(&high).is_le();
+                Some(inside != *negated)
+            }
+            Expr::Like {
+                negated,
+                expr,
+                pattern,
+                escape_char,
+            } => self
+                .like(doc, expr, pattern, *escape_char, false)?
+                .map(|found| found != *negated),
+            Expr::ILike {
+                negated,
+                expr,
+                pattern,
+                escape_char,
+            } => self
+                .like(doc, expr, pattern, *escape_char, true)?
+                .map(|found| found != *negated),
+            other => {
+                return Err(QueryError::Unimplemented(format!(
+                    "Unsupported WHERE expression: {}",
+                    other
+                )))
+            }
+        })
+    }
+
+    /// Runs the `IN (SELECT ...)` subqueries of a WHERE clause on the tables of `from`,
+    /// once each and before any document is read, so `predicate` only looks
+    /// their values up. Each must select a single column.
+    fn run_subqueries(
+        &mut self,
+        selection: &Expr,
+        from: &[TableWithJoins],
+    ) -> Result<(), QueryError> {
+        let mut outer = self.enclosing_tables.clone();
+        outer.extend(Self::table_names(from));
+        let mut exprs = Vec::new();
+        Self::sub_expressions(selection, &mut exprs);
+        for expr in exprs {
+            let Expr::InSubquery { subquery, .. } = expr else {
+                continue;
+            };
+            let key = subquery.to_string();
+            if self.subqueries.contains_key(&key) {
+                continue;
+            }
+            if self.subquery_depth == MAX_SUBQUERY_DEPTH {
+                return Err(QueryError::Invalid(format!(
+                    "Subqueries can only nest {} levels deep",
+                    MAX_SUBQUERY_DEPTH
+                )));
+            }
+            if self.is_correlated(subquery, &outer)? {
+                return Err(QueryError::Unimplemented(format!(
+                    "Correlated subqueries are not supported: ({})",
+                    subquery
+                )));
+            }
+            // The statement's grants were checked for its own table only
+            self.authorize(&Statement::Query(subquery.clone()))?;
+
+            self.subquery_depth += 1;
+            let enclosing = std::mem::replace(&mut self.enclosing_tables, outer.clone());
+            let result = self.handle_select(subquery);
+            self.enclosing_tables = enclosing;
+            self.subquery_depth -= 1;
+            let mut values: Vec<DataValue> = match result? {
+                QueryResult::Rows { columns, rows, .. } if columns.len() == 1 => {
+                    rows.into_iter().flatten().collect()
+                }
+                QueryResult::Rows { columns, .. } => {
+                    return Err(QueryError::Invalid(format!(
+                        "Subquery ({}) returns {} columns, IN needs exactly one",
+                        subquery,
+                        columns.len()
+                    )))
+                }
+                _ => Vec::new(),
+            };
+            // NULL is never IN anything, so it needn't be looked up
+            values.retain(|value| *value != DataValue::Null);
+            values.sort_by(|a, b| a.sort_cmp(b));
+            values.dedup_by(|a, b| a.sort_cmp(b).is_eq());
+            self.subqueries.insert(key, values);
+        }
+        Ok(())
+    }
+
+    /// Does `subquery` refer to a table of the queries around it (`outer`: their
+    /// names and aliases), as in `WHERE users.id = orders.user_id`? Fields of
+    /// schemaless documents can't be told apart otherwise, so only qualified names
+    /// count. A qualifier that is neither a table on either side nor a column of
+    /// the subquery's tables (a path like `profile.age`) is an error.
+    fn is_correlated(
+        &mut self,
+        subquery: &sqlparser::ast::Query,
+        outer: &[String],
+    ) -> Result<bool, QueryError> {
+        let SetExpr::Select(select) = &*subquery.body else {
+            return Ok(false);
+        };
+        let inner = Self::table_names(&select.from);
+        let mut exprs = Vec::new();
+        if let Some(selection) = &select.selection {
+            Self::sub_expressions(selection, &mut exprs);
+        }
+        for item in &select.projection {
+            if let SelectItem::UnnamedExpr(expr) | SelectItem::ExprWithAlias { expr, .. } = item {
+                Self::sub_expressions(expr, &mut exprs);
+            }
+        }
+
+        let mut inner_columns: Option<HashSet<String>> = None;
+        for expr in exprs {
+            let Expr::CompoundIdentifier(parts) = expr else {
+                continue;
+            };
+            // The innermost table of a name wins, as in `users.id` within `FROM users`
+            let qualifier = &parts[0].value;
+            if inner.contains(qualifier) {
+                continue;
+            }
+            if outer.contains(qualifier) {
+                return Ok(true);
+            }
+            let columns = match &inner_columns {
+                Some(columns) => columns,
+                None => {
+                    let mut columns = HashSet::new();
+                    for table in Self::tables_from(&select.from) {
+                        columns.extend(self.table_columns(&table)?);
+                    }
+                    inner_columns.insert(columns)
+                }
+            };
+            if !columns.contains(qualifier) {
+                return Err(QueryError::UnknownColumn(format!(
+                    "{} ({} is not a table of the subquery or of the query around it)",
+                    expr, qualifier
+                )));
+            }
+        }
+        Ok(false)
+    }
+
+    /// Rewrites the column references of a SELECT to the fields its documents have.
+    /// A join names every field `<qualifier>.<column>`, so a bare column gets the
+    /// qualifier of the one table that has it; a single table's name (or alias)
+    /// is dropped instead. ORDER BY may also name an alias of the SELECT list.
+    fn resolve_columns(
+        &mut self,
+        query: &sqlparser::ast::Query,
+        join: Option<&JoinSpec>,
+    ) -> Result<sqlparser::ast::Query, QueryError> {
+        let mut query = query.clone();
+        let SetExpr::Select(select) = &mut *query.body else {
+            return Ok(query);
+        };
+        let mut scope = match (join, select.from.first().map(|t| &t.relation)) {
+            (Some(join), _) => Scope::Join(join, None),
+            (None, Some(TableFactor::Table { name, alias, .. })) => Scope::Table(
+                alias
+                    .as_ref()
+                    .map_or_else(|| name.to_string(), |alias| alias.name.value.clone()),
+            ),
+            (None, _) => return Ok(query),
+        };
+
+        for item in &mut query.order_by {
+            if let Expr::Identifier(ident) = &item.expr {
+                let aliased = select.projection.iter().find_map(|item| match item {
+                    SelectItem::ExprWithAlias { expr, alias } if alias.value == ident.value => {
+                        Some(expr.clone())
+                    }
+                    _ => None,
+                });
+                if let Some(expr) = aliased {
+                    item.expr = expr;
+                }
+            }
+            self.resolve_expr(&mut item.expr, &mut scope)?;
+        }
+        if let Some(selection) = &mut select.selection {
+            self.resolve_expr(selection, &mut scope)?;
+        }
+        for item in &mut select.projection {
+            match item {
+                // A bare column keeps its name as the header once it is qualified
+                SelectItem::UnnamedExpr(Expr::Identifier(ident)) => {
+                    let alias = ident.clone();
+                    let mut expr = Expr::Identifier(ident.clone());
+                    self.resolve_expr(&mut expr, &mut scope)?;
+                    if !matches!(&expr, Expr::Identifier(resolved) if *resolved == alias) {
+                        *item = SelectItem::ExprWithAlias { expr, alias };
+                    }
+                }
+                SelectItem::UnnamedExpr(expr) | SelectItem::ExprWithAlias { expr, .. } => {
+                    self.resolve_expr(expr, &mut scope)?
+                }
+                _ => {}
+            }
+        }
+        Ok(query)
+    }
+
+    /// Resolves every column reference within `expr` (see `resolve_columns`)
+    fn resolve_expr(&mut self, expr: &mut Expr, scope: &mut Scope) -> Result<(), QueryError> {
+        let parts = match expr {
+            Expr::Identifier(ident) => vec![ident.clone()],
+            Expr::CompoundIdentifier(parts) => parts.clone(),
+            _ => {
+                for inner in Self::child_expressions_mut(expr) {
+                    self.resolve_expr(inner, scope)?;
+                }
+                return Ok(());
+            }
+        };
+
+        match scope {
+            // users.name or u.name: the qualifier goes, a path (profile.age) stays
+            Scope::Table(qualifier) => {
+                if let [first, rest @ ..] = parts.as_slice() {
+                    if first.value == *qualifier {
+                        *expr = match rest {
+                            [] => return Ok(()),
+                            [column] => Expr::Identifier(column.clone()),
+                            path => Expr::CompoundIdentifier(path.to_vec()),
+                        };
+                    }
+                }
+            }
+            Scope::Join(join, columns) => {
+                let sides = [&join.left, &join.right];
+                if parts.len() > 1 && sides.iter().any(|side| side.qualifier == parts[0].value) {
+                    return Ok(());
+                }
+                let columns = match columns {
+                    Some(columns) => columns,
+                    None => columns.insert([
+                        self.table_columns(&join.left.table)?,
+                        self.table_columns(&join.right.table)?,
+                    ]),
+                };
+                let column = &parts[0].value;
+                let owners: Vec<&JoinSide> = sides
+                    .into_iter()
+                    .zip(columns.iter())
+                    .filter(|(_, columns)| columns.contains(column))
+                    .map(|(side, _)| side)
+                    .collect();
+                match owners.as_slice() {
+                    [side] => {
+                        let qualifier = Ident::new(side.qualifier.as_str());
+                        *expr = Expr::CompoundIdentifier(
+                            std::iter::once(qualifier).chain(parts).collect(),
+                        );
+                    }
+                    [left, right] => {
+                        return Err(QueryError::AmbiguousColumn(format!(
+                            "{} could be {}.{} or {}.{}",
+                            column, left.qualifier, column, right.qualifier, column
+                        )))
+                    }
+                    // Neither table has it: it reads as NULL, as in a single table
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The columns a table has: the declared ones, or else every field of its documents
+    fn table_columns(&mut self, table: &str) -> Result<HashSet<String>, QueryError> {
+        let mut columns: HashSet<String> = match self.schema(table)? {
+            Some(schema) => schema.columns.into_iter().map(|c| c.name).collect(),
+            None => self
+                .table_documents(table)?
+                .into_iter()
+                .flat_map(|doc| doc.data.into_keys())
+                .collect(),
+        };
+        columns.insert("id".to_string());
+        Ok(columns)
+    }
+
+    /// The expressions directly within `expr` that `predicate` evaluates: one level
+    /// of `sub_expressions`, mutably
+    fn child_expressions_mut(expr: &mut Expr) -> Vec<&mut Expr> {
+        match expr {
+            Expr::Nested(inner)
+            | Expr::UnaryOp { expr: inner, .. }
+            | Expr::IsNull(inner)
+            | Expr::IsNotNull(inner)
+            | Expr::InSubquery { expr: inner, .. }
+            | Expr::Cast { expr: inner, .. } => vec![inner],
+            Expr::BinaryOp { left, right, .. }
+            | Expr::Like {
+                expr: left,
+                pattern: right,
+                ..
+            }
+            | Expr::ILike {
+                expr: left,
+                pattern: right,
+                ..
+            } => vec![left, right],
+            Expr::InList { expr, list, .. } => std::iter::once(&mut **expr)
+                .chain(list.iter_mut())
+                .collect(),
+            Expr::Between {
+                expr, low, high, ..
+            } => vec![expr, low, high],
+            _ => Vec::new(),
+        }
+    }
+
+    /// `expr` and every expression within it that `predicate` evaluates, outermost
+    /// first. Subqueries are not entered: they are their own statements.
+    fn sub_expressions<'e>(expr: &'e Expr, out: &mut Vec<&'e Expr>) {
+        out.push(expr);
+        match expr {
+            Expr::Nested(inner)
+            | Expr::UnaryOp { expr: inner, .. }
+            | Expr::IsNull(inner)
+            | Expr::IsNotNull(inner)
+            | Expr::InSubquery { expr: inner, .. }
+            | Expr::Cast { expr: inner, .. } => Self::sub_expressions(inner, out),
+            Expr::BinaryOp { left, right, .. }
+            | Expr::Like {
+                expr: left,
+                pattern: right,
+                ..
+            }
+            | Expr::ILike {
+                expr: left,
+                pattern: right,
+                ..
+            } => {
+                Self::sub_expressions(left, out);
+                Self::sub_expressions(right, out);
+            }
+            Expr::InList { expr, list, .. } => {
+                Self::sub_expressions(expr, out);
+                for item in list {
+                    Self::sub_expressions(item, out);
+                }
+            }
+            Expr::Between {
+                expr, low, high, ..
+            } => {
+                for inner in [expr, low, high] {
+                    Self::sub_expressions(inner, out);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// `expr LIKE pattern`: `%` matches any run of characters, `_` exactly one
+    /// (a character, not a byte), and `escape` makes the next character literal.
+    /// Non-text values match by their text form; NULL on either side gives NULL.
+    fn like(
+        &self,
+        doc: &AuraDocument,
+        expr: &Expr,
+        pattern: &Expr,
+        escape: Option<char>,
+        ignore_case: bool,
+    ) -> Result<Option<bool>, QueryError> {
+        let (value, pattern) = (
+            self.evaluate(Some(doc), expr)?,
+            self.evaluate(Some(doc), pattern)?,
+        );
+        if value == DataValue::Null || pattern == DataValue::Null {
+            return Ok(None);
+        }
+        let (mut value, mut pattern) = (value.to_string(), pattern.to_string());
+        if ignore_case {
+            value = value.to_lowercase();
+            pattern = pattern.to_lowercase();
+        }
+
+        enum Token {
+            AnyRun,
+            AnyOne,
+            Literal(char),
+        }
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            tokens.push(match c {
+                c if Some(c) == escape => match chars.next() {
+                    Some(literal) => Token::Literal(literal),
+                    None => {
+                        return Err(QueryError::Invalid(format!(
+                            "LIKE pattern '{}' ends with its escape character",
+                            pattern
+                        )))
+                    }
+                },
+                '%' => Token::AnyRun,
+                '_' => Token::AnyOne,
+                c => Token::Literal(c),
+            });
+        }
+
+        // matched[j]: the tokens so far match the first j characters
+        let text: Vec<char> = value.chars().collect();
+        let mut matched = vec![false; text.len() + 1];
+        matched[0] = true;
+        for token in &tokens {
+            let mut next = vec![false; text.len() + 1];
+            match token {
+                Token::AnyRun => {
+                    let mut any = false;
+                    for j in 0..=text.len() {
+                        any |= matched[j];
+                        next[j] = any;
+                    }
+                }
+                Token::AnyOne => next[1..].copy_from_slice(&matched[..text.len()]),
+                Token::Literal(c) => {
+                    for j in 1..=text.len() {
+                        next[j] = matched[j - 1] && text[j - 1] == *c;
+                    }
+                }
+            }
+            matched = next;
+        }
+        Ok(Some(matched[text.len()]))
+    }
+
+    /// Compared with a boolean, 1/0 and 'true'/'false' read as booleans too, and
+    /// compared with a timestamp, ISO-8601 strings read as timestamps: the same
+    /// spellings BOOLEAN and TIMESTAMP columns accept on INSERT
+    fn comparable(value: DataValue, other: &DataValue) -> DataValue {
+        match (other, &value) {
+            (DataValue::Boolean(_), _) => value.as_boolean().map_or(value, DataValue::Boolean),
+            (DataValue::Timestamp(_), DataValue::Text(text)) => {
+                DataValue::parse_timestamp(text).unwrap_or(value)
+            }
+            _ => value,
+        }
+    }
+
+    /// Evaluates a scalar expression: a column reference (the document's field),
+    /// a literal, a negated number or a CAST. INSERT ... VALUES, the WHERE clause
+    /// and the SELECT list all go through here; VALUES has no document, so column
+    /// references are an error there.
+    fn evaluate(&self, doc: Option<&AuraDocument>, expr: &Expr) -> Result<DataValue, QueryError> {
+        let row = || {
+            doc.ok_or_else(|| {
+                QueryError::Invalid(format!(
+                    "Column {} cannot be used here, a literal is expected",
+                    expr
+                ))
+            })
+        };
+        match expr {
+            Expr::Identifier(col) => Ok(Self::field(row()?, &col.value)),
+            Expr::CompoundIdentifier(parts) => {
+                let path: Vec<&str> = parts.iter().map(|part| part.value.as_str()).collect();
+                Ok(Self::field(row()?, &path.join(".")))
+            }
+            Expr::Nested(inner) => self.evaluate(doc, inner),
+            Expr::Value(_) | Expr::TypedString { .. } => self.literal(expr),
+            Expr::Function(f) if Self::is_now(f) || Self::encrypted_arg(f).is_some() => {
+                self.literal(expr)
+            }
+            Expr::UnaryOp {
+                op: op @ (UnaryOperator::Minus | UnaryOperator::Plus),
+                expr: inner,
+            } => match (op, self.evaluate(doc, inner)?) {
+                (_, DataValue::Null) => Ok(DataValue::Null),
+                (UnaryOperator::Plus, value @ (DataValue::Integer(_) | DataValue::Float(_))) => {
+                    Ok(value)
+                }
+                (_, DataValue::Integer(i)) => {
+                    i.checked_neg().map(DataValue::Integer).ok_or_else(|| {
+                        QueryError::TypeMismatch(format!("Integer overflow in {}", expr))
+                    })
+                }
+                (_, DataValue::Float(x)) => Ok(DataValue::Float(-x)),
+                (_, other) => Err(QueryError::TypeMismatch(format!(
+                    "{} applies to numbers, not {}",
+                    op, other
+                ))),
+            },
+            Expr::BinaryOp { left, op, right } => {
+                let (left, right) = (self.evaluate(doc, left)?, self.evaluate(doc, right)?);
+                Self::arithmetic(expr, op, left, right)
+            }
+            Expr::Cast {
+                expr: inner,
+                data_type,
+                ..
+            } => {
+                let target = catalog::column_type(data_type)?;
+                let value = self.evaluate(doc, inner)?;
+                target.cast(&value).ok_or_else(|| {
+                    QueryError::TypeMismatch(format!("Cannot cast {:?} to {}", value, target))
+                })
+            }
+            other => Err(QueryError::Unimplemented(format!(
+                "Unsupported expression: {}",
+                other
+            ))),
+        }
+    }
+
+    /// `left <op> right` for the value operators: + - * / % on numbers and ||
+    /// on anything with a text form. NULL in gives NULL out. Two Integers give an
+    /// Integer (division truncates, overflow is an error); with a Float in the
+    /// mix the result is a Float. Dividing by zero is an error rather than NULL,
+    /// so bad data doesn't quietly drop out of results.
+    fn arithmetic(
+        expr: &Expr,
+        op: &BinaryOperator,
+        left: DataValue,
+        right: DataValue,
+    ) -> Result<DataValue, QueryError> {
+        use DataValue::{Float, Integer, Null, Text};
+        if left == Null || right == Null {
+            return Ok(Null);
+        }
+        if *op == BinaryOperator::StringConcat {
+            return Ok(Text(format!("{}{}", left, right)));
+        }
+        if !matches!(
+            op,
+            BinaryOperator::Plus
+                | BinaryOperator::Minus
+                | BinaryOperator::Multiply
+                | BinaryOperator::Divide
+                | BinaryOperator::Modulo
+        ) {
+            return Err(QueryError::Unimplemented(format!(
+                "Unsupported operator {} in {}",
+                op, expr
+            )));
+        }
+        let zero = matches!(right, Integer(0)) || matches!(right, Float(x) if x == 0.0);
+        if zero && matches!(op, BinaryOperator::Divide | BinaryOperator::Modulo) {
+            return Err(QueryError::Invalid(format!("Division by zero in {}", expr)));
+        }
+
+        match (left, right) {
+            (Integer(a), Integer(b)) => {
+                let result = match op {
+                    BinaryOperator::Plus => a.checked_add(b),
+                    BinaryOperator::Minus => a.checked_sub(b),
+                    BinaryOperator::Multiply => a.checked_mul(b),
+                    BinaryOperator::Divide => a.checked_div(b),
+                    _ => a.checked_rem(b),
+                };
+                result.map(Integer).ok_or_else(|| {
+                    QueryError::TypeMismatch(format!("Integer overflow in {}", expr))
+                })
+            }
+            (a @ (Integer(_) | Float(_)), b @ (Integer(_) | Float(_))) => {
+                let as_float = |value| match value {
+                    Integer(i) => i as f64,
+                    Float(x) => x,
+                    _ => unreachable!(),
+                };
+                let (a, b) = (as_float(a), as_float(b));
+                Ok(Float(match op {
+                    BinaryOperator::Plus => a + b,
+                    BinaryOperator::Minus => a - b,
+                    BinaryOperator::Multiply => a * b,
+                    BinaryOperator::Divide => a / b,
+                    _ => a % b,
+                }))
+            }
+            (a, b) => Err(QueryError::TypeMismatch(format!(
+                "{} applies to numbers, not {} and {}",
+                op, a, b
+            ))),
+        }
+    }
+
+    /// Reads the table's documents in primary key order, keeping those that pass
+    /// `filter`, and stops reading pages once `limit` of them are kept
+    fn scan_documents(
+        &mut self,
+        table: &str,
+        filter: Option<&Expr>,
+        limit: Option<usize>,
+    ) -> Result<Vec<AuraDocument>, QueryError> {
+        // Collect the page ids first so we don't hold a borrow on the index while reading
+        let page_ids = self.table_page_ids(table);
+        let limit = limit.unwrap_or(usize::MAX);
+
+        let mut docs = Vec::with_capacity(page_ids.len().min(limit));
+        for page_id in page_ids {
+            if docs.len() >= limit {
+                break;
+            }
+            let doc = self.read_document(page_id)?;
+            if filter.map_or(Ok(true), |expr| self.matches(&doc, expr))? {
+                docs.push(doc);
+            }
+        }
+        Ok(docs)
+    }
+
+    /// Data pages of every document in `table`, in primary key order
+    fn table_page_ids(&self, table: &str) -> Vec<u32> {
+        let prefix = Self::index_key(table, "");
+        self.pager
+            .index
+            .map
+            .range(prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .map(|(_, &page_id)| page_id)
+            // Page 0 is the Index Page, never a document
+            .filter(|&page_id| page_id != 0)
+            .collect()
+    }
+
+    fn read_document(&mut self, page_id: u32) -> Result<AuraDocument, QueryError> {
+        self.rows_examined.set(self.rows_examined.get() + 1);
+        let (page_type, stored_bytes) = self.pager.read_chain(page_id)?;
+        if page_type != 1 {
+            return Err(StoreError::Malformed {
+                page_id,
+                reason: format!("not a data page (type {})", page_type),
+            }
+            .into());
+        }
+
+        AuraDocument::from_bytes(&stored_bytes).map_err(|e| {
+            StoreError::Malformed {
+                page_id,
+                reason: format!("not a document ({})", e),
+            }
+            .into()
+        })
+    }
+
+    /// Every field across `docs`: `id` first, the rest alphabetically
+    fn all_columns(docs: &[AuraDocument]) -> Vec<String> {
+        let fields: BTreeSet<&String> = docs
+            .iter()
+            .flat_map(|doc| doc.data.keys())
+            .filter(|name| *name != "id")
+            .collect();
+        std::iter::once("id".to_string())
+            .chain(fields.into_iter().cloned())
+            .collect()
+    }
+
+    /// Lays documents out as a result set with the given columns.
+    /// Fields a document doesn't have come back as NULL.
+    fn to_rows(docs: &[AuraDocument], columns: &[String]) -> QueryResult {
+        let rows = docs
+            .iter()
+            .map(|doc| columns.iter().map(|col| Self::field(doc, col)).collect())
+            .collect();
+
+        QueryResult::rows(columns.to_vec(), rows)
+    }
+
+    /// A document's value for `col` (NULL if it doesn't have that field).
+    /// A dotted name that isn't a field itself is a path into nested values:
+    /// "profile.city" is the city key of the profile object, "tags.0" an array element.
+    fn field(doc: &AuraDocument, col: &str) -> DataValue {
+        match doc.data.get(col) {
+            Some(value) => value.clone(),
+            // Auto-generated ids live only on the document itself
+            None if col == "id" => DataValue::Text(doc.id.clone()),
+            None if col == VERSION_COLUMN => DataValue::Integer(doc.version as i64),
+            None => Self::path_value(doc, col)
+                .cloned()
+                .unwrap_or(DataValue::Null),
+        }
+    }
+
+    fn path_value<'d>(doc: &'d AuraDocument, path: &str) -> Option<&'d DataValue> {
+        let mut parts = path.split('.');
+        let mut value = doc.data.get(parts.next()?)?;
+        for key in parts {
+            value = match value {
+                DataValue::Object(map) => map.get(key)?,
+                DataValue::Array(items) => items.get(key.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+
+    /// Columns every document has without storing them as fields
+    fn is_pseudo_column(col: &str) -> bool {
+        col == "id" || col == VERSION_COLUMN
+    }
+
+    // --- HELPERS: AST Extraction ---
+
+    /// Index keys are namespaced by table so that scans only see their own documents.
+    fn index_key(table: &str, id: &str) -> String {
+        format!("{}/{}", table, id)
+    }
+
+    /// The document id an `id` value is stored under. Integers are tagged "i:5",
+    /// so Integer 5 and Text '5' are different documents; Text is stored as is,
+    /// unless it could be mistaken for a tagged id ("i:5" becomes "s:i:5").
+    fn document_key(value: &DataValue) -> Option<String> {
+        match value {
+            DataValue::Integer(i) => Some(format!("i:{}", i)),
+            DataValue::Text(s) if s.starts_with("i:") || s.starts_with("s:") => {
+                Some(format!("s:{}", s))
+            }
+            DataValue::Text(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    /// What a secondary index files a value under: Text as is, numbers in decimal
+    /// (2 and 2.0 both as "2", since they compare equal). Other values aren't indexed.
+    fn index_value(value: &DataValue) -> Option<String> {
+        match value {
+            DataValue::Text(s) => Some(s.clone()),
+            DataValue::Integer(i) => Some(i.to_string()),
+            // -0.0 = 0
+            DataValue::Float(f) if *f == 0.0 => Some("0".to_string()),
+            DataValue::Float(f) => Some(f.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Secondary index keys are "<value>\0<doc id>" so equal values stay distinct
+    fn secondary_key(value: &str, doc_id: &str) -> String {
+        format!("{}\0{}", value, doc_id)
+    }
+
+    /// Every table a FROM clause reads, joined ones included
+    fn tables_from(from: &[TableWithJoins]) -> Vec<String> {
+        from.iter()
+            .flat_map(|t| std::iter::once(&t.relation).chain(t.joins.iter().map(|j| &j.relation)))
+            .filter_map(|relation| match relation {
+                TableFactor::Table { name, .. } => Some(name.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Every name the tables of a FROM clause go by: their names and their aliases
+    fn table_names(from: &[TableWithJoins]) -> Vec<String> {
+        from.iter()
+            .flat_map(|t| std::iter::once(&t.relation).chain(t.joins.iter().map(|j| &j.relation)))
+            .filter_map(|relation| match relation {
+                TableFactor::Table { name, alias, .. } => Some(
+                    std::iter::once(name.to_string())
+                        .chain(alias.as_ref().map(|alias| alias.name.value.clone())),
+                ),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// The JOIN of a FROM clause, if it has one. Only a single inner join whose
+    /// ON clause equates a column of each table is supported.
+    fn join_spec(from: &[TableWithJoins]) -> Result<Option<JoinSpec>, QueryError> {
+        let [from] = from else {
+            return Err(QueryError::Unimplemented(
+                "Tables can only be combined with JOIN ... ON".into(),
+            ));
+        };
+        let join = match from.joins.as_slice() {
+            [] => return Ok(None),
+            [join] => join,
+            _ => {
+                return Err(QueryError::Unimplemented(
+                    "Only one JOIN per SELECT is supported".into(),
+                ))
+            }
+        };
+        let JoinOperator::Inner(JoinConstraint::On(on)) = &join.join_operator else {
+            return Err(QueryError::Unimplemented(
+                "Only INNER JOIN ... ON is supported".into(),
+            ));
+        };
+
+        let relation = |relation: &TableFactor| match relation {
+            TableFactor::Table { name, alias, .. } => Ok((
+                name.to_string(),
+                alias
+                    .as_ref()
+                    .map_or_else(|| name.to_string(), |alias| alias.name.value.clone()),
+            )),
+            _ => Err(QueryError::Unimplemented(
+                "JOIN only combines tables".into(),
+            )),
+        };
+        let (left_table, left_qualifier) = relation(&from.relation)?;
+        let (right_table, right_qualifier) = relation(&join.relation)?;
+        if left_qualifier == right_qualifier {
+            return Err(QueryError::Invalid(format!(
+                "Both sides of the JOIN are called {}; give one an alias",
+                left_qualifier
+            )));
+        }
+
+        let unsupported = || {
+            QueryError::Unimplemented(format!(
+                "JOIN ... ON must equate a column of each table, got {}",
+                on
+            ))
+        };
+        let Expr::BinaryOp {
+            left,
+            op: BinaryOperator::Eq,
+            right,
+        } = on
+        else {
+            return Err(unsupported());
+        };
+        let column = |expr: &Expr| match expr {
+            Expr::CompoundIdentifier(parts) if parts.len() == 2 => {
+                Some((parts[0].value.clone(), parts[1].value.clone()))
+            }
+            _ => None,
+        };
+        let (Some(a), Some(b)) = (column(left), column(right)) else {
+            return Err(unsupported());
+        };
+        let (left_column, right_column) = if a.0 == left_qualifier && b.0 == right_qualifier {
+            (a.1, b.1)
+        } else if a.0 == right_qualifier && b.0 == left_qualifier {
+            (b.1, a.1)
+        } else {
+            return Err(unsupported());
+        };
+
+        Ok(Some(JoinSpec {
+            left: JoinSide {
+                table: left_table,
+                qualifier: left_qualifier,
+                column: left_column,
+            },
+            right: JoinSide {
+                table: right_table,
+                qualifier: right_qualifier,
+                column: right_column,
+            },
+        }))
+    }
+
+    fn table_from(from: &[TableWithJoins]) -> Result<String, QueryError> {
+        match from.first().map(|t| &t.relation) {
+            Some(TableFactor::Table { name, .. }) => Ok(name.to_string()),
+            _ => Err(QueryError::Unimplemented(
+                "SELECT requires a FROM <table> clause".into(),
+            )),
+        }
+    }
+
+    /// Matches `id = 'x'` (or `'x' = id`, or `id = $1`) and `id IN ('a', 'b', ...)`,
+    /// returning the ids to look up (duplicates removed, in the order written).
+    fn id_filter(&self, expr: &Expr) -> Result<Option<Vec<String>>, QueryError> {
+        let candidates: Vec<&Expr> = match expr {
+            Expr::Nested(inner) => return self.id_filter(inner),
+            Expr::BinaryOp {
+                left,
+                op: BinaryOperator::Eq,
+                right,
+            } => match (&**left, &**right) {
+                (Expr::Identifier(col), value) | (value, Expr::Identifier(col))
+                    if col.value == "id" =>
+                {
+                    vec![value]
+                }
+                _ => return Ok(None),
+            },
+            Expr::InList {
+                expr,
+                list,
+                negated: false,
+            } if matches!(&**expr, Expr::Identifier(col) if col.value == "id") => {
+                list.iter().collect()
+            }
+            _ => return Ok(None),
+        };
+
+        let mut ids: Vec<String> = Vec::with_capacity(candidates.len());
+        for candidate in candidates {
+            if !Self::is_literal(candidate) {
+                return Ok(None);
+            }
+            let Some(id) = Self::document_key(&self.literal(candidate)?) else {
+                return Ok(None);
+            };
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        Ok(Some(ids))
+    }
+
+    /// Map SQL Value -> Aura DataValue (placeholders resolve to bound parameters)
+    fn literal(&self, expr: &Expr) -> Result<DataValue, QueryError> {
+        Ok(match expr {
+            Expr::Value(Value::Number(n, _)) => match n.parse::<i64>() {
+                Ok(i) => DataValue::Integer(i),
+                Err(_) => DataValue::Float(n.parse().map_err(|_| {
+                    QueryError::TypeMismatch(format!("Invalid number literal: {}", n))
+                })?),
+            },
+            Expr::Value(
+                Value::SingleQuotedString(s)
+                | Value::EscapedStringLiteral(s)
+                | Value::NationalStringLiteral(s)
+                | Value::RawStringLiteral(s),
+            ) => DataValue::Text(s.clone()),
+            Expr::Value(Value::DollarQuotedString(s)) => DataValue::Text(s.value.clone()),
+            Expr::Value(Value::Null) => DataValue::Null,
+            Expr::Value(Value::Boolean(b)) => DataValue::Boolean(*b),
+            Expr::Value(Value::HexStringLiteral(hex)) => {
+                DataValue::Binary(Self::decode_hex(hex).ok_or_else(|| {
+                    QueryError::TypeMismatch(format!("Invalid hex literal: X'{}'", hex))
+                })?)
+            }
+            // A ciphertext made by the client, stored as-is for FHE_SUM
+            Expr::Function(f) if Self::encrypted_arg(f).is_some() => {
+                let arg = Self::encrypted_arg(f).expect("checked by the guard");
+                match self.literal(arg)? {
+                    DataValue::Binary(bytes) | DataValue::Encrypted(bytes) => {
+                        DataValue::Encrypted(bytes)
+                    }
+                    DataValue::Null => DataValue::Null,
+                    other => {
+                        return Err(QueryError::TypeMismatch(format!(
+                            "ENCRYPTED() takes the ciphertext's bytes (X'...'), not {}",
+                            other
+                        )))
+                    }
+                }
+            }
+            Expr::TypedString {
+                data_type: DataType::Timestamp(_, _) | DataType::Datetime(_) | DataType::Date,
+                value,
+            } => DataValue::parse_timestamp(value).ok_or_else(|| {
+                QueryError::TypeMismatch(format!("Invalid timestamp literal: '{}'", value))
+            })?,
+            // Evaluated when the statement runs
+            Expr::Function(f) if Self::is_now(f) => DataValue::now(),
+            Expr::Value(Value::Placeholder(p)) => {
+                let index = p
+                    .strip_prefix('$')
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .filter(|&n| n >= 1 && n <= self.params.len())
+                    .ok_or_else(|| QueryError::Bind(format!("Unbound parameter {}", p)))?;
+                self.params[index - 1].clone()
+            }
+            // Byte strings, unquoted strings and anything that isn't a value at all
+            other => {
+                return Err(QueryError::Unimplemented(format!(
+                    "Unsupported value: {}",
+                    other
+                )))
+            }
+        })
+    }
+
+    /// Whether `literal` takes `expr` (a constant, a parameter or NOW())
+    fn is_literal(expr: &Expr) -> bool {
+        match expr {
+            Expr::Value(_) | Expr::TypedString { .. } => true,
+            Expr::Function(f) => Self::is_now(f) || Self::encrypted_arg(f).is_some(),
+            _ => false,
+        }
+    }
+
+    fn is_now(f: &Function) -> bool {
+        f.name.to_string().eq_ignore_ascii_case("NOW") && f.args.is_empty()
+    }
+
+    /// The argument of `ENCRYPTED(bytes)`, if `f` is that
+    fn encrypted_arg(f: &Function) -> Option<&Expr> {
+        match f.args.as_slice() {
+            [FunctionArg::Unnamed(FunctionArgExpr::Expr(arg))]
+                if f.name.to_string().eq_ignore_ascii_case("ENCRYPTED") =>
+            {
+                Some(arg)
+            }
+            _ => None,
+        }
+    }
+
+    /// The bytes of an `X'...'` literal: two hex digits each
+    fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+        if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+            .collect()
+    }
+
+    /// Rewrites positional `?` placeholders into numbered `$n` ones (in textual order)
+    /// and returns how many parameters the statement expects.
+    fn number_placeholders(sql: &str) -> Result<(String, usize), QueryError> {
+        let tokens = Tokenizer::new(&GenericDialect {}, sql)
+            .tokenize_with_location()
+            .map_err(|e| ParserError::TokenizerError(e.to_string()))?;
+
+        // Token locations are (line, column) in chars, both 1-based
+        let line_starts: Vec<usize> = std::iter::once(0)
+            .chain(sql.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        let mut rewritten = String::with_capacity(sql.len());
+        let mut copied_up_to = 0;
+        let mut positional = 0;
+        let mut highest_numbered = 0;
+
+        for token in tokens {
+            let Token::Placeholder(placeholder) = &token.token else {
+                continue;
+            };
+
+            if placeholder == "?" {
+                positional += 1;
+                let line_start = line_starts[token.location.line as usize - 1];
+                let offset = sql[line_start..]
+                    .char_indices()
+                    .nth(token.location.column as usize - 1)
+                    .map(|(i, _)| line_start + i)
+                    .unwrap_or(sql.len());
+
+                rewritten.push_str(&sql[copied_up_to..offset]);
+                rewritten.push_str(&format!("${}", positional));
+                copied_up_to = offset + 1;
+            } else if let Some(n) = placeholder
+                .strip_prefix('$')
+                .and_then(|n| n.parse::<usize>().ok())
+            {
+                highest_numbered = highest_numbered.max(n);
+            } else {
+                return Err(QueryError::Bind(format!(
+                    "Unsupported placeholder: {}",
+                    placeholder
+                )));
+            }
+        }
+
+        if positional > 0 && highest_numbered > 0 {
+            return Err(QueryError::Bind(
+                "Cannot mix ? and $n placeholders in one statement".into(),
+            ));
+        }
+
+        rewritten.push_str(&sql[copied_up_to..]);
+        Ok((rewritten, positional.max(highest_numbered)))
     }
 }
@@ -1,4 +1,6 @@
+pub mod catalog;
 pub mod executor;
+pub mod temp;
 pub mod tests;
 
 use thiserror::Error;
@@ -13,4 +15,46 @@ pub enum QueryError {
     Store(#[from] aura_store::StoreError),
     #[error("Serialization Error: {0}")]
     Serialization(String),
+    #[error("Bind Error: {0}")]
+    Bind(String),
+    #[error("Unknown Column: {0}")]
+    UnknownColumn(String),
+    /// A bare column name that more than one table of the query has,
+    /// e.g. "name could be u.name or o.name"
+    #[error("Ambiguous Column: {0}")]
+    AmbiguousColumn(String),
+    #[error("Type Mismatch: {0}")]
+    TypeMismatch(String),
+    #[error("Table Already Exists: {0}")]
+    TableExists(String),
+    #[error("Invalid Statement: {0}")]
+    Invalid(String),
+    #[error("Duplicate Key: {0}")]
+    DuplicateKey(String),
+    /// The logged-in user lacks a grant, e.g. "alice has no INSERT privilege on users"
+    #[error("Access Denied: {0}")]
+    AccessDenied(String),
+    /// A value breaks a column constraint, e.g. ("users.email", "UNIQUE")
+    #[error("Constraint Violation: {column} is {constraint}")]
+    ConstraintViolation {
+        column: String,
+        constraint: &'static str,
+    },
+    /// A Text or Binary value longer than `ValueLimits` allows, in bytes
+    #[error("Value Too Long: {column} is {len} bytes, the limit is {max}")]
+    ValueTooLong {
+        column: String,
+        len: usize,
+        max: usize,
+    },
+    /// The statement was stopped before it finished (see `QueryEngine::with_cancel`)
+    #[error("Statement Cancelled")]
+    Cancelled,
+    /// A statement of a multi-statement request failed (`index` counts from 1).
+    /// The statements before it were applied; the ones after it were not run.
+    #[error("Statement {index} failed: {source}")]
+    Statement {
+        index: usize,
+        source: Box<QueryError>,
+    },
 }
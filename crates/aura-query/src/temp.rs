@@ -0,0 +1,64 @@
+// Connection-scoped temporary tables (CREATE TEMP TABLE).
+// They live in memory only and belong to whoever owns the `TempTables` (the server
+// keeps one per connection): nothing reaches the pager, so no other connection can
+// see them, and they are gone as soon as the owner drops them.
+use aura_common::{AuraDocument, TableSchema};
+use std::collections::{BTreeMap, HashMap};
+
+pub(crate) struct TempTable {
+    pub schema: TableSchema,
+    /// Documents by id (so scans come back in primary key order, like real tables)
+    pub docs: BTreeMap<String, AuraDocument>,
+}
+
+#[derive(Default)]
+pub struct TempTables {
+    tables: HashMap<String, TempTable>,
+}
+
+impl TempTables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contains(&self, table: &str) -> bool {
+        self.tables.contains_key(table)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    pub(crate) fn get(&self, table: &str) -> Option<&TempTable> {
+        self.tables.get(table)
+    }
+
+    pub(crate) fn get_mut(&mut self, table: &str) -> Option<&mut TempTable> {
+        self.tables.get_mut(table)
+    }
+
+    /// Names of the temporary tables, sorted
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.tables.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub(crate) fn drop(&mut self, table: &str) -> bool {
+        self.tables.remove(table).is_some()
+    }
+
+    pub(crate) fn create(&mut self, schema: TableSchema) {
+        self.tables.insert(
+            schema.name.clone(),
+            TempTable {
+                schema,
+                docs: BTreeMap::new(),
+            },
+        );
+    }
+}
@@ -1,6 +1,12 @@
 use crate::CryptoError;
-use tfhe::{ClientKey, ConfigBuilder, FheUint32, ServerKey};
+use tfhe::prelude::*;
+use tfhe::{ClientKey, ConfigBuilder, FheUint32};
 
+// Callers hold and pass the key around without depending on tfhe themselves
+pub use tfhe::ServerKey;
+
+/// Note: tfhe gives no way to wipe a ClientKey, so unlike the symmetric and
+/// Kyber keys it is freed without being zeroed.
 pub struct FheContext {
     pub client_key: ClientKey, // Held ONLY by the Client
     server_key: ServerKey,     // Held by the Database Engine
@@ -20,6 +26,19 @@ impl FheContext {
     pub fn get_server_key(&self) -> ServerKey {
         self.server_key.clone()
     }
+
+    /// Encrypts a number into the blob stored in an ENCRYPTED column
+    pub fn encrypt_u32(&self, value: u32) -> Result<Vec<u8>, CryptoError> {
+        let encrypted = FheUint32::encrypt(value, &self.client_key);
+        bincode::serialize(&encrypted).map_err(|_| CryptoError::SerializationFailed)
+    }
+
+    /// Reverses `encrypt_u32` (only possible with the client key)
+    pub fn decrypt_u32(&self, bytes: &[u8]) -> Result<u32, CryptoError> {
+        let encrypted: FheUint32 =
+            bincode::deserialize(bytes).map_err(|_| CryptoError::DecryptionFailed)?;
+        Ok(encrypted.decrypt(&self.client_key))
+    }
 }
 
 impl Default for FheContext {
@@ -28,11 +47,20 @@ impl Default for FheContext {
     }
 }
 
-/// Operations the Database can perform BLINDLY
+/// Operations the Database can perform BLINDLY.
+/// Cloning is cheap: tfhe shares the server key between clones.
+#[derive(Clone)]
 pub struct FheComputer {
     server_key: ServerKey,
 }
 
+/// Never prints the key itself
+impl std::fmt::Debug for FheComputer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("FheComputer(..)")
+    }
+}
+
 impl FheComputer {
     pub fn new(server_key: ServerKey) -> Self {
         Self { server_key }
@@ -40,18 +68,59 @@ impl FheComputer {
 
     /// Adds two encrypted integers without decrypting them
     pub fn sum_encrypted(&self, a_bytes: &[u8], b_bytes: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        self.compute(a_bytes, b_bytes, |a, b| a + b)
+    }
+
+    /// Subtracts `b` from `a` without decrypting them. Like the u32 it encrypts,
+    /// the result wraps around below zero.
+    pub fn sub_encrypted(&self, a_bytes: &[u8], b_bytes: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        self.compute(a_bytes, b_bytes, |a, b| a - b)
+    }
+
+    /// Multiplies two encrypted integers without decrypting them (wrapping on overflow).
+    /// Much slower than addition: expect roughly an order of magnitude more CPU
+    /// time per operation, so budget for it in weighted sums.
+    pub fn mul_encrypted(&self, a_bytes: &[u8], b_bytes: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        self.compute(a_bytes, b_bytes, |a, b| a * b)
+    }
+
+    /// Adds a public constant to an encrypted integer (wrapping on overflow).
+    /// Cheaper than `sum_encrypted`, and the constant never needs encrypting.
+    pub fn add_scalar(&self, a_bytes: &[u8], scalar: u32) -> Result<Vec<u8>, CryptoError> {
+        let a = Self::ciphertext(a_bytes)?;
+        self.finish(|| &a + scalar)
+    }
+
+    /// Multiplies an encrypted integer by a public constant (wrapping on overflow),
+    /// e.g. to apply a rate. Far cheaper than `mul_encrypted`.
+    pub fn mul_scalar(&self, a_bytes: &[u8], scalar: u32) -> Result<Vec<u8>, CryptoError> {
+        let a = Self::ciphertext(a_bytes)?;
+        self.finish(|| &a * scalar)
+    }
+
+    fn compute(
+        &self,
+        a_bytes: &[u8],
+        b_bytes: &[u8],
+        op: impl FnOnce(&FheUint32, &FheUint32) -> FheUint32,
+    ) -> Result<Vec<u8>, CryptoError> {
         // 1. Deserialize the encrypted blobs
-        let a: FheUint32 =
-            bincode::deserialize(a_bytes).map_err(|_| CryptoError::DecryptionFailed)?;
-        let b: FheUint32 =
-            bincode::deserialize(b_bytes).map_err(|_| CryptoError::DecryptionFailed)?;
+        let a = Self::ciphertext(a_bytes)?;
+        let b = Self::ciphertext(b_bytes)?;
+        self.finish(|| op(&a, &b))
+    }
+
+    fn ciphertext(bytes: &[u8]) -> Result<FheUint32, CryptoError> {
+        bincode::deserialize(bytes).map_err(|_| CryptoError::DecryptionFailed)
+    }
 
+    fn finish(&self, op: impl FnOnce() -> FheUint32) -> Result<Vec<u8>, CryptoError> {
         // 2. Perform the Math (CPU Intensive!)
         // Notice: We define the server_key context to perform the operation
         tfhe::set_server_key(self.server_key.clone());
-        let result = &a + &b;
+        let result = op();
 
         // 3. Serialize the encrypted result back to bytes
-        bincode::serialize(&result).map_err(|_| CryptoError::DecryptionFailed)
+        bincode::serialize(&result).map_err(|_| CryptoError::SerializationFailed)
     }
 }
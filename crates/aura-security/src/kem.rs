@@ -1,6 +1,79 @@
 use crate::CryptoError;
+use hkdf::Hkdf;
 use pqcrypto_kyber::kyber1024; // Highest security level
-use pqcrypto_traits::kem::{Ciphertext, PublicKey, SharedSecret};
+use pqcrypto_traits::kem::{Ciphertext, PublicKey, SecretKey, SharedSecret};
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey as X25519PublicKey};
+use zeroize::Zeroize;
+
+pub const KYBER_PUBLIC_KEY_BYTES: usize = 1568;
+pub const KYBER_CIPHERTEXT_BYTES: usize = 1568;
+pub const X25519_KEY_BYTES: usize = 32;
+
+/// HKDF `info` for the hybrid session key (changing it changes every key)
+const HYBRID_KDF_INFO: &[u8] = b"aura handshake v1: x25519+kyber1024";
+
+/// HKDF `info` prefix of `derive_session_key`; the caller's context follows it
+const SESSION_KDF_LABEL: &[u8] = b"aura session key v1: ";
+
+/// The key exchanges a handshake can use. The discriminant is the id sent on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KemAlgorithm {
+    /// Kyber-1024 alone
+    Kyber1024 = 1,
+    /// X25519 and Kyber-1024 together: the session stays safe unless both are broken
+    HybridX25519Kyber1024 = 2,
+}
+
+impl KemAlgorithm {
+    /// Every algorithm we implement, most preferred first
+    pub const ALL: [KemAlgorithm; 2] = [Self::HybridX25519Kyber1024, Self::Kyber1024];
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|alg| alg.id() == id)
+    }
+
+    /// Config name: "kyber" or "hybrid"
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Kyber1024 => "kyber",
+            Self::HybridX25519Kyber1024 => "hybrid",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|alg| alg.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Bytes the server sends as its public key
+    pub fn public_key_len(self) -> usize {
+        match self {
+            Self::Kyber1024 => KYBER_PUBLIC_KEY_BYTES,
+            Self::HybridX25519Kyber1024 => KYBER_PUBLIC_KEY_BYTES + X25519_KEY_BYTES,
+        }
+    }
+
+    /// Bytes the client answers with
+    pub fn ciphertext_len(self) -> usize {
+        match self {
+            Self::Kyber1024 => KYBER_CIPHERTEXT_BYTES,
+            Self::HybridX25519Kyber1024 => KYBER_CIPHERTEXT_BYTES + X25519_KEY_BYTES,
+        }
+    }
+}
+
+/// Picks the first algorithm of `offered` (the server's list, in its order of
+/// preference) that we also support. None means the peers have nothing in common.
+pub fn negotiate(offered: &[KemAlgorithm], supported: &[KemAlgorithm]) -> Option<KemAlgorithm> {
+    offered.iter().copied().find(|alg| supported.contains(alg))
+}
 
 /// A wrapper for the User's Identity Keypair
 pub struct PQCKeyPair {
@@ -16,6 +89,18 @@ impl PQCKeyPair {
     }
 }
 
+impl Drop for PQCKeyPair {
+    /// The Kyber secret key can't wipe itself, so it is overwritten with an
+    /// all-zero key (volatile, so the write isn't optimized away as dead)
+    fn drop(&mut self) {
+        let zeroed = vec![0u8; kyber1024::secret_key_bytes()];
+        let zeroed = kyber1024::SecretKey::from_bytes(&zeroed).expect("secret_key_bytes long");
+        // SAFETY: `self.sk` is a valid, aligned place and SecretKey has no Drop to skip
+        unsafe { std::ptr::write_volatile(&mut self.sk, zeroed) };
+        std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
 /// Step 2: Client Encapsulates (Creates the shared secret)
 /// Returns: (The Secret to keep, The Ciphertext to send to Server)
 pub fn encapsulate(pk_bytes: &[u8]) -> Result<(Vec<u8>, Vec<u8>), CryptoError> {
@@ -42,3 +127,156 @@ pub fn decapsulate(
     let shared_secret = kyber1024::decapsulate(&ciphertext, sk);
     Ok(shared_secret.as_bytes().to_vec())
 }
+
+/// Encrypts a message to the holder of a Kyber public key: encapsulates a fresh
+/// shared secret and uses it as the symmetric key.
+/// Output Format: [Ciphertext Length (u32, big-endian) | Kyber Ciphertext | Encrypted Payload]
+pub fn hybrid_encrypt(plaintext: &[u8], recipient_pk_bytes: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let (mut shared_secret, kem_ciphertext) = encapsulate(recipient_pk_bytes)?;
+    let payload = crate::symmetric::encrypt(plaintext, &shared_secret);
+    shared_secret.zeroize();
+
+    let mut blob = Vec::with_capacity(4 + kem_ciphertext.len());
+    blob.extend_from_slice(&(kem_ciphertext.len() as u32).to_be_bytes());
+    blob.extend_from_slice(&kem_ciphertext);
+    blob.extend_from_slice(&payload?);
+    Ok(blob)
+}
+
+/// Reverses `hybrid_encrypt`. A different secret key recovers a different shared
+/// secret, so it fails like a modified ciphertext.
+pub fn hybrid_decrypt(blob: &[u8], sk: &kyber1024::SecretKey) -> Result<Vec<u8>, CryptoError> {
+    let (len, rest) = blob
+        .split_at_checked(4)
+        .ok_or(CryptoError::DecryptionFailed)?;
+    let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+    let (kem_ciphertext, payload) = rest
+        .split_at_checked(len)
+        .ok_or(CryptoError::DecryptionFailed)?;
+
+    let mut shared_secret = decapsulate(kem_ciphertext, sk)?;
+    let plaintext = crate::symmetric::decrypt(payload, &shared_secret);
+    shared_secret.zeroize();
+    plaintext
+}
+
+/// The server's ephemeral keys for a hybrid handshake
+pub struct HybridKeyPair {
+    pub kyber: PQCKeyPair,
+    x25519: EphemeralSecret,
+    pub x25519_pk: X25519PublicKey,
+}
+
+impl HybridKeyPair {
+    pub fn generate() -> Self {
+        let x25519 = EphemeralSecret::random_from_rng(OsRng);
+        let x25519_pk = X25519PublicKey::from(&x25519);
+        Self {
+            kyber: PQCKeyPair::generate(),
+            x25519,
+            x25519_pk,
+        }
+    }
+
+    /// What goes on the wire: the Kyber public key, then the X25519 one
+    pub fn public_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.kyber.pk.as_bytes().to_vec();
+        bytes.extend_from_slice(self.x25519_pk.as_bytes());
+        bytes
+    }
+
+    /// Recovers the session key from the client's answer (see `hybrid_encapsulate`).
+    /// Consumes the keys: the X25519 secret can only be used once.
+    pub fn decapsulate(self, ciphertext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        if ciphertext.len() != KYBER_CIPHERTEXT_BYTES + X25519_KEY_BYTES {
+            return Err(CryptoError::KemFailed);
+        }
+        let (kyber_ct, client_pk) = ciphertext.split_at(KYBER_CIPHERTEXT_BYTES);
+
+        let client_pk: [u8; X25519_KEY_BYTES] =
+            client_pk.try_into().map_err(|_| CryptoError::KemFailed)?;
+        let mut kyber_secret = decapsulate(kyber_ct, &self.kyber.sk)?;
+        let x25519_secret = self
+            .x25519
+            .diffie_hellman(&X25519PublicKey::from(client_pk));
+        if !x25519_secret.was_contributory() {
+            kyber_secret.zeroize();
+            return Err(CryptoError::KemFailed);
+        }
+
+        let session_key = hybrid_session_key(x25519_secret.as_bytes(), &kyber_secret);
+        kyber_secret.zeroize();
+        session_key
+    }
+}
+
+/// Client side of the hybrid handshake.
+/// Returns: (The session key, The Kyber ciphertext followed by our X25519 public key)
+pub fn hybrid_encapsulate(pk_bytes: &[u8]) -> Result<(Vec<u8>, Vec<u8>), CryptoError> {
+    if pk_bytes.len() != KYBER_PUBLIC_KEY_BYTES + X25519_KEY_BYTES {
+        return Err(CryptoError::KemFailed);
+    }
+    let (kyber_pk, server_pk) = pk_bytes.split_at(KYBER_PUBLIC_KEY_BYTES);
+
+    let server_pk: [u8; X25519_KEY_BYTES] =
+        server_pk.try_into().map_err(|_| CryptoError::KemFailed)?;
+    let (mut kyber_secret, mut ciphertext) = encapsulate(kyber_pk)?;
+    let x25519 = EphemeralSecret::random_from_rng(OsRng);
+    ciphertext.extend_from_slice(X25519PublicKey::from(&x25519).as_bytes());
+    let x25519_secret = x25519.diffie_hellman(&X25519PublicKey::from(server_pk));
+    if !x25519_secret.was_contributory() {
+        kyber_secret.zeroize();
+        return Err(CryptoError::KemFailed);
+    }
+
+    let session_key = hybrid_session_key(x25519_secret.as_bytes(), &kyber_secret);
+    kyber_secret.zeroize();
+    Ok((session_key?, ciphertext))
+}
+
+/// What the session key is bound to: the server's hello ([version][count][ids...],
+/// as sent), the algorithm the client chose, the public key(s) and the ciphertext.
+/// A peer that saw a different offer (say, one stripped down to pure Kyber) ends up
+/// with a different key.
+pub fn handshake_transcript(
+    hello: &[u8],
+    algorithm: KemAlgorithm,
+    public_key: &[u8],
+    ciphertext: &[u8],
+) -> Vec<u8> {
+    let mut transcript = Vec::with_capacity(hello.len() + 1 + public_key.len() + ciphertext.len());
+    transcript.extend_from_slice(hello);
+    transcript.push(algorithm.id());
+    transcript.extend_from_slice(public_key);
+    transcript.extend_from_slice(ciphertext);
+    transcript
+}
+
+/// HKDF-SHA256 over both shared secrets (classical first), so the key is only
+/// exposed if both exchanges are
+fn hybrid_session_key(x25519_secret: &[u8], kyber_secret: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let mut ikm = Vec::with_capacity(x25519_secret.len() + kyber_secret.len());
+    ikm.extend_from_slice(x25519_secret);
+    ikm.extend_from_slice(kyber_secret);
+
+    let mut key = vec![0u8; crate::symmetric::KEY_SIZE];
+    let expanded = Hkdf::<Sha256>::new(None, &ikm).expand(HYBRID_KDF_INFO, &mut key);
+    ikm.zeroize();
+    expanded.map_err(|_| CryptoError::KemFailed)?;
+    Ok(key)
+}
+
+/// Turns a key exchange's shared secret into a symmetric key (HKDF-SHA256) instead
+/// of using it raw. `context` says what the key is for: the handshake passes its
+/// transcript (see `handshake_transcript`), so the key
+/// is bound to that exchange and a secret reused elsewhere yields a different key.
+pub fn derive_session_key(
+    shared_secret: &[u8],
+    context: &[u8],
+) -> [u8; crate::symmetric::KEY_SIZE] {
+    let mut key = [0u8; crate::symmetric::KEY_SIZE];
+    Hkdf::<Sha256>::new(None, shared_secret)
+        .expand_multi_info(&[SESSION_KDF_LABEL, context], &mut key)
+        .expect("KEY_SIZE is a valid HKDF-SHA256 output length");
+    key
+}
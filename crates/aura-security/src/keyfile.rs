@@ -0,0 +1,59 @@
+// Passphrase protection for the database master key when it is kept in a file.
+// Format: [Magic "AURAKEY1"][Salt (16 bytes)][Nonce | Sealed key | Tag]
+// The wrapping key is Argon2id(passphrase, salt); the master key is sealed with
+// the same XChaCha20-Poly1305 cipher the pages use.
+use crate::symmetric::{self, MasterKey, KEY_SIZE};
+use crate::CryptoError;
+use argon2::Argon2;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use zeroize::Zeroize;
+
+pub const MAGIC: &[u8; 8] = b"AURAKEY1";
+pub const SALT_SIZE: usize = 16;
+
+/// Whether `bytes` look like the output of `seal` (as opposed to a bare key)
+pub fn is_sealed(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Encrypts the master key under a passphrase, with a fresh random salt
+pub fn seal(key: &MasterKey, passphrase: &str) -> Result<Vec<u8>, CryptoError> {
+    let mut salt = [0u8; SALT_SIZE];
+    OsRng.fill_bytes(&mut salt);
+
+    let mut wrapping_key = derive_key(passphrase, &salt)?;
+    let sealed = symmetric::encrypt(key.as_bytes(), &wrapping_key);
+    wrapping_key.zeroize();
+
+    let mut result = Vec::with_capacity(MAGIC.len() + SALT_SIZE);
+    result.extend_from_slice(MAGIC);
+    result.extend_from_slice(&salt);
+    result.extend_from_slice(&sealed?);
+    Ok(result)
+}
+
+/// Reverses `seal`. A wrong passphrase or a modified file gives `DecryptionFailed`.
+pub fn open(bytes: &[u8], passphrase: &str) -> Result<MasterKey, CryptoError> {
+    if !is_sealed(bytes) || bytes.len() < MAGIC.len() + SALT_SIZE {
+        return Err(CryptoError::DecryptionFailed);
+    }
+    let (salt, sealed) = bytes[MAGIC.len()..].split_at(SALT_SIZE);
+
+    let mut wrapping_key = derive_key(passphrase, salt)?;
+    let opened = symmetric::decrypt(sealed, &wrapping_key);
+    wrapping_key.zeroize();
+
+    let mut plaintext = opened?;
+    let key = MasterKey::from_slice(&plaintext);
+    plaintext.zeroize();
+    key
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; KEY_SIZE], CryptoError> {
+    let mut key = [0u8; KEY_SIZE];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|_| CryptoError::KeyDerivationFailed)?;
+    Ok(key)
+}
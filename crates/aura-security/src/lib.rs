@@ -1,12 +1,17 @@
 pub mod homomorphic;
 pub mod kem;
+pub mod keyfile;
+pub mod password;
 pub mod sign;
 pub mod symmetric;
 #[cfg(test)]
 pub mod tests;
 
 // Re-export KEM functions for convenience
-pub use kem::{decapsulate, encapsulate, PQCKeyPair};
+pub use kem::{
+    decapsulate, encapsulate, hybrid_decrypt, hybrid_encrypt, negotiate, KemAlgorithm, PQCKeyPair,
+};
+pub use sign::SignKeyPair;
 
 // Re-export common errors
 use thiserror::Error;
@@ -19,4 +24,10 @@ pub enum CryptoError {
     InvalidSignature,
     #[error("Decryption Failed (Tag Mismatch)")]
     DecryptionFailed,
+    #[error("Invalid Key Length: expected 32 bytes, got {0}")]
+    InvalidKeyLength(usize),
+    #[error("Key Derivation Failed")]
+    KeyDerivationFailed,
+    #[error("Serialization Failed")]
+    SerializationFailed,
 }
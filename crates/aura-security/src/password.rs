@@ -0,0 +1,26 @@
+// Login passwords, stored as Argon2id PHC strings:
+// "$argon2id$v=19$m=19456,t=2,p=1$<salt>$<hash>"
+// The salt and cost parameters travel with the hash, so they can be raised
+// later without invalidating existing users.
+use crate::CryptoError;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rand::rngs::OsRng;
+
+/// Hashes a password with a fresh random salt
+pub fn hash(password: &str) -> Result<String, CryptoError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|_| CryptoError::KeyDerivationFailed)
+}
+
+/// Whether `password` is the one `hash` was made from. A malformed hash matches nothing.
+pub fn verify(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
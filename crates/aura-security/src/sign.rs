@@ -1 +1,40 @@
-// Placeholder for post-quantum signatures
+// Post-quantum signatures (Dilithium-5), e.g. for signing responses or documents
+use crate::CryptoError;
+use pqcrypto_dilithium::dilithium5; // Highest security level
+use pqcrypto_traits::sign::{DetachedSignature, PublicKey};
+
+pub const DILITHIUM_PUBLIC_KEY_BYTES: usize = dilithium5::public_key_bytes();
+pub const DILITHIUM_SIGNATURE_BYTES: usize = dilithium5::signature_bytes();
+
+/// A signer's identity: the public key is shared, the secret key never leaves
+pub struct SignKeyPair {
+    pub pk: dilithium5::PublicKey,
+    pub sk: dilithium5::SecretKey,
+}
+
+impl SignKeyPair {
+    pub fn generate() -> Self {
+        let (pk, sk) = dilithium5::keypair();
+        Self { pk, sk }
+    }
+
+    /// The public key as sent to verifiers
+    pub fn public_bytes(&self) -> Vec<u8> {
+        self.pk.as_bytes().to_vec()
+    }
+}
+
+/// Signs `msg`. The signature is detached: send it alongside the message.
+pub fn sign(msg: &[u8], sk: &dilithium5::SecretKey) -> Vec<u8> {
+    dilithium5::detached_sign(msg, sk).as_bytes().to_vec()
+}
+
+/// Checks that `sig` is a signature of `msg` by the owner of `pk_bytes`.
+/// Any mismatch (message, signature or key) is `InvalidSignature`.
+pub fn verify(msg: &[u8], sig: &[u8], pk_bytes: &[u8]) -> Result<(), CryptoError> {
+    let pk =
+        dilithium5::PublicKey::from_bytes(pk_bytes).map_err(|_| CryptoError::InvalidSignature)?;
+    let sig = dilithium5::DetachedSignature::from_bytes(sig)
+        .map_err(|_| CryptoError::InvalidSignature)?;
+    dilithium5::verify_detached_signature(&sig, msg, &pk).map_err(|_| CryptoError::InvalidSignature)
+}
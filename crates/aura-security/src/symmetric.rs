@@ -1,28 +1,160 @@
 use crate::CryptoError;
 use chacha20poly1305::{
-    aead::{Aead, AeadCore, KeyInit, OsRng},
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
     XChaCha20Poly1305, XNonce,
 };
+use std::fmt;
+use zeroize::Zeroize;
 
 pub const KEY_SIZE: usize = 32;
 pub const NONCE_SIZE: usize = 24; // XChaCha uses 24-byte nonces
 pub const TAG_SIZE: usize = 16;
 
+/// The symmetric key of a client session. It is always KEY_SIZE bytes: derived
+/// from the handshake (`derive`), or length-checked once (`from_slice`). Wiped on drop.
+#[derive(Clone)]
+pub struct SessionKey([u8; KEY_SIZE]);
+
+impl SessionKey {
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, CryptoError> {
+        let key: [u8; KEY_SIZE] = bytes
+            .try_into()
+            .map_err(|_| CryptoError::InvalidKeyLength(bytes.len()))?;
+        Ok(Self(key))
+    }
+
+    /// The key for a handshake: derived from its shared secret and `transcript`
+    /// (see `kem::derive_session_key`). Wipes the secret.
+    pub fn derive(mut shared_secret: Vec<u8>, transcript: &[u8]) -> Self {
+        let key = Self(crate::kem::derive_session_key(&shared_secret, transcript));
+        shared_secret.zeroize();
+        key
+    }
+
+    pub fn as_bytes(&self) -> &[u8; KEY_SIZE] {
+        &self.0
+    }
+}
+
+impl TryFrom<Vec<u8>> for SessionKey {
+    type Error = CryptoError;
+
+    /// Takes a shared secret, wiping the vector either way
+    fn try_from(mut secret: Vec<u8>) -> Result<Self, CryptoError> {
+        let key = Self::from_slice(&secret);
+        secret.zeroize();
+        key
+    }
+}
+
+impl Drop for SessionKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// Never prints the key itself
+impl fmt::Debug for SessionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SessionKey(..)")
+    }
+}
+
+/// Which way a transport frame travels over a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    ClientToServer = 1,
+    ServerToClient = 2,
+}
+
+impl Direction {
+    pub fn reverse(self) -> Self {
+        match self {
+            Direction::ClientToServer => Direction::ServerToClient,
+            Direction::ServerToClient => Direction::ClientToServer,
+        }
+    }
+}
+
+/// The associated data a transport frame is sealed with:
+/// [direction | sequence number (u64 BE) | frame type].
+/// Opening needs the same values, so a frame can't be replayed, reordered,
+/// reflected back to its sender or given another type.
+pub fn frame_aad(direction: Direction, seq: u64, frame_type: u8) -> [u8; 10] {
+    let mut aad = [0u8; 10];
+    aad[0] = direction as u8;
+    aad[1..9].copy_from_slice(&seq.to_be_bytes());
+    aad[9] = frame_type;
+    aad
+}
+
+/// A 256-bit key for data at rest, e.g. the database master key. Wiped on drop,
+/// so clone it only when a second owner really needs it.
+/// (`repr(transparent)`: the key bytes are all there is to it.)
+#[derive(Clone)]
+#[repr(transparent)]
+pub struct MasterKey([u8; KEY_SIZE]);
+
+impl MasterKey {
+    /// Takes ownership of the bytes (the caller's copy is the caller's to wipe)
+    pub fn from_bytes(bytes: [u8; KEY_SIZE]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, CryptoError> {
+        let key: [u8; KEY_SIZE] = bytes
+            .try_into()
+            .map_err(|_| CryptoError::InvalidKeyLength(bytes.len()))?;
+        Ok(Self(key))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; KEY_SIZE] {
+        &self.0
+    }
+}
+
+impl Drop for MasterKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// Never prints the key itself
+impl fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("MasterKey(..)")
+    }
+}
+
 /// Generates a random 256-bit encryption key
-pub fn generate_key() -> [u8; KEY_SIZE] {
-    XChaCha20Poly1305::generate_key(&mut OsRng).into()
+pub fn generate_key() -> MasterKey {
+    MasterKey(XChaCha20Poly1305::generate_key(&mut OsRng).into())
+}
+
+/// Random bytes from the OS, e.g. for a salt
+pub fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    rand::RngCore::fill_bytes(&mut rand::rngs::OsRng, &mut bytes);
+    bytes
 }
 
 /// Encrypts a block of data.
 /// Output Format: [Nonce (24 bytes) | Ciphertext | Tag (16 bytes)]
 pub fn encrypt(data: &[u8], key: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    encrypt_with_aad(data, key, &[])
+}
+
+/// Like `encrypt`, but the tag also covers `aad` (associated data), which is not
+/// stored: decrypting needs the same `aad`, so the ciphertext is bound to it
+/// (e.g. a page to its page id).
+pub fn encrypt_with_aad(data: &[u8], key: &[u8], aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
     let cipher = XChaCha20Poly1305::new_from_slice(key).map_err(|_| CryptoError::KemFailed)?; // Using generic error for key issues
 
     let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng); // 24 random bytes
 
     // Encrypt (Ciphertext + Tag appended automatically)
     let ciphertext = cipher
-        .encrypt(&nonce, data)
+        .encrypt(&nonce, Payload { msg: data, aad })
         .map_err(|_| CryptoError::KemFailed)?;
 
     // Prepend nonce to the result so we can read it later
@@ -36,6 +168,15 @@ pub fn encrypt(data: &[u8], key: &[u8]) -> Result<Vec<u8>, CryptoError> {
 /// Decrypts a block.
 /// Input Format: [Nonce (24 bytes) | Ciphertext + Tag]
 pub fn decrypt(encrypted_data: &[u8], key: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    decrypt_with_aad(encrypted_data, key, &[])
+}
+
+/// Reverses `encrypt_with_aad`. A different `aad` fails like a modified ciphertext.
+pub fn decrypt_with_aad(
+    encrypted_data: &[u8],
+    key: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
     if encrypted_data.len() < NONCE_SIZE + TAG_SIZE {
         return Err(CryptoError::DecryptionFailed);
     }
@@ -48,7 +189,7 @@ pub fn decrypt(encrypted_data: &[u8], key: &[u8]) -> Result<Vec<u8>, CryptoError
 
     // Decrypt (Verifies Tag automatically)
     let plaintext = cipher
-        .decrypt(nonce, payload)
+        .decrypt(nonce, Payload { msg: payload, aad })
         .map_err(|_| CryptoError::DecryptionFailed)?;
 
     Ok(plaintext)
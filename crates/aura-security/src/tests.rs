@@ -48,19 +48,87 @@ fn test_homomorphic_addition() {
     println!("✅ FHE Addition Successful: Enc(10) + Enc(20) = Enc(30)");
 }
 
+#[test]
+fn test_homomorphic_multiplication_and_subtraction() {
+    let ctx = crate::homomorphic::FheContext::new();
+    let computer = crate::homomorphic::FheComputer::new(ctx.get_server_key());
+    let encrypt =
+        |value: u32| bincode::serialize(&FheUint32::encrypt(value, &ctx.client_key)).unwrap();
+    let decrypt = |bytes: &[u8]| -> u32 {
+        let encrypted: FheUint32 = bincode::deserialize(bytes).unwrap();
+        encrypted.decrypt(&ctx.client_key)
+    };
+
+    let product = computer.mul_encrypted(&encrypt(6), &encrypt(7)).unwrap();
+    assert_eq!(decrypt(&product), 42);
+
+    let difference = computer.sub_encrypted(&encrypt(20), &encrypt(5)).unwrap();
+    assert_eq!(decrypt(&difference), 15);
+
+    // Garbage is rejected, not computed on
+    assert!(computer
+        .mul_encrypted(b"not a ciphertext", &encrypt(1))
+        .is_err());
+    println!("✅ FHE Multiplication and Subtraction Successful");
+}
+
+#[test]
+fn test_homomorphic_scalar_operations() {
+    let ctx = crate::homomorphic::FheContext::new();
+    let computer = crate::homomorphic::FheComputer::new(ctx.get_server_key());
+    let hundred = ctx.encrypt_u32(100).unwrap();
+
+    // The constants stay in the clear: only the ciphertext is encrypted
+    let scaled = computer.mul_scalar(&hundred, 3).unwrap();
+    assert_eq!(ctx.decrypt_u32(&scaled).unwrap(), 300);
+
+    let raised = computer.add_scalar(&hundred, 10).unwrap();
+    assert_eq!(ctx.decrypt_u32(&raised).unwrap(), 110);
+
+    assert!(computer.add_scalar(b"not a ciphertext", 1).is_err());
+    println!("✅ FHE Scalar Operations Successful: Enc(100) * 3 = Enc(300)");
+}
+
 #[test]
 fn test_symmetric_encryption_decryption() {
     // Test basic encrypt/decrypt
     let key = crate::symmetric::generate_key();
     let plaintext = b"Hello, Quantum World!";
 
-    let encrypted = crate::symmetric::encrypt(plaintext, &key).unwrap();
-    let decrypted = crate::symmetric::decrypt(&encrypted, &key).unwrap();
+    let encrypted = crate::symmetric::encrypt(plaintext, key.as_bytes()).unwrap();
+    let decrypted = crate::symmetric::decrypt(&encrypted, key.as_bytes()).unwrap();
 
     assert_eq!(plaintext, decrypted.as_slice());
     println!("✅ Symmetric Encryption/Decryption Successful");
 }
 
+#[test]
+fn test_symmetric_encryption_with_aad() {
+    let key = crate::symmetric::generate_key();
+    let plaintext = b"page five";
+
+    let encrypted =
+        crate::symmetric::encrypt_with_aad(plaintext, key.as_bytes(), &5u32.to_le_bytes()).unwrap();
+    let decrypted =
+        crate::symmetric::decrypt_with_aad(&encrypted, key.as_bytes(), &5u32.to_le_bytes())
+            .unwrap();
+    assert_eq!(decrypted, plaintext);
+
+    // The same ciphertext presented as another page (or with no AAD) fails authentication
+    assert!(
+        crate::symmetric::decrypt_with_aad(&encrypted, key.as_bytes(), &6u32.to_le_bytes())
+            .is_err()
+    );
+    assert!(crate::symmetric::decrypt(&encrypted, key.as_bytes()).is_err());
+
+    // No AAD is the same as an empty one
+    let encrypted = crate::symmetric::encrypt(plaintext, key.as_bytes()).unwrap();
+    assert_eq!(
+        crate::symmetric::decrypt_with_aad(&encrypted, key.as_bytes(), &[]).unwrap(),
+        plaintext
+    );
+}
+
 #[test]
 fn test_symmetric_encryption_different_keys() {
     // Test that different keys produce different ciphertexts
@@ -68,15 +136,15 @@ fn test_symmetric_encryption_different_keys() {
     let key2 = crate::symmetric::generate_key();
     let plaintext = b"Same message, different keys";
 
-    let encrypted1 = crate::symmetric::encrypt(plaintext, &key1).unwrap();
-    let encrypted2 = crate::symmetric::encrypt(plaintext, &key2).unwrap();
+    let encrypted1 = crate::symmetric::encrypt(plaintext, key1.as_bytes()).unwrap();
+    let encrypted2 = crate::symmetric::encrypt(plaintext, key2.as_bytes()).unwrap();
 
     // Different keys should produce different ciphertexts
     assert_ne!(encrypted1, encrypted2);
 
     // But each should decrypt correctly with its own key
-    let decrypted1 = crate::symmetric::decrypt(&encrypted1, &key1).unwrap();
-    let decrypted2 = crate::symmetric::decrypt(&encrypted2, &key2).unwrap();
+    let decrypted1 = crate::symmetric::decrypt(&encrypted1, key1.as_bytes()).unwrap();
+    let decrypted2 = crate::symmetric::decrypt(&encrypted2, key2.as_bytes()).unwrap();
 
     assert_eq!(plaintext, decrypted1.as_slice());
     assert_eq!(plaintext, decrypted2.as_slice());
@@ -89,20 +157,20 @@ fn test_symmetric_encryption_edge_cases() {
 
     // Test empty data
     let empty_data = b"";
-    let encrypted = crate::symmetric::encrypt(empty_data, &key).unwrap();
-    let decrypted = crate::symmetric::decrypt(&encrypted, &key).unwrap();
+    let encrypted = crate::symmetric::encrypt(empty_data, key.as_bytes()).unwrap();
+    let decrypted = crate::symmetric::decrypt(&encrypted, key.as_bytes()).unwrap();
     assert_eq!(empty_data, decrypted.as_slice());
 
     // Test large data
     let large_data = vec![42u8; 10000]; // 10KB of data
-    let encrypted = crate::symmetric::encrypt(&large_data, &key).unwrap();
-    let decrypted = crate::symmetric::decrypt(&encrypted, &key).unwrap();
+    let encrypted = crate::symmetric::encrypt(&large_data, key.as_bytes()).unwrap();
+    let decrypted = crate::symmetric::decrypt(&encrypted, key.as_bytes()).unwrap();
     assert_eq!(large_data, decrypted);
 
     // Test binary data
     let binary_data = &[0, 1, 255, 0, 128, 64, 32, 16, 8, 4, 2, 1];
-    let encrypted = crate::symmetric::encrypt(binary_data, &key).unwrap();
-    let decrypted = crate::symmetric::decrypt(&encrypted, &key).unwrap();
+    let encrypted = crate::symmetric::encrypt(binary_data, key.as_bytes()).unwrap();
+    let decrypted = crate::symmetric::decrypt(&encrypted, key.as_bytes()).unwrap();
     assert_eq!(binary_data, decrypted.as_slice());
 
     println!("✅ Symmetric Encryption Edge Cases Successful");
@@ -114,21 +182,21 @@ fn test_symmetric_decryption_errors() {
 
     // Test decryption with wrong key
     let plaintext = b"Secret message";
-    let encrypted = crate::symmetric::encrypt(plaintext, &key).unwrap();
+    let encrypted = crate::symmetric::encrypt(plaintext, key.as_bytes()).unwrap();
 
     let wrong_key = crate::symmetric::generate_key();
-    assert!(crate::symmetric::decrypt(&encrypted, &wrong_key).is_err());
+    assert!(crate::symmetric::decrypt(&encrypted, wrong_key.as_bytes()).is_err());
 
     // Test decryption with corrupted data
     let mut corrupted = encrypted.clone();
     if corrupted.len() > 10 {
         corrupted[10] ^= 0xFF; // Flip some bits
-        assert!(crate::symmetric::decrypt(&corrupted, &key).is_err());
+        assert!(crate::symmetric::decrypt(&corrupted, key.as_bytes()).is_err());
     }
 
     // Test decryption with too short data
     let too_short = vec![1, 2, 3]; // Less than nonce + tag
-    assert!(crate::symmetric::decrypt(&too_short, &key).is_err());
+    assert!(crate::symmetric::decrypt(&too_short, key.as_bytes()).is_err());
 
     println!("✅ Symmetric Decryption Error Handling Successful");
 }
@@ -190,6 +258,38 @@ fn test_kem_invalid_ciphertext() {
     println!("✅ KEM Invalid Ciphertext Handling Successful");
 }
 
+#[test]
+fn test_hybrid_encrypt_round_trip() {
+    use crate::kem::{hybrid_decrypt, hybrid_encrypt};
+    let recipient = crate::kem::PQCKeyPair::generate();
+    let message = b"Sealed for the holder of this key";
+
+    let blob = hybrid_encrypt(message, recipient.pk.as_bytes()).unwrap();
+    assert_ne!(&blob[blob.len() - message.len()..], message);
+    assert_eq!(hybrid_decrypt(&blob, &recipient.sk).unwrap(), message);
+
+    // Empty messages work too
+    let blob = hybrid_encrypt(b"", recipient.pk.as_bytes()).unwrap();
+    assert!(hybrid_decrypt(&blob, &recipient.sk).unwrap().is_empty());
+
+    // Truncated blobs are rejected, not panicked on
+    assert!(hybrid_decrypt(&blob[..2], &recipient.sk).is_err());
+    assert!(hybrid_decrypt(&blob[..100], &recipient.sk).is_err());
+}
+
+#[test]
+fn test_hybrid_decrypt_wrong_key_fails() {
+    use crate::kem::{hybrid_decrypt, hybrid_encrypt};
+    let recipient = crate::kem::PQCKeyPair::generate();
+    let someone_else = crate::kem::PQCKeyPair::generate();
+
+    let blob = hybrid_encrypt(b"not for you", recipient.pk.as_bytes()).unwrap();
+    assert!(matches!(
+        hybrid_decrypt(&blob, &someone_else.sk),
+        Err(crate::CryptoError::DecryptionFailed)
+    ));
+}
+
 #[test]
 fn test_homomorphic_context_creation() {
     // Test FHE context creation
@@ -224,3 +324,221 @@ fn test_homomorphic_computation_errors() {
 
     println!("✅ Homomorphic Error Handling Successful");
 }
+
+#[test]
+fn test_hybrid_handshake() {
+    use crate::kem::{hybrid_encapsulate, HybridKeyPair, KemAlgorithm};
+
+    // 1. Server generates ephemeral X25519 + Kyber keys
+    let server_keys = HybridKeyPair::generate();
+    let public_bytes = server_keys.public_bytes();
+    assert_eq!(
+        public_bytes.len(),
+        KemAlgorithm::HybridX25519Kyber1024.public_key_len()
+    );
+
+    // 2. Client answers with a Kyber ciphertext and its own X25519 key
+    let (client_key, ciphertext) = hybrid_encapsulate(&public_bytes).unwrap();
+    assert_eq!(
+        ciphertext.len(),
+        KemAlgorithm::HybridX25519Kyber1024.ciphertext_len()
+    );
+
+    // 3. Both sides derive the same session key
+    let server_key = server_keys.decapsulate(&ciphertext).unwrap();
+    assert_eq!(client_key, server_key);
+    assert_eq!(client_key.len(), crate::symmetric::KEY_SIZE);
+
+    // A truncated answer is rejected
+    let other_keys = HybridKeyPair::generate();
+    assert!(other_keys.decapsulate(&ciphertext[..100]).is_err());
+
+    // So is a low-order X25519 key (all zeros), which would make the classical
+    // secret all zeros whatever our own key is, on either side
+    let mut zeroed = ciphertext.clone();
+    let x25519_start = zeroed.len() - crate::kem::X25519_KEY_BYTES;
+    zeroed[x25519_start..].fill(0);
+    let other_keys = HybridKeyPair::generate();
+    assert!(matches!(
+        other_keys.decapsulate(&zeroed),
+        Err(crate::CryptoError::KemFailed)
+    ));
+    let mut zeroed = HybridKeyPair::generate().public_bytes();
+    let x25519_start = zeroed.len() - crate::kem::X25519_KEY_BYTES;
+    zeroed[x25519_start..].fill(0);
+    assert!(matches!(
+        hybrid_encapsulate(&zeroed),
+        Err(crate::CryptoError::KemFailed)
+    ));
+}
+
+#[test]
+fn test_handshake_negotiation() {
+    use crate::kem::{negotiate, KemAlgorithm};
+    let hybrid = KemAlgorithm::HybridX25519Kyber1024;
+    let kyber = KemAlgorithm::Kyber1024;
+
+    // The server's preference wins when both sides support everything
+    assert_eq!(negotiate(&[hybrid, kyber], &[kyber, hybrid]), Some(hybrid));
+
+    // A pure-Kyber peer on either side falls back to Kyber
+    assert_eq!(negotiate(&[hybrid, kyber], &[kyber]), Some(kyber));
+    assert_eq!(negotiate(&[kyber], &[hybrid, kyber]), Some(kyber));
+
+    // Nothing in common
+    assert_eq!(negotiate(&[hybrid], &[kyber]), None);
+
+    assert_eq!(KemAlgorithm::from_id(hybrid.id()), Some(hybrid));
+    assert_eq!(KemAlgorithm::from_name("Kyber"), Some(kyber));
+    assert_eq!(KemAlgorithm::from_id(0), None);
+}
+
+#[test]
+fn test_session_key_length_is_checked() {
+    use crate::symmetric::{SessionKey, KEY_SIZE};
+
+    // Both handshakes produce secrets that make valid keys
+    let server_keys = crate::kem::PQCKeyPair::generate();
+    let (secret, _) = crate::kem::encapsulate(server_keys.pk.as_bytes()).unwrap();
+    let key = SessionKey::try_from(secret).unwrap();
+    assert_eq!(key.as_bytes().len(), KEY_SIZE);
+
+    let hybrid_keys = crate::kem::HybridKeyPair::generate();
+    let (secret, _) = crate::kem::hybrid_encapsulate(&hybrid_keys.public_bytes()).unwrap();
+    assert!(SessionKey::try_from(secret).is_ok());
+
+    // Anything else is refused
+    assert!(matches!(
+        SessionKey::from_slice(&[0u8; 16]),
+        Err(crate::CryptoError::InvalidKeyLength(16))
+    ));
+    assert!(SessionKey::try_from(vec![0u8; KEY_SIZE + 1]).is_err());
+    assert_eq!(format!("{:?}", key), "SessionKey(..)");
+}
+
+#[test]
+fn test_derive_session_key() {
+    use crate::kem::derive_session_key;
+    use crate::symmetric::SessionKey;
+
+    let server_keys = crate::kem::PQCKeyPair::generate();
+    let (secret, ciphertext) = crate::kem::encapsulate(server_keys.pk.as_bytes()).unwrap();
+    let transcript = [server_keys.pk.as_bytes(), &ciphertext].concat();
+
+    // Same secret and context: same key, on both sides of the handshake
+    let client_key = derive_session_key(&secret, &transcript);
+    let server_secret = crate::kem::decapsulate(&ciphertext, &server_keys.sk).unwrap();
+    assert_eq!(derive_session_key(&server_secret, &transcript), client_key);
+
+    // Never the raw secret, and another context gives another key
+    assert_ne!(client_key.as_slice(), secret.as_slice());
+    assert_ne!(derive_session_key(&secret, b"backups"), client_key);
+    assert_ne!(
+        derive_session_key(&secret, &transcript[1..]),
+        derive_session_key(&secret, &transcript)
+    );
+
+    let session_key = SessionKey::derive(secret, &transcript);
+    assert_eq!(session_key.as_bytes(), &client_key);
+}
+
+#[test]
+fn test_keyfile_seal_and_open() {
+    let key = crate::symmetric::generate_key();
+    let sealed = crate::keyfile::seal(&key, "correct horse").unwrap();
+    assert!(crate::keyfile::is_sealed(&sealed));
+    assert!(!crate::keyfile::is_sealed(key.as_bytes()));
+    assert!(!contains_key(&sealed, key.as_bytes()));
+
+    assert_eq!(
+        crate::keyfile::open(&sealed, "correct horse")
+            .unwrap()
+            .as_bytes(),
+        key.as_bytes()
+    );
+    assert!(matches!(
+        crate::keyfile::open(&sealed, "battery staple"),
+        Err(crate::CryptoError::DecryptionFailed)
+    ));
+
+    // Salts are random, so sealing twice gives different files
+    assert_ne!(crate::keyfile::seal(&key, "correct horse").unwrap(), sealed);
+
+    let mut tampered = sealed.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    assert!(crate::keyfile::open(&tampered, "correct horse").is_err());
+}
+
+#[test]
+fn test_password_hashing() {
+    let hash = crate::password::hash("correct horse").unwrap();
+    assert!(hash.starts_with("$argon2id$"));
+    assert!(!hash.contains("correct horse"));
+
+    assert!(crate::password::verify("correct horse", &hash));
+    assert!(!crate::password::verify("battery staple", &hash));
+    assert!(!crate::password::verify("correct horse", "not a hash"));
+
+    // Salted: the same password never hashes the same way twice
+    let again = crate::password::hash("correct horse").unwrap();
+    assert_ne!(again, hash);
+    assert!(crate::password::verify("correct horse", &again));
+}
+
+fn contains_key(haystack: &[u8], key: &[u8]) -> bool {
+    haystack.windows(key.len()).any(|window| window == key)
+}
+
+#[test]
+fn test_dilithium_signatures() {
+    let signer = crate::sign::SignKeyPair::generate();
+    let pk = signer.public_bytes();
+    assert_eq!(pk.len(), crate::sign::DILITHIUM_PUBLIC_KEY_BYTES);
+
+    let msg = b"SELECT * FROM users -> 3 rows";
+    let sig = crate::sign::sign(msg, &signer.sk);
+    assert_eq!(sig.len(), crate::sign::DILITHIUM_SIGNATURE_BYTES);
+    assert!(crate::sign::verify(msg, &sig, &pk).is_ok());
+
+    // A tampered message fails
+    assert!(matches!(
+        crate::sign::verify(b"SELECT * FROM users -> 4 rows", &sig, &pk),
+        Err(crate::CryptoError::InvalidSignature)
+    ));
+
+    // So does someone else's public key, or a malformed one
+    let other = crate::sign::SignKeyPair::generate();
+    assert!(matches!(
+        crate::sign::verify(msg, &sig, &other.public_bytes()),
+        Err(crate::CryptoError::InvalidSignature)
+    ));
+    assert!(crate::sign::verify(msg, &sig, &pk[..100]).is_err());
+}
+
+#[test]
+fn test_key_material_is_wiped_on_drop() {
+    use crate::symmetric::{MasterKey, KEY_SIZE};
+    use pqcrypto_traits::kem::SecretKey;
+    use std::mem::MaybeUninit;
+
+    // Drop each key in place, then look at the memory it occupied
+    let mut slot = MaybeUninit::new(crate::symmetric::generate_key());
+    let key: *mut MasterKey = slot.as_mut_ptr();
+    unsafe {
+        assert_ne!(*(key as *const [u8; KEY_SIZE]), [0u8; KEY_SIZE]);
+        std::ptr::drop_in_place(key);
+        // The slot is still ours (MaybeUninit never drops), and MasterKey is
+        // repr(transparent), so these are exactly the key's bytes
+        assert_eq!(*(key as *const [u8; KEY_SIZE]), [0u8; KEY_SIZE]);
+    }
+
+    let mut slot = MaybeUninit::new(crate::kem::PQCKeyPair::generate());
+    let pair = slot.as_mut_ptr();
+    unsafe {
+        let sk = std::ptr::addr_of!((*pair).sk);
+        assert!((*sk).as_bytes().iter().any(|&b| b != 0));
+        std::ptr::drop_in_place(pair);
+        assert!((*sk).as_bytes().iter().all(|&b| b == 0));
+    }
+}
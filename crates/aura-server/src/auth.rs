@@ -0,0 +1,63 @@
+// Logins: who may use a connection once its channel is encrypted.
+// Users live in the catalog ("$user/<name>") with an Argon2id password hash.
+// With `require_auth` on, a connection can only AUTH or PING until it logs in.
+// Once logged in, its statements are checked against the user's GRANTs.
+use anyhow::Result;
+use aura_common::UserDef;
+use aura_query::catalog;
+use aura_security::password;
+use aura_store::pager::Pager;
+use tokio::sync::Mutex;
+
+// The login AURA_ADMIN_PASSWORD creates (or resets) at startup
+pub use aura_query::catalog::ADMIN_USER;
+
+/// Failed AUTH attempts a connection gets before it is closed
+pub const MAX_AUTH_ATTEMPTS: u32 = 3;
+
+/// What an unknown user's password is checked against: a well-formed hash with
+/// the cost of a real one (that no password matches), so the check takes as
+/// long as a wrong password's and doesn't tell which logins exist
+pub(crate) const DUMMY_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$\
+    YXVyYWR1bW15c2FsdDAwMA$AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+
+/// Creates the login, or replaces its password (keeping its grants) if it already exists
+pub fn set_password(pager: &mut Pager, name: &str, password: &str) -> Result<()> {
+    let password_hash =
+        password::hash(password).map_err(|e| anyhow::anyhow!("Failed to hash password: {}", e))?;
+    let user = match catalog::load_user(pager, name)? {
+        Some(user) => UserDef {
+            password_hash,
+            ..user
+        },
+        None => UserDef {
+            name: name.to_string(),
+            password_hash,
+            grants: Vec::new(),
+        },
+    };
+
+    pager.begin();
+    let stored = catalog::store_user(pager, &user)
+        .map_err(anyhow::Error::from)
+        .and_then(|()| Ok(pager.sync_index()?));
+    pager.commit()?;
+    stored
+}
+
+/// Checks a username and password against the stored logins.
+/// Argon2 is slow on purpose, so it runs on a blocking thread, outside the lock.
+/// Unknown users cost the same check (see `DUMMY_HASH`) and never match.
+pub async fn verify(db: &Mutex<Pager>, username: &str, password: &str) -> Result<bool> {
+    let user = catalog::load_user(&mut *db.lock().await, username)?;
+    let password = password.to_string();
+    let matches = tokio::task::spawn_blocking(move || match user {
+        Some(user) => password::verify(&password, &user.password_hash),
+        None => {
+            password::verify(&password, DUMMY_HASH);
+            false
+        }
+    })
+    .await?;
+    Ok(matches)
+}
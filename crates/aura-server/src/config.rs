@@ -0,0 +1,193 @@
+// Server-wide settings, shared by every connection.
+use aura_query::executor::ValueLimits;
+use aura_security::homomorphic::FheComputer;
+use aura_security::KemAlgorithm;
+use aura_store::pager::SyncPolicy;
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Where the master key is kept when no `--keyfile` is given
+pub const DEFAULT_KEYFILE: &str = "aura_main.key";
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Maximum number of statements a single connection may run (None = unlimited).
+    /// The statement after the last allowed one is rejected and the connection closed.
+    pub statement_quota: Option<u64>,
+
+    /// Key exchanges offered in the handshake, most preferred first
+    pub handshake: Vec<KemAlgorithm>,
+
+    /// Log every statement's plan, pages read and rows returned (at INFO)
+    pub query_log: bool,
+
+    /// Connections must AUTH with a stored login before running statements.
+    /// On by default: turning it off gives anyone who can connect full access.
+    pub require_auth: bool,
+
+    /// Connections the kernel queues for `accept` before refusing more
+    pub listen_backlog: u32,
+
+    /// Bind with SO_REUSEPORT, so several servers can share the port: a new one
+    /// can start before the old one stops, or a few can split the load (Unix only).
+    /// Each needs its own database file: a database is locked by the server that
+    /// opened it, so the new one can only open it once the old one has exited.
+    pub reuse_port: bool,
+
+    /// Longest Text and Binary values (in bytes) a statement may store
+    pub value_limits: ValueLimits,
+
+    /// Statements within this long of the last index write share the next one,
+    /// which is made at the latest this long later (zero = every statement writes
+    /// it, see `PagerOptions::index_sync_window`)
+    pub index_sync_window: Duration,
+
+    /// When the database file and its WAL are fsynced
+    pub sync_policy: SyncPolicy,
+
+    /// Computes FHE_SUM with the clients' FHE server key (None = FHE_SUM is refused).
+    /// Not read from the environment: whoever embeds the server registers it.
+    pub fhe: Option<FheComputer>,
+}
+
+/// Listen backlog when AURA_LISTEN_BACKLOG is not set
+pub const DEFAULT_LISTEN_BACKLOG: u32 = 1024;
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            statement_quota: None,
+            handshake: KemAlgorithm::ALL.to_vec(),
+            query_log: false,
+            require_auth: true,
+            listen_backlog: DEFAULT_LISTEN_BACKLOG,
+            reuse_port: false,
+            value_limits: ValueLimits::default(),
+            index_sync_window: Duration::ZERO,
+            sync_policy: SyncPolicy::OnCommit,
+            fhe: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Reads the config from the environment:
+    /// AURA_STATEMENT_QUOTA - per-connection statement quota (unset = unlimited)
+    /// AURA_HANDSHAKE - key exchanges to offer, e.g. "hybrid,kyber" (unset = all)
+    /// AURA_QUERY_LOG - "true" to log each query's plan and row counts (unset = off)
+    /// AURA_REQUIRE_AUTH - "false" to run statements without AUTH (unset = AUTH required)
+    /// AURA_LISTEN_BACKLOG - pending connections to queue (unset = DEFAULT_LISTEN_BACKLOG)
+    /// AURA_REUSE_PORT - "true" to share the port with other servers (unset = off)
+    /// AURA_MAX_TEXT_LEN, AURA_MAX_BINARY_LEN - longest Text / Binary value in bytes
+    /// (unset = only the document size limit applies)
+    /// AURA_INDEX_SYNC_MS - coalescing window for index writes, in milliseconds
+    /// (unset or 0 = write the index with every statement)
+    /// AURA_SYNC - "always", "on_commit" or "never", see `SyncPolicy` (unset = on_commit)
+    pub fn from_env() -> anyhow::Result<Self> {
+        let statement_quota =
+            match env::var("AURA_STATEMENT_QUOTA") {
+                Ok(value) => Some(value.parse().map_err(|e| {
+                    anyhow::anyhow!("Invalid AURA_STATEMENT_QUOTA '{}': {}", value, e)
+                })?),
+                Err(_) => None,
+            };
+        let handshake = match env::var("AURA_HANDSHAKE") {
+            Ok(value) => Self::parse_handshake(&value)?,
+            Err(_) => KemAlgorithm::ALL.to_vec(),
+        };
+        let query_log = match env::var("AURA_QUERY_LOG") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid AURA_QUERY_LOG '{}': {}", value, e))?,
+            Err(_) => false,
+        };
+        let require_auth = match env::var("AURA_REQUIRE_AUTH") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid AURA_REQUIRE_AUTH '{}': {}", value, e))?,
+            Err(_) => true,
+        };
+        let listen_backlog = match env::var("AURA_LISTEN_BACKLOG") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid AURA_LISTEN_BACKLOG '{}': {}", value, e))?,
+            Err(_) => DEFAULT_LISTEN_BACKLOG,
+        };
+        let reuse_port = match env::var("AURA_REUSE_PORT") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid AURA_REUSE_PORT '{}': {}", value, e))?,
+            Err(_) => false,
+        };
+        let defaults = ValueLimits::default();
+        let value_limits = ValueLimits {
+            max_text: Self::parse_length("AURA_MAX_TEXT_LEN", defaults.max_text)?,
+            max_binary: Self::parse_length("AURA_MAX_BINARY_LEN", defaults.max_binary)?,
+        };
+        let index_sync_window =
+            Duration::from_millis(Self::parse_length("AURA_INDEX_SYNC_MS", 0)? as u64);
+        let sync_policy = match env::var("AURA_SYNC") {
+            Ok(value) => SyncPolicy::from_name(&value)
+                .ok_or_else(|| anyhow::anyhow!("Invalid AURA_SYNC '{}'", value))?,
+            Err(_) => SyncPolicy::OnCommit,
+        };
+        Ok(Self {
+            statement_quota,
+            handshake,
+            query_log,
+            require_auth,
+            listen_backlog,
+            reuse_port,
+            value_limits,
+            index_sync_window,
+            sync_policy,
+            fhe: None,
+        })
+    }
+
+    fn parse_length(var: &str, default: usize) -> anyhow::Result<usize> {
+        match env::var(var) {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid {} '{}': {}", var, value, e)),
+            Err(_) => Ok(default),
+        }
+    }
+
+    fn parse_handshake(value: &str) -> anyhow::Result<Vec<KemAlgorithm>> {
+        let mut algorithms = Vec::new();
+        for name in value.split(',') {
+            let algorithm = KemAlgorithm::from_name(name).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid AURA_HANDSHAKE '{}': unknown algorithm '{}'",
+                    value,
+                    name.trim()
+                )
+            })?;
+            if !algorithms.contains(&algorithm) {
+                algorithms.push(algorithm);
+            }
+        }
+        Ok(algorithms)
+    }
+}
+
+/// The key file path from the command line: `--keyfile <path>` or `--keyfile=<path>`
+pub fn keyfile_from_args(args: impl IntoIterator<Item = String>) -> anyhow::Result<PathBuf> {
+    let mut args = args.into_iter();
+    let mut keyfile = PathBuf::from(DEFAULT_KEYFILE);
+    while let Some(arg) = args.next() {
+        if arg == "--keyfile" {
+            let path = args
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("--keyfile needs a path"))?;
+            keyfile = PathBuf::from(path);
+        } else if let Some(path) = arg.strip_prefix("--keyfile=") {
+            keyfile = PathBuf::from(path);
+        } else {
+            anyhow::bail!("Unknown argument '{}'", arg);
+        }
+    }
+    Ok(keyfile)
+}
@@ -1,97 +1,431 @@
+use crate::auth;
+use crate::config::ServerConfig;
+use crate::notify::{ChannelRegistry, PubSubCommand};
+use crate::protocol::{
+    self, Frame, SecureChannel, FRAME_AUTH, FRAME_PING, FRAME_PONG, FRAME_QUERY, FRAME_QUERY_PARAMS,
+};
 use anyhow::{bail, Result};
+use aura_common::{AuthRequest, DataValue, QueryRequest, QueryResult};
 use aura_query::executor::QueryEngine;
-use aura_security::kem;
+use aura_query::temp::TempTables;
+use aura_query::QueryError;
+use aura_security::symmetric::SessionKey;
+use aura_security::{kem, KemAlgorithm};
 use aura_store::pager::Pager;
 use pqcrypto_traits::kem::PublicKey;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::OwnedWriteHalf;
 use tokio::net::TcpStream;
-use tokio::sync::Mutex;
-use tracing::{debug, info};
+use tokio::sync::{mpsc, Mutex};
+use tracing::{debug, info, warn};
 
 // The Protocol States
-// TODO: Implement encrypted communication using session_key
-#[allow(dead_code)]
+// (Logging in with AUTH happens inside the encrypted command loop.)
 pub enum ConnectionState {
     Handshake,
-    Authenticated { session_key: Vec<u8> },
+    Encrypted { session_key: SessionKey },
 }
 
-pub async fn handle_socket(mut socket: TcpStream, db: Arc<Mutex<Pager>>) -> Result<()> {
+pub async fn handle_socket(
+    mut socket: TcpStream,
+    db: Arc<Mutex<Pager>>,
+    channels: Arc<ChannelRegistry>,
+    config: Arc<ServerConfig>,
+) -> Result<()> {
     let mut state = ConnectionState::Handshake;
-    let mut buffer = [0u8; 4096];
 
     loop {
         match state {
             // --- STEP 1: QUANTUM HANDSHAKE ---
             ConnectionState::Handshake => {
                 debug!("Initiating PQC Handshake...");
-
-                // A. Server generates ephemeral Kyber Keypair
-                let server_keys = kem::PQCKeyPair::generate();
-
-                // B. Send Public Key to Client (1568 bytes for Kyber1024)
-                let pk_bytes = server_keys.pk.as_bytes();
-                socket.write_all(pk_bytes).await?;
-
-                // C. Wait for Client's Encapsulated Secret
-                // Kyber1024 Ciphertext is 1568 bytes
-                let mut ct_buffer = vec![0u8; 1568];
-                let n = socket.read(&mut ct_buffer).await?;
-                if n == 0 {
-                    return Ok(());
-                } // Client disconnected
-                if n != 1568 {
-                    bail!("Expected 1568 bytes for ciphertext, got {}", n);
-                }
-
-                // D. Decapsulate to get Shared Secret
-                let shared_secret = match kem::decapsulate(&ct_buffer, &server_keys.sk) {
-                    Ok(secret) => secret,
-                    Err(_) => {
-                        bail!("Handshake Failed: Invalid Kyber Ciphertext");
-                    }
+                let Some(session_key) = handshake(&mut socket, &config.handshake).await? else {
+                    return Ok(()); // Client disconnected
                 };
 
-                // E. Upgrade State
-                state = ConnectionState::Authenticated {
-                    session_key: shared_secret,
-                };
+                // Upgrade State
+                state = ConnectionState::Encrypted { session_key };
                 info!("🔒 Handshake Success. Secure Channel Established.");
             }
 
             // --- STEP 2: SECURE COMMAND LOOP ---
-            ConnectionState::Authenticated { session_key: _ } => {
-                // A. Read Encrypted Request
-                let n = socket.read(&mut buffer).await?;
-                if n == 0 {
-                    return Ok(());
-                }
+            ConnectionState::Encrypted { session_key } => {
+                let conn_id = channels.register_connection();
+                let result =
+                    command_loop(socket, &db, &channels, &config, conn_id, session_key).await;
+
+                // Whatever happened, this connection no longer listens to anything
+                channels.unlisten_all(conn_id);
+                return result;
+            }
+        }
+    }
+}
+
+/// Agrees on a session key with the client. Returns None if it hangs up first.
+///
+/// A. Server -> Client: [PROTOCOL_VERSION][count][algorithm ids...], most preferred first
+/// B. Client -> Server: [algorithm id], the first offered one it supports
+/// C. Server -> Client: ephemeral public key(s) for that algorithm
+/// D. Client -> Server: the encapsulated secret(s)
+///
+/// The session key is derived from the shared secret and the transcript of A to D.
+pub(crate) async fn handshake(
+    socket: &mut TcpStream,
+    offered: &[KemAlgorithm],
+) -> Result<Option<SessionKey>> {
+    let mut hello = vec![protocol::PROTOCOL_VERSION, offered.len() as u8];
+    hello.extend(offered.iter().map(|alg| alg.id()));
+    socket.write_all(&hello).await?;
+
+    let mut choice = [0u8; 1];
+    if !read_handshake(socket, &mut choice).await? {
+        return Ok(None);
+    }
+    let Some(algorithm) = KemAlgorithm::from_id(choice[0]).filter(|alg| offered.contains(alg))
+    else {
+        bail!(
+            "Handshake Failed: client chose unoffered algorithm {}",
+            choice[0]
+        );
+    };
+    debug!("Negotiated {} key exchange", algorithm.name());
+
+    let mut ct_buffer = vec![0u8; algorithm.ciphertext_len()];
+    let (public_key, shared_secret) = match algorithm {
+        KemAlgorithm::Kyber1024 => {
+            // Server generates ephemeral Kyber Keypair and sends the Public Key
+            let server_keys = kem::PQCKeyPair::generate();
+            let public_key = server_keys.pk.as_bytes().to_vec();
+            socket.write_all(&public_key).await?;
+            if !read_handshake(socket, &mut ct_buffer).await? {
+                return Ok(None);
+            }
+            (public_key, kem::decapsulate(&ct_buffer, &server_keys.sk))
+        }
+        KemAlgorithm::HybridX25519Kyber1024 => {
+            let server_keys = kem::HybridKeyPair::generate();
+            let public_key = server_keys.public_bytes();
+            socket.write_all(&public_key).await?;
+            if !read_handshake(socket, &mut ct_buffer).await? {
+                return Ok(None);
+            }
+            (public_key, server_keys.decapsulate(&ct_buffer))
+        }
+    };
+
+    let Ok(shared_secret) = shared_secret else {
+        bail!("Handshake Failed: Invalid {} Ciphertext", algorithm.name());
+    };
+    // Both sides bind the key to everything exchanged, the offer included
+    let transcript = kem::handshake_transcript(&hello, algorithm, &public_key, &ct_buffer);
+    Ok(Some(SessionKey::derive(shared_secret, &transcript)))
+}
+
+/// Fills `buf` from the socket. False if the client disconnected before sending it all.
+async fn read_handshake(socket: &mut TcpStream, buf: &mut [u8]) -> Result<bool> {
+    match socket.read_exact(buf).await {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Serves framed requests until the client disconnects.
+/// Notifications from other connections can be pushed between responses.
+async fn command_loop(
+    socket: TcpStream,
+    db: &Arc<Mutex<Pager>>,
+    channels: &ChannelRegistry,
+    config: &ServerConfig,
+    conn_id: u64,
+    session_key: SessionKey,
+) -> Result<()> {
+    let (mut reader, mut writer) = socket.into_split();
+    let mut channel = SecureChannel::server(session_key);
+
+    // Set once the client is gone, so a statement still running for it (an FHE
+    // computation can take seconds) stops instead of burning CPU for nobody
+    let cancel = Arc::new(AtomicBool::new(false));
+
+    // Reading a frame is not cancel-safe, so a dedicated task owns the read half
+    // and hands complete frames over a channel we can `select!` on.
+    let (request_tx, mut request_rx) = mpsc::channel::<std::io::Result<Frame>>(16);
+    let reader_cancel = cancel.clone();
+    let reader_task = tokio::spawn(async move {
+        loop {
+            let frame = match protocol::read_frame(&mut reader).await {
+                Ok(Some(frame)) => Ok(frame),
+                Ok(None) => break, // Client disconnected
+                Err(e) => Err(e),
+            };
+            let failed = frame.is_err();
+            if request_tx.send(frame).await.is_err() || failed {
+                break;
+            }
+        }
+        reader_cancel.store(true, Ordering::Relaxed);
+    });
+
+    // Outbox for notifications pushed by the ChannelRegistry
+    let (notify_tx, mut notify_rx) = mpsc::unbounded_channel::<Frame>();
+
+    // Statements executed on this connection (checked against the quota)
+    let mut statements: u64 = 0;
+
+    // CREATE TEMP TABLE tables: private to this connection, dropped with it
+    let mut temp_tables = TempTables::new();
+
+    // The login this connection AUTHed as, and how many attempts failed
+    let mut user: Option<String> = None;
+    let mut failed_logins: u32 = 0;
+
+    let result = loop {
+        tokio::select! {
+            request = request_rx.recv() => {
+                // A. Read Request
+                let frame = match request {
+                    Some(Ok(frame)) => frame,
+                    Some(Err(e)) => break Err(e.into()),
+                    None => break Ok(()),
+                };
+
+                // B. Decrypt (Using the Shared Session Key).
+                // Anything that doesn't decrypt, or arrives out of order, ends the connection.
+                let frame = match channel.open(&frame) {
+                    Ok(frame) => frame,
+                    Err(e) => break Err(anyhow::anyhow!("Undecryptable frame: {}", e)),
+                };
 
-                let encrypted_req = &buffer[0..n];
+                let is_statement = matches!(frame.frame_type, FRAME_QUERY | FRAME_QUERY_PARAMS);
+                if is_statement && config.require_auth && user.is_none() {
+                    let response = Frame::error("Authentication required: send AUTH first");
+                    if let Err(e) = send_frame(&mut writer, &response, &mut channel).await {
+                        break Err(e);
+                    }
+                    continue;
+                }
 
-                // B. Decrypt (Using the Shared Session Key)
-                // For Step 7, we skip actual decryption to test connectivity first.
-                // TODO: Wrap this in symmetric::decrypt(encrypted_req, session_key)
-                let request_str = String::from_utf8_lossy(encrypted_req).trim().to_string();
-                debug!("Received Query: {}", request_str);
+                if is_statement {
+                    if config.statement_quota.is_some_and(|quota| statements >= quota) {
+                        info!("Connection {} hit its statement quota ({})", conn_id, statements);
+                        let response = Frame::error(format!(
+                            "Statement quota exceeded: {} statements per connection",
+                            statements
+                        ));
+                        let _ = send_frame(&mut writer, &response, &mut channel).await;
+                        break Ok(());
+                    }
+                    statements += 1;
+                }
 
-                // C. Execute Query
-                let response = {
-                    // Lock the DB, Execute, Unlock immediately
-                    let mut engine_lock = db.lock().await;
-                    let mut query_engine = QueryEngine::new(&mut engine_lock);
+                let response = match frame.frame_type {
+                    FRAME_PING => Frame::new(FRAME_PONG, frame.payload),
+                    FRAME_AUTH => match login(db, &frame.payload, conn_id).await {
+                        Ok(name) => {
+                            let response = Frame::response(&QueryResult::Message(format!("AUTH {}", name)));
+                            user = Some(name);
+                            response
+                        }
+                        Err(response) => {
+                            failed_logins += 1;
+                            if failed_logins >= auth::MAX_AUTH_ATTEMPTS {
+                                let _ = send_frame(&mut writer, &response, &mut channel).await;
+                                break Ok(());
+                            }
+                            response
+                        }
+                    },
+                    FRAME_QUERY => {
+                        let request_str = String::from_utf8_lossy(&frame.payload).trim().to_string();
+                        debug!("Received Query: {}", request_str);
 
-                    match query_engine.execute(&request_str) {
-                        Ok(res) => format!("OK: {}", res),
-                        Err(e) => format!("ERROR: {}", e),
+                        // C. Execute (Pub/Sub commands are per-connection, everything else is SQL)
+                        match PubSubCommand::parse(&request_str) {
+                            Some(Ok(command)) => {
+                                Frame::response(&run_pubsub(command, channels, conn_id, &notify_tx))
+                            }
+                            Some(Err(e)) => Frame::error(e),
+                            None => execute_sql(db, &request_str, &[], &mut temp_tables, user.as_deref(), config, &cancel).await,
+                        }
                     }
+                    FRAME_QUERY_PARAMS => match QueryRequest::from_bytes(&frame.payload) {
+                        Ok(request) => {
+                            debug!("Received Query ({} params): {}", request.params.len(), request.sql);
+                            execute_sql(db, &request.sql, &request.params, &mut temp_tables, user.as_deref(), config, &cancel).await
+                        }
+                        Err(e) => Frame::error(format!("Malformed parameterized query: {}", e)),
+                    },
+                    other => break Err(anyhow::anyhow!("Unexpected frame type {}", other)),
                 };
 
-                // D. Send Response (Should be Encrypted)
-                // TODO: Wrap in symmetric::encrypt(response, session_key)
-                socket.write_all(response.as_bytes()).await?;
+                // Nobody is left to answer
+                if cancel.load(Ordering::Relaxed) {
+                    info!("Connection {} closed while its statement ran", conn_id);
+                    break Ok(());
+                }
+
+                // D. Send Response (Encrypted)
+                if let Err(e) = send_frame(&mut writer, &response, &mut channel).await {
+                    break Err(e);
+                }
             }
+            Some(notification) = notify_rx.recv() => {
+                if let Err(e) = send_frame(&mut writer, &notification, &mut channel).await {
+                    break Err(e);
+                }
+            }
+        }
+    };
+
+    reader_task.abort();
+    result
+}
+
+/// Seals the next frame on the channel and writes it
+async fn send_frame(
+    writer: &mut OwnedWriteHalf,
+    frame: &Frame,
+    channel: &mut SecureChannel,
+) -> Result<()> {
+    let sealed = channel
+        .seal(frame)
+        .map_err(|e| anyhow::anyhow!("Failed to encrypt frame: {}", e))?;
+    protocol::write_frame(writer, &sealed).await?;
+    Ok(())
+}
+
+/// Checks the credentials in an AUTH frame.
+/// The login name on success, otherwise the Error frame to answer with.
+async fn login(db: &Mutex<Pager>, payload: &[u8], conn_id: u64) -> Result<String, Frame> {
+    let request = AuthRequest::from_bytes(payload)
+        .map_err(|e| Frame::error(format!("Malformed AUTH request: {}", e)))?;
+    match auth::verify(db, &request.username, &request.password).await {
+        Ok(true) => {
+            info!(
+                "👤 Connection {} logged in as {}",
+                conn_id, request.username
+            );
+            Ok(request.username)
+        }
+        Ok(false) => {
+            warn!(
+                "Connection {} failed to log in as {}",
+                conn_id, request.username
+            );
+            Err(Frame::error("Authentication failed"))
+        }
+        Err(e) => Err(Frame::error(format!("Authentication failed: {}", e))),
+    }
+}
+
+/// Runs SQL as the connection's login, if it has one (see `QueryEngine::with_user`).
+/// Statements run on the blocking thread pool, so a slow one (FHE_SUM) doesn't
+/// stall the connections sharing its runtime thread; setting `cancel` stops it.
+/// The database is only locked while it is read: FHE_SUM's additions run after.
+async fn execute_sql(
+    db: &Arc<Mutex<Pager>>,
+    sql: &str,
+    params: &[DataValue],
+    temp_tables: &mut TempTables,
+    user: Option<&str>,
+    config: &ServerConfig,
+    cancel: &Arc<AtomicBool>,
+) -> Frame {
+    // Lock the DB, Execute, Unlock immediately
+    let mut engine_lock = db.clone().lock_owned().await;
+    let (sql, params) = (sql.to_string(), params.to_vec());
+    let user = user.map(str::to_string);
+    let (query_log, value_limits) = (config.query_log, config.value_limits);
+    let fhe = config.fhe.clone();
+    let cancel = cancel.clone();
+    // The task needs its own copy; it is handed back with the response
+    let mut temp = std::mem::take(temp_tables);
+
+    let task = tokio::task::spawn_blocking(move || {
+        let mut query_engine = QueryEngine::new(&mut engine_lock)
+            .with_temp_tables(&mut temp)
+            .with_query_log(query_log)
+            .with_value_limits(value_limits)
+            .with_deferred_fhe_sum(true)
+            .with_cancel(cancel);
+        if let Some(fhe) = fhe {
+            query_engine = query_engine.with_fhe(fhe);
+        }
+        if let Some(user) = &user {
+            query_engine = query_engine.with_user(user);
+        }
+
+        let result = query_engine.execute_prepared(&sql, &params);
+        let stats = query_engine.stats();
+        let pending_sum = query_engine.take_pending_fhe_sum();
+        // A transaction doesn't outlive its request (the lock is released after
+        // it), and one left open is an error: its statements are undone
+        let abandoned = query_engine.abandon_transaction();
+        drop(query_engine);
+        // Other connections can use the database while FHE_SUM adds up its values
+        drop(engine_lock);
+
+        let response = match abandoned {
+            Ok(false) => {
+                let result = match pending_sum {
+                    Some(sum) => result.and_then(|_| sum.compute()),
+                    None => result,
+                };
+                match result {
+                    Ok(result) => Frame::response_with_stats(result, stats),
+                    Err(e) => Frame::error(e.to_string()),
+                }
+            }
+            Ok(true) => Frame::error(
+                QueryError::Invalid(
+                    "Transaction not committed by the end of the request, rolled back \
+                     (send BEGIN ... COMMIT in one request)"
+                        .to_string(),
+                )
+                .to_string(),
+            ),
+            Err(e) => {
+                warn!("Rolling back an unfinished transaction failed: {}", e);
+                Frame::error(e.to_string())
+            }
+        };
+        (response, temp)
+    });
+
+    match task.await {
+        Ok((response, temp)) => {
+            *temp_tables = temp;
+            response
+        }
+        Err(e) => {
+            warn!("Statement task failed: {}", e);
+            Frame::error("Internal error: the statement did not finish")
+        }
+    }
+}
+
+fn run_pubsub(
+    command: PubSubCommand,
+    channels: &ChannelRegistry,
+    conn_id: u64,
+    outbox: &mpsc::UnboundedSender<Frame>,
+) -> QueryResult {
+    match command {
+        PubSubCommand::Listen(channel) => {
+            channels.listen(&channel, conn_id, outbox.clone());
+            QueryResult::Message(format!("LISTEN {}", channel))
+        }
+        PubSubCommand::Unlisten(channel) => {
+            channels.unlisten(&channel, conn_id);
+            QueryResult::Message(format!("UNLISTEN {}", channel))
+        }
+        PubSubCommand::Notify { channel, payload } => {
+            let delivered = channels.notify(&channel, &payload);
+            debug!("NOTIFY {} delivered to {} listener(s)", channel, delivered);
+            QueryResult::Message(format!("NOTIFY {}", channel))
         }
     }
 }
@@ -0,0 +1,62 @@
+// Keeps the master key between restarts. Without it every page written by a
+// previous run would be undecryptable (`Tampered`) after a restart.
+use anyhow::{bail, Context, Result};
+use aura_security::keyfile;
+use aura_security::symmetric::{self, MasterKey, KEY_SIZE};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Loads the master key from `path`, or generates one and saves it there on first start.
+/// With a passphrase the file holds the key sealed by `keyfile::seal`; without one
+/// it holds the bare key, so the file's permissions are all that protects it.
+pub fn load_or_create(path: &Path, passphrase: Option<&str>) -> Result<MasterKey> {
+    if path.exists() {
+        let bytes = fs::read(path)
+            .with_context(|| format!("Failed to read key file {}", path.display()))?;
+        return if keyfile::is_sealed(&bytes) {
+            let Some(passphrase) = passphrase else {
+                bail!(
+                    "Key file {} is passphrase-protected; set AURA_KEY_PASSPHRASE",
+                    path.display()
+                );
+            };
+            info!("🔑 Unlocking Master Key from {}", path.display());
+            keyfile::open(&bytes, passphrase)
+                .map_err(|e| anyhow::anyhow!("Failed to unlock key file {}: {}", path.display(), e))
+        } else {
+            info!("🔑 Loading Master Key from {}", path.display());
+            MasterKey::from_slice(&bytes).map_err(|_| {
+                anyhow::anyhow!(
+                    "Key file {} is corrupt: expected {} bytes, got {}",
+                    path.display(),
+                    KEY_SIZE,
+                    bytes.len()
+                )
+            })
+        };
+    }
+
+    info!("🔑 Generating Master Key into {}", path.display());
+    let key = symmetric::generate_key();
+    let bytes = match passphrase {
+        Some(passphrase) => keyfile::seal(&key, passphrase)
+            .map_err(|e| anyhow::anyhow!("Failed to seal master key: {}", e))?,
+        None => {
+            warn!("⚠️ No AURA_KEY_PASSPHRASE set, the key file is not encrypted");
+            key.as_bytes().to_vec()
+        }
+    };
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to create key file {}", path.display()))?;
+    file.write_all(&bytes)?;
+    file.sync_all()?;
+    Ok(key)
+}
@@ -1,2 +1,8 @@
+pub mod auth;
+pub mod config;
 pub mod connection;
+pub mod keystore;
+pub mod notify;
+pub mod protocol;
+pub mod server;
 pub mod tests;
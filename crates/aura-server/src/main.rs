@@ -1,12 +1,11 @@
-mod connection;
-mod protocol;
-
-use aura_security::symmetric;
-use aura_store::pager::Pager;
+use aura_query::catalog;
+use aura_server::config::{self, ServerConfig};
+use aura_server::notify::ChannelRegistry;
+use aura_server::{auth, keystore, protocol, server};
+use aura_store::pager::{Pager, PagerOptions, SyncPolicy};
 use std::sync::Arc;
-use tokio::net::TcpListener;
 use tokio::sync::Mutex;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -18,36 +17,111 @@ async fn main() -> anyhow::Result<()> {
     );
 
     // 2. Initialize The Vault (Thread-Safe)
-    // In production, we'd load this key from a secure KMS.
-    // For now, we generate a new one on startup (Ephemeral DB).
-    // To persist, save this key to a file!
-    info!("🔑 Generating Master Key (Memory Only)...");
-    let master_key = symmetric::generate_key();
+    // The master key is created on first start and loaded from the key file
+    // afterwards, sealed with AURA_KEY_PASSPHRASE if that is set.
+    let keyfile = config::keyfile_from_args(std::env::args().skip(1))?;
+    let passphrase = std::env::var("AURA_KEY_PASSPHRASE").ok();
+    let master_key = keystore::load_or_create(&keyfile, passphrase.as_deref())?;
+
+    let config = ServerConfig::from_env()?;
 
     // Open the DB file
-    let pager =
-        Pager::open("aura_main.db", master_key).expect("Failed to initialize storage engine");
+    let options = PagerOptions {
+        index_sync_window: config.index_sync_window,
+        sync: config.sync_policy,
+        ..PagerOptions::default()
+    };
+    let mut pager = Pager::open_with_options("aura_main.db", master_key, options)
+        .expect("Failed to initialize storage engine");
+
+    // AURA_ADMIN_PASSWORD creates the "admin" login (or resets its password)
+    if let Ok(password) = std::env::var("AURA_ADMIN_PASSWORD") {
+        auth::set_password(&mut pager, auth::ADMIN_USER, &password)?;
+        info!(
+            "👤 Login '{}' set from AURA_ADMIN_PASSWORD",
+            auth::ADMIN_USER
+        );
+    } else if config.require_auth && catalog::load_user(&mut pager, auth::ADMIN_USER)?.is_none() {
+        warn!(
+            "⚠️ No '{}' login yet: set AURA_ADMIN_PASSWORD to create it",
+            auth::ADMIN_USER
+        );
+    }
 
     // Wrap in Arc<Mutex> so multiple TCP threads can access it safely
     let db_engine = Arc::new(Mutex::new(pager));
 
+    // Shared LISTEN/NOTIFY subscriptions
+    let channels = Arc::new(ChannelRegistry::new());
+
+    let config = Arc::new(config);
+    if let Some(quota) = config.statement_quota {
+        info!("📏 Statement quota: {} per connection", quota);
+    }
+    if !config.index_sync_window.is_zero() {
+        info!(
+            "⏱️ Index writes coalesced over {:?}",
+            config.index_sync_window
+        );
+    }
+    if config.sync_policy != SyncPolicy::OnCommit {
+        info!("💽 Sync policy: {}", config.sync_policy.name());
+    }
+    if config.query_log {
+        info!("📝 Query log enabled");
+    }
+    if config.require_auth {
+        info!("👤 Authentication required");
+    } else {
+        warn!("⚠️ AURA_REQUIRE_AUTH is off: anyone who can connect has full access");
+    }
+    let handshake: Vec<&str> = config.handshake.iter().map(|alg| alg.name()).collect();
+    info!("🤝 Handshake algorithms: {}", handshake.join(", "));
+
     // 3. Start TCP Listener
-    let addr = "0.0.0.0:7654"; // Port 7654 (PQL - Post Quantum Link)
-    let listener = TcpListener::bind(addr).await?;
-    info!("✅ Listening on {} for Secure Connections", addr);
+    let addr = "0.0.0.0:7654".parse()?; // Port 7654 (PQL - Post Quantum Link)
+    let listener = server::bind(addr, &config)?;
+    info!(
+        "✅ Listening on {} for Secure Connections (backlog {}{})",
+        addr,
+        config.listen_backlog,
+        if config.reuse_port {
+            ", SO_REUSEPORT"
+        } else {
+            ""
+        }
+    );
 
-    loop {
-        // 4. Accept Incoming Connection
-        let (socket, remote_addr) = listener.accept().await?;
-        info!("🔗 New connection from {}", remote_addr);
+    // 4. Serve until Ctrl-C / SIGTERM, then finish in-flight work and sync
+    server::serve(listener, db_engine, channels, config, shutdown_signal()).await
+}
 
-        let db_ref = db_engine.clone();
+/// Completes on Ctrl-C, or on SIGTERM (what systemd sends) where there is one
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
 
-        // 5. Spawn a dedicated async task for this client
-        tokio::spawn(async move {
-            if let Err(e) = connection::handle_socket(socket, db_ref).await {
-                error!("❌ Connection Error [{}]: {}", remote_addr, e);
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
             }
-        });
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
     }
 }
@@ -0,0 +1,112 @@
+use crate::protocol::Frame;
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Session-level pub/sub commands, handled by the connection (not the QueryEngine)
+#[derive(Debug, PartialEq)]
+pub enum PubSubCommand {
+    Listen(String),
+    Unlisten(String),
+    Notify { channel: String, payload: String },
+}
+
+impl PubSubCommand {
+    /// Recognizes `LISTEN ch`, `UNLISTEN ch` and `NOTIFY ch[, 'payload']`.
+    /// Returns None for anything else so it can go to the SQL engine.
+    pub fn parse(input: &str) -> Option<Result<Self, String>> {
+        let input = input.trim().trim_end_matches(';').trim();
+        let (keyword, rest) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+        let rest = rest.trim();
+
+        let command = match keyword.to_ascii_uppercase().as_str() {
+            "LISTEN" => Self::channel_name(rest).map(Self::Listen),
+            "UNLISTEN" => Self::channel_name(rest).map(Self::Unlisten),
+            "NOTIFY" => {
+                let (channel, payload) = match rest.split_once(',') {
+                    Some((channel, payload)) => (channel.trim(), Self::quoted(payload.trim())),
+                    None => (rest, Ok(String::new())),
+                };
+                Self::channel_name(channel)
+                    .and_then(|channel| payload.map(|payload| Self::Notify { channel, payload }))
+            }
+            _ => return None,
+        };
+        Some(command)
+    }
+
+    fn channel_name(name: &str) -> Result<String, String> {
+        if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            Ok(name.to_string())
+        } else {
+            Err(format!("Invalid channel name: '{}'", name))
+        }
+    }
+
+    /// Parses a single-quoted SQL string literal ('' is an escaped quote)
+    fn quoted(literal: &str) -> Result<String, String> {
+        literal
+            .strip_prefix('\'')
+            .and_then(|s| s.strip_suffix('\''))
+            .map(|s| s.replace("''", "'"))
+            .ok_or_else(|| format!("NOTIFY payload must be a quoted string: {}", literal))
+    }
+}
+
+struct Listener {
+    conn_id: u64,
+    outbox: UnboundedSender<Frame>,
+}
+
+/// Shared registry of LISTEN subscriptions across all connections.
+/// Channel Name -> Connections waiting for notifications on it.
+#[derive(Default)]
+pub struct ChannelRegistry {
+    channels: DashMap<String, Vec<Listener>>,
+    next_conn_id: AtomicU64,
+}
+
+impl ChannelRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hands out a unique id for a connection's subscriptions
+    pub fn register_connection(&self) -> u64 {
+        self.next_conn_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub fn listen(&self, channel: &str, conn_id: u64, outbox: UnboundedSender<Frame>) {
+        let mut listeners = self.channels.entry(channel.to_string()).or_default();
+        if !listeners.iter().any(|l| l.conn_id == conn_id) {
+            listeners.push(Listener { conn_id, outbox });
+        }
+    }
+
+    pub fn unlisten(&self, channel: &str, conn_id: u64) {
+        if let Some(mut listeners) = self.channels.get_mut(channel) {
+            listeners.retain(|l| l.conn_id != conn_id);
+        }
+        self.channels
+            .remove_if(channel, |_, listeners| listeners.is_empty());
+    }
+
+    /// Drops every subscription of a connection (called on disconnect)
+    pub fn unlisten_all(&self, conn_id: u64) {
+        for mut listeners in self.channels.iter_mut() {
+            listeners.retain(|l| l.conn_id != conn_id);
+        }
+        self.channels.retain(|_, listeners| !listeners.is_empty());
+    }
+
+    /// Pushes a notification to every listener. Returns how many received it.
+    pub fn notify(&self, channel: &str, payload: &str) -> usize {
+        let Some(mut listeners) = self.channels.get_mut(channel) else {
+            return 0;
+        };
+
+        // Sending fails only if the connection is gone; prune those as we go
+        listeners.retain(|l| l.outbox.send(Frame::notification(channel, payload)).is_ok());
+        listeners.len()
+    }
+}
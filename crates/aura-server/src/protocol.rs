@@ -1,3 +1,192 @@
-// In Step 9, we will define packet framing here.
-// e.g. [Length: u32][Type: u8][Payload...]
-pub const PROTOCOL_VERSION: u8 = 1;
+// Packet framing for everything sent after the PQC handshake.
+// Wire format: [Length: u32 (BE)][Type: u8][Payload...]
+// `Length` counts only the payload bytes.
+// Every payload is sealed with the session key (see `Frame::seal`).
+use aura_common::{QueryResponse, QueryResult, QueryStats};
+use aura_security::symmetric::{self, Direction, SessionKey};
+use aura_security::CryptoError;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Sent first in the handshake (v2 added algorithm negotiation, v3 query stats in
+/// responses, v4 session keys derived from the handshake transcript, v5 column
+/// types in result sets, v6 frames sealed with their direction and sequence number,
+/// v7 the offer and the chosen algorithm in the handshake transcript)
+pub const PROTOCOL_VERSION: u8 = 7;
+
+/// Upper bound on a single frame so a bad length prefix can't make us allocate gigabytes
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// Client -> Server: a SQL statement (UTF-8)
+pub const FRAME_QUERY: u8 = 1;
+/// Server -> Client: the reply to the last Query frame (postcard `QueryResponse`)
+pub const FRAME_RESPONSE: u8 = 2;
+/// Server -> Client: pushed at any time to connections that ran LISTEN
+/// Payload: "channel:payload" (UTF-8)
+pub const FRAME_NOTIFICATION: u8 = 3;
+/// Client -> Server: a statement with bound parameters (postcard `QueryRequest`)
+pub const FRAME_QUERY_PARAMS: u8 = 4;
+/// Server -> Client: the last Query frame failed (UTF-8 error message)
+pub const FRAME_ERROR: u8 = 5;
+/// Client -> Server: log in (postcard `AuthRequest`). Answered with a Response or an Error.
+pub const FRAME_AUTH: u8 = 6;
+/// Client -> Server: liveness check, allowed before AUTH. Answered with a Pong.
+pub const FRAME_PING: u8 = 7;
+/// Server -> Client: the reply to a Ping, echoing its payload
+pub const FRAME_PONG: u8 = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub frame_type: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(frame_type: u8, payload: impl Into<Vec<u8>>) -> Self {
+        Self {
+            frame_type,
+            payload: payload.into(),
+        }
+    }
+
+    /// Encodes a successful result that ran no SQL (AUTH, LISTEN...) as a Response frame
+    pub fn response(result: &QueryResult) -> Self {
+        Self::encode_response(result.clone(), None)
+    }
+
+    /// Encodes a successful statement result, with what it cost, as a Response frame
+    pub fn response_with_stats(result: QueryResult, stats: QueryStats) -> Self {
+        Self::encode_response(result, Some(stats))
+    }
+
+    fn encode_response(result: QueryResult, stats: Option<QueryStats>) -> Self {
+        match (QueryResponse { result, stats }).to_bytes() {
+            Ok(bytes) => Self::new(FRAME_RESPONSE, bytes),
+            Err(e) => Self::error(format!("Failed to encode result: {}", e)),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(FRAME_ERROR, message.into())
+    }
+
+    pub fn notification(channel: &str, payload: &str) -> Self {
+        Self::new(FRAME_NOTIFICATION, format!("{}:{}", channel, payload))
+    }
+
+    /// Encrypts the payload with the session key: [Nonce | Ciphertext | Tag].
+    /// The frame type stays readable so the peer knows what to expect; it is
+    /// authenticated along with the direction and the frame's sequence number
+    /// in that direction (see `symmetric::frame_aad`).
+    pub fn seal(
+        &self,
+        key: &SessionKey,
+        direction: Direction,
+        seq: u64,
+    ) -> Result<Self, CryptoError> {
+        let aad = symmetric::frame_aad(direction, seq, self.frame_type);
+        let payload = symmetric::encrypt_with_aad(&self.payload, key.as_bytes(), &aad)?;
+        Ok(Self::new(self.frame_type, payload))
+    }
+
+    /// Reverses `seal`. Fails if the payload or type was modified, or it was sealed
+    /// with another key, for the other direction or at another position.
+    pub fn open(
+        &self,
+        key: &SessionKey,
+        direction: Direction,
+        seq: u64,
+    ) -> Result<Self, CryptoError> {
+        let aad = symmetric::frame_aad(direction, seq, self.frame_type);
+        let payload = symmetric::decrypt_with_aad(&self.payload, key.as_bytes(), &aad)?;
+        Ok(Self::new(self.frame_type, payload))
+    }
+}
+
+/// One end of an encrypted session: the key, and how many frames went each way.
+/// Frames must be opened in the order they were sealed; anything replayed,
+/// dropped, reordered or sent back at us fails to open.
+pub struct SecureChannel {
+    key: SessionKey,
+    outgoing: Direction,
+    sent: u64,
+    received: u64,
+}
+
+impl SecureChannel {
+    /// The server's end of a session
+    pub fn server(key: SessionKey) -> Self {
+        Self::new(key, Direction::ServerToClient)
+    }
+
+    /// The client's end of a session
+    pub fn client(key: SessionKey) -> Self {
+        Self::new(key, Direction::ClientToServer)
+    }
+
+    fn new(key: SessionKey, outgoing: Direction) -> Self {
+        Self {
+            key,
+            outgoing,
+            sent: 0,
+            received: 0,
+        }
+    }
+
+    pub fn key(&self) -> &SessionKey {
+        &self.key
+    }
+
+    /// Seals the next outgoing frame
+    pub fn seal(&mut self, frame: &Frame) -> Result<Frame, CryptoError> {
+        let sealed = frame.seal(&self.key, self.outgoing, self.sent)?;
+        self.sent += 1;
+        Ok(sealed)
+    }
+
+    /// Opens the next incoming frame
+    pub fn open(&mut self, frame: &Frame) -> Result<Frame, CryptoError> {
+        let opened = frame.open(&self.key, self.outgoing.reverse(), self.received)?;
+        self.received += 1;
+        Ok(opened)
+    }
+}
+
+/// Reads one frame. Returns Ok(None) if the peer closed the connection cleanly.
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> std::io::Result<Option<Frame>> {
+    let mut header = [0u8; 5];
+    match reader.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Frame too large: {} bytes", len),
+        ));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+
+    Ok(Some(Frame {
+        frame_type: header[4],
+        payload,
+    }))
+}
+
+/// Writes one frame (header + payload) and flushes it.
+pub async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    frame: &Frame,
+) -> std::io::Result<()> {
+    let mut bytes = Vec::with_capacity(5 + frame.payload.len());
+    bytes.extend_from_slice(&(frame.payload.len() as u32).to_be_bytes());
+    bytes.push(frame.frame_type);
+    bytes.extend_from_slice(&frame.payload);
+
+    writer.write_all(&bytes).await?;
+    writer.flush().await
+}
@@ -0,0 +1,121 @@
+// The accept loop, and how it winds down.
+use crate::config::ServerConfig;
+use crate::connection;
+use crate::notify::ChannelRegistry;
+use anyhow::Result;
+use aura_store::pager::Pager;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
+use tracing::{error, info, warn};
+
+/// How long shutdown waits for open connections before dropping them
+pub const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// Pause after a failed accept, so running out of file descriptors doesn't spin
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Binds the listening socket: SO_REUSEADDR always (a restart needn't wait for the
+/// old connections' TIME_WAIT), SO_REUSEPORT if configured, and the configured backlog.
+/// Must be called from within the Tokio runtime.
+pub fn bind(addr: SocketAddr, config: &ServerConfig) -> Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    if config.reuse_port {
+        #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+        socket.set_reuse_port(true)?;
+        #[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos")))))]
+        anyhow::bail!("SO_REUSEPORT is not available on this platform");
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    // The kernel caps it (somaxconn), so clamping to i32 loses nothing
+    socket.listen(config.listen_backlog.min(i32::MAX as u32) as i32)?;
+    Ok(TcpListener::from_std(socket.into())?)
+}
+
+/// Accepts connections until `shutdown` completes. Then stops accepting, gives
+/// the open connections `SHUTDOWN_GRACE` to finish, and syncs the index one
+/// last time so every acknowledged write is on disk when this returns.
+pub async fn serve(
+    listener: TcpListener,
+    db: Arc<Mutex<Pager>>,
+    channels: Arc<ChannelRegistry>,
+    config: Arc<ServerConfig>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    let mut connections = JoinSet::new();
+    tokio::pin!(shutdown);
+
+    // Writes the index the statements of the last window held back
+    let window = config.index_sync_window;
+    let mut index_flush = tokio::time::interval(window.max(Duration::from_millis(1)));
+    index_flush.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+
+            _ = index_flush.tick(), if !window.is_zero() => {
+                let mut pager = db.lock().await;
+                pager.begin();
+                let flushed = pager.flush_index();
+                if let Err(e) = flushed.and_then(|()| pager.commit()) {
+                    error!("❌ Index flush failed: {}", e);
+                }
+            }
+
+            // Reap finished connections so the set doesn't grow forever
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+
+            accepted = listener.accept() => {
+                // A failed accept (out of file descriptors, a connection reset
+                // while queued...) is that connection's problem, not the listener's
+                let (socket, remote_addr) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!("⚠️ Accept failed: {}", e);
+                        tokio::time::sleep(ACCEPT_BACKOFF).await;
+                        continue;
+                    }
+                };
+                info!("🔗 New connection from {}", remote_addr);
+
+                let (db, channels, config) = (db.clone(), channels.clone(), config.clone());
+                connections.spawn(async move {
+                    if let Err(e) = connection::handle_socket(socket, db, channels, config).await {
+                        error!("❌ Connection Error [{}]: {}", remote_addr, e);
+                    }
+                });
+            }
+        }
+    }
+
+    info!(
+        "🛑 Shutting down, waiting for {} connection(s)...",
+        connections.len()
+    );
+    drop(listener);
+    let drained = tokio::time::timeout(SHUTDOWN_GRACE, async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_err() {
+        warn!(
+            "⏱️ Closing {} connection(s) that didn't finish",
+            connections.len()
+        );
+        connections.shutdown().await;
+    }
+
+    let mut pager = db.lock().await;
+    pager.flush_index()?;
+    pager.flush()?;
+    info!("💾 Index synced. Goodbye.");
+    Ok(())
+}
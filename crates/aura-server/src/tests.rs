@@ -1,13 +1,161 @@
 #[cfg(test)]
 #[allow(clippy::module_inception)]
 mod tests {
+    use crate::auth;
+    use crate::config::{keyfile_from_args, ServerConfig, DEFAULT_KEYFILE};
+    use crate::keystore;
+    use crate::notify::{ChannelRegistry, PubSubCommand};
+    use crate::protocol::{
+        self, Frame, SecureChannel, FRAME_AUTH, FRAME_ERROR, FRAME_NOTIFICATION, FRAME_PING,
+        FRAME_PONG, FRAME_QUERY, FRAME_QUERY_PARAMS, FRAME_RESPONSE,
+    };
+    use crate::server::{bind, serve};
     use aura_common::document::{AuraDocument, DataValue};
-    use aura_security::symmetric;
-    use aura_store::pager::Pager;
+    use aura_common::{AuthRequest, QueryRequest, QueryResponse, QueryResult};
+    use aura_query::executor::QueryEngine;
+    use aura_security::homomorphic::{FheComputer, FheContext};
+    use aura_security::symmetric::{Direction, SessionKey, KEY_SIZE};
+    use aura_security::{kem, password, symmetric, KemAlgorithm};
+    use aura_store::pager::{Pager, PagerOptions};
+    use aura_store::wal::Wal;
     use std::fs;
+    use std::net::SocketAddr;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::Duration;
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
     use tokio::net::TcpListener;
     use tokio::net::TcpStream;
+    use tokio::sync::{oneshot, Mutex};
+
+    /// Starts a real server (handshake + command loop) on a free port
+    async fn spawn_test_server(db_path: &str) -> SocketAddr {
+        spawn_test_server_with_config(db_path, open_access()).await
+    }
+
+    /// For tests that don't log in: statements run without AUTH
+    fn open_access() -> ServerConfig {
+        ServerConfig {
+            require_auth: false,
+            ..ServerConfig::default()
+        }
+    }
+
+    async fn spawn_test_server_with_config(db_path: &str, config: ServerConfig) -> SocketAddr {
+        let _ = fs::remove_file(db_path);
+        let pager = Pager::open(db_path, symmetric::generate_key()).unwrap();
+        spawn_test_server_on(pager, config).await
+    }
+
+    /// Serves an already opened database (e.g. one with logins set up)
+    async fn spawn_test_server_on(pager: Pager, config: ServerConfig) -> SocketAddr {
+        spawn_test_server_sharing(Arc::new(Mutex::new(pager)), config).await
+    }
+
+    /// Serves a database the test can lock too
+    async fn spawn_test_server_sharing(db: Arc<Mutex<Pager>>, config: ServerConfig) -> SocketAddr {
+        let channels = Arc::new(ChannelRegistry::new());
+        let config = Arc::new(config);
+
+        let listener = bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(serve(
+            listener,
+            db,
+            channels,
+            config,
+            std::future::pending(),
+        ));
+        addr
+    }
+
+    /// A connection past the handshake: frames are sealed with its session key
+    struct TestClient {
+        stream: TcpStream,
+        channel: SecureChannel,
+    }
+
+    impl TestClient {
+        async fn write_frame(&mut self, frame: &Frame) {
+            let sealed = self.channel.seal(frame).unwrap();
+            protocol::write_frame(&mut self.stream, &sealed)
+                .await
+                .unwrap();
+        }
+
+        /// None once the server has closed the connection
+        async fn read_frame(&mut self) -> Option<Frame> {
+            let frame = protocol::read_frame(&mut self.stream).await.unwrap()?;
+            Some(self.channel.open(&frame).unwrap())
+        }
+    }
+
+    /// Connects and performs the client side of the handshake
+    async fn connect_test_client(addr: SocketAddr) -> TestClient {
+        connect_test_client_with(addr, &KemAlgorithm::ALL).await.0
+    }
+
+    /// Handshakes as a client that only supports `supported`.
+    /// Returns the client and the key exchange that was negotiated.
+    async fn connect_test_client_with(
+        addr: SocketAddr,
+        supported: &[KemAlgorithm],
+    ) -> (TestClient, KemAlgorithm) {
+        connect_test_client_seeing(addr, supported, |hello| hello).await
+    }
+
+    /// Handshakes like `connect_test_client_with`, but as if the server's hello
+    /// had been rewritten by `tamper` on its way over
+    async fn connect_test_client_seeing(
+        addr: SocketAddr,
+        supported: &[KemAlgorithm],
+        tamper: impl FnOnce(Vec<u8>) -> Vec<u8>,
+    ) -> (TestClient, KemAlgorithm) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        let mut header = [0u8; 2];
+        stream.read_exact(&mut header).await.unwrap();
+        assert_eq!(header[0], protocol::PROTOCOL_VERSION);
+        let mut hello = vec![0u8; 2 + header[1] as usize];
+        hello[..2].copy_from_slice(&header);
+        stream.read_exact(&mut hello[2..]).await.unwrap();
+        let hello = tamper(hello);
+        let offered: Vec<KemAlgorithm> = hello[2..]
+            .iter()
+            .copied()
+            .filter_map(KemAlgorithm::from_id)
+            .collect();
+
+        let algorithm = kem::negotiate(&offered, supported).expect("no common algorithm");
+        stream.write_all(&[algorithm.id()]).await.unwrap();
+
+        let mut pk = vec![0u8; algorithm.public_key_len()];
+        stream.read_exact(&mut pk).await.unwrap();
+        let (secret, ciphertext) = match algorithm {
+            KemAlgorithm::Kyber1024 => kem::encapsulate(&pk).unwrap(),
+            KemAlgorithm::HybridX25519Kyber1024 => kem::hybrid_encapsulate(&pk).unwrap(),
+        };
+        stream.write_all(&ciphertext).await.unwrap();
+        let transcript = kem::handshake_transcript(&hello, algorithm, &pk, &ciphertext);
+        let key = SessionKey::derive(secret, &transcript);
+        let channel = SecureChannel::client(key);
+        (TestClient { stream, channel }, algorithm)
+    }
+
+    async fn send_query(client: &mut TestClient, sql: &str) -> Result<QueryResult, String> {
+        client.write_frame(&Frame::new(FRAME_QUERY, sql)).await;
+        let frame = client.read_frame().await.unwrap();
+        decode_response(frame)
+    }
+
+    fn decode_response(frame: Frame) -> Result<QueryResult, String> {
+        match frame.frame_type {
+            FRAME_RESPONSE => Ok(QueryResponse::from_bytes(&frame.payload).unwrap().result),
+            FRAME_ERROR => Err(String::from_utf8(frame.payload).unwrap()),
+            other => panic!("Unexpected frame type {}", other),
+        }
+    }
 
     #[tokio::test]
     async fn test_tcp_listener_creation() {
@@ -39,12 +187,14 @@ mod tests {
         let state = ConnectionState::Handshake;
         assert!(matches!(state, ConnectionState::Handshake));
 
-        let state = ConnectionState::Authenticated {
-            session_key: vec![1, 2, 3],
+        // The session key has to be a full-length key
+        assert!(SessionKey::try_from(vec![1, 2, 3]).is_err());
+        let state = ConnectionState::Encrypted {
+            session_key: SessionKey::try_from(vec![7; KEY_SIZE]).unwrap(),
         };
         assert!(matches!(
             state,
-            ConnectionState::Authenticated { session_key: _ }
+            ConnectionState::Encrypted { session_key: _ }
         ));
     }
 
@@ -141,4 +291,780 @@ mod tests {
         let default_host = "127.0.0.1";
         assert!(!default_host.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_listen_notify_pushes_to_listener() {
+        let db_path = "test_server_notify.db";
+        let addr = spawn_test_server(db_path).await;
+
+        let mut listener = connect_test_client(addr).await;
+        let mut notifier = connect_test_client(addr).await;
+
+        assert_eq!(
+            send_query(&mut listener, "LISTEN orders").await,
+            Ok(QueryResult::Message("LISTEN orders".to_string()))
+        );
+        assert_eq!(
+            send_query(&mut notifier, "NOTIFY orders, 'order_42 shipped'").await,
+            Ok(QueryResult::Message("NOTIFY orders".to_string()))
+        );
+
+        // The listener receives the push without sending anything
+        let frame = tokio::time::timeout(Duration::from_secs(5), listener.read_frame())
+            .await
+            .expect("Notification not delivered")
+            .unwrap();
+        assert_eq!(frame.frame_type, FRAME_NOTIFICATION);
+        assert_eq!(frame.payload, b"orders:order_42 shipped");
+
+        // Regular SQL still works on the listening connection
+        let response = send_query(&mut listener, "SELECT * FROM users").await;
+        assert!(matches!(response, Ok(QueryResult::Rows { .. })));
+
+        // Errors come back as their own frame type
+        let response = send_query(&mut listener, "DELETE FROM users").await;
+        assert!(response.unwrap_err().contains("Not Implemented"));
+
+        // Cleanup
+        fs::remove_file(db_path).unwrap();
+    }
+
+    #[test]
+    fn test_pubsub_command_parsing() {
+        assert_eq!(
+            PubSubCommand::parse("listen orders;"),
+            Some(Ok(PubSubCommand::Listen("orders".to_string())))
+        );
+        assert_eq!(
+            PubSubCommand::parse("NOTIFY orders, 'it''s here'"),
+            Some(Ok(PubSubCommand::Notify {
+                channel: "orders".to_string(),
+                payload: "it's here".to_string(),
+            }))
+        );
+        assert!(matches!(
+            PubSubCommand::parse("NOTIFY orders, unquoted"),
+            Some(Err(_))
+        ));
+        assert!(PubSubCommand::parse("SELECT * FROM users").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_parameterized_query_over_the_wire() {
+        let db_path = "test_server_params.db";
+        let addr = spawn_test_server(db_path).await;
+        let mut client = connect_test_client(addr).await;
+
+        let request = QueryRequest::new(
+            "INSERT INTO users (id, name) VALUES (?, ?)",
+            vec![
+                DataValue::Text("user_1".to_string()),
+                DataValue::Text("Robert'); DROP TABLE users;--".to_string()),
+            ],
+        );
+        let frame = Frame::new(FRAME_QUERY_PARAMS, request.to_bytes().unwrap());
+        client.write_frame(&frame).await;
+        let response = client.read_frame().await.unwrap();
+        assert_eq!(
+            decode_response(response),
+            Ok(QueryResult::Inserted {
+                id: "user_1".to_string()
+            })
+        );
+
+        let response = send_query(&mut client, "SELECT name FROM users WHERE id = 'user_1'").await;
+        let QueryResult::Rows { columns, rows, .. } = response.unwrap() else {
+            panic!("Expected rows");
+        };
+        let name = columns.iter().position(|c| c == "name").unwrap();
+        assert_eq!(
+            rows[0][name],
+            DataValue::Text("Robert'); DROP TABLE users;--".to_string())
+        );
+
+        // Cleanup
+        fs::remove_file(db_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_index_writes_coalesce_within_the_window() {
+        let db_path = "test_server_index_window.db";
+        let _ = fs::remove_file(db_path);
+        let window = Duration::from_millis(300);
+
+        let key = symmetric::generate_key();
+        let options = PagerOptions {
+            index_sync_window: window,
+            ..PagerOptions::default()
+        };
+        let pager = Pager::open_with_options(db_path, key.clone(), options).unwrap();
+        let db = Arc::new(Mutex::new(pager));
+        let config = ServerConfig {
+            index_sync_window: window,
+            ..open_access()
+        };
+        let addr = spawn_test_server_sharing(db.clone(), config).await;
+        let mut client = connect_test_client(addr).await;
+
+        // Each insert writes one data page, plus the index pages when it syncs them
+        let inserts = 10;
+        let mut pages_written = 0;
+        for i in 0..inserts {
+            let sql = format!("INSERT INTO events (id, n) VALUES ('e{}', {})", i, i);
+            client.write_frame(&Frame::new(FRAME_QUERY, sql)).await;
+            let frame = client.read_frame().await.unwrap();
+            assert_eq!(frame.frame_type, FRAME_RESPONSE);
+            let response = QueryResponse::from_bytes(&frame.payload).unwrap();
+            pages_written += response.stats.unwrap().pages_written;
+        }
+        let index_writes = pages_written - inserts;
+        assert!(index_writes < inserts, "{} index writes", index_writes);
+
+        // Within a window of the burst, every insert is in the index on disk
+        // (read from a copy: the server's Pager keeps the file locked)
+        tokio::time::sleep(window * 2).await;
+        let copy_path = "test_server_index_window_copy.db";
+        {
+            let _db = db.lock().await;
+            fs::copy(db_path, copy_path).unwrap();
+            fs::copy(
+                Wal::path_for(Path::new(db_path)),
+                Wal::path_for(Path::new(copy_path)),
+            )
+            .unwrap();
+        }
+        let mut on_disk = Pager::open(copy_path, key).unwrap();
+        for i in 0..inserts {
+            assert!(on_disk.index.get(&format!("events/e{}", i)).is_some());
+        }
+        assert!(on_disk.read_page(0).is_ok());
+
+        // Cleanup
+        drop(client);
+        fs::remove_file(db_path).unwrap();
+        fs::remove_file(copy_path).unwrap();
+        let _ = fs::remove_file(Wal::path_for(Path::new(copy_path)));
+    }
+
+    #[tokio::test]
+    async fn test_statement_quota_closes_connection() {
+        let db_path = "test_server_quota.db";
+        let config = ServerConfig {
+            statement_quota: Some(2),
+            ..open_access()
+        };
+        let addr = spawn_test_server_with_config(db_path, config).await;
+        let mut client = connect_test_client(addr).await;
+
+        assert!(send_query(&mut client, "SELECT * FROM users").await.is_ok());
+        assert!(send_query(&mut client, "LISTEN orders").await.is_ok());
+
+        // The third statement is refused and the server hangs up
+        let response = send_query(&mut client, "SELECT * FROM users").await;
+        assert!(response.unwrap_err().contains("quota exceeded"));
+        assert!(client.read_frame().await.is_none());
+
+        // The quota is per connection: a new one starts from zero
+        let mut other = connect_test_client(addr).await;
+        assert!(send_query(&mut other, "SELECT * FROM users").await.is_ok());
+
+        // Cleanup
+        fs::remove_file(db_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_temp_tables_are_private_to_their_connection() {
+        let db_path = "test_server_temp.db";
+        let addr = spawn_test_server(db_path).await;
+        let mut owner = connect_test_client(addr).await;
+        let mut other = connect_test_client(addr).await;
+
+        assert_eq!(
+            send_query(
+                &mut owner,
+                "CREATE TEMP TABLE scratch (id TEXT, score INTEGER)"
+            )
+            .await,
+            Ok(QueryResult::Message(
+                "CREATE TEMP TABLE scratch".to_string()
+            ))
+        );
+        send_query(
+            &mut owner,
+            "INSERT INTO scratch (id, score) VALUES ('a', 1)",
+        )
+        .await
+        .unwrap();
+        let (_, rows) = rows_of(send_query(&mut owner, "SELECT * FROM scratch").await);
+        assert_eq!(rows.len(), 1);
+
+        // The other connection neither sees the rows nor the declared columns
+        let (_, rows) = rows_of(send_query(&mut other, "SELECT * FROM scratch").await);
+        assert!(rows.is_empty());
+        send_query(
+            &mut other,
+            "INSERT INTO scratch (id, note) VALUES ('b', 'x')",
+        )
+        .await
+        .unwrap();
+
+        // Once the owner disconnects, its temp table is gone for good
+        drop(owner);
+        let mut owner = connect_test_client(addr).await;
+        let (_, rows) = rows_of(send_query(&mut owner, "SELECT id FROM scratch").await);
+        assert_eq!(rows, vec![vec![DataValue::Text("b".to_string())]]);
+
+        // Cleanup
+        fs::remove_file(db_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_transactions_must_end_within_their_request() {
+        let db_path = "test_server_transactions.db";
+        let addr = spawn_test_server(db_path).await;
+        let mut client = connect_test_client(addr).await;
+        let count = |response| rows_of(response).1.len();
+
+        // Left open after an INSERT: an error, and the row is gone
+        let response = send_query(
+            &mut client,
+            "BEGIN; INSERT INTO users (id, name) VALUES ('u1', 'Ann')",
+        )
+        .await;
+        assert!(
+            matches!(&response, Err(msg) if msg.contains("rolled back")),
+            "{:?}",
+            response
+        );
+        assert_eq!(
+            count(send_query(&mut client, "SELECT id FROM users").await),
+            0
+        );
+
+        // A BEGIN on its own, as a shell sends it, is refused the same way
+        let response = send_query(&mut client, "BEGIN").await;
+        assert!(matches!(&response, Err(msg) if msg.contains("rolled back")));
+        let response = send_query(&mut client, "COMMIT").await;
+        assert!(matches!(&response, Err(msg) if msg.contains("without a transaction")));
+
+        // In one request, the transaction commits
+        send_query(
+            &mut client,
+            "BEGIN; INSERT INTO users (id, name) VALUES ('u2', 'Bob'); COMMIT",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            count(send_query(&mut client, "SELECT id FROM users").await),
+            1
+        );
+
+        fs::remove_file(db_path).unwrap();
+    }
+
+    fn rows_of(response: Result<QueryResult, String>) -> (Vec<String>, Vec<Vec<DataValue>>) {
+        match response {
+            Ok(QueryResult::Rows { columns, rows, .. }) => (columns, rows),
+            other => panic!("Expected rows, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fhe_sum_is_cancelled_when_its_client_leaves() {
+        let db_path = "test_server_fhe_cancel.db";
+        let _ = fs::remove_file(db_path);
+        let fhe = FheContext::new();
+        let config = ServerConfig {
+            fhe: Some(FheComputer::new(fhe.get_server_key())),
+            ..open_access()
+        };
+        let pager = Pager::open(db_path, symmetric::generate_key()).unwrap();
+        let db = Arc::new(Mutex::new(pager));
+        let addr = spawn_test_server_sharing(db.clone(), config).await;
+        let mut client = connect_test_client(addr).await;
+
+        for (id, balance) in [("acc_1", 10), ("acc_2", 20), ("acc_3", 30)] {
+            let request = QueryRequest::new(
+                "INSERT INTO accounts (id, balance) VALUES (?, ?)",
+                vec![
+                    DataValue::Text(id.to_string()),
+                    DataValue::Encrypted(fhe.encrypt_u32(balance).unwrap()),
+                ],
+            );
+            let frame = Frame::new(FRAME_QUERY_PARAMS, request.to_bytes().unwrap());
+            client.write_frame(&frame).await;
+            decode_response(client.read_frame().await.unwrap()).unwrap();
+        }
+        let sum = "SELECT FHE_SUM(balance) FROM accounts";
+        let (_, rows) = rows_of(send_query(&mut client, sum).await);
+        let DataValue::Encrypted(total) = &rows[0][0] else {
+            panic!("expected a ciphertext, got {:?}", rows[0][0]);
+        };
+        assert_eq!(fhe.decrypt_u32(total).unwrap(), 60);
+
+        // The SUM_ENC spelling takes the same path
+        let (columns, rows) =
+            rows_of(send_query(&mut client, "SELECT SUM_ENC(balance) FROM accounts").await);
+        assert_eq!(columns, vec!["SUM_ENC(balance)"]);
+        let DataValue::Encrypted(total) = &rows[0][0] else {
+            panic!("expected a ciphertext, got {:?}", rows[0][0]);
+        };
+        assert_eq!(fhe.decrypt_u32(total).unwrap(), 60);
+
+        // The client hangs up while its FHE_SUM is underway (held up on the database)
+        let db_lock = db.lock().await;
+        client.write_frame(&Frame::new(FRAME_QUERY, sum)).await;
+        client.stream.shutdown().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        drop(db_lock);
+
+        // The sum is abandoned and the connection closed, with no answer sent
+        let closed = tokio::time::timeout(Duration::from_secs(5), closed(&mut client));
+        assert!(closed.await.unwrap());
+
+        // Nothing is left holding the database
+        let mut other = connect_test_client(addr).await;
+        let (_, rows) = rows_of(send_query(&mut other, "SELECT COUNT(*) FROM accounts").await);
+        assert_eq!(rows, vec![vec![DataValue::Integer(3)]]);
+
+        // Cleanup
+        fs::remove_file(db_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_handshake_negotiates_hybrid_or_falls_back_to_kyber() {
+        let addr = spawn_test_server("test_handshake.db").await;
+
+        // Both sides support the hybrid exchange, so it is used
+        let (mut client, algorithm) = connect_test_client_with(addr, &KemAlgorithm::ALL).await;
+        assert_eq!(algorithm, KemAlgorithm::HybridX25519Kyber1024);
+        assert!(send_query(&mut client, "SELECT * FROM users").await.is_ok());
+
+        // A pure-Kyber client still gets in
+        let (mut client, algorithm) =
+            connect_test_client_with(addr, &[KemAlgorithm::Kyber1024]).await;
+        assert_eq!(algorithm, KemAlgorithm::Kyber1024);
+        assert!(send_query(&mut client, "SELECT * FROM users").await.is_ok());
+
+        // And so does a hybrid-capable client talking to a pure-Kyber server
+        let config = ServerConfig {
+            handshake: vec![KemAlgorithm::Kyber1024],
+            ..open_access()
+        };
+        let kyber_addr = spawn_test_server_with_config("test_handshake_kyber.db", config).await;
+        let (mut client, algorithm) =
+            connect_test_client_with(kyber_addr, &KemAlgorithm::ALL).await;
+        assert_eq!(algorithm, KemAlgorithm::Kyber1024);
+        assert!(send_query(&mut client, "SELECT * FROM users").await.is_ok());
+
+        let _ = fs::remove_file("test_handshake.db");
+        let _ = fs::remove_file("test_handshake_kyber.db");
+    }
+
+    #[tokio::test]
+    async fn test_handshake_yields_matching_session_keys() {
+        for algorithm in KemAlgorithm::ALL {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server = tokio::spawn(async move {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                crate::connection::handshake(&mut socket, &[algorithm])
+                    .await
+                    .unwrap()
+                    .unwrap()
+            });
+
+            let (client, negotiated) = connect_test_client_with(addr, &KemAlgorithm::ALL).await;
+            let server_key = server.await.unwrap();
+
+            assert_eq!(negotiated, algorithm);
+            assert_eq!(server_key.as_bytes().len(), KEY_SIZE);
+            assert_eq!(server_key.as_bytes(), client.channel.key().as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handshake_binds_the_offer_into_the_key() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            crate::connection::handshake(&mut socket, &KemAlgorithm::ALL)
+                .await
+                .unwrap()
+                .unwrap()
+        });
+
+        // A man in the middle strips the hybrid exchange from the offer. Kyber is
+        // still offered, so the exchange itself goes through...
+        let strip_hybrid = |hello: Vec<u8>| {
+            let kyber = KemAlgorithm::Kyber1024.id();
+            vec![hello[0], 1, kyber]
+        };
+        let (client, negotiated) =
+            connect_test_client_seeing(addr, &KemAlgorithm::ALL, strip_hybrid).await;
+        let server_key = server.await.unwrap();
+        assert_eq!(negotiated, KemAlgorithm::Kyber1024);
+
+        // ...but the two ends saw different offers, so their keys don't match
+        assert_ne!(server_key.as_bytes(), client.channel.key().as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_session_traffic_is_encrypted() {
+        let db_path = "test_server_encrypted.db";
+        let addr = spawn_test_server(db_path).await;
+        let mut client = connect_test_client(addr).await;
+
+        // Capture exactly what goes on the wire for the INSERT
+        let sql = "INSERT INTO users (id, name) VALUES ('u1', 'Plaintext Paula')";
+        let sealed = client.channel.seal(&Frame::new(FRAME_QUERY, sql)).unwrap();
+        let mut wire = Vec::new();
+        protocol::write_frame(&mut wire, &sealed).await.unwrap();
+        assert!(!contains(&wire, sql.as_bytes()));
+        assert!(!contains(&wire, b"Plaintext Paula"));
+
+        // The server decrypts it and runs it; the response is sealed too
+        client.stream.write_all(&wire).await.unwrap();
+        let response = protocol::read_frame(&mut client.stream)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!contains(&response.payload, b"u1"));
+        assert_eq!(
+            decode_response(client.channel.open(&response).unwrap()),
+            Ok(QueryResult::Inserted { id: "u1".into() })
+        );
+        let (_, rows) = rows_of(send_query(&mut client, "SELECT name FROM users").await);
+        assert_eq!(rows, vec![vec![DataValue::Text("Plaintext Paula".into())]]);
+
+        // A frame that doesn't decrypt with the session key closes the connection
+        protocol::write_frame(
+            &mut client.stream,
+            &Frame::new(FRAME_QUERY, "SELECT * FROM users"),
+        )
+        .await
+        .unwrap();
+        assert!(protocol::read_frame(&mut client.stream)
+            .await
+            .unwrap()
+            .is_none());
+
+        // Cleanup
+        fs::remove_file(db_path).unwrap();
+    }
+
+    /// True if the server hangs up instead of sending another frame
+    async fn closed(client: &mut TestClient) -> bool {
+        protocol::read_frame(&mut client.stream)
+            .await
+            .unwrap()
+            .is_none()
+    }
+
+    #[tokio::test]
+    async fn test_frames_are_bound_to_their_order_and_direction() {
+        let db_path = "test_server_frame_binding.db";
+        let addr = spawn_test_server(db_path).await;
+        let ping = Frame::new(FRAME_PING, "hello");
+
+        // A frame opens only with the direction, position and type it was sealed with
+        let key = connect_test_client(addr).await.channel.key().clone();
+        let sealed = ping.seal(&key, Direction::ClientToServer, 3).unwrap();
+        assert_eq!(
+            sealed.open(&key, Direction::ClientToServer, 3).unwrap(),
+            ping
+        );
+        assert!(sealed.open(&key, Direction::ServerToClient, 3).is_err());
+        assert!(sealed.open(&key, Direction::ClientToServer, 2).is_err());
+        let retyped = Frame::new(FRAME_QUERY, sealed.payload.clone());
+        assert!(retyped.open(&key, Direction::ClientToServer, 3).is_err());
+
+        // Replay: the same sealed frame sent twice
+        let mut client = connect_test_client(addr).await;
+        let sealed = client.channel.seal(&ping).unwrap();
+        protocol::write_frame(&mut client.stream, &sealed)
+            .await
+            .unwrap();
+        assert_eq!(client.read_frame().await.unwrap().frame_type, FRAME_PONG);
+        protocol::write_frame(&mut client.stream, &sealed)
+            .await
+            .unwrap();
+        assert!(closed(&mut client).await);
+
+        // Reordering: the second frame arrives first
+        let mut client = connect_test_client(addr).await;
+        let _first = client.channel.seal(&ping).unwrap();
+        let second = client.channel.seal(&ping).unwrap();
+        protocol::write_frame(&mut client.stream, &second)
+            .await
+            .unwrap();
+        assert!(closed(&mut client).await);
+
+        // Reflection: a frame sealed the way the server seals its own
+        let mut client = connect_test_client(addr).await;
+        let reflected = ping
+            .seal(client.channel.key(), Direction::ServerToClient, 0)
+            .unwrap();
+        protocol::write_frame(&mut client.stream, &reflected)
+            .await
+            .unwrap();
+        assert!(closed(&mut client).await);
+
+        // Cleanup
+        fs::remove_file(db_path).unwrap();
+    }
+
+    #[test]
+    fn test_master_key_persists_across_restarts() {
+        let db_path = "test_server_keyfile.db";
+        let key_path = Path::new("test_server_keyfile.key");
+        let _ = fs::remove_file(db_path);
+        let _ = fs::remove_file(key_path);
+
+        // First start: the key is generated and written out
+        let key = keystore::load_or_create(key_path, None).unwrap();
+        let mut pager = Pager::open(db_path, key).unwrap();
+        QueryEngine::new(&mut pager)
+            .execute("INSERT INTO users (id, name) VALUES ('u1', 'Ann')")
+            .unwrap();
+        drop(pager);
+
+        // Restart: the same key comes back from disk and the data still decrypts
+        let key = keystore::load_or_create(key_path, None).unwrap();
+        let mut pager = Pager::open(db_path, key).unwrap();
+        let result = QueryEngine::new(&mut pager)
+            .execute("SELECT name FROM users WHERE id = 'u1'")
+            .unwrap();
+        assert_eq!(
+            rows_of(Ok(result)).1,
+            vec![vec![DataValue::Text("Ann".into())]]
+        );
+        fs::remove_file(key_path).unwrap();
+
+        // A passphrase-protected key file only opens with its passphrase
+        let sealed_key = keystore::load_or_create(key_path, Some("hunter2")).unwrap();
+        assert_ne!(fs::read(key_path).unwrap(), sealed_key.as_bytes().to_vec());
+        assert_eq!(
+            keystore::load_or_create(key_path, Some("hunter2"))
+                .unwrap()
+                .as_bytes(),
+            sealed_key.as_bytes()
+        );
+        assert!(keystore::load_or_create(key_path, Some("wrong")).is_err());
+        assert!(keystore::load_or_create(key_path, None).is_err());
+
+        // The path comes from --keyfile
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            keyfile_from_args(args(&[])).unwrap(),
+            Path::new(DEFAULT_KEYFILE)
+        );
+        assert_eq!(
+            keyfile_from_args(args(&["--keyfile", "/etc/aura/db.key"])).unwrap(),
+            Path::new("/etc/aura/db.key")
+        );
+        assert_eq!(
+            keyfile_from_args(args(&["--keyfile=db.key"])).unwrap(),
+            Path::new("db.key")
+        );
+        assert!(keyfile_from_args(args(&["--keyfile"])).is_err());
+
+        // Cleanup
+        fs::remove_file(db_path).unwrap();
+        fs::remove_file(key_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_graceful_shutdown_syncs_the_index() {
+        let db_path = "test_server_shutdown.db";
+        let _ = fs::remove_file(db_path);
+        let key = symmetric::generate_key();
+        // Long enough that only the shutdown can write the index
+        let window = Duration::from_secs(3600);
+        let options = PagerOptions {
+            index_sync_window: window,
+            ..PagerOptions::default()
+        };
+        let pager = Pager::open_with_options(db_path, key.clone(), options).unwrap();
+        let db = Arc::new(Mutex::new(pager));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn(serve(
+            listener,
+            db.clone(),
+            Arc::new(ChannelRegistry::new()),
+            Arc::new(ServerConfig {
+                index_sync_window: window,
+                ..open_access()
+            }),
+            async {
+                let _ = stopped.await;
+            },
+        ));
+
+        // The first write syncs the index; the next one, within the window, waits
+        let mut client = connect_test_client(addr).await;
+        for id in ["first", "last"] {
+            let sql = format!("INSERT INTO users (id) VALUES ('{}')", id);
+            send_query(&mut client, &sql).await.unwrap();
+        }
+        drop(client);
+
+        // Not in the index on disk yet (read from a copy: the server has the file locked)
+        let copy_path = "test_server_shutdown_copy.db";
+        {
+            let _db = db.lock().await;
+            fs::copy(db_path, copy_path).unwrap();
+            fs::copy(
+                Wal::path_for(Path::new(db_path)),
+                Wal::path_for(Path::new(copy_path)),
+            )
+            .unwrap();
+        }
+        let copy = Pager::open(copy_path, key.clone()).unwrap();
+        assert!(copy.index.get("users/first").is_some());
+        assert!(copy.index.get("users/last").is_none());
+        drop(copy);
+        fs::remove_file(copy_path).unwrap();
+        let _ = fs::remove_file(Wal::path_for(Path::new(copy_path)));
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+
+        // No new connections once it has stopped
+        assert!(TcpStream::connect(addr).await.is_err());
+
+        // The index on disk has the last insert
+        drop(db);
+        let pager = Pager::open(db_path, key).unwrap();
+        assert!(pager.index.get("users/last").is_some());
+
+        // Cleanup
+        fs::remove_file(db_path).unwrap();
+    }
+
+    async fn send_auth(
+        client: &mut TestClient,
+        username: &str,
+        password: &str,
+    ) -> Result<QueryResult, String> {
+        let request = AuthRequest::new(username, password).to_bytes().unwrap();
+        client.write_frame(&Frame::new(FRAME_AUTH, request)).await;
+        let frame = client.read_frame().await.unwrap();
+        decode_response(frame)
+    }
+
+    #[tokio::test]
+    async fn test_statements_need_auth_when_required() {
+        let db_path = "test_server_auth.db";
+        let _ = fs::remove_file(db_path);
+        let mut pager = Pager::open(db_path, symmetric::generate_key()).unwrap();
+        auth::set_password(&mut pager, "alice", "correct horse").unwrap();
+        QueryEngine::new(&mut pager)
+            .execute("GRANT SELECT, INSERT ON users TO alice")
+            .unwrap();
+        // Logging in is required unless turned off
+        let config = ServerConfig::default();
+        assert!(config.require_auth);
+        let addr = spawn_test_server_on(pager, config).await;
+        let mut client = connect_test_client(addr).await;
+
+        // Before AUTH: PING works, statements don't
+        client.write_frame(&Frame::new(FRAME_PING, "hello")).await;
+        assert_eq!(
+            client.read_frame().await.unwrap(),
+            Frame::new(FRAME_PONG, "hello")
+        );
+        let refused = send_query(&mut client, "SELECT * FROM users").await;
+        assert!(refused.unwrap_err().contains("Authentication required"));
+
+        // Wrong password and unknown users look the same
+        assert_eq!(
+            send_auth(&mut client, "alice", "battery staple").await,
+            Err("Authentication failed".to_string())
+        );
+        assert_eq!(
+            send_auth(&mut client, "mallory", "correct horse").await,
+            Err("Authentication failed".to_string())
+        );
+        assert!(send_query(&mut client, "SELECT * FROM users")
+            .await
+            .is_err());
+        // ...and take as long: an unknown user is checked against a hash that costs
+        // what a real one does
+        let params = |hash: &str| hash.rsplitn(3, '$').nth(2).unwrap().to_string();
+        assert_eq!(
+            params(auth::DUMMY_HASH),
+            params(&password::hash("correct horse").unwrap())
+        );
+        assert!(!password::verify("", auth::DUMMY_HASH));
+
+        assert_eq!(
+            send_auth(&mut client, "alice", "correct horse").await,
+            Ok(QueryResult::Message("AUTH alice".into()))
+        );
+        send_query(
+            &mut client,
+            "INSERT INTO users (id, name) VALUES ('u1', 'Ann')",
+        )
+        .await
+        .unwrap();
+        let (_, rows) = rows_of(send_query(&mut client, "SELECT name FROM users").await);
+        assert_eq!(rows, vec![vec![DataValue::Text("Ann".into())]]);
+
+        // Logged in, statements are limited to the user's grants
+        let denied = send_query(&mut client, "TRUNCATE TABLE users").await;
+        assert_eq!(
+            denied.unwrap_err(),
+            "Access Denied: alice has no TRUNCATE privilege on users"
+        );
+
+        // A connection that keeps guessing is closed
+        let mut guesser = connect_test_client(addr).await;
+        for _ in 0..auth::MAX_AUTH_ATTEMPTS {
+            assert!(send_auth(&mut guesser, "alice", "guess").await.is_err());
+        }
+        assert!(guesser.read_frame().await.is_none());
+
+        // Cleanup
+        fs::remove_file(db_path).unwrap();
+    }
+
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    #[tokio::test]
+    async fn test_reuse_port_shares_the_listening_port() {
+        // Two servers (here two listeners) bound to one port with SO_REUSEPORT
+        let config = ServerConfig {
+            reuse_port: true,
+            listen_backlog: 16,
+            ..open_access()
+        };
+        let first = bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let addr = first.local_addr().unwrap();
+        let second = bind(addr, &config).unwrap();
+
+        // Without it, the port is taken
+        assert!(bind(addr, &ServerConfig::default()).is_err());
+
+        // The kernel spreads new connections over both
+        let mut accepted = [0; 2];
+        let mut clients = Vec::new();
+        while accepted.contains(&0) && clients.len() < 64 {
+            clients.push(TcpStream::connect(addr).await.unwrap());
+            tokio::select! {
+                result = first.accept() => { result.unwrap(); accepted[0] += 1; }
+                result = second.accept() => { result.unwrap(); accepted[1] += 1; }
+            }
+        }
+        assert!(!accepted.contains(&0), "accepted {:?}", accepted);
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
 }
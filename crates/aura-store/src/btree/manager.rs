@@ -2,7 +2,15 @@ use crate::btree::node::{BTreeNode, NodeType};
 use crate::page::Page;
 use crate::pager::Pager;
 use crate::StoreError;
-use std::io::Error;
+use std::collections::HashSet;
+
+/// No real tree gets this tall (50 keys per node: 50^32 keys). Descending further
+/// means a corrupt child pointer has made a cycle, which is reported instead of
+/// looping forever.
+pub const MAX_TREE_HEIGHT: usize = 32;
+
+/// Why a descent past `MAX_TREE_HEIGHT` fails
+const TOO_DEEP: &str = "B-tree deeper than MAX_TREE_HEIGHT (child pointer cycle?)";
 
 pub struct BTreeManager<'a> {
     pager: &'a mut Pager,
@@ -10,18 +18,33 @@ pub struct BTreeManager<'a> {
 }
 
 impl<'a> BTreeManager<'a> {
-    /// Initialize the manager.
-    /// If root_id is 0, it assumes a new tree needs to be created.
+    /// Initialize the manager over an existing tree (see `create` for a new one).
+    /// A root that was never written or has been freed is reported by the first
+    /// operation as `StoreError::PageNotFound(root_id)`.
     pub fn new(pager: &'a mut Pager, root_id: u32) -> Self {
         Self { pager, root_id }
     }
 
+    /// Allocates and writes an empty leaf root, returning a manager for the new tree
+    pub fn create(pager: &'a mut Pager) -> Result<Self, StoreError> {
+        let root_id = pager.allocate_page();
+        let mut tree = Self::new(pager, root_id);
+        tree.write_node(&BTreeNode::new_leaf(root_id))?;
+        Ok(tree)
+    }
+
+    /// The current root page. It changes when the root splits,
+    /// so callers that persist it must re-read it after inserting.
+    pub fn root_id(&self) -> u32 {
+        self.root_id
+    }
+
     /// SEARCH: O(log n)
     /// Returns the Data Page ID for a given Key
     pub fn search(&mut self, key: &str) -> Result<Option<u32>, StoreError> {
         let mut current_id = self.root_id;
 
-        loop {
+        for _ in 0..MAX_TREE_HEIGHT {
             let node = self.read_node(current_id)?;
 
             match node.node_type {
@@ -41,6 +64,68 @@ impl<'a> BTreeManager<'a> {
                 }
             }
         }
+        Err(StoreError::Malformed {
+            page_id: current_id,
+            reason: TOO_DEEP.to_string(),
+        })
+    }
+
+    /// PREFIX SEARCH: every (key, data page) whose key starts with `prefix`, in key order.
+    /// Only subtrees whose key range can overlap the prefix are visited.
+    pub fn search_prefix(&mut self, prefix: &str) -> Result<Vec<(String, u32)>, StoreError> {
+        let mut matches = Vec::new();
+        self.collect_prefix(self.root_id, prefix, &mut matches, 0)?;
+        Ok(matches)
+    }
+
+    fn collect_prefix(
+        &mut self,
+        node_id: u32,
+        prefix: &str,
+        matches: &mut Vec<(String, u32)>,
+        depth: usize,
+    ) -> Result<(), StoreError> {
+        let node = self.read_node_at(node_id, depth)?;
+
+        match node.node_type {
+            NodeType::Leaf => {
+                let start = node.keys.partition_point(|k| k.as_str() < prefix);
+                for (key, &page_id) in node.keys[start..].iter().zip(&node.children[start..]) {
+                    if !key.starts_with(prefix) {
+                        break;
+                    }
+                    matches.push((key.clone(), page_id));
+                }
+            }
+            NodeType::Internal => {
+                // Child i holds keys in [keys[i-1], keys[i])
+                for (i, &child_id) in node.children.iter().enumerate() {
+                    let below_upper = i == node.keys.len() || node.keys[i].as_str() > prefix;
+                    let above_lower = i == 0
+                        || node.keys[i - 1].as_str() < prefix
+                        || node.keys[i - 1].starts_with(prefix);
+                    if below_upper && above_lower {
+                        self.collect_prefix(child_id, prefix, matches, depth + 1)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// RANGE SCAN: every (key, data page) with `start <= key < end`, in key order.
+    /// A missing bound is open, so `range(None, None)` walks the whole tree. Keys
+    /// are read a leaf at a time as the iterator advances; a read error ends it.
+    pub fn range<'m>(&'m mut self, start: Option<&str>, end: Option<&str>) -> RangeIter<'m, 'a> {
+        let root = Some(self.root_id);
+        RangeIter {
+            tree: self,
+            start: start.map(str::to_string),
+            end: end.map(str::to_string),
+            root,
+            ancestors: Vec::new(),
+            leaf: Vec::new().into_iter(),
+        }
     }
 
     /// INSERT: The complex part.
@@ -80,16 +165,22 @@ impl<'a> BTreeManager<'a> {
             self.root_id = new_root_id;
 
             // 7. Finally insert the data into the new structure
-            self.insert_non_full(new_root_id, key, data_page_id)?;
+            self.insert_non_full(new_root_id, key, data_page_id, 0)?;
         } else {
             // Normal insert
-            self.insert_non_full(self.root_id, key, data_page_id)?;
+            self.insert_non_full(self.root_id, key, data_page_id, 0)?;
         }
         Ok(())
     }
 
-    fn insert_non_full(&mut self, node_id: u32, key: String, value: u32) -> Result<(), StoreError> {
-        let mut node = self.read_node(node_id)?;
+    fn insert_non_full(
+        &mut self,
+        node_id: u32,
+        key: String,
+        value: u32,
+        depth: usize,
+    ) -> Result<(), StoreError> {
+        let mut node = self.read_node_at(node_id, depth)?;
 
         match node.node_type {
             NodeType::Leaf => {
@@ -118,21 +209,227 @@ impl<'a> BTreeManager<'a> {
                     }
                 }
 
-                self.insert_non_full(node.children[idx], key, value)?;
+                self.insert_non_full(node.children[idx], key, value, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// DELETE: Removes `key`, returning whether it was there.
+    /// Nodes left with too few keys borrow from a sibling or merge with it on the
+    /// way back up; a root left with a single child is replaced by that child.
+    pub fn delete(&mut self, key: &str) -> Result<bool, StoreError> {
+        if !self.delete_from(self.root_id, key, 0)? {
+            return Ok(false);
+        }
+
+        // The tree shrinks in height once the root has no separators left
+        let root = self.read_node(self.root_id)?;
+        if root.node_type == NodeType::Internal && root.keys.is_empty() {
+            let mut new_root = self.read_node(root.children[0])?;
+            new_root.parent = None;
+            self.write_node(&new_root)?;
+            self.root_id = new_root.id;
+            self.pager.free_page(root.id)?;
+        }
+        Ok(true)
+    }
+
+    fn delete_from(&mut self, node_id: u32, key: &str, depth: usize) -> Result<bool, StoreError> {
+        let mut node = self.read_node_at(node_id, depth)?;
+
+        match node.node_type {
+            NodeType::Leaf => {
+                let Ok(idx) = node.keys.binary_search_by(|k| k.as_str().cmp(key)) else {
+                    return Ok(false);
+                };
+                node.keys.remove(idx);
+                node.children.remove(idx);
+                self.write_node(&node)?;
+                Ok(true)
+            }
+            NodeType::Internal => {
+                let idx = node.keys.partition_point(|k| k.as_str() <= key);
+                if !self.delete_from(node.children[idx], key, depth + 1)? {
+                    return Ok(false);
+                }
+
+                let child = self.read_node(node.children[idx])?;
+                if child.is_underflowing() {
+                    self.rebalance(&mut node, idx)?;
+                }
+
+                // Leaves hold every key; internal keys are copies of the first key of
+                // the subtree to their right. If the deleted key was such a separator,
+                // it is replaced by the key that now comes right after it in order.
+                // (Its in-order predecessor lives in the left subtree, so using it would
+                // send lookups for that very key to the wrong side.)
+                if let Some(pos) = node.keys.iter().position(|k| k == key) {
+                    if let Some(successor) = self.first_key(node.children[pos + 1])? {
+                        node.keys[pos] = successor;
+                    }
+                }
+
+                self.write_node(&node)?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Refills `parent.children[idx]` after a delete left it underflowing
+    fn rebalance(&mut self, parent: &mut BTreeNode, idx: usize) -> Result<(), StoreError> {
+        let mut child = self.read_node(parent.children[idx])?;
+
+        // 1. Borrow from the left sibling
+        if idx > 0 {
+            let mut left = self.read_node(parent.children[idx - 1])?;
+            if left.can_lend() {
+                let key = left.keys.pop().expect("lender has keys");
+                let moved = left.children.pop().expect("lender has children");
+                match child.node_type {
+                    NodeType::Leaf => {
+                        child.keys.insert(0, key);
+                        parent.keys[idx - 1] = child.keys[0].clone();
+                    }
+                    NodeType::Internal => {
+                        // The separator comes down, the lender's last key goes up
+                        let separator = std::mem::replace(&mut parent.keys[idx - 1], key);
+                        child.keys.insert(0, separator);
+                        self.set_parent(moved, child.id)?;
+                    }
+                }
+                child.children.insert(0, moved);
+                self.write_node(&left)?;
+                return self.write_node(&child);
+            }
+        }
+
+        // 2. Borrow from the right sibling
+        if idx + 1 < parent.children.len() {
+            let mut right = self.read_node(parent.children[idx + 1])?;
+            if right.can_lend() {
+                let key = right.keys.remove(0);
+                let moved = right.children.remove(0);
+                match child.node_type {
+                    NodeType::Leaf => {
+                        child.keys.push(key);
+                        parent.keys[idx] = right.keys[0].clone();
+                    }
+                    NodeType::Internal => {
+                        let separator = std::mem::replace(&mut parent.keys[idx], key);
+                        child.keys.push(separator);
+                        self.set_parent(moved, child.id)?;
+                    }
+                }
+                child.children.push(moved);
+                self.write_node(&right)?;
+                return self.write_node(&child);
+            }
+        }
+
+        // 3. Neither sibling can spare a key: merge with one of them
+        if idx > 0 {
+            self.merge(parent, idx - 1)
+        } else {
+            self.merge(parent, idx)
+        }
+    }
+
+    /// Folds `parent.children[idx + 1]` into `parent.children[idx]` and frees its page
+    fn merge(&mut self, parent: &mut BTreeNode, idx: usize) -> Result<(), StoreError> {
+        let mut left = self.read_node(parent.children[idx])?;
+        let right = self.read_node(parent.children[idx + 1])?;
+
+        let separator = parent.keys.remove(idx);
+        parent.children.remove(idx + 1);
+
+        if left.node_type == NodeType::Internal {
+            // Internal separators are not duplicated below, so it comes down
+            left.keys.push(separator);
+            for &grandchild_id in &right.children {
+                self.set_parent(grandchild_id, left.id)?;
             }
         }
+        left.keys.extend(right.keys);
+        left.children.extend(right.children);
+
+        self.write_node(&left)?;
+        self.pager.free_page(right.id)?;
         Ok(())
     }
 
+    /// Removes every key at once: all nodes but the root are freed
+    /// and the root becomes an empty leaf (so `root_id` stays the same).
+    pub fn clear(&mut self) -> Result<(), StoreError> {
+        let mut stack = vec![self.root_id];
+        let mut seen = HashSet::new();
+        while let Some(node_id) = stack.pop() {
+            // A node reachable twice would be freed twice (or loop forever)
+            if !seen.insert(node_id) {
+                return Err(StoreError::Malformed {
+                    page_id: node_id,
+                    reason: "B-tree node reachable twice (child pointer cycle?)".to_string(),
+                });
+            }
+            let node = self.read_node(node_id)?;
+            if node.node_type == NodeType::Internal {
+                stack.extend(&node.children);
+            }
+            if node_id != self.root_id {
+                self.pager.free_page(node_id)?;
+            }
+        }
+        self.write_node(&BTreeNode::new_leaf(self.root_id))
+    }
+
+    /// Smallest key in the subtree rooted at `node_id` (None if it is empty)
+    fn first_key(&mut self, node_id: u32) -> Result<Option<String>, StoreError> {
+        let mut node = self.read_node(node_id)?;
+        for depth in 1.. {
+            if node.node_type == NodeType::Leaf {
+                break;
+            }
+            node = self.read_node_at(node.children[0], depth)?;
+        }
+        Ok(node.keys.first().cloned())
+    }
+
+    fn set_parent(&mut self, node_id: u32, parent_id: u32) -> Result<(), StoreError> {
+        let mut node = self.read_node(node_id)?;
+        node.parent = Some(parent_id);
+        self.write_node(&node)
+    }
+
     // --- HELPER: Read/Write Nodes using the Encrypted Pager ---
 
     fn read_node(&mut self, node_id: u32) -> Result<BTreeNode, StoreError> {
+        let is_root = node_id == self.root_id;
+        // Page 0 is the index page and a freed page may hold anything: neither is a root
+        if is_root && (node_id == 0 || self.pager.is_free(node_id)) {
+            return Err(StoreError::PageNotFound(node_id));
+        }
+
         let page = self.pager.read_page(node_id)?;
+        // Every node serializes to some bytes, so an empty page was never written as one
+        if is_root && page.used_space == 0 {
+            return Err(StoreError::PageNotFound(node_id));
+        }
         let bytes = &page.data[..page.used_space as usize];
-        let node = BTreeNode::from_bytes(bytes).map_err(|_| {
-            StoreError::Io(Error::new(std::io::ErrorKind::InvalidData, "Node Corrupt"))
-        })?;
-        Ok(node)
+        BTreeNode::from_bytes(bytes).map_err(|e| StoreError::Malformed {
+            page_id: node_id,
+            reason: format!("not a B-tree node ({})", e),
+        })
+    }
+
+    /// Reads a node found `depth` levels below where the descent started
+    fn read_node_at(&mut self, node_id: u32, depth: usize) -> Result<BTreeNode, StoreError> {
+        if depth >= MAX_TREE_HEIGHT {
+            return Err(StoreError::Malformed {
+                page_id: node_id,
+                reason: TOO_DEEP.to_string(),
+            });
+        }
+        self.read_node(node_id)
     }
 
     fn write_node(&mut self, node: &BTreeNode) -> Result<(), StoreError> {
@@ -240,3 +537,95 @@ impl<'a> BTreeManager<'a> {
         Ok(())
     }
 }
+
+/// The iterator of `BTreeManager::range`. Rather than linking leaves to their
+/// siblings, it keeps the path from the root: each internal node on it, with the
+/// next child to visit.
+pub struct RangeIter<'m, 'a> {
+    tree: &'m mut BTreeManager<'a>,
+    start: Option<String>,
+    end: Option<String>,
+    /// The root, until the first descent
+    root: Option<u32>,
+    ancestors: Vec<(BTreeNode, usize)>,
+    /// The current leaf's entries not yet returned
+    leaf: std::vec::IntoIter<(String, u32)>,
+}
+
+impl RangeIter<'_, '_> {
+    /// The next subtree to the right: the next child of the deepest ancestor that has one
+    fn next_subtree(&mut self) -> Option<u32> {
+        if let Some(root) = self.root.take() {
+            return Some(root);
+        }
+        while let Some((node, next)) = self.ancestors.last_mut() {
+            if let Some(&child) = node.children.get(*next) {
+                // Every key from here on is at least this separator
+                let past_end = self
+                    .end
+                    .as_ref()
+                    .is_some_and(|end| *next > 0 && node.keys[*next - 1] >= *end);
+                if past_end {
+                    break;
+                }
+                *next += 1;
+                return Some(child);
+            }
+            self.ancestors.pop();
+        }
+        self.ancestors.clear();
+        None
+    }
+
+    /// Walks down to the leftmost leaf of `node_id` that can hold keys >= start
+    fn descend(&mut self, mut node_id: u32) -> Result<(), StoreError> {
+        loop {
+            let node = self.tree.read_node_at(node_id, self.ancestors.len())?;
+            match node.node_type {
+                NodeType::Leaf => {
+                    self.leaf = node
+                        .keys
+                        .into_iter()
+                        .zip(node.children)
+                        .collect::<Vec<_>>()
+                        .into_iter();
+                    return Ok(());
+                }
+                NodeType::Internal => {
+                    let idx = match &self.start {
+                        Some(start) => node.keys.partition_point(|k| k <= start),
+                        None => 0,
+                    };
+                    node_id = node.children[idx];
+                    self.ancestors.push((node, idx + 1));
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for RangeIter<'_, '_> {
+    type Item = Result<(String, u32), StoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((key, page_id)) = self.leaf.next() {
+                if self.end.as_ref().is_some_and(|end| key >= *end) {
+                    self.ancestors.clear();
+                    self.leaf = Vec::new().into_iter();
+                    return None;
+                }
+                if self.start.as_ref().is_some_and(|start| key < *start) {
+                    continue;
+                }
+                return Some(Ok((key, page_id)));
+            }
+
+            let subtree = self.next_subtree()?;
+            if let Err(e) = self.descend(subtree) {
+                self.ancestors.clear();
+                return Some(Err(e));
+            }
+        }
+    }
+}
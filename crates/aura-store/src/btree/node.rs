@@ -3,6 +3,9 @@ use serde::{Deserialize, Serialize};
 /// The Max size of a node (Must fit in Page - Metadata)
 const NODE_CAPACITY: usize = 50;
 
+/// Fewest keys a non-root node may keep after a delete (a split leaves at least this many)
+const MIN_KEYS: usize = NODE_CAPACITY / 2 - 1;
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum NodeType {
     Internal,
@@ -45,6 +48,16 @@ impl BTreeNode {
         self.keys.len() >= NODE_CAPACITY
     }
 
+    /// Too few keys: must borrow from a sibling or merge (never applies to the root)
+    pub fn is_underflowing(&self) -> bool {
+        self.keys.len() < MIN_KEYS
+    }
+
+    /// Can give a key to a sibling without underflowing itself
+    pub fn can_lend(&self) -> bool {
+        self.keys.len() > MIN_KEYS
+    }
+
     /// Serializes to fit in a 4KB Page
     pub fn to_bytes(&self) -> Result<Vec<u8>, postcard::Error> {
         postcard::to_allocvec(self)
@@ -0,0 +1,61 @@
+use crate::page::Page;
+use std::collections::{BTreeMap, HashMap};
+
+/// Decrypted pages kept by the Pager, so reading a page again (a B-tree root, the
+/// pages of a repeated scan) skips the seek, read and decryption. When full, the
+/// page used least recently makes room.
+pub(crate) struct PageCache {
+    capacity: usize,
+    /// Page id -> (page, when it was last used)
+    pages: HashMap<u32, (Page, u64)>,
+    /// When a page was last used -> its id, oldest first
+    order: BTreeMap<u64, u32>,
+    clock: u64,
+}
+
+impl PageCache {
+    /// A capacity of 0 caches nothing
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            pages: HashMap::new(),
+            order: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    pub(crate) fn get(&mut self, id: u32) -> Option<Page> {
+        let (page, used) = self.pages.get_mut(&id)?;
+        self.order.remove(used);
+        self.clock += 1;
+        *used = self.clock;
+        self.order.insert(self.clock, id);
+        Some(*page)
+    }
+
+    pub(crate) fn insert(&mut self, page: Page) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.remove(page.id);
+        if self.pages.len() >= self.capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.pages.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.pages.insert(page.id, (page, self.clock));
+        self.order.insert(self.clock, page.id);
+    }
+
+    pub(crate) fn remove(&mut self, id: u32) {
+        if let Some((_, used)) = self.pages.remove(&id) {
+            self.order.remove(&used);
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.pages.clear();
+        self.order.clear();
+    }
+}
@@ -0,0 +1,119 @@
+// The file header: the first FILE_HEADER_SIZE bytes of a database, before page 0.
+// Layout (little-endian):
+// [magic: 8][format version: u32][page size: u32][encryption: u32][salt: 16]
+// [zeros up to byte 60][crc32 of bytes 0..60: u32]
+// Files from before the header (pages from offset 0) are format version 0.
+use crate::page::PAGE_SIZE;
+use crate::StoreError;
+use aura_security::symmetric;
+
+pub const MAGIC: &[u8; 8] = b"AURADATA";
+pub const FILE_HEADER_SIZE: usize = 64;
+
+/// The layout this build reads and writes
+pub const FORMAT_VERSION: u32 = 1;
+
+/// `FileHeader::encryption` of pages sealed with XChaCha20-Poly1305
+pub const ENCRYPTION_XCHACHA20_POLY1305: u32 = 1;
+
+pub const SALT_SIZE: usize = 16;
+
+const VERSION_OFFSET: usize = 8;
+const PAGE_SIZE_OFFSET: usize = 12;
+const ENCRYPTION_OFFSET: usize = 16;
+const SALT_OFFSET: usize = 20;
+const CRC_OFFSET: usize = FILE_HEADER_SIZE - 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHeader {
+    pub version: u32,
+    pub page_size: u32,
+    pub encryption: u32,
+    /// Random per database, and part of every page's authenticated data: a page
+    /// copied in from another database under the same key doesn't authenticate
+    pub salt: [u8; SALT_SIZE],
+}
+
+impl FileHeader {
+    /// The header of a new database, with a fresh salt
+    pub fn new() -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            page_size: PAGE_SIZE as u32,
+            encryption: ENCRYPTION_XCHACHA20_POLY1305,
+            salt: symmetric::random_bytes(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; FILE_HEADER_SIZE] {
+        let mut bytes = [0u8; FILE_HEADER_SIZE];
+        bytes[..MAGIC.len()].copy_from_slice(MAGIC);
+        bytes[VERSION_OFFSET..VERSION_OFFSET + 4].copy_from_slice(&self.version.to_le_bytes());
+        bytes[PAGE_SIZE_OFFSET..PAGE_SIZE_OFFSET + 4]
+            .copy_from_slice(&self.page_size.to_le_bytes());
+        bytes[ENCRYPTION_OFFSET..ENCRYPTION_OFFSET + 4]
+            .copy_from_slice(&self.encryption.to_le_bytes());
+        bytes[SALT_OFFSET..SALT_OFFSET + SALT_SIZE].copy_from_slice(&self.salt);
+        let crc = crc32fast::hash(&bytes[..CRC_OFFSET]);
+        bytes[CRC_OFFSET..].copy_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    /// Checks the magic first, then the version (a later format may lay out the
+    /// rest differently), then the checksum and the settings this build supports.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StoreError> {
+        let invalid = |reason: &str| StoreError::InvalidDatabase(reason.to_string());
+        if !bytes.starts_with(MAGIC) {
+            return Err(invalid("no AuraDB header"));
+        }
+        if bytes.len() < FILE_HEADER_SIZE {
+            return Err(invalid("truncated header"));
+        }
+
+        let field = |offset: usize| {
+            u32::from_le_bytes(
+                bytes[offset..offset + 4]
+                    .try_into()
+                    .expect("slice is 4 bytes"),
+            )
+        };
+        let version = field(VERSION_OFFSET);
+        if version != FORMAT_VERSION {
+            return Err(StoreError::VersionMismatch {
+                found: version,
+                supported: FORMAT_VERSION,
+            });
+        }
+        if field(CRC_OFFSET) != crc32fast::hash(&bytes[..CRC_OFFSET]) {
+            return Err(invalid("header checksum mismatch"));
+        }
+
+        let header = Self {
+            version,
+            page_size: field(PAGE_SIZE_OFFSET),
+            encryption: field(ENCRYPTION_OFFSET),
+            salt: bytes[SALT_OFFSET..SALT_OFFSET + SALT_SIZE]
+                .try_into()
+                .expect("slice is SALT_SIZE bytes"),
+        };
+        if header.page_size != PAGE_SIZE as u32 {
+            return Err(StoreError::InvalidDatabase(format!(
+                "page size {} (this build uses {})",
+                header.page_size, PAGE_SIZE
+            )));
+        }
+        if header.encryption != ENCRYPTION_XCHACHA20_POLY1305 {
+            return Err(StoreError::InvalidDatabase(format!(
+                "unknown encryption scheme {}",
+                header.encryption
+            )));
+        }
+        Ok(header)
+    }
+}
+
+impl Default for FileHeader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
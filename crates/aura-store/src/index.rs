@@ -1,14 +1,39 @@
 use crate::StoreError;
 use postcard;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+
+/// Fewest removals that make `PrimaryIndex::needs_compaction` true
+pub const COMPACT_MIN_CHURN: usize = 1024;
 
 /// A simple Primary Key Index.
 /// Maps a String Key (e.g., "user_123") -> Page ID (e.g., 5).
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PrimaryIndex {
     pub map: BTreeMap<String, u32>,
     pub dirty: bool, // Has the index changed since last save?
+
+    /// Keys per table ("<table>/<id>"), kept in step with `map` so COUNT(*)
+    /// is O(1). Rebuilt on load, never persisted.
+    #[serde(skip)]
+    row_counts: HashMap<String, usize>,
+
+    /// Keys removed since the last `compact` (see `needs_compaction`)
+    #[serde(skip)]
+    churn: usize,
+
+    /// Set by `track_changes`, so a rolled back transaction can be undone
+    #[serde(skip)]
+    journal: Option<Journal>,
+}
+
+/// What the index was before the tracked changes: each changed key's first
+/// value (None = it wasn't there), and the counters
+#[derive(Debug, Clone, Default)]
+struct Journal {
+    before: HashMap<String, Option<u32>>,
+    dirty: bool,
+    churn: usize,
 }
 
 impl PrimaryIndex {
@@ -16,18 +41,121 @@ impl PrimaryIndex {
         Self {
             map: BTreeMap::new(),
             dirty: false,
+            row_counts: HashMap::new(),
+            churn: 0,
+            journal: None,
         }
     }
 
     pub fn insert(&mut self, key: String, page_id: u32) {
-        self.map.insert(key, page_id);
+        let table = Self::table_of(&key).map(str::to_string);
+        let previous = match &mut self.journal {
+            Some(journal) => {
+                let previous = self.map.insert(key.clone(), page_id);
+                journal.before.entry(key).or_insert(previous);
+                previous
+            }
+            None => self.map.insert(key, page_id),
+        };
+        if previous.is_none() {
+            if let Some(table) = table {
+                *self.row_counts.entry(table).or_default() += 1;
+            }
+        }
         self.dirty = true;
     }
 
+    pub fn remove(&mut self, key: &str) -> Option<u32> {
+        let removed = self.map.remove(key);
+        if removed.is_some() {
+            if let Some(journal) = &mut self.journal {
+                journal.before.entry(key.to_string()).or_insert(removed);
+            }
+            if let Some(count) = Self::table_of(key).and_then(|t| self.row_counts.get_mut(t)) {
+                *count -= 1;
+            }
+            self.churn += 1;
+            self.dirty = true;
+        }
+        removed
+    }
+
     pub fn get(&self, key: &str) -> Option<u32> {
         self.map.get(key).copied()
     }
 
+    /// Number of keys in `table` (those under "<table>/"), without walking them
+    pub fn row_count(&self, table: &str) -> usize {
+        self.row_counts.get(table).copied().unwrap_or(0)
+    }
+
+    /// Number of keys starting with `prefix` (e.g. "users/" = rows in `users`)
+    pub fn len_for_prefix(&self, prefix: &str) -> usize {
+        self.map
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .count()
+    }
+
+    /// Whether enough keys were removed since the last `compact` for it to pay off:
+    /// at least `COMPACT_MIN_CHURN`, and more than there are keys left
+    pub fn needs_compaction(&self) -> bool {
+        self.churn >= COMPACT_MIN_CHURN && self.churn > self.map.len()
+    }
+
+    /// Rebuilds the index from its live entries. Removals leave the BTreeMap's
+    /// nodes partly empty and tables that were emptied in `row_counts`; a rebuild
+    /// packs the nodes and forgets those tables. The serialized form (and so the
+    /// index pages) only ever holds live keys, so this is about memory, not disk.
+    pub fn compact(&mut self) {
+        // Built from sorted input, so every node is filled
+        self.map = std::mem::take(&mut self.map).into_iter().collect();
+        self.row_counts.retain(|_, count| *count > 0);
+        self.row_counts.shrink_to_fit();
+        self.churn = 0;
+    }
+
+    /// Starts recording changes, so `undo_changes` can revert them. Only the keys
+    /// that change are remembered, not the whole index.
+    pub fn track_changes(&mut self) {
+        self.journal = Some(Journal {
+            dirty: self.dirty,
+            churn: self.churn,
+            ..Journal::default()
+        });
+    }
+
+    /// Stops recording: the changes so far stay
+    pub fn forget_changes(&mut self) {
+        self.journal = None;
+    }
+
+    /// Reverts every change since `track_changes`, and stops recording
+    pub fn undo_changes(&mut self) {
+        let Some(journal) = self.journal.take() else {
+            return;
+        };
+        for (key, before) in journal.before {
+            let now = match before {
+                Some(page_id) => self.map.insert(key.clone(), page_id),
+                None => self.map.remove(&key),
+            };
+            if let Some(table) = Self::table_of(&key) {
+                match (now.is_some(), before.is_some()) {
+                    (false, true) => *self.row_counts.entry(table.to_string()).or_default() += 1,
+                    (true, false) => {
+                        if let Some(count) = self.row_counts.get_mut(table) {
+                            *count -= 1;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.dirty = journal.dirty;
+        self.churn = journal.churn;
+    }
+
     /// Serializes the entire index to bytes (to be saved in a Page)
     pub fn to_bytes(&self) -> Result<Vec<u8>, StoreError> {
         postcard::to_allocvec(&self.map)
@@ -39,7 +167,21 @@ impl PrimaryIndex {
         let map: BTreeMap<String, u32> = postcard::from_bytes(bytes)
             .map_err(|_| StoreError::Io(std::io::Error::other("Index corruption")))?;
 
-        Ok(Self { map, dirty: false })
+        let mut row_counts: HashMap<String, usize> = HashMap::new();
+        for table in map.keys().filter_map(|key| Self::table_of(key)) {
+            *row_counts.entry(table.to_string()).or_default() += 1;
+        }
+        Ok(Self {
+            map,
+            dirty: false,
+            row_counts,
+            churn: 0,
+            journal: None,
+        })
+    }
+
+    fn table_of(key: &str) -> Option<&str> {
+        key.split_once('/').map(|(table, _)| table)
     }
 }
 
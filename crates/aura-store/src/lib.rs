@@ -1,8 +1,12 @@
 pub mod btree;
+mod cache;
+pub mod header;
 pub mod index;
 pub mod page;
+mod page_list;
 pub mod pager;
 pub mod tests;
+pub mod wal;
 
 use thiserror::Error;
 
@@ -12,6 +16,24 @@ pub enum StoreError {
     Io(#[from] std::io::Error),
     #[error("Page not found: {0}")]
     PageNotFound(u32),
+    /// The page failed authentication: modified on disk, or the wrong master key
     #[error("Integrity Violation: Hash Mismatch on Page {0}")]
     Tampered(u32),
+    /// The page is authentic but damaged: a bad plaintext CRC, a truncated file,
+    /// or an invalid structure (e.g. a broken page chain)
+    #[error("Data Corruption: Checksum Mismatch on Page {0}")]
+    Corrupted(u32),
+    /// The page decrypted and passed its checksum, but what it holds doesn't
+    /// decode as what it should be, e.g. "not a B-tree node (...)"
+    #[error("Data Corruption: Page {page_id} is {reason}")]
+    Malformed { page_id: u32, reason: String },
+    /// The file is not an AuraDB database (or its header is damaged)
+    #[error("Not an AuraDB Database: {0}")]
+    InvalidDatabase(String),
+    /// A database in a format this build can't read (version 0: from before the header)
+    #[error("Unsupported Database Format: version {found}, this build reads version {supported}")]
+    VersionMismatch { found: u32, supported: u32 },
+    /// Another Pager (in this process or another one) has the file open
+    #[error("Database Locked: {0} is already open elsewhere")]
+    Locked(String),
 }
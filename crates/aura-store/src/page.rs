@@ -1,5 +1,22 @@
+use std::mem::{offset_of, size_of};
+
 pub const PAGE_SIZE: usize = 4096;
-pub const DATA_SIZE: usize = 3997; // Leave space for metadata
+
+// On-disk layout (little-endian), independent of how the struct sits in memory:
+// [0..4] id | [4] page_type | [5] unused | [6..8] used_space | [8..12] next_page
+// [12..100] reserved | [100..4096] data
+pub const HEADER_SIZE: usize = 100;
+pub const RESERVED_OFFSET: usize = 12;
+
+// Everything else follows from the three numbers above
+pub const RESERVED_SIZE: usize = HEADER_SIZE - RESERVED_OFFSET;
+pub const DATA_SIZE: usize = PAGE_SIZE - HEADER_SIZE;
+
+// Changing a header field without fixing the constants fails the build
+// instead of silently shifting the payload.
+const _: () = assert!(size_of::<Page>() == PAGE_SIZE);
+const _: () = assert!(offset_of!(Page, reserved) == RESERVED_OFFSET);
+const _: () = assert!(offset_of!(Page, data) == HEADER_SIZE);
 
 /// The physical representation of a block on disk.
 /// This entire struct is what gets encrypted.
@@ -7,11 +24,11 @@ pub const DATA_SIZE: usize = 3997; // Leave space for metadata
 #[derive(Copy, Clone)]
 pub struct Page {
     pub id: u32,
-    pub page_type: u8, // 1 = Data, 2 = Index
+    pub page_type: u8, // 1 = Data, 2 = Index, 3 = Catalog, 4 = Free list, 5 = Overflow
     pub used_space: u16,
-    pub next_page: u32,        // For linked lists of pages
-    pub reserved: [u8; 88],    // Padding to align headers
-    pub data: [u8; DATA_SIZE], // The actual payload
+    pub next_page: u32,                // Next page of a chain (0 = last)
+    pub reserved: [u8; RESERVED_SIZE], // Padding to align headers
+    pub data: [u8; DATA_SIZE],         // The actual payload
 }
 
 impl Page {
@@ -21,8 +38,36 @@ impl Page {
             page_type: 1,
             used_space: 0,
             next_page: 0,
-            reserved: [0; 88],
+            reserved: [0; RESERVED_SIZE],
             data: [0; DATA_SIZE],
         }
     }
+
+    /// Packs the page into its exact on-disk byte image
+    pub fn to_bytes(&self) -> [u8; PAGE_SIZE] {
+        let mut bytes = [0u8; PAGE_SIZE];
+        bytes[0..4].copy_from_slice(&self.id.to_le_bytes());
+        bytes[4] = self.page_type;
+        bytes[6..8].copy_from_slice(&self.used_space.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.next_page.to_le_bytes());
+        bytes[RESERVED_OFFSET..HEADER_SIZE].copy_from_slice(&self.reserved);
+        bytes[HEADER_SIZE..].copy_from_slice(&self.data);
+        bytes
+    }
+
+    /// Unpacks an on-disk byte image (the inverse of `to_bytes`)
+    pub fn from_bytes(bytes: &[u8; PAGE_SIZE]) -> Self {
+        let u32_at = |at: usize| {
+            u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+        };
+
+        let mut page = Self::new(u32_at(0));
+        page.page_type = bytes[4];
+        page.used_space = u16::from_le_bytes([bytes[6], bytes[7]]);
+        page.next_page = u32_at(8);
+        page.reserved
+            .copy_from_slice(&bytes[RESERVED_OFFSET..HEADER_SIZE]);
+        page.data.copy_from_slice(&bytes[HEADER_SIZE..]);
+        page
+    }
 }
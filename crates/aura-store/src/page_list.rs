@@ -0,0 +1,107 @@
+use std::collections::HashSet;
+use std::ops::Deref;
+
+/// A list of page ids (the free list, a chain's pages) whose changes since
+/// `track` can be undone. Within a transaction the pager only pushes and pops,
+/// so undoing costs as much as the changes did, not the length of the list.
+/// A page is in a list at most once, which `contains` answers without a scan.
+#[derive(Debug, Default)]
+pub(crate) struct PageList {
+    ids: Vec<u32>,
+    /// The same ids, for `contains`
+    members: HashSet<u32>,
+    /// Set while tracking: what was done to `ids`, oldest first
+    undo: Option<Vec<Change>>,
+}
+
+#[derive(Debug)]
+enum Change {
+    Pushed,
+    Popped(u32),
+    /// Anything else (`edit`, `take`) saves the whole list
+    Replaced(Vec<u32>),
+}
+
+impl PageList {
+    pub(crate) fn push(&mut self, id: u32) {
+        self.ids.push(id);
+        self.members.insert(id);
+        self.record(|| Change::Pushed);
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<u32> {
+        let id = self.ids.pop()?;
+        self.members.remove(&id);
+        self.record(|| Change::Popped(id));
+        Some(id)
+    }
+
+    pub(crate) fn contains(&self, id: &u32) -> bool {
+        self.members.contains(id)
+    }
+
+    /// Empties the list, returning what it held
+    pub(crate) fn take(&mut self) -> Vec<u32> {
+        let ids = std::mem::take(&mut self.ids);
+        self.members.clear();
+        self.record(|| Change::Replaced(ids.clone()));
+        ids
+    }
+
+    /// Any other change. While tracking this copies the list first.
+    pub(crate) fn edit(&mut self, f: impl FnOnce(&mut Vec<u32>)) {
+        if let Some(undo) = &mut self.undo {
+            undo.push(Change::Replaced(self.ids.clone()));
+        }
+        f(&mut self.ids);
+        self.members = self.ids.iter().copied().collect();
+    }
+
+    /// Starts recording changes, forgetting earlier ones
+    pub(crate) fn track(&mut self) {
+        self.undo = Some(Vec::new());
+    }
+
+    /// Stops recording: the changes so far stay
+    pub(crate) fn forget(&mut self) {
+        self.undo = None;
+    }
+
+    /// Reverts every change since `track`, and stops recording
+    pub(crate) fn undo(&mut self) {
+        let Some(undo) = self.undo.take() else {
+            return;
+        };
+        for change in undo.into_iter().rev() {
+            match change {
+                Change::Pushed => {
+                    if let Some(id) = self.ids.pop() {
+                        self.members.remove(&id);
+                    }
+                }
+                Change::Popped(id) => {
+                    self.ids.push(id);
+                    self.members.insert(id);
+                }
+                Change::Replaced(ids) => {
+                    self.members = ids.iter().copied().collect();
+                    self.ids = ids;
+                }
+            }
+        }
+    }
+
+    fn record(&mut self, change: impl FnOnce() -> Change) {
+        if let Some(undo) = &mut self.undo {
+            undo.push(change());
+        }
+    }
+}
+
+impl Deref for PageList {
+    type Target = [u32];
+
+    fn deref(&self) -> &[u32] {
+        &self.ids
+    }
+}
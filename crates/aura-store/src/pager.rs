@@ -1,34 +1,251 @@
+use crate::cache::PageCache;
+use crate::header::{FileHeader, FILE_HEADER_SIZE, SALT_SIZE};
 use crate::index::PrimaryIndex;
-use crate::page::{Page, PAGE_SIZE};
+use crate::page::{Page, DATA_SIZE, PAGE_SIZE, RESERVED_OFFSET};
+use crate::page_list::PageList;
+use crate::wal::Wal;
 use crate::StoreError;
-use aura_security::symmetric::{self, KEY_SIZE};
-use std::fs::{File, OpenOptions};
+use aura_security::symmetric::{self, MasterKey};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions, TryLockError};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::Path;
+use std::time::{Duration, Instant};
 
 // Encrypted page size = PAGE_SIZE + NONCE_SIZE + TAG_SIZE
 pub const ENCRYPTED_PAGE_SIZE: usize = PAGE_SIZE + symmetric::NONCE_SIZE + symmetric::TAG_SIZE;
 
+// The CRC32 of the plaintext page lives in the first 4 `reserved` bytes
+const CRC_OFFSET: usize = RESERVED_OFFSET;
+
+/// Page type of the free-list page (1 = Data, 2 = Index, 3 = Catalog)
+pub const FREE_LIST_PAGE_TYPE: u8 = 4;
+
+/// Page type of the pages after the head of a chain (see `write_chain`)
+pub const OVERFLOW_PAGE_TYPE: u8 = 5;
+
+// The index page (page 0) keeps the free-list page id in `reserved[4..8]`,
+// right after the CRC; its `next_page` links to the rest of the index.
+const FREE_LIST_POINTER: usize = 4;
+
+/// Tuning knobs for `Pager::open_with_options`
+#[derive(Debug, Clone)]
+pub struct PagerOptions {
+    /// Verify the plaintext CRC32 of every page on read (default: true).
+    /// Turning this off saves CPU on trusted storage (ECC RAM, checksumming
+    /// filesystems) but means bit-rot inside a page is no longer reported as
+    /// `StoreError::Corrupted`. The AEAD tag is always verified regardless,
+    /// since it is part of decryption itself.
+    pub verify_checksums: bool,
+
+    /// Apply the committed records left in the WAL on open (default: true).
+    /// Turn this off to inspect the log first (`wal_records`, `replay_up_to`);
+    /// don't write through such a pager before the log has been dealt with.
+    pub recover_wal: bool,
+
+    /// Compact the index in `sync_index` once removals outnumber the keys left
+    /// (default: true). See `PrimaryIndex::compact`.
+    pub compact_index: bool,
+
+    /// How many decrypted pages to keep in memory for repeated reads, evicting
+    /// the least recently used (default: DEFAULT_CACHE_PAGES). 0 turns the cache
+    /// off, so every read goes to the file.
+    pub cache_pages: usize,
+
+    /// Lets `sync_index` skip the write when the index was written less than this
+    /// long ago, so a burst of single-row statements shares one index write
+    /// (default: zero, every call writes). The caller runs `flush_index` at least
+    /// once per window to bound the delay. Until then a crash loses the documents
+    /// written since the last index write: their pages leak, nothing is corrupted.
+    pub index_sync_window: Duration,
+
+    /// When writes are fsynced (default: `SyncPolicy::OnCommit`)
+    pub sync: SyncPolicy,
+}
+
+/// When the Pager fsyncs the WAL and the main file (see `PagerStats::syncs`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Also fsync the WAL after every page logged, not only at commit. Recovery
+    /// still applies whole batches only, so this costs a lot and buys little.
+    Always,
+    /// Fsync the WAL when a batch commits and the main file when it is
+    /// checkpointed: a committed batch survives a power loss
+    OnCommit,
+    /// Never fsync on our own: only `flush` does, and so does closing the Pager.
+    /// A crash of the machine (a process crash is fine) can lose recent commits
+    /// or leave a batch half applied.
+    Never,
+}
+
+impl SyncPolicy {
+    pub const ALL: [SyncPolicy; 3] = [Self::Always, Self::OnCommit, Self::Never];
+
+    /// "always", "on_commit" or "never"
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Always => "always",
+            Self::OnCommit => "on_commit",
+            Self::Never => "never",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|policy| policy.name().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+/// Pages cached when `PagerOptions::cache_pages` is not set (1 MiB of plaintext)
+pub const DEFAULT_CACHE_PAGES: usize = 256;
+
+impl Default for PagerOptions {
+    fn default() -> Self {
+        Self {
+            verify_checksums: true,
+            recover_wal: true,
+            compact_index: true,
+            cache_pages: DEFAULT_CACHE_PAGES,
+            index_sync_window: Duration::ZERO,
+            sync: SyncPolicy::OnCommit,
+        }
+    }
+}
+
+/// One page write found in the WAL (see `Pager::wal_records`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct WalRecordInfo {
+    pub seq: u64,
+    pub page_id: u32,
+    /// Size of the logged (encrypted) page image
+    pub size: usize,
+    /// Whether a commit follows it, i.e. whether recovery would apply it
+    pub committed: bool,
+}
+
+/// I/O counters of a Pager (see `Pager::stats`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PagerStats {
+    /// `read_page` calls
+    pub pages_read: u64,
+    /// `write_page` calls (each goes to the WAL first)
+    pub pages_written: u64,
+    /// Reads the page cache could not answer, which read and decrypted the page
+    pub pages_decrypted: u64,
+    /// Fsyncs of the main file and the WAL
+    pub syncs: u64,
+}
+
+impl std::ops::Sub for PagerStats {
+    type Output = PagerStats;
+
+    fn sub(self, earlier: PagerStats) -> PagerStats {
+        PagerStats {
+            pages_read: self.pages_read - earlier.pages_read,
+            pages_written: self.pages_written - earlier.pages_written,
+            pages_decrypted: self.pages_decrypted - earlier.pages_decrypted,
+            syncs: self.syncs - earlier.syncs,
+        }
+    }
+}
+
+/// What `rollback` puts back besides the changes the index and the page lists
+/// record themselves: the in-memory state as `begin_transaction` found it
+struct Savepoint {
+    total_pages: u32,
+    free_list_page: u32,
+    free_list_dirty: bool,
+    index_written_at: Option<Instant>,
+}
+
 pub struct Pager {
     file: File,
     total_pages: u32,
-    master_key: [u8; KEY_SIZE],
+    master_key: MasterKey,
+    options: PagerOptions,
+    header: FileHeader,
+
+    /// Pages read and written since open (lets tests and callers see how much a query touched)
+    pages_read: u64,
+    pages_written: u64,
+    pages_decrypted: u64,
+    /// Fsyncs of the main file (the WAL counts its own)
+    syncs: u64,
+
+    /// Recently read pages, decrypted. A write drops the page's entry, so the
+    /// next read sees the new contents.
+    cache: PageCache,
+
+    // Every write goes through the WAL first. Inside a `begin`/`commit` batch the
+    // encrypted images wait here (and are served to reads) until the commit.
+    wal: Wal,
+    pending: HashMap<u32, Vec<u8>>,
+    in_batch: bool,
+    /// Set by `begin_transaction`, so the batch can be rolled back
+    savepoint: Option<Savepoint>,
+
+    // Pages given back with `free_page`, reused by `allocate_page` before the file grows.
+    // Persisted by `sync_index` in a chain of its own pages: the index page points
+    // at the head, the rest follow through `next_page` (kept in `free_list_pages`).
+    free_pages: PageList,
+    free_list_page: u32,
+    free_list_pages: PageList,
+    free_list_dirty: bool,
+
+    /// Continuation pages of the primary index, in order (page 0 is the head)
+    index_pages: PageList,
+
+    // With an `index_sync_window`: when the index was last written, and the pages
+    // freed since. The index on disk may still point at those, so they are only
+    // reused once the next index write has replaced it.
+    index_written_at: Option<Instant>,
+    released_pages: PageList,
 
     // NEW: The Index lives here
     pub index: PrimaryIndex,
 }
 
 impl Pager {
-    pub fn open(path: impl AsRef<Path>, master_key: [u8; KEY_SIZE]) -> Result<Self, StoreError> {
-        let file = OpenOptions::new()
+    pub fn open(path: impl AsRef<Path>, master_key: MasterKey) -> Result<Self, StoreError> {
+        Self::open_with_options(path, master_key, PagerOptions::default())
+    }
+
+    pub fn open_with_options(
+        path: impl AsRef<Path>,
+        master_key: MasterKey,
+        options: PagerOptions,
+    ) -> Result<Self, StoreError> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
             .truncate(false)
             .open(path)?;
+        // One Pager per file: two would overwrite each other's pages and WAL.
+        // The advisory lock is released when the file is closed.
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(StoreError::Locked(path.display().to_string()))
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        // Before anything is written to it (WAL recovery included)
+        let header = Self::read_header(&mut file, &master_key)?;
+
+        // Finish whatever the last process committed but did not checkpoint
+        // (it may have died halfway through). Replaying full page images is idempotent.
+        let mut wal = Wal::open(Wal::path_for(path))?;
+        if options.recover_wal {
+            Self::recover(&mut file, &mut wal)?;
+        }
+        wal.set_sync(options.sync != SyncPolicy::Never);
 
         let len = file.metadata()?.len();
-        let total_pages = (len / ENCRYPTED_PAGE_SIZE as u64) as u32;
+        // A torn last page still counts, so reading it reports corruption
+        let total_pages = Self::pages_in(len);
 
         // LOAD THE INDEX
         // Convention: Page 0 is ALWAYS the Index Page.
@@ -40,10 +257,29 @@ impl Pager {
             // This is a bit circular, but we'll handle it
         }
 
+        let cache = PageCache::new(options.cache_pages);
         let mut pager = Self {
             file,
             total_pages,
             master_key,
+            options,
+            header,
+            pages_read: 0,
+            pages_written: 0,
+            pages_decrypted: 0,
+            syncs: 0,
+            cache,
+            wal,
+            pending: HashMap::new(),
+            in_batch: false,
+            savepoint: None,
+            free_pages: PageList::default(),
+            free_list_page: 0,
+            free_list_pages: PageList::default(),
+            free_list_dirty: false,
+            index_pages: PageList::default(),
+            index_written_at: None,
+            released_pages: PageList::default(),
             index,
         };
 
@@ -52,16 +288,26 @@ impl Pager {
             match pager.read_page(0) {
                 Ok(page) if page.page_type == 2 => {
                     // Index page type
-                    let index_bytes = &page.data[..page.used_space as usize];
-                    match PrimaryIndex::from_bytes(index_bytes) {
+                    // An unreadable free list only means those pages are not reused
+                    let free_list_page = u32::from_le_bytes(
+                        page.reserved[FREE_LIST_POINTER..FREE_LIST_POINTER + 4]
+                            .try_into()
+                            .expect("slice is 4 bytes"),
+                    );
+                    if free_list_page != 0 && pager.load_free_list(free_list_page).is_err() {
+                        pager.free_pages.take();
+                    }
+                    match pager.load_index(page) {
                         Ok(loaded_index) => {
                             pager.index = loaded_index;
                         }
                         Err(_) => {
                             // Index corruption, start fresh
                             pager.index = PrimaryIndex::new();
+                            pager.index_pages.take();
                         }
                     }
+                    pager.reconcile_free_list();
                 }
                 _ => {
                     // No index page or wrong type, start fresh
@@ -72,67 +318,394 @@ impl Pager {
         Ok(pager)
     }
 
-    /// Writes a page to disk with transparent encryption
-    pub fn write_page(&mut self, page: &Page) -> Result<(), StoreError> {
-        let offset = page.id as u64 * ENCRYPTED_PAGE_SIZE as u64;
-        self.file.seek(SeekFrom::Start(offset))?;
+    /// Validates the file header, or writes one if the file is new
+    fn read_header(file: &mut File, master_key: &MasterKey) -> Result<FileHeader, StoreError> {
+        let len = file.metadata()?.len();
+        if len == 0 {
+            let header = FileHeader::new();
+            file.write_all(&header.to_bytes())?;
+            file.sync_data()?;
+            return Ok(header);
+        }
 
-        // Convert struct to raw bytes safely
-        let plaintext =
-            unsafe { std::slice::from_raw_parts(page as *const Page as *const u8, PAGE_SIZE) };
+        let mut bytes = vec![0u8; FILE_HEADER_SIZE.min(len as usize)];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut bytes)?;
+        FileHeader::from_bytes(&bytes).map_err(|e| {
+            // Without a header, page 0 used to start the file, sealed with no
+            // associated data or (later) bound to its page id
+            let mut page = vec![0u8; ENCRYPTED_PAGE_SIZE];
+            let legacy = matches!(e, StoreError::InvalidDatabase(_))
+                && file.seek(SeekFrom::Start(0)).is_ok()
+                && file.read_exact(&mut page).is_ok()
+                && [&[][..], &0u32.to_le_bytes()].iter().any(|aad| {
+                    symmetric::decrypt_with_aad(&page, master_key.as_bytes(), aad).is_ok()
+                });
+            if legacy {
+                StoreError::VersionMismatch {
+                    found: 0,
+                    supported: crate::header::FORMAT_VERSION,
+                }
+            } else {
+                e
+            }
+        })
+    }
+
+    /// The database's file header
+    pub fn header(&self) -> &FileHeader {
+        &self.header
+    }
+
+    /// Where a page's image starts in the file: pages follow the header
+    pub fn page_offset(id: u32) -> u64 {
+        FILE_HEADER_SIZE as u64 + id as u64 * ENCRYPTED_PAGE_SIZE as u64
+    }
+
+    /// Pages in a file of `len` bytes, a torn last one included
+    fn pages_in(len: u64) -> u32 {
+        len.saturating_sub(FILE_HEADER_SIZE as u64)
+            .div_ceil(ENCRYPTED_PAGE_SIZE as u64) as u32
+    }
+
+    /// Reassembles the index from page 0 and its continuation pages
+    fn load_index(&mut self, head: Page) -> Result<PrimaryIndex, StoreError> {
+        let mut bytes = head.data[..head.used_space as usize].to_vec();
+        let mut next = head.next_page;
+        while next != 0 {
+            if self.index_pages.contains(&next) {
+                return Err(StoreError::Malformed {
+                    page_id: next,
+                    reason: "loop in page chain".to_string(),
+                });
+            }
+            let page = self.read_page(next)?;
+            if page.page_type != OVERFLOW_PAGE_TYPE {
+                return Err(StoreError::Malformed {
+                    page_id: next,
+                    reason: "not an overflow page".to_string(),
+                });
+            }
+            bytes.extend_from_slice(&page.data[..page.used_space as usize]);
+            self.index_pages.push(next);
+            next = page.next_page;
+        }
+        PrimaryIndex::from_bytes(&bytes)
+    }
 
-        // Encrypt the page data
-        let encrypted_data = symmetric::encrypt(plaintext, &self.master_key)
-            .map_err(|_| StoreError::Tampered(page.id))?;
+    fn recover(file: &mut File, wal: &mut Wal) -> Result<(), StoreError> {
+        let records = wal.committed_records()?;
+        if !records.is_empty() {
+            for record in &records {
+                file.seek(SeekFrom::Start(Self::page_offset(record.page_id)))?;
+                file.write_all(&record.image)?;
+            }
+            file.sync_data()?;
+        }
+        wal.truncate()
+    }
+
+    /// Lists the page records in the WAL, in log order: the current batch's, or
+    /// whatever a crashed process left behind if recovery was skipped on open.
+    pub fn wal_records(&mut self) -> Result<Vec<WalRecordInfo>, StoreError> {
+        let (records, committed) = self.wal.scan()?;
+        Ok(records
+            .into_iter()
+            .enumerate()
+            .map(|(i, record)| WalRecordInfo {
+                seq: record.seq,
+                page_id: record.page_id,
+                size: record.image.len(),
+                committed: i < committed,
+            })
+            .collect())
+    }
+
+    /// Debugging aid for point-in-time recovery: copies the WAL records up to and
+    /// including `seq` into the main file, committed or not, then discards the log
+    /// and any batch in progress. Returns how many records were applied.
+    ///
+    /// The in-memory index is not reloaded; reopen the pager to see the result.
+    pub fn replay_up_to(&mut self, seq: u64) -> Result<usize, StoreError> {
+        let (records, _) = self.wal.scan()?;
+        let mut applied = 0;
+        for record in records.iter().take_while(|record| record.seq <= seq) {
+            self.file
+                .seek(SeekFrom::Start(Self::page_offset(record.page_id)))?;
+            self.file.write_all(&record.image)?;
+            applied += 1;
+        }
+        self.sync_file()?;
+        self.wal.truncate()?;
+
+        self.pending.clear();
+        self.cache.clear();
+        self.in_batch = false;
+        self.end_transaction();
+        self.total_pages = Self::pages_in(self.file.metadata()?.len());
+        Ok(applied)
+    }
+
+    /// Starts a batch: until `commit`, written pages only go to the WAL,
+    /// so the whole batch reaches the main file or none of it does.
+    pub fn begin(&mut self) {
+        self.in_batch = true;
+    }
+
+    /// Starts a batch that `rollback` can also abandon. Changes to the in-memory
+    /// index and free list are recorded as they happen (so undoing them costs
+    /// what making them did), but prefer `begin` unless the batch may be undone.
+    pub fn begin_transaction(&mut self) {
+        self.savepoint = Some(Savepoint {
+            total_pages: self.total_pages,
+            free_list_page: self.free_list_page,
+            free_list_dirty: self.free_list_dirty,
+            index_written_at: self.index_written_at,
+        });
+        self.index.track_changes();
+        for list in self.page_lists() {
+            list.track();
+        }
+        self.begin();
+    }
+
+    /// Keeps the transaction's changes: nothing is recorded for undoing anymore
+    fn end_transaction(&mut self) {
+        self.savepoint = None;
+        self.index.forget_changes();
+        for list in self.page_lists() {
+            list.forget();
+        }
+    }
+
+    fn page_lists(&mut self) -> [&mut PageList; 4] {
+        [
+            &mut self.free_pages,
+            &mut self.free_list_pages,
+            &mut self.index_pages,
+            &mut self.released_pages,
+        ]
+    }
+
+    /// Abandons the batch started by `begin_transaction`: its pages never reach
+    /// the main file, and the index and free list are as they were before it.
+    /// Does nothing after a plain `begin`, which has nothing to go back to.
+    pub fn rollback(&mut self) -> Result<(), StoreError> {
+        let Some(savepoint) = self.savepoint.take() else {
+            return Ok(());
+        };
+        self.wal.truncate()?;
+        self.pending.clear();
+        // It may hold pages read from the abandoned batch
+        self.cache.clear();
+        self.in_batch = false;
+
+        self.index.undo_changes();
+        for list in self.page_lists() {
+            list.undo();
+        }
+        self.total_pages = savepoint.total_pages;
+        self.free_list_page = savepoint.free_list_page;
+        self.free_list_dirty = savepoint.free_list_dirty;
+        self.index_written_at = savepoint.index_written_at;
+        Ok(())
+    }
+
+    /// Makes the batch durable in the WAL, then checkpoints it into the main file.
+    pub fn commit(&mut self) -> Result<(), StoreError> {
+        self.in_batch = false;
+        self.end_transaction();
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        self.wal.commit()?;
+        self.checkpoint()
+    }
+
+    /// Copies committed pages into the main file and empties the WAL.
+    /// A crash in here is harmless: the WAL is only truncated after the fsync.
+    fn checkpoint(&mut self) -> Result<(), StoreError> {
+        for (id, image) in &self.pending {
+            self.file.seek(SeekFrom::Start(Self::page_offset(*id)))?;
+            self.file.write_all(image)?;
+        }
+        if self.options.sync != SyncPolicy::Never {
+            self.sync_file()?;
+        }
+        self.wal.truncate()?;
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Fsyncs the main file and the WAL, whatever the `SyncPolicy`: with
+    /// `SyncPolicy::Never`, what has been committed so far is durable after this.
+    /// (Not to be confused with `flush_index`, which writes the index.)
+    pub fn flush(&mut self) -> Result<(), StoreError> {
+        self.sync_file()?;
+        self.wal.sync()
+    }
+
+    fn sync_file(&mut self) -> Result<(), StoreError> {
+        self.file.sync_data()?;
+        self.syncs += 1;
+        Ok(())
+    }
 
-        // Write encrypted data to disk
-        self.file.write_all(&encrypted_data)?;
+    /// Writes a page with transparent encryption.
+    /// Outside a batch this commits immediately.
+    pub fn write_page(&mut self, page: &Page) -> Result<(), StoreError> {
+        let image = self.seal_page(page)?;
+        self.wal.append_page(page.id, &image)?;
+        if self.options.sync == SyncPolicy::Always {
+            self.wal.sync()?;
+        }
+        self.pending.insert(page.id, image);
+        self.cache.remove(page.id);
+        self.pages_written += 1;
 
         // Update total_pages if we wrote beyond the current end
         if page.id >= self.total_pages {
             self.total_pages = page.id + 1;
         }
 
+        if !self.in_batch {
+            self.commit()?;
+        }
         Ok(())
     }
 
-    /// Reads a page from disk with transparent decryption
+    /// The encrypted on-disk image of a page (as stored in the main file and the WAL)
+    pub(crate) fn seal_page(&self, page: &Page) -> Result<Vec<u8>, StoreError> {
+        // Pack the header fields and data into the on-disk image
+        let mut plaintext = page.to_bytes();
+
+        // Stamp the checksum (always written, even if reads skip verifying it)
+        let crc = Self::page_checksum(&plaintext);
+        plaintext[CRC_OFFSET..CRC_OFFSET + 4].copy_from_slice(&crc.to_le_bytes());
+
+        // Encrypt the page data, bound to the page's id
+        symmetric::encrypt_with_aad(
+            &plaintext,
+            self.master_key.as_bytes(),
+            &self.page_aad(page.id),
+        )
+        .map_err(|_| StoreError::Tampered(page.id))
+    }
+
+    /// Associated data of a page's encryption: the database's salt and the page
+    /// id. An image copied over another page's slot, or from another database,
+    /// no longer authenticates, so pages can't be swapped.
+    pub(crate) fn page_aad(&self, id: u32) -> [u8; SALT_SIZE + 4] {
+        let mut aad = [0u8; SALT_SIZE + 4];
+        aad[..SALT_SIZE].copy_from_slice(&self.header.salt);
+        aad[SALT_SIZE..].copy_from_slice(&id.to_le_bytes());
+        aad
+    }
+
+    /// Reads a page from disk with transparent decryption (or from the page cache)
     pub fn read_page(&mut self, id: u32) -> Result<Page, StoreError> {
         if id >= self.total_pages {
             return Err(StoreError::PageNotFound(id));
         }
 
-        let offset = id as u64 * ENCRYPTED_PAGE_SIZE as u64;
-        self.file.seek(SeekFrom::Start(offset))?;
+        self.pages_read += 1;
+        if let Some(page) = self.cache.get(id) {
+            return Ok(page);
+        }
+        self.pages_decrypted += 1;
 
-        // Read encrypted data from disk
-        let mut encrypted_data = vec![0u8; ENCRYPTED_PAGE_SIZE];
-        self.file.read_exact(&mut encrypted_data)?;
+        // Read encrypted data from the uncommitted batch, or from disk
+        let encrypted_data = match self.pending.get(&id) {
+            Some(image) => image.clone(),
+            None => {
+                self.file.seek(SeekFrom::Start(Self::page_offset(id)))?;
+                let mut encrypted_data = vec![0u8; ENCRYPTED_PAGE_SIZE];
+                match self.file.read_exact(&mut encrypted_data) {
+                    Ok(()) => {}
+                    // The file ends partway through the page (truncated)
+                    Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                        return Err(StoreError::Corrupted(id))
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+                encrypted_data
+            }
+        };
 
-        // Decrypt the data
-        let plaintext = symmetric::decrypt(&encrypted_data, &self.master_key)
-            .map_err(|_| StoreError::Tampered(id))?;
+        // Decrypt the data. A failure here is a bad tag: the bytes were modified,
+        // moved from another page (or the key is wrong), which the CRC below can't
+        // tell from bit-rot.
+        let plaintext = symmetric::decrypt_with_aad(
+            &encrypted_data,
+            self.master_key.as_bytes(),
+            &self.page_aad(id),
+        )
+        .map_err(|_| StoreError::Tampered(id))?;
 
         // Ensure decrypted data is exactly PAGE_SIZE
-        if plaintext.len() != PAGE_SIZE {
-            return Err(StoreError::Tampered(id));
-        }
+        let plaintext: &[u8; PAGE_SIZE] = plaintext
+            .as_slice()
+            .try_into()
+            .map_err(|_| StoreError::Tampered(id))?;
 
-        // Convert back to Page struct safely
-        let mut page: Page = unsafe { std::mem::zeroed() };
-        unsafe {
-            std::ptr::copy_nonoverlapping(
-                plaintext.as_ptr(),
-                &mut page as *mut Page as *mut u8,
-                PAGE_SIZE,
+        // The AEAD tag proved authenticity; the CRC catches bit-rot that happened
+        // before encryption (e.g. a bad RAM write).
+        if self.options.verify_checksums {
+            let stored = u32::from_le_bytes(
+                plaintext[CRC_OFFSET..CRC_OFFSET + 4]
+                    .try_into()
+                    .expect("slice is 4 bytes"),
             );
+            if stored != Self::page_checksum(plaintext) {
+                return Err(StoreError::Corrupted(id));
+            }
         }
 
+        let page = Page::from_bytes(plaintext);
+        self.cache.insert(page);
         Ok(page)
     }
 
-    /// Allocates a new empty page
+    /// CRC32 over the plaintext page, with the checksum field itself zeroed
+    fn page_checksum(plaintext: &[u8]) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&plaintext[..CRC_OFFSET]);
+        hasher.update(&[0u8; 4]);
+        hasher.update(&plaintext[CRC_OFFSET + 4..]);
+        hasher.finalize()
+    }
+
+    /// Number of page ids handed out so far (including the index page)
+    pub fn total_pages(&self) -> u32 {
+        self.total_pages
+    }
+
+    /// Number of `read_page` calls since the pager was opened
+    pub fn pages_read(&self) -> u64 {
+        self.pages_read
+    }
+
+    /// Page reads, writes and fsyncs since the pager was opened. Subtract an earlier
+    /// snapshot to see what a piece of work cost.
+    pub fn stats(&self) -> PagerStats {
+        PagerStats {
+            pages_read: self.pages_read,
+            pages_written: self.pages_written,
+            pages_decrypted: self.pages_decrypted,
+            syncs: self.syncs + self.wal.syncs(),
+        }
+    }
+
+    /// Allocates a page: a freed one if there is any, otherwise a new one at the end
     pub fn allocate_page(&mut self) -> u32 {
+        if let Some(id) = self.free_pages.pop() {
+            self.free_list_dirty = true;
+            return id;
+        }
+        self.allocate_new_page()
+    }
+
+    fn allocate_new_page(&mut self) -> u32 {
         // Page 0 is reserved for index, so start from page 1
         let id = if self.total_pages == 0 {
             1
@@ -143,35 +716,328 @@ impl Pager {
         id
     }
 
-    // NEW: Save the index to Page 0
+    /// Writes `bytes` across as many pages as it takes, linked through `next_page`.
+    /// The head page gets `page_type`, the rest are overflow pages. Returns the head's id.
+    pub fn write_chain(&mut self, page_type: u8, bytes: &[u8]) -> Result<u32, StoreError> {
+        let chunks: Vec<&[u8]> = if bytes.is_empty() {
+            vec![bytes]
+        } else {
+            bytes.chunks(DATA_SIZE).collect()
+        };
+        let ids: Vec<u32> = chunks.iter().map(|_| self.allocate_page()).collect();
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            let mut page = Page::new(ids[i]);
+            page.page_type = if i == 0 {
+                page_type
+            } else {
+                OVERFLOW_PAGE_TYPE
+            };
+            page.next_page = ids.get(i + 1).copied().unwrap_or(0);
+            page.data[..chunk.len()].copy_from_slice(chunk);
+            page.used_space = chunk.len() as u16;
+            self.write_page(&page)?;
+        }
+        Ok(ids[0])
+    }
+
+    /// Reads a chain written by `write_chain`: the head's page type and the reassembled bytes
+    pub fn read_chain(&mut self, head: u32) -> Result<(u8, Vec<u8>), StoreError> {
+        let page = self.read_page(head)?;
+        let page_type = page.page_type;
+        let mut bytes = page.data[..page.used_space as usize].to_vec();
+
+        let mut next = page.next_page;
+        let mut hops = 0;
+        while next != 0 {
+            // A chain can't be longer than the file; anything else is a loop
+            hops += 1;
+            if hops >= self.total_pages {
+                return Err(StoreError::Malformed {
+                    page_id: next,
+                    reason: "loop in page chain".to_string(),
+                });
+            }
+            let page = self.read_page(next)?;
+            if page.page_type != OVERFLOW_PAGE_TYPE {
+                return Err(StoreError::Malformed {
+                    page_id: next,
+                    reason: "not an overflow page".to_string(),
+                });
+            }
+            bytes.extend_from_slice(&page.data[..page.used_space as usize]);
+            next = page.next_page;
+        }
+        Ok((page_type, bytes))
+    }
+
+    /// Frees every page of a chain written by `write_chain`
+    pub fn free_chain(&mut self, head: u32) -> Result<(), StoreError> {
+        let mut next = head;
+        while next != 0 {
+            let page = self.read_page(next)?;
+            self.free_page(next)?;
+            next = page.next_page;
+        }
+        Ok(())
+    }
+
+    /// Gives a page back for reuse. Its old contents stay on disk until it is rewritten.
+    pub fn free_page(&mut self, id: u32) -> Result<(), StoreError> {
+        if id == 0
+            || id == self.free_list_page
+            || self.free_list_pages.contains(&id)
+            || id >= self.total_pages
+        {
+            return Err(StoreError::PageNotFound(id));
+        }
+        if self.is_free(id) {
+            return Ok(());
+        }
+        if self.options.index_sync_window.is_zero() {
+            self.free_pages.push(id);
+            self.free_list_dirty = true;
+        } else {
+            self.released_pages.push(id);
+        }
+        Ok(())
+    }
+
+    /// Pages currently waiting to be reused
+    pub fn free_pages(&self) -> &[u32] {
+        &self.free_pages
+    }
+
+    /// Whether page `id` is waiting to be reused (without a scan of `free_pages`)
+    pub fn is_free(&self, id: u32) -> bool {
+        self.free_pages.contains(&id) || self.released_pages.contains(&id)
+    }
+
+    fn load_free_list(&mut self, page_id: u32) -> Result<(), StoreError> {
+        let mut bytes = Vec::new();
+        let mut ids = Vec::new();
+        let mut seen = std::collections::HashSet::new();
+        let mut next = page_id;
+        while next != 0 {
+            if !seen.insert(next) {
+                return Err(StoreError::Malformed {
+                    page_id: next,
+                    reason: "loop in page chain".to_string(),
+                });
+            }
+            let page = self.read_page(next)?;
+            if page.page_type != FREE_LIST_PAGE_TYPE {
+                return Err(StoreError::Malformed {
+                    page_id: next,
+                    reason: "not a free-list page".to_string(),
+                });
+            }
+            bytes.extend_from_slice(&page.data[..page.used_space as usize]);
+            ids.push(next);
+            next = page.next_page;
+        }
+        let free_pages = postcard::from_bytes(&bytes).map_err(|e| StoreError::Malformed {
+            page_id,
+            reason: format!("not a free list ({})", e),
+        })?;
+        self.free_pages.edit(|ids| *ids = free_pages);
+        self.free_list_page = page_id;
+        let chain = ids.split_off(1);
+        self.free_list_pages.edit(|ids| *ids = chain);
+        Ok(())
+    }
+
+    /// Squares the persisted free list with the file as it is now:
+    /// ids past the end (the file was truncated), duplicated or still referenced by
+    /// the index are dropped, and freed pages at the very end are handed back to the
+    /// file so `total_pages` doesn't count them. They still get reused, just in file order rather than from the list.
+    fn reconcile_free_list(&mut self) {
+        let (total_pages, free_list_page) = (self.total_pages, self.free_list_page);
+        let free_list_pages: std::collections::HashSet<u32> =
+            self.free_list_pages.iter().copied().collect();
+        let before = self.free_pages.len();
+
+        // A page the index (or its chain) still points at is in use, whatever the list says
+        let in_use: std::collections::HashSet<u32> = self
+            .index
+            .map
+            .values()
+            .chain(self.index_pages.iter())
+            .copied()
+            .collect();
+        let mut seen = std::collections::HashSet::new();
+        let mut total_pages = total_pages;
+        self.free_pages.edit(|free_pages| {
+            free_pages.retain(|&id| {
+                id != 0
+                    && id != free_list_page
+                    && !free_list_pages.contains(&id)
+                    && id < total_pages
+                    && !in_use.contains(&id)
+                    && seen.insert(id)
+            });
+
+            while let Some(pos) = free_pages.iter().position(|&id| id + 1 == total_pages) {
+                free_pages.remove(pos);
+                total_pages -= 1;
+            }
+        });
+        self.total_pages = total_pages;
+
+        if self.free_pages.len() != before {
+            self.free_list_dirty = true;
+        }
+    }
+
+    fn write_free_list(&mut self) -> Result<(), StoreError> {
+        // The list's own pages never come from the list
+        if self.free_list_page == 0 {
+            self.free_list_page = self.allocate_new_page();
+        }
+
+        // Size the chain to the list. A page the chain no longer needs joins the
+        // list, which can take one more page to hold: that one comes from the end
+        // of the file, so this settles.
+        let bytes = loop {
+            let bytes = postcard::to_allocvec(&*self.free_pages).map_err(|_| {
+                StoreError::Io(std::io::Error::other("Free list serialization failed"))
+            })?;
+            let needed = bytes.len().div_ceil(DATA_SIZE).max(1) - 1;
+            if self.free_list_pages.len() < needed {
+                let id = self.allocate_new_page();
+                self.free_list_pages.push(id);
+            } else if self.free_list_pages.len() > needed {
+                let id = self
+                    .free_list_pages
+                    .pop()
+                    .expect("chain is longer than needed");
+                self.free_pages.push(id);
+            } else {
+                break bytes;
+            }
+        };
+
+        let ids: Vec<u32> = std::iter::once(self.free_list_page)
+            .chain(self.free_list_pages.iter().copied())
+            .collect();
+        let chunks: Vec<&[u8]> = if bytes.is_empty() {
+            vec![&bytes[..]]
+        } else {
+            bytes.chunks(DATA_SIZE).collect()
+        };
+        for (i, chunk) in chunks.iter().enumerate() {
+            let mut page = Page::new(ids[i]);
+            page.page_type = FREE_LIST_PAGE_TYPE;
+            page.next_page = ids.get(i + 1).copied().unwrap_or(0);
+            page.data[..chunk.len()].copy_from_slice(chunk);
+            page.used_space = chunk.len() as u16;
+            self.write_page(&page)?;
+        }
+        self.free_list_dirty = false;
+        Ok(())
+    }
+
+    /// Saves the index, unless it was written within the `index_sync_window`:
+    /// then the changes wait for a later `sync_index` or `flush_index`.
+    /// `SyncPolicy::Always` ignores the window.
     pub fn sync_index(&mut self) -> Result<(), StoreError> {
-        if !self.index.dirty {
+        let window = self.options.index_sync_window;
+        if self.options.sync != SyncPolicy::Always
+            && self
+                .index_written_at
+                .is_some_and(|written| written.elapsed() < window)
+        {
             return Ok(());
         }
+        self.flush_index()
+    }
+
+    /// Saves the index now: page 0 holds the start, and if it doesn't fit, the rest
+    /// continues on a chain of pages linked through `next_page` (0 = last).
+    /// The chain's pages are kept between syncs, grown or shrunk as needed.
+    pub fn flush_index(&mut self) -> Result<(), StoreError> {
+        // The index written below no longer references these
+        if !self.released_pages.is_empty() {
+            for id in self.released_pages.take() {
+                self.free_pages.push(id);
+            }
+            self.free_list_dirty = true;
+        }
+        if !self.free_list_dirty && !self.index.dirty {
+            return Ok(());
+        }
+
+        if self.options.compact_index && self.index.needs_compaction() {
+            self.index.compact();
+        }
 
         let bytes = self.index.to_bytes()?;
+        let chunks: Vec<&[u8]> = if bytes.is_empty() {
+            vec![&bytes[..]]
+        } else {
+            bytes.chunks(DATA_SIZE).collect()
+        };
 
         // Ensure Page 0 exists
         if self.total_pages == 0 {
             self.total_pages = 1;
         }
 
-        let mut page = Page::new(0); // Page 0 is reserved
-        page.page_type = 2; // 2 = Index Type
-
-        // Safety: If index > 4KB, this crashes.
-        // FUTURE TODO: B-Tree splitting. For now, we assume small index.
-        if bytes.len() > crate::page::DATA_SIZE {
-            return Err(StoreError::Io(std::io::Error::other(
-                "Index too big for Page 0",
-            )));
+        // Size the continuation chain first: both directions touch the free list,
+        // which has to be written after that
+        while self.index_pages.len() + 1 < chunks.len() {
+            let id = self.allocate_page();
+            self.index_pages.push(id);
+        }
+        while self.index_pages.len() + 1 > chunks.len() {
+            let id = self.index_pages.pop().expect("chain is longer than needed");
+            self.free_page(id)?;
+        }
+        if self.free_list_dirty {
+            self.write_free_list()?;
         }
 
-        page.data[..bytes.len()].copy_from_slice(&bytes);
-        page.used_space = bytes.len() as u16;
+        let ids: Vec<u32> = std::iter::once(0)
+            .chain(self.index_pages.iter().copied())
+            .collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let mut page = Page::new(ids[i]);
+            if i == 0 {
+                // Page 0 is reserved
+                page.page_type = 2; // 2 = Index Type
+                page.reserved[FREE_LIST_POINTER..FREE_LIST_POINTER + 4]
+                    .copy_from_slice(&self.free_list_page.to_le_bytes());
+            } else {
+                page.page_type = OVERFLOW_PAGE_TYPE;
+            }
+            page.next_page = ids.get(i + 1).copied().unwrap_or(0);
+            page.data[..chunk.len()].copy_from_slice(chunk);
+            page.used_space = chunk.len() as u16;
+            self.write_page(&page)?;
+        }
 
-        self.write_page(&page)?;
         self.index.dirty = false;
+        self.index_written_at = Some(Instant::now());
         Ok(())
     }
 }
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        // A transaction nobody committed is abandoned
+        let _ = self.rollback();
+
+        // Changes held back by the index_sync_window are not lost on a clean close
+        if !self.options.index_sync_window.is_zero() {
+            self.begin();
+            let _ = self.flush_index().and_then(|()| self.commit());
+        }
+        if self.options.sync == SyncPolicy::Never {
+            let _ = self.flush();
+        }
+
+        // A checkpointed WAL is empty and not worth keeping around.
+        // Anything still in it (a failed checkpoint) is left for the next open to replay.
+        let _ = self.wal.remove_if_empty();
+    }
+}
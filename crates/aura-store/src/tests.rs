@@ -1,15 +1,18 @@
 #[cfg(test)]
 use crate::{
-    page::Page,
-    pager::{Pager, ENCRYPTED_PAGE_SIZE},
+    header::{FileHeader, FILE_HEADER_SIZE, FORMAT_VERSION},
+    index::PrimaryIndex,
+    page::{Page, DATA_SIZE, HEADER_SIZE, PAGE_SIZE},
+    pager::{Pager, PagerOptions, WalRecordInfo, ENCRYPTED_PAGE_SIZE},
+    wal::Wal,
     StoreError,
 };
 #[cfg(test)]
-use aura_security::symmetric::generate_key;
+use aura_security::symmetric::{self, generate_key};
 #[cfg(test)]
 use std::fs;
 #[cfg(test)]
-use std::io::{Seek, SeekFrom, Write};
+use std::io::{Read, Seek, SeekFrom, Write};
 #[cfg(test)]
 use tempfile::NamedTempFile;
 
@@ -62,8 +65,10 @@ fn test_tamper_detection() {
 
     // Manually corrupt the encrypted data on disk (corrupt the authentication tag)
     let mut file = fs::OpenOptions::new().write(true).open(db_path).unwrap();
-    file.seek(SeekFrom::Start((ENCRYPTED_PAGE_SIZE - 5) as u64))
-        .unwrap(); // Seek to near the end of the tag
+    file.seek(SeekFrom::Start(
+        Pager::page_offset(0) + (ENCRYPTED_PAGE_SIZE - 5) as u64,
+    ))
+    .unwrap(); // Seek to near the end of the tag
     file.write_all(b"XXXXX").unwrap();
 
     // Attempting to read should detect tampering
@@ -71,6 +76,137 @@ fn test_tamper_detection() {
     assert!(matches!(result, Err(StoreError::Tampered(_))));
 }
 
+#[test]
+fn test_swapped_pages_fail_authentication() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db_path = temp_file.path();
+    let master_key = generate_key();
+
+    let mut pager = Pager::open(db_path, master_key.clone()).unwrap();
+    for (id, tag) in [(5, b"five"), (6, b"six!")] {
+        let mut page = Page::new(id);
+        page.data[0..4].copy_from_slice(tag);
+        pager.write_page(&page).unwrap();
+    }
+    drop(pager);
+
+    // Copy page 5's (validly encrypted) image over page 6
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(db_path)
+        .unwrap();
+    let mut image = vec![0u8; ENCRYPTED_PAGE_SIZE];
+    file.seek(SeekFrom::Start(Pager::page_offset(5))).unwrap();
+    file.read_exact(&mut image).unwrap();
+    file.seek(SeekFrom::Start(Pager::page_offset(6))).unwrap();
+    file.write_all(&image).unwrap();
+
+    // The page id is part of the authenticated data, so the copy is caught
+    let mut pager = Pager::open(db_path, master_key).unwrap();
+    assert_eq!(&pager.read_page(5).unwrap().data[0..4], b"five");
+    assert!(matches!(pager.read_page(6), Err(StoreError::Tampered(6))));
+}
+
+#[test]
+fn test_file_header_round_trips() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db_path = temp_file.path();
+    let master_key = generate_key();
+
+    let header = Pager::open(db_path, master_key.clone())
+        .unwrap()
+        .header()
+        .clone();
+    assert_eq!(header.version, FORMAT_VERSION);
+    assert_eq!(header.page_size, PAGE_SIZE as u32);
+
+    let bytes = fs::read(db_path).unwrap();
+    assert_eq!(FileHeader::from_bytes(&bytes).unwrap(), header);
+    let pager = Pager::open(db_path, master_key).unwrap();
+    assert_eq!(pager.header(), &header);
+
+    // Every database gets its own salt
+    let other = NamedTempFile::new().unwrap();
+    let pager = Pager::open(other.path(), generate_key()).unwrap();
+    assert_ne!(pager.header().salt, header.salt);
+}
+
+#[test]
+fn test_a_database_is_open_in_one_pager_at_a_time() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db_path = temp_file.path();
+    let master_key = generate_key();
+
+    let pager = Pager::open(db_path, master_key.clone()).unwrap();
+    assert!(matches!(
+        Pager::open(db_path, master_key.clone()),
+        Err(StoreError::Locked(_))
+    ));
+
+    // Closing it releases the file
+    drop(pager);
+    assert!(Pager::open(db_path, master_key).is_ok());
+}
+
+#[test]
+fn test_open_rejects_files_it_cannot_read() {
+    let master_key = generate_key();
+    let open_with = |bytes: &[u8]| {
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), bytes).unwrap();
+        Pager::open(temp_file.path(), master_key.clone()).err()
+    };
+
+    // Not a database at all, however long
+    let random = symmetric::random_bytes::<10_000>();
+    assert!(matches!(
+        open_with(&random),
+        Some(StoreError::InvalidDatabase(_))
+    ));
+    assert!(matches!(
+        open_with(b"AURA"),
+        Some(StoreError::InvalidDatabase(_))
+    ));
+
+    // A later format, or a damaged header
+    let mut future = FileHeader::new();
+    future.version = FORMAT_VERSION + 1;
+    let err = open_with(&future.to_bytes()).unwrap();
+    assert!(matches!(
+        err,
+        StoreError::VersionMismatch { found, supported }
+            if found == FORMAT_VERSION + 1 && supported == FORMAT_VERSION
+    ));
+    assert!(err
+        .to_string()
+        .contains(&format!("version {}", FORMAT_VERSION + 1)));
+    let mut damaged = FileHeader::new().to_bytes();
+    damaged[30] ^= 0x01;
+    assert!(matches!(
+        open_with(&damaged),
+        Some(StoreError::InvalidDatabase(_))
+    ));
+
+    // A database from before the header: page 0 first, sealed with plain
+    // `encrypt`, or bound to its id alone once pages had associated data
+    let legacy = symmetric::encrypt(&Page::new(0).to_bytes(), master_key.as_bytes()).unwrap();
+    assert!(matches!(
+        open_with(&legacy),
+        Some(StoreError::VersionMismatch { found: 0, .. })
+    ));
+    let legacy = symmetric::encrypt_with_aad(
+        &Page::new(0).to_bytes(),
+        master_key.as_bytes(),
+        &0u32.to_le_bytes(),
+    )
+    .unwrap();
+    assert!(matches!(
+        open_with(&legacy),
+        Some(StoreError::VersionMismatch { found: 0, .. })
+    ));
+}
+
 #[test]
 fn test_different_keys_produce_different_ciphertext() {
     // Create a temporary file
@@ -91,8 +227,8 @@ fn test_different_keys_produce_different_ciphertext() {
         pager1.write_page(&page).unwrap();
     }
 
-    // Read the raw encrypted bytes
-    let encrypted_data1 = fs::read(db_path).unwrap();
+    // Read the raw encrypted bytes (past the file header)
+    let encrypted_data1 = fs::read(db_path).unwrap()[FILE_HEADER_SIZE..].to_vec();
 
     // Clear the file and write with key2
     fs::write(db_path, []).unwrap();
@@ -102,7 +238,7 @@ fn test_different_keys_produce_different_ciphertext() {
     }
 
     // Read the raw encrypted bytes
-    let encrypted_data2 = fs::read(db_path).unwrap();
+    let encrypted_data2 = fs::read(db_path).unwrap()[FILE_HEADER_SIZE..].to_vec();
 
     // The encrypted data should be different (different keys produce different ciphertext)
     assert_ne!(encrypted_data1, encrypted_data2);
@@ -154,6 +290,109 @@ fn test_btree_basic_operations() {
     assert_eq!(result, None);
 }
 
+#[test]
+fn test_btree_cycle_is_reported_not_followed() {
+    use crate::btree::manager::BTreeManager;
+    use crate::btree::node::{BTreeNode, NodeType};
+
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut pager = Pager::open(temp_file.path(), generate_key()).unwrap();
+
+    // Two internal nodes whose child pointers lead back to each other
+    for (id, child) in [(1, 2), (2, 1)] {
+        let node = BTreeNode {
+            id,
+            parent: None,
+            node_type: NodeType::Internal,
+            keys: vec!["m".to_string()],
+            children: vec![child, child],
+        };
+        let bytes = node.to_bytes().unwrap();
+        let mut page = Page::new(id);
+        page.used_space = bytes.len() as u16;
+        page.data[..bytes.len()].copy_from_slice(&bytes);
+        pager.write_page(&page).unwrap();
+    }
+
+    let mut btree = BTreeManager::new(&mut pager, 1);
+    let corrupted = |result: Result<_, StoreError>| match result {
+        Err(StoreError::Malformed { reason, .. }) => reason.contains("cycle"),
+        _ => false,
+    };
+    assert!(corrupted(btree.search("a").map(|_| ())));
+    assert!(corrupted(btree.search_prefix("a").map(|_| ())));
+    assert!(corrupted(btree.insert("a".to_string(), 7)));
+    assert!(corrupted(btree.delete("a").map(|_| ())));
+    assert!(corrupted(btree.clear()));
+}
+
+#[test]
+fn test_page_chain_loop_is_reported_not_followed() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut pager = Pager::open(temp_file.path(), generate_key()).unwrap();
+
+    // A three-page chain whose last page points back at the second
+    let head = pager.write_chain(1, &vec![7u8; DATA_SIZE * 2 + 1]).unwrap();
+    let mut tail = pager.read_page(head + 2).unwrap();
+    tail.next_page = head + 1;
+    pager.write_page(&tail).unwrap();
+
+    let err = pager.read_chain(head).unwrap_err();
+    assert!(matches!(
+        &err,
+        StoreError::Malformed { reason, .. } if reason == "loop in page chain"
+    ));
+    assert!(err.to_string().contains("loop in page chain"));
+}
+
+#[test]
+fn test_btree_missing_root_is_page_not_found() {
+    use crate::btree::manager::BTreeManager;
+
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut pager = Pager::open(temp_file.path(), generate_key()).unwrap();
+
+    // Past the end of the file, the index page, and a page written blank
+    let blank = pager.allocate_page();
+    pager.write_page(&Page::new(blank)).unwrap();
+    for root_id in [999, 0, blank] {
+        let mut btree = BTreeManager::new(&mut pager, root_id);
+        assert!(matches!(btree.search("a"), Err(StoreError::PageNotFound(id)) if id == root_id));
+        assert!(matches!(
+            btree.insert("a".to_string(), 7),
+            Err(StoreError::PageNotFound(id)) if id == root_id
+        ));
+    }
+
+    // A tree whose root has since been freed
+    let root_id = BTreeManager::create(&mut pager).unwrap().root_id();
+    pager.free_page(root_id).unwrap();
+    let mut btree = BTreeManager::new(&mut pager, root_id);
+    assert!(matches!(btree.search("a"), Err(StoreError::PageNotFound(id)) if id == root_id));
+}
+
+#[test]
+fn test_btree_undecodable_node_names_its_page() {
+    use crate::btree::manager::BTreeManager;
+
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut pager = Pager::open(temp_file.path(), generate_key()).unwrap();
+
+    // An authentic page whose data is not a node
+    let id = pager.allocate_page();
+    let mut page = Page::new(id);
+    page.data[..8].copy_from_slice(&[0xff; 8]);
+    page.used_space = 8;
+    pager.write_page(&page).unwrap();
+
+    let mut btree = BTreeManager::new(&mut pager, id);
+    let err = btree.search("a").unwrap_err();
+    assert!(matches!(err, StoreError::Malformed { page_id, .. } if page_id == id));
+    let message = err.to_string();
+    assert!(message.contains(&format!("Page {}", id)), "{}", message);
+    assert!(message.contains("not a B-tree node"), "{}", message);
+}
+
 #[test]
 fn test_btree_split_and_growth() {
     let file = "test_btree.db";
@@ -190,3 +429,756 @@ fn test_btree_split_and_growth() {
 
     println!("✅ B-Tree Successfully Split and Rebalanced!");
 }
+
+#[test]
+fn test_btree_prefix_search_across_splits() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut pager = Pager::open(temp_file.path(), generate_key()).unwrap();
+
+    let mut btree = crate::btree::manager::BTreeManager::create(&mut pager).unwrap();
+    let first_root = btree.root_id();
+
+    // Composite keys "<value>\0<doc id>", 200 of them so the tree has several levels
+    for i in 0..200u32 {
+        let value = if i % 10 == 0 { "berlin" } else { "paris" };
+        btree
+            .insert(format!("{}\0doc_{:03}", value, i), i)
+            .expect("Insert failed");
+    }
+    assert_ne!(btree.root_id(), first_root);
+
+    let berlin = btree.search_prefix("berlin\0").unwrap();
+    let pages: Vec<u32> = berlin.iter().map(|(_, page)| *page).collect();
+    assert_eq!(pages, (0..200).step_by(10).collect::<Vec<u32>>());
+
+    assert_eq!(btree.search_prefix("paris\0").unwrap().len(), 180);
+    assert!(btree.search_prefix("rome\0").unwrap().is_empty());
+}
+
+#[test]
+fn test_btree_range_scan() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut pager = Pager::open(temp_file.path(), generate_key()).unwrap();
+    let mut btree = crate::btree::manager::BTreeManager::create(&mut pager).unwrap();
+
+    // Inserted out of order, so leaves split all over the tree
+    for i in (0..300u32).rev().step_by(2).chain((0..300u32).step_by(2)) {
+        btree.insert(format!("key_{:03}", i), i).unwrap();
+    }
+    let mut scan = |start: Option<&str>, end: Option<&str>| -> Vec<u32> {
+        btree
+            .range(start, end)
+            .map(|entry| entry.unwrap().1)
+            .collect()
+    };
+
+    // Start is inclusive, end exclusive, and the keys come in order
+    assert_eq!(
+        scan(Some("key_100"), Some("key_200")),
+        (100..200).collect::<Vec<u32>>()
+    );
+    assert_eq!(scan(Some("key_0995"), Some("key_1005")), [100]);
+    assert_eq!(
+        scan(Some("key_250"), None),
+        (250..300).collect::<Vec<u32>>()
+    );
+    assert_eq!(scan(None, Some("key_050")), (0..50).collect::<Vec<u32>>());
+    assert_eq!(scan(None, None), (0..300).collect::<Vec<u32>>());
+    assert!(scan(Some("key_300"), None).is_empty());
+    assert!(scan(Some("key_200"), Some("key_100")).is_empty());
+
+    // Entries come back with their keys
+    let first: Vec<(String, u32)> = btree
+        .range(Some("key_298"), None)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(
+        first,
+        [("key_298".to_string(), 298), ("key_299".to_string(), 299)]
+    );
+}
+
+#[test]
+fn test_btree_delete_rebalances() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut pager = Pager::open(temp_file.path(), generate_key()).unwrap();
+    let mut btree = crate::btree::manager::BTreeManager::create(&mut pager).unwrap();
+
+    // 300 keys: three levels, so internal nodes borrow and merge too
+    for i in 0..300u32 {
+        btree.insert(format!("key_{:03}", i), i).unwrap();
+    }
+    let tall_root = btree.root_id();
+
+    // Delete from the front (separators, borrowing) and every third key elsewhere
+    let deleted: Vec<u32> = (0..300).filter(|i| *i < 120 || i % 3 == 0).collect();
+    for &i in &deleted {
+        assert!(
+            btree.delete(&format!("key_{:03}", i)).unwrap(),
+            "key_{:03}",
+            i
+        );
+    }
+    assert!(!btree.delete("key_000").unwrap());
+    assert!(!btree.delete("missing").unwrap());
+
+    for i in 0..300u32 {
+        let expected = (!deleted.contains(&i)).then_some(i);
+        assert_eq!(btree.search(&format!("key_{:03}", i)).unwrap(), expected);
+    }
+    let remaining = btree.search_prefix("key_").unwrap();
+    assert_eq!(remaining.len(), 300 - deleted.len());
+    assert!(remaining.windows(2).all(|w| w[0].0 < w[1].0));
+
+    // Deleting everything but one key merges the tree back into a single leaf
+    for (key, _) in &remaining[1..] {
+        assert!(btree.delete(key).unwrap());
+    }
+    assert_ne!(btree.root_id(), tall_root);
+    assert_eq!(btree.search_prefix("").unwrap(), vec![remaining[0].clone()]);
+
+    // Merged-away nodes went back to the free list
+    assert!(!pager.free_pages().is_empty());
+}
+
+#[test]
+fn test_checksum_verification_toggle() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db_path = temp_file.path();
+    let master_key = generate_key();
+
+    // Write a page normally (CRC is stamped on write)
+    let aad = {
+        let mut pager = Pager::open(db_path, master_key.clone()).unwrap();
+        let mut page = Page::new(0);
+        page.used_space = 4;
+        page.data[0..4].copy_from_slice(b"rust");
+        pager.write_page(&page).unwrap();
+        pager.page_aad(0)
+    };
+
+    // Simulate bit-rot that happened BEFORE encryption:
+    // decrypt, flip a data byte, re-encrypt with the real key (AEAD stays valid)
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(db_path)
+        .unwrap();
+    let mut encrypted = vec![0u8; ENCRYPTED_PAGE_SIZE];
+    file.seek(SeekFrom::Start(Pager::page_offset(0))).unwrap();
+    file.read_exact(&mut encrypted).unwrap();
+    let mut plaintext =
+        symmetric::decrypt_with_aad(&encrypted, master_key.as_bytes(), &aad).unwrap();
+    plaintext[200] ^= 0x01;
+    let reencrypted = symmetric::encrypt_with_aad(&plaintext, master_key.as_bytes(), &aad).unwrap();
+    file.seek(SeekFrom::Start(Pager::page_offset(0))).unwrap();
+    file.write_all(&reencrypted).unwrap();
+
+    // Verification on (default): the CRC mismatch is reported as corruption
+    let mut pager = Pager::open(db_path, master_key.clone()).unwrap();
+    assert!(matches!(pager.read_page(0), Err(StoreError::Corrupted(0))));
+    drop(pager);
+
+    // Verification off: the page decrypts and is returned as-is
+    let options = PagerOptions {
+        verify_checksums: false,
+        ..PagerOptions::default()
+    };
+    let mut pager = Pager::open_with_options(db_path, master_key, options).unwrap();
+    let page = pager.read_page(0).unwrap();
+    assert_eq!(&page.data[0..4], b"rust");
+}
+
+#[test]
+fn test_truncated_file_is_corruption_not_tampering() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db_path = temp_file.path();
+    let master_key = generate_key();
+
+    {
+        let mut pager = Pager::open(db_path, master_key.clone()).unwrap();
+        pager.write_page(&Page::new(0)).unwrap();
+        pager.write_page(&Page::new(1)).unwrap();
+    }
+
+    // Lose the tail of the last page
+    let file = fs::OpenOptions::new().write(true).open(db_path).unwrap();
+    file.set_len(Pager::page_offset(2) - 100).unwrap();
+
+    let mut pager = Pager::open(db_path, master_key).unwrap();
+    assert!(pager.read_page(0).is_ok());
+    assert!(matches!(pager.read_page(1), Err(StoreError::Corrupted(1))));
+}
+
+#[test]
+fn test_page_header_fields_round_trip() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut pager = Pager::open(temp_file.path(), generate_key()).unwrap();
+
+    // Every header field distinct and non-zero, data filled to the very last byte
+    let mut page = Page::new(7);
+    page.page_type = 0xA5;
+    page.used_space = 0xBEEF;
+    page.next_page = 0x0102_0304;
+    for (i, byte) in page.reserved.iter_mut().enumerate() {
+        *byte = i as u8 + 1;
+    }
+    for (i, byte) in page.data.iter_mut().enumerate() {
+        *byte = (i % 251) as u8 + 1;
+    }
+    pager.write_page(&page).unwrap();
+
+    let loaded = pager.read_page(7).unwrap();
+    assert_eq!(loaded.id, 7);
+    assert_eq!(loaded.page_type, 0xA5);
+    assert_eq!(loaded.used_space, 0xBEEF);
+    assert_eq!(loaded.next_page, 0x0102_0304);
+    // The first 4 reserved bytes carry the CRC, the rest come back untouched
+    assert_eq!(loaded.reserved[4..], page.reserved[4..]);
+    assert_eq!(loaded.data[..], page.data[..]);
+
+    // The on-disk image has a fixed little-endian layout
+    let bytes = page.to_bytes();
+    assert_eq!(bytes.len(), PAGE_SIZE);
+    assert_eq!(bytes[0..4], 7u32.to_le_bytes());
+    assert_eq!(bytes[6..8], 0xBEEFu16.to_le_bytes());
+    assert_eq!(Page::from_bytes(&bytes).data[..], page.data[..]);
+}
+
+#[test]
+fn test_page_layout_is_self_consistent() {
+    assert_eq!(std::mem::size_of::<Page>(), PAGE_SIZE);
+
+    let page = Page::new(0);
+    assert_eq!(page.data.len(), DATA_SIZE);
+    assert_eq!(HEADER_SIZE + DATA_SIZE, PAGE_SIZE);
+}
+
+#[test]
+fn test_wal_batch_is_invisible_until_commit() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db_path = temp_file.path();
+    let mut pager = Pager::open(db_path, generate_key()).unwrap();
+
+    pager.begin();
+    let mut page = Page::new(1);
+    page.data[0..4].copy_from_slice(b"wal!");
+    pager.write_page(&page).unwrap();
+
+    // Readable through the pager, but the main file is untouched until commit
+    assert_eq!(&pager.read_page(1).unwrap().data[0..4], b"wal!");
+    assert_eq!(
+        fs::metadata(db_path).unwrap().len(),
+        FILE_HEADER_SIZE as u64
+    );
+
+    pager.commit().unwrap();
+    assert_eq!(fs::metadata(db_path).unwrap().len(), Pager::page_offset(2));
+    assert_eq!(fs::metadata(Wal::path_for(db_path)).unwrap().len(), 0);
+}
+
+#[test]
+fn test_wal_recovery_after_partial_write() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db_path = temp_file.path();
+    let wal_path = Wal::path_for(db_path);
+    let master_key = generate_key();
+
+    let page_with = |id: u32, text: &[u8]| {
+        let mut page = Page::new(id);
+        page.used_space = text.len() as u16;
+        page.data[..text.len()].copy_from_slice(text);
+        page
+    };
+
+    // Page 1 is on disk with its old contents
+    {
+        let mut pager = Pager::open(db_path, master_key.clone()).unwrap();
+        pager.write_page(&page_with(1, b"old")).unwrap();
+    }
+
+    // The process commits a batch to the WAL, then dies while logging the next one:
+    // its last record is torn and it never got to checkpoint anything
+    {
+        let pager = Pager::open(db_path, master_key.clone()).unwrap();
+        let mut wal = Wal::open(&wal_path).unwrap();
+        wal.append_page(1, &pager.seal_page(&page_with(1, b"new")).unwrap())
+            .unwrap();
+        wal.append_page(2, &pager.seal_page(&page_with(2, b"two")).unwrap())
+            .unwrap();
+        wal.commit().unwrap();
+        wal.append_page(3, &pager.seal_page(&page_with(3, b"lost")).unwrap())
+            .unwrap();
+    }
+    let wal_len = fs::metadata(&wal_path).unwrap().len();
+    fs::OpenOptions::new()
+        .write(true)
+        .open(&wal_path)
+        .unwrap()
+        .set_len(wal_len - 100)
+        .unwrap();
+    let crashed_wal = fs::read(&wal_path).unwrap();
+
+    let check = |pager: &mut Pager| {
+        assert_eq!(&pager.read_page(1).unwrap().data[0..3], b"new");
+        assert_eq!(&pager.read_page(2).unwrap().data[0..3], b"two");
+        assert!(matches!(
+            pager.read_page(3),
+            Err(StoreError::PageNotFound(3))
+        ));
+    };
+
+    // Reopening replays the committed batch and drops the torn one
+    let mut pager = Pager::open(db_path, master_key.clone()).unwrap();
+    check(&mut pager);
+    drop(pager);
+    assert!(!wal_path.exists());
+
+    // Dying mid-checkpoint leaves the same WAL behind with the main file
+    // already (partly) updated: replaying it again must give the same result
+    fs::write(&wal_path, &crashed_wal).unwrap();
+    let mut pager = Pager::open(db_path, master_key).unwrap();
+    check(&mut pager);
+}
+
+#[test]
+fn test_freed_pages_are_reused() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db_path = temp_file.path();
+    let master_key = generate_key();
+
+    {
+        let mut pager = Pager::open(db_path, master_key.clone()).unwrap();
+        let ids: Vec<u32> = (0..4).map(|_| pager.allocate_page()).collect();
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+        for &id in &ids {
+            pager.write_page(&Page::new(id)).unwrap();
+        }
+
+        pager.free_page(2).unwrap();
+        assert_eq!(pager.allocate_page(), 2);
+        assert_eq!(pager.allocate_page(), 5);
+
+        // Page 0 is the index and can never be freed
+        assert!(pager.free_page(0).is_err());
+
+        pager.free_page(3).unwrap();
+        pager.sync_index().unwrap();
+    }
+
+    // The free list survives a restart
+    let mut pager = Pager::open(db_path, master_key).unwrap();
+    assert_eq!(pager.free_pages(), &[3]);
+    assert!(pager.is_free(3) && !pager.is_free(2));
+    assert_eq!(pager.allocate_page(), 3);
+    assert!(!pager.is_free(3));
+    assert!(pager.allocate_page() > 5);
+}
+
+#[test]
+fn test_rollback_undoes_index_and_free_list_changes() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut pager = Pager::open(temp_file.path(), generate_key()).unwrap();
+
+    pager.begin();
+    let ids: Vec<u32> = (0..4).map(|_| pager.allocate_page()).collect();
+    for &id in &ids {
+        pager.write_page(&Page::new(id)).unwrap();
+    }
+    for (key, &id) in ["t/a", "t/b", "t/c"].iter().zip(&ids) {
+        pager.index.insert(key.to_string(), id);
+    }
+    pager.free_page(ids[3]).unwrap();
+    pager.sync_index().unwrap();
+    pager.commit().unwrap();
+
+    // Every kind of change, then a rollback
+    pager.begin_transaction();
+    pager.index.remove("t/a");
+    pager.index.insert("t/b".to_string(), 50);
+    pager.index.insert("t/d".to_string(), 60);
+    assert_eq!(pager.allocate_page(), ids[3]);
+    pager.free_page(ids[2]).unwrap();
+    pager.sync_index().unwrap();
+    pager.rollback().unwrap();
+
+    assert_eq!(pager.index.get("t/a"), Some(ids[0]));
+    assert_eq!(pager.index.get("t/b"), Some(ids[1]));
+    assert_eq!(pager.index.get("t/c"), Some(ids[2]));
+    assert_eq!(pager.index.get("t/d"), None);
+    assert_eq!(pager.index.row_count("t"), 3);
+    assert!(!pager.index.dirty);
+    assert_eq!(pager.free_pages(), &[ids[3]]);
+
+    // What a committed transaction changed stays
+    pager.begin_transaction();
+    pager.index.insert("t/d".to_string(), 60);
+    pager.commit().unwrap();
+    pager.rollback().unwrap();
+    assert_eq!(pager.index.get("t/d"), Some(60));
+    assert_eq!(pager.index.row_count("t"), 4);
+}
+
+#[test]
+fn test_free_list_spans_several_pages() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db_path = temp_file.path();
+    let master_key = generate_key();
+
+    // Far more freed pages than one page can list
+    let freed: Vec<u32> = {
+        let mut pager = Pager::open(db_path, master_key.clone()).unwrap();
+        pager.begin();
+        let ids: Vec<u32> = (0..3000).map(|_| pager.allocate_page()).collect();
+        for &id in &ids {
+            pager.write_page(&Page::new(id)).unwrap();
+        }
+        // The last one stays in use, so the freed ones aren't trimmed off the end
+        for &id in &ids[..ids.len() - 1] {
+            pager.free_page(id).unwrap();
+        }
+        pager.sync_index().unwrap();
+        pager.commit().unwrap();
+        ids[..ids.len() - 1].to_vec()
+    };
+
+    let mut pager = Pager::open(db_path, master_key.clone()).unwrap();
+    assert_eq!(pager.free_pages(), &freed[..]);
+
+    // Shrinking the list gives the chain's spare pages back to it
+    pager.begin();
+    for _ in 0..2990 {
+        pager.allocate_page();
+    }
+    pager.sync_index().unwrap();
+    pager.commit().unwrap();
+    let remaining = pager.free_pages().to_vec();
+    assert_eq!(remaining[..9], freed[..9]);
+    assert!(
+        remaining[9..].iter().all(|&id| id > 3000),
+        "{:?}",
+        remaining
+    );
+    drop(pager);
+
+    // (Those at the very end of the file are trimmed off on open)
+    let pager = Pager::open(db_path, master_key).unwrap();
+    assert_eq!(pager.free_pages()[..9], freed[..9]);
+    assert!(pager.free_pages().iter().all(|id| remaining.contains(id)));
+}
+
+#[test]
+fn test_free_list_never_hands_out_indexed_pages() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db_path = temp_file.path();
+    let master_key = generate_key();
+
+    {
+        let mut pager = Pager::open(db_path, master_key.clone()).unwrap();
+        for _ in 0..3 {
+            let id = pager.allocate_page();
+            pager.write_page(&Page::new(id)).unwrap();
+        }
+        // Page 2 is freed by mistake while a document still lives there
+        pager.index.insert("users/u1".to_string(), 2);
+        pager.free_page(1).unwrap();
+        pager.free_page(2).unwrap();
+        pager.sync_index().unwrap();
+    }
+
+    let mut pager = Pager::open(db_path, master_key).unwrap();
+    assert_eq!(pager.free_pages(), &[1]);
+    assert_eq!(pager.allocate_page(), 1);
+    assert_ne!(pager.allocate_page(), 2);
+}
+
+#[test]
+fn test_open_reconciles_total_pages_with_free_list() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db_path = temp_file.path();
+    let master_key = generate_key();
+
+    {
+        let mut pager = Pager::open(db_path, master_key.clone()).unwrap();
+        for _ in 0..3 {
+            let id = pager.allocate_page();
+            pager.write_page(&Page::new(id)).unwrap();
+        }
+        // The free-list page goes to the end of the file (page 4)
+        pager.free_page(1).unwrap();
+        pager.sync_index().unwrap();
+        assert_eq!(pager.allocate_page(), 1);
+
+        for _ in 0..3 {
+            let id = pager.allocate_page();
+            pager.write_page(&Page::new(id)).unwrap();
+        }
+        assert_eq!(pager.total_pages(), 8);
+
+        // One page mid-file, and the last two pages of the file
+        for id in [2, 6, 7] {
+            pager.free_page(id).unwrap();
+        }
+        pager.sync_index().unwrap();
+    }
+    let file_len = fs::metadata(db_path).unwrap().len();
+
+    // The trailing free pages no longer count; the mid-file one stays on the list
+    let mut pager = Pager::open(db_path, master_key).unwrap();
+    assert_eq!(pager.total_pages(), 6);
+    assert_eq!(pager.free_pages(), &[2]);
+
+    // Allocation reuses the freed pages instead of growing the file
+    assert_eq!(pager.allocate_page(), 2);
+    assert_eq!(pager.allocate_page(), 6);
+    assert_eq!(pager.allocate_page(), 7);
+    for id in [2, 6, 7] {
+        pager.write_page(&Page::new(id)).unwrap();
+    }
+    assert_eq!(fs::metadata(db_path).unwrap().len(), file_len);
+}
+
+#[test]
+fn test_primary_index_spans_multiple_pages() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db_path = temp_file.path();
+    let master_key = generate_key();
+
+    let mut keys = Vec::new();
+    {
+        let mut pager = Pager::open(db_path, master_key.clone()).unwrap();
+        pager.begin();
+        for i in 0..2000 {
+            let id = pager.allocate_page();
+            pager.write_page(&Page::new(id)).unwrap();
+            let key = format!("users/user_{:04}", i);
+            pager.index.insert(key.clone(), id);
+            keys.push((key, id));
+        }
+        pager.sync_index().unwrap();
+        pager.commit().unwrap();
+        assert!(
+            pager.index.to_bytes().unwrap().len() > DATA_SIZE,
+            "the index should not fit in page 0"
+        );
+    }
+
+    let mut pager = Pager::open(db_path, master_key.clone()).unwrap();
+    for (key, id) in &keys {
+        assert_eq!(pager.index.get(key), Some(*id), "{key} was lost");
+    }
+
+    // Shrinking the index hands its spare continuation pages back
+    pager.index = PrimaryIndex::new();
+    pager.index.insert("users/only".to_string(), 1);
+    pager.sync_index().unwrap();
+    assert!(!pager.free_pages().is_empty());
+    drop(pager);
+
+    let pager = Pager::open(db_path, master_key).unwrap();
+    assert_eq!(pager.index.get("users/only"), Some(1));
+    assert_eq!(pager.index.get("users/user_0000"), None);
+}
+
+#[test]
+fn test_primary_index_row_counts() {
+    let mut index = PrimaryIndex::new();
+    index.insert("users/a".to_string(), 1);
+    index.insert("users/b".to_string(), 2);
+    index.insert("users_archive/a".to_string(), 3);
+    assert_eq!(index.row_count("users"), 2);
+    assert_eq!(index.row_count("users_archive"), 1);
+    assert_eq!(index.row_count("nobody"), 0);
+
+    // Overwriting a key moves it, it doesn't add a row
+    index.insert("users/a".to_string(), 4);
+    assert_eq!(index.row_count("users"), 2);
+    index.remove("users/b");
+    index.remove("users/b");
+    assert_eq!(index.row_count("users"), 1);
+
+    let reloaded = PrimaryIndex::from_bytes(&index.to_bytes().unwrap()).unwrap();
+    assert_eq!(reloaded.row_count("users"), 1);
+    assert_eq!(reloaded.row_count("users_archive"), 1);
+}
+
+#[test]
+fn test_index_compaction_under_churn() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut pager = Pager::open(temp_file.path(), generate_key()).unwrap();
+
+    // Rounds of bulk inserts followed by deleting nearly all of them
+    let mut file_pages = Vec::new();
+    for round in 0..4 {
+        for i in 0..3000 {
+            pager.index.insert(format!("events/{}-{:04}", round, i), 1);
+        }
+        pager.sync_index().unwrap();
+        for i in 10..3000 {
+            pager.index.remove(&format!("events/{}-{:04}", round, i));
+        }
+        assert!(pager.index.needs_compaction());
+        pager.sync_index().unwrap();
+        assert!(!pager.index.needs_compaction());
+        file_pages.push(pager.total_pages());
+    }
+
+    // The index holds exactly the survivors: it is as small as one built from scratch
+    let mut fresh = PrimaryIndex::new();
+    for round in 0..4 {
+        for i in 0..10 {
+            fresh.insert(format!("events/{}-{:04}", round, i), 1);
+        }
+    }
+    let bytes = pager.index.to_bytes().unwrap();
+    assert_eq!(bytes, fresh.to_bytes().unwrap());
+    assert!(bytes.len() <= DATA_SIZE, "index no longer fits on page 0");
+    assert_eq!(pager.index.row_count("events"), 40);
+
+    // The chain pages a round needed are reused by the next, so the file stops growing
+    assert!(
+        file_pages.windows(2).all(|w| w[0] == w[1]),
+        "{:?}",
+        file_pages
+    );
+}
+
+#[test]
+fn test_wal_replay_up_to_applies_a_prefix() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db_path = temp_file.path();
+    let master_key = generate_key();
+
+    let page_with = |id: u32, tag: &[u8; 4]| {
+        let mut page = Page::new(id);
+        page.data[0..4].copy_from_slice(tag);
+        page
+    };
+
+    let mut pager = Pager::open(db_path, master_key.clone()).unwrap();
+    for id in 1..=4 {
+        pager.write_page(&page_with(id, b"old!")).unwrap();
+    }
+
+    // Four page writes sit in the WAL, none of them committed
+    pager.begin();
+    for id in 1..=4 {
+        pager.write_page(&page_with(id, b"new!")).unwrap();
+    }
+    let records = pager.wal_records().unwrap();
+    assert_eq!(records.len(), 4);
+    assert_eq!(
+        records.iter().map(|r| r.page_id).collect::<Vec<_>>(),
+        vec![1, 2, 3, 4]
+    );
+    assert!(records.iter().all(|r| !r.committed));
+    assert!(records.iter().all(|r| r.size == ENCRYPTED_PAGE_SIZE));
+    assert!(records.windows(2).all(|w| w[0].seq < w[1].seq));
+
+    // Replay only the first two
+    let WalRecordInfo { seq, .. } = records[1];
+    assert_eq!(pager.replay_up_to(seq).unwrap(), 2);
+    assert!(pager.wal_records().unwrap().is_empty());
+
+    for id in 1..=4 {
+        let expected: &[u8] = if id <= 2 { b"new!" } else { b"old!" };
+        assert_eq!(&pager.read_page(id).unwrap().data[0..4], expected);
+    }
+    drop(pager);
+
+    // Same picture from the file itself
+    let mut pager = Pager::open(db_path, master_key).unwrap();
+    assert_eq!(&pager.read_page(2).unwrap().data[0..4], b"new!");
+    assert_eq!(&pager.read_page(3).unwrap().data[0..4], b"old!");
+}
+
+#[test]
+fn test_page_cache_decrypts_repeated_reads_once() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db_path = temp_file.path();
+    let master_key = generate_key();
+
+    let mut pager = Pager::open(db_path, master_key.clone()).unwrap();
+    for id in 1..=3 {
+        pager.write_page(&Page::new(id)).unwrap();
+    }
+
+    let before = pager.stats();
+    for _ in 0..5 {
+        pager.read_page(1).unwrap();
+    }
+    let cost = pager.stats() - before;
+    assert_eq!(cost.pages_read, 5);
+    assert_eq!(cost.pages_decrypted, 1);
+    drop(pager);
+
+    // Bypassed, every read decrypts
+    let options = PagerOptions {
+        cache_pages: 0,
+        ..PagerOptions::default()
+    };
+    let mut pager = Pager::open_with_options(db_path, master_key.clone(), options).unwrap();
+    let before = pager.stats();
+    for _ in 0..5 {
+        pager.read_page(1).unwrap();
+    }
+    assert_eq!((pager.stats() - before).pages_decrypted, 5);
+    drop(pager);
+
+    // When full, the least recently used page is the one evicted
+    let options = PagerOptions {
+        cache_pages: 2,
+        ..PagerOptions::default()
+    };
+    let mut pager = Pager::open_with_options(db_path, master_key, options).unwrap();
+    for id in [1, 2, 1, 3] {
+        pager.read_page(id).unwrap();
+    }
+    let before = pager.stats();
+    pager.read_page(1).unwrap();
+    pager.read_page(3).unwrap();
+    assert_eq!((pager.stats() - before).pages_decrypted, 0);
+    pager.read_page(2).unwrap();
+    assert_eq!((pager.stats() - before).pages_decrypted, 1);
+}
+
+#[test]
+fn test_page_cache_never_returns_stale_pages() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db_path = temp_file.path();
+    let master_key = generate_key();
+
+    let page_with = |id: u32, tag: &[u8; 4]| {
+        let mut page = Page::new(id);
+        page.data[0..4].copy_from_slice(tag);
+        page
+    };
+
+    let mut pager = Pager::open(db_path, master_key.clone()).unwrap();
+    pager.write_page(&page_with(1, b"one!")).unwrap();
+    pager.write_page(&page_with(2, b"two!")).unwrap();
+    assert_eq!(&pager.read_page(1).unwrap().data[0..4], b"one!");
+    assert_eq!(&pager.read_page(2).unwrap().data[0..4], b"two!");
+
+    // A write replaces the cached copy, committed or still in a batch
+    pager.write_page(&page_with(1, b"uno!")).unwrap();
+    assert_eq!(&pager.read_page(1).unwrap().data[0..4], b"uno!");
+    pager.begin();
+    pager.write_page(&page_with(2, b"dos!")).unwrap();
+    assert_eq!(&pager.read_page(2).unwrap().data[0..4], b"dos!");
+    assert_eq!(&pager.read_page(1).unwrap().data[0..4], b"uno!");
+    pager.commit().unwrap();
+    assert_eq!(&pager.read_page(2).unwrap().data[0..4], b"dos!");
+
+    // Replaying the WAL rewrites pages behind the cache's back, so it starts over
+    pager.begin();
+    pager.write_page(&page_with(1, b"wal!")).unwrap();
+    let seq = pager.wal_records().unwrap()[0].seq;
+    pager.replay_up_to(seq).unwrap();
+    assert_eq!(&pager.read_page(1).unwrap().data[0..4], b"wal!");
+    drop(pager);
+
+    let mut pager = Pager::open(db_path, master_key).unwrap();
+    assert_eq!(&pager.read_page(1).unwrap().data[0..4], b"wal!");
+    assert_eq!(&pager.read_page(2).unwrap().data[0..4], b"dos!");
+}
@@ -0,0 +1,184 @@
+// Write-Ahead Log: page images are appended here (and fsynced) before the main
+// file is touched, so a crash can never leave a half-applied group of pages.
+//
+// Record layout (little-endian):
+// [seq: u64][page_id: u32][kind: u8][len: u32][payload: len bytes][crc32: u32]
+// The payload of a Page record is the encrypted page image, exactly as it will be
+// written to the main file. A Commit record (no payload) seals every record before it.
+// The CRC covers everything before it, so a torn trailing record is detected and ignored.
+use crate::StoreError;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const RECORD_PAGE: u8 = 1;
+const RECORD_COMMIT: u8 = 2;
+const HEADER_LEN: usize = 8 + 4 + 1 + 4;
+const CRC_LEN: usize = 4;
+
+/// A page image waiting in the log to be applied to the main file
+#[derive(Debug, Clone, PartialEq)]
+pub struct WalRecord {
+    pub seq: u64,
+    pub page_id: u32,
+    pub image: Vec<u8>,
+}
+
+pub struct Wal {
+    file: File,
+    path: PathBuf,
+    next_seq: u64,
+    /// Whether `commit` and `truncate` fsync (see `set_sync`)
+    sync: bool,
+    syncs: u64,
+}
+
+impl Wal {
+    /// The log lives next to the database: "data.db" -> "data.db.wal"
+    pub fn path_for(db_path: &Path) -> PathBuf {
+        let mut path = db_path.as_os_str().to_owned();
+        path.push(".wal");
+        PathBuf::from(path)
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        let mut wal = Self {
+            file,
+            path,
+            next_seq: 1,
+            sync: true,
+            syncs: 0,
+        };
+        let (records, _) = wal.scan()?;
+        if let Some(last) = records.last() {
+            wal.next_seq = last.seq + 1;
+        }
+        Ok(wal)
+    }
+
+    /// Logs a page image. Returns its sequence number.
+    pub fn append_page(&mut self, page_id: u32, image: &[u8]) -> Result<u64, StoreError> {
+        self.append(RECORD_PAGE, page_id, image)
+    }
+
+    /// Seals everything logged so far and makes it durable.
+    /// Once this returns, recovery will apply those records even if we crash.
+    pub fn commit(&mut self) -> Result<(), StoreError> {
+        self.append(RECORD_COMMIT, 0, &[])?;
+        if self.sync {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Fsyncs the log, whatever `set_sync` says
+    pub fn sync(&mut self) -> Result<(), StoreError> {
+        self.file.sync_data()?;
+        self.syncs += 1;
+        Ok(())
+    }
+
+    /// Off, `commit` and `truncate` leave the log to the OS to write back: a
+    /// crash of the machine (not just the process) can then lose commits
+    pub fn set_sync(&mut self, sync: bool) {
+        self.sync = sync;
+    }
+
+    /// Fsyncs of the log since it was opened
+    pub fn syncs(&self) -> u64 {
+        self.syncs
+    }
+
+    /// Page records covered by a commit, in log order.
+    /// Anything after the last commit (or after a torn record) is left out.
+    pub fn committed_records(&mut self) -> Result<Vec<WalRecord>, StoreError> {
+        let (mut records, committed) = self.scan()?;
+        records.truncate(committed);
+        Ok(records)
+    }
+
+    /// Empties the log (after its records have reached the main file)
+    pub fn truncate(&mut self) -> Result<(), StoreError> {
+        self.file.set_len(0)?;
+        if self.sync {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the log file if it holds nothing (a clean shutdown)
+    pub fn remove_if_empty(&self) -> Result<(), StoreError> {
+        if self.file.metadata()?.len() == 0 {
+            std::fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, kind: u8, page_id: u32, payload: &[u8]) -> Result<u64, StoreError> {
+        let seq = self.next_seq;
+
+        let mut record = Vec::with_capacity(HEADER_LEN + payload.len() + CRC_LEN);
+        record.extend_from_slice(&seq.to_le_bytes());
+        record.extend_from_slice(&page_id.to_le_bytes());
+        record.push(kind);
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        record.extend_from_slice(payload);
+        record.extend_from_slice(&crc32fast::hash(&record).to_le_bytes());
+
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.write_all(&record)?;
+        self.next_seq += 1;
+        Ok(seq)
+    }
+
+    /// Reads every intact record. Returns the page records and how many of them
+    /// are followed by a commit.
+    pub fn scan(&mut self) -> Result<(Vec<WalRecord>, usize), StoreError> {
+        let mut bytes = Vec::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_end(&mut bytes)?;
+
+        let mut records = Vec::new();
+        let mut committed = 0;
+        let mut pos = 0;
+
+        while pos + HEADER_LEN <= bytes.len() {
+            let header = &bytes[pos..pos + HEADER_LEN];
+            let seq = u64::from_le_bytes(header[0..8].try_into().expect("8 bytes"));
+            let page_id = u32::from_le_bytes(header[8..12].try_into().expect("4 bytes"));
+            let kind = header[12];
+            let len = u32::from_le_bytes(header[13..17].try_into().expect("4 bytes")) as usize;
+
+            let end = pos + HEADER_LEN + len + CRC_LEN;
+            if end > bytes.len() {
+                break; // Torn write: the record was never completed
+            }
+            let body = &bytes[pos..end - CRC_LEN];
+            let crc = u32::from_le_bytes(bytes[end - CRC_LEN..end].try_into().expect("4 bytes"));
+            if crc != crc32fast::hash(body) {
+                break;
+            }
+
+            match kind {
+                RECORD_PAGE => records.push(WalRecord {
+                    seq,
+                    page_id,
+                    image: body[HEADER_LEN..].to_vec(),
+                }),
+                RECORD_COMMIT => committed = records.len(),
+                _ => break,
+            }
+            pos = end;
+        }
+
+        Ok((records, committed))
+    }
+}